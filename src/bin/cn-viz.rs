@@ -0,0 +1,79 @@
+//! Print the layout of a network from the command line.
+//!
+//! ```text
+//! cn-viz <bitonic|butterfly|periodic> <width> [ascii|dot|balancers|stats]
+//! ```
+//!
+//! Only built with the `viz` feature, for example with
+//! `cargo run --features viz --bin cn-viz -- bitonic 8 stats`.
+
+use counting_networks::networks::{
+    BitonicConfiguration, ButterflyConfiguration, Network, PeriodicConfiguration, Topology, Width,
+};
+use std::{env, fmt::Write, process, sync::atomic::AtomicUsize};
+
+const USAGE: &str =
+    "usage: cn-viz <bitonic|butterfly|periodic> <width> [ascii|dot|balancers|stats]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match run(&args) {
+        Ok(output) => print!("{}", output),
+        Err(message) => {
+            eprintln!("cn-viz: {}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    let (kind, width, format) = match args {
+        [kind, width] => (kind, width, "ascii"),
+        [kind, width, format] => (kind, width, format.as_str()),
+        _ => return Err(String::from("expected a network type and a width")),
+    };
+
+    let width = width
+        .parse()
+        .ok()
+        .and_then(Width::new)
+        .ok_or_else(|| format!("width must be a power of two, not {:?}", width))?;
+    let topology = match kind.as_str() {
+        "bitonic" => Topology::from_config::<BitonicConfiguration>(width),
+        "butterfly" => Topology::from_config::<ButterflyConfiguration>(width),
+        "periodic" => Topology::from_config::<PeriodicConfiguration>(width),
+        _ => return Err(format!("unknown network type {:?}", kind)),
+    };
+
+    match format {
+        "ascii" => Ok(topology.to_ascii()),
+        "dot" => Ok(topology.to_dot()),
+        "balancers" => Ok(topology.to_string()),
+        "stats" => Ok(stats(&topology)),
+        _ => Err(format!("unknown output format {:?}", format)),
+    }
+}
+
+fn stats(topology: &Topology) -> String {
+    // Memory of a counter on this network, with a `usize` bucket per wire
+    let buckets = (0..topology.width()).map(|_| AtomicUsize::new(0)).collect();
+    let usage = Network::<_, Topology>::from_topology(topology, buckets).memory_usage();
+
+    let mut stats = String::new();
+    writeln!(stats, "width      {}", topology.width()).unwrap();
+    writeln!(stats, "balancers  {}", topology.balancers().len()).unwrap();
+    writeln!(stats, "depth      {}", topology.depth()).unwrap();
+    writeln!(stats, "memory     {} bytes", usage.total()).unwrap();
+    writeln!(
+        stats,
+        "  segments {} bytes, {} of them padding",
+        usage.segments(),
+        usage.padding()
+    )
+    .unwrap();
+    writeln!(stats, "  buckets  {} bytes", usage.outputs()).unwrap();
+    writeln!(stats, "  entries  {} bytes", usage.entry_points()).unwrap();
+
+    stats
+}
@@ -0,0 +1,1142 @@
+//! Data structures that use counting networks to balance access between
+//! threads.
+
+use crate::{
+    counters::{BitonicCountingNetwork, Counter},
+    networks::BitonicNetwork,
+};
+use core::{
+    fmt, iter, mem,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use std::{
+    error::Error,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+/// A borrowed slice that hands out its elements in a balanced way.
+///
+/// Each call to [`get`](BalancedSlice::get) traverses a
+/// [`BitonicNetwork`](crate::networks::BitonicNetwork) whose outputs are the
+/// elements of the slice. Once all concurrent calls have finished, every
+/// element will have been returned either `n` or `n + 1` times, with the
+/// elements returned `n + 1` times all at the front of the slice.
+///
+/// This is useful for picking one of a fixed set of resources (connections,
+/// shards, queues) fairly across many threads.
+pub struct BalancedSlice<'a, T> {
+    slice: &'a [T],
+    network: BitonicNetwork<&'a T>,
+}
+
+impl<'a, T> BalancedSlice<'a, T> {
+    /// Create a new balanced view over the given slice.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the slice is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::BalancedSlice;
+    ///
+    /// let shards = ["a", "b", "c", "d"];
+    /// let balanced = BalancedSlice::new(&shards);
+    ///
+    /// assert_eq!(balanced.as_slice(), &shards);
+    /// ```
+    pub fn new(slice: &'a [T]) -> Self {
+        let network = BitonicNetwork::new(slice.iter().collect());
+
+        BalancedSlice { slice, network }
+    }
+
+    /// Returns the next element of the slice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::BalancedSlice;
+    ///
+    /// let shards = ["a", "b", "c", "d"];
+    /// let balanced = BalancedSlice::new(&shards);
+    ///
+    /// assert_eq!(balanced.get(), &"a");
+    /// assert_eq!(balanced.get(), &"b");
+    /// assert_eq!(balanced.get(), &"c");
+    /// assert_eq!(balanced.get(), &"d");
+    /// assert_eq!(balanced.get(), &"a");
+    /// ```
+    pub fn get(&self) -> &'a T {
+        self.network.traverse()
+    }
+
+    /// Returns the underlying slice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::BalancedSlice;
+    ///
+    /// let shards = [1, 2];
+    /// let balanced = BalancedSlice::new(&shards);
+    ///
+    /// assert_eq!(balanced.as_slice(), &[1, 2]);
+    /// ```
+    pub fn as_slice(&self) -> &'a [T] {
+        self.slice
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BalancedSlice<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BalancedSlice")
+            .field("slice", &self.slice)
+            .finish()
+    }
+}
+
+/// A handle returned by a [`GenerationalAllocator`].
+///
+/// The generation distinguishes between different uses of the same index, so
+/// a handle that outlives its entry can be detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: usize,
+}
+
+impl Handle {
+    /// Returns the index of the entry, which is less than the capacity of the
+    /// allocator that created the handle.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(4, 2);
+    ///
+    /// assert_eq!(allocator.allocate().unwrap().index(), 0);
+    /// ```
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of times the index had been freed before this
+    /// handle was allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(1, 1);
+    ///
+    /// let first = allocator.allocate().unwrap();
+    /// assert_eq!(first.generation(), 0);
+    ///
+    /// allocator.free(first);
+    /// assert_eq!(allocator.allocate().unwrap().generation(), 1);
+    /// ```
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+}
+
+/// An allocator of indices with generations, for entity or slot map style
+/// storage shared between threads.
+///
+/// Fresh indices are taken from a counting network, so threads allocating for
+/// the first time do not contend on a single counter. Once every index has
+/// been handed out, freed indices are reused from a free list, which is
+/// protected by a lock.
+///
+/// Freeing a handle bumps the generation of its index with a single atomic
+/// compare-and-swap, so a handle can only be freed once, and stale handles are
+/// detected by [`is_valid`](GenerationalAllocator::is_valid).
+pub struct GenerationalAllocator {
+    counter: BitonicCountingNetwork,
+    // Set once the counter has produced a value past the end of `generations`.
+    exhausted: AtomicBool,
+    // One more than the current generation of each index, or zero if the index
+    // has never been handed out, so that handles for those are rejected.
+    generations: Box<[AtomicUsize]>,
+    free: Mutex<Vec<usize>>,
+}
+
+impl GenerationalAllocator {
+    /// Create a new allocator that hands out indices less than `capacity`,
+    /// using a counting network of the given width for fresh indices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(1024, 8);
+    ///
+    /// assert_eq!(allocator.capacity(), 1024);
+    /// ```
+    pub fn new(capacity: usize, width: usize) -> Self {
+        GenerationalAllocator {
+            counter: BitonicCountingNetwork::new(width),
+            exhausted: AtomicBool::new(capacity == 0),
+            generations: (0..capacity).map(|_| AtomicUsize::new(0)).collect(),
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the number of indices that the allocator can hand out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(3, 2);
+    ///
+    /// assert_eq!(allocator.capacity(), 3);
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.generations.len()
+    }
+
+    /// Allocate a new handle, or return `None` if every index is in use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(2, 2);
+    ///
+    /// assert!(allocator.allocate().is_some());
+    /// assert!(allocator.allocate().is_some());
+    /// assert!(allocator.allocate().is_none());
+    /// ```
+    pub fn allocate(&self) -> Option<Handle> {
+        if !self.exhausted.load(Ordering::Relaxed) {
+            let index = self.counter.next();
+            if index < self.capacity() {
+                self.generations[index].store(1, Ordering::Release);
+
+                return Some(Handle {
+                    index,
+                    generation: 0,
+                });
+            }
+
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+
+        let index = self.free.lock().unwrap().pop()?;
+
+        Some(Handle {
+            index,
+            generation: self.generations[index]
+                .load(Ordering::Acquire)
+                .wrapping_sub(1),
+        })
+    }
+
+    /// Free a handle so its index can be reused, returning false if the handle
+    /// was already stale, or its index has never been handed out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(4, 2);
+    /// let handle = allocator.allocate().unwrap();
+    ///
+    /// assert!(allocator.free(handle));
+    /// assert!(!allocator.free(handle));
+    /// ```
+    pub fn free(&self, handle: Handle) -> bool {
+        let generation = match self.generations.get(handle.index) {
+            Some(generation) => generation,
+            None => return false,
+        };
+
+        let bumped = generation
+            .compare_exchange(
+                handle.generation.wrapping_add(1),
+                handle.generation.wrapping_add(2),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_ok();
+        if bumped {
+            self.free.lock().unwrap().push(handle.index);
+        }
+
+        bumped
+    }
+
+    /// Returns true if the handle has not been freed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::GenerationalAllocator;
+    ///
+    /// let allocator = GenerationalAllocator::new(4, 2);
+    /// let handle = allocator.allocate().unwrap();
+    ///
+    /// assert!(allocator.is_valid(handle));
+    /// allocator.free(handle);
+    /// assert!(!allocator.is_valid(handle));
+    /// ```
+    pub fn is_valid(&self, handle: Handle) -> bool {
+        match self.generations.get(handle.index) {
+            Some(generation) => {
+                generation.load(Ordering::Acquire) == handle.generation.wrapping_add(1)
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for GenerationalAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GenerationalAllocator")
+            .field("capacity", &self.capacity())
+            .field("exhausted", &self.exhausted.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Hands out each of the indices `0..len` exactly once, across all threads.
+///
+/// This is for giving each of a set of workers a distinct slot, such as a
+/// partition to own or a column to write. Indices are taken from a counting
+/// network, so workers claiming at the same time do not contend on a single
+/// counter. Once every index has been claimed, further claims fail.
+///
+/// A claimed index is held by a [`Slot`]. If the thread holding a slot panics
+/// before the slot is dropped, the index is returned to the assigner, so that
+/// a later claim can take it instead of it being skipped, and the assigner is
+/// marked as [poisoned](SlotAssigner::is_poisoned). Use
+/// [`Slot::into_index`] once the slot is set up, so that later panics don't
+/// return it.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::collections::SlotAssigner;
+/// use std::{sync::Arc, thread};
+///
+/// let assigner = Arc::new(SlotAssigner::new(4));
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let assigner = Arc::clone(&assigner);
+///         thread::spawn(move || assigner.claim().unwrap().into_index())
+///     })
+///     .collect();
+/// let mut slots: Vec<_> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+/// slots.sort_unstable();
+///
+/// assert_eq!(slots, vec![0, 1, 2, 3]);
+/// assert!(assigner.claim().is_err());
+/// ```
+pub struct SlotAssigner {
+    counter: BitonicCountingNetwork,
+    len: usize,
+    // Set once the counter has produced a value past the last index.
+    exhausted: AtomicBool,
+    // Indices returned by threads that panicked while holding them.
+    returned: Mutex<Vec<usize>>,
+    poisoned: AtomicBool,
+}
+
+impl SlotAssigner {
+    /// Create an assigner for the indices `0..len`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    ///
+    /// let assigner = SlotAssigner::new(16);
+    ///
+    /// assert_eq!(assigner.len(), 16);
+    /// ```
+    pub fn new(len: usize) -> Self {
+        SlotAssigner {
+            counter: BitonicCountingNetwork::new(8),
+            len,
+            exhausted: AtomicBool::new(len == 0),
+            returned: Mutex::new(Vec::new()),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the number of indices the assigner hands out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    ///
+    /// assert_eq!(SlotAssigner::new(3).len(), 3);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the assigner has no indices to hand out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    ///
+    /// assert!(SlotAssigner::new(0).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Claim an index that no other claim has been given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error once every index has been claimed. A claim that runs
+    /// while another thread is panicking can fail just before that thread
+    /// returns its index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    ///
+    /// let assigner = SlotAssigner::new(2);
+    ///
+    /// assert_eq!(assigner.claim().unwrap().index(), 0);
+    /// assert_eq!(assigner.claim().unwrap().index(), 1);
+    /// assert!(assigner.claim().is_err());
+    /// ```
+    pub fn claim(&self) -> Result<Slot<'_>, SlotsExhausted> {
+        if !self.exhausted.load(Ordering::Relaxed) {
+            let index = self.counter.next();
+            if index < self.len {
+                return Ok(Slot {
+                    assigner: self,
+                    index,
+                });
+            }
+
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+
+        match self.returned.lock().unwrap().pop() {
+            Some(index) => Ok(Slot {
+                assigner: self,
+                index,
+            }),
+            None => Err(SlotsExhausted),
+        }
+    }
+
+    /// Returns true if a thread panicked while holding a slot, so its index
+    /// was returned to be claimed again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    /// use std::{panic, sync::Arc, thread};
+    ///
+    /// let assigner = Arc::new(SlotAssigner::new(1));
+    ///
+    /// let crashed = {
+    ///     let assigner = Arc::clone(&assigner);
+    ///     thread::spawn(move || {
+    ///         let _slot = assigner.claim().unwrap();
+    ///         panic!("failed to set up the slot");
+    ///     })
+    /// };
+    /// assert!(crashed.join().is_err());
+    ///
+    /// assert!(assigner.is_poisoned());
+    /// assert_eq!(assigner.claim().unwrap().index(), 0);
+    /// ```
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for SlotAssigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SlotAssigner")
+            .field("len", &self.len)
+            .field("exhausted", &self.exhausted.load(Ordering::Relaxed))
+            .field("poisoned", &self.is_poisoned())
+            .finish()
+    }
+}
+
+/// An index claimed from a [`SlotAssigner`].
+///
+/// Dropping the slot keeps the index claimed, unless the thread is panicking,
+/// in which case the index is returned to the assigner.
+#[derive(Debug)]
+pub struct Slot<'a> {
+    assigner: &'a SlotAssigner,
+    index: usize,
+}
+
+impl<'a> Slot<'a> {
+    /// Returns the claimed index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    ///
+    /// let assigner = SlotAssigner::new(1);
+    ///
+    /// assert_eq!(assigner.claim().unwrap().index(), 0);
+    /// ```
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Keep the index claimed for good, even if the thread panics later.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::SlotAssigner;
+    ///
+    /// let assigner = SlotAssigner::new(1);
+    ///
+    /// assert_eq!(assigner.claim().unwrap().into_index(), 0);
+    /// ```
+    pub fn into_index(self) -> usize {
+        let index = self.index;
+        mem::forget(self);
+
+        index
+    }
+}
+
+impl<'a> Drop for Slot<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // A poisoned lock still holds a valid list of indices.
+            let mut returned = self
+                .assigner
+                .returned
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            returned.push(self.index);
+            self.assigner.poisoned.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The error returned when every index of a [`SlotAssigner`] has been
+/// claimed.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::collections::SlotAssigner;
+///
+/// let error = SlotAssigner::new(0).claim().unwrap_err();
+///
+/// assert_eq!(error.to_string(), "every slot has been claimed");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotsExhausted;
+
+impl fmt::Display for SlotsExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("every slot has been claimed")
+    }
+}
+
+impl Error for SlotsExhausted {}
+
+/// Create a channel whose messages are stamped with sequence numbers from a
+/// counting network of the given width.
+///
+/// Every message sent through any clone of the [`SequencedSender`] takes the
+/// next value of a shared counter, so the sequence numbers of all messages are
+/// exactly `0, 1, 2, ...` without a single atomic counter that every producer
+/// contends on. A sender can be descheduled between stamping a message and
+/// sending it, so messages can arrive out of order, which the
+/// [`SequencedReceiver`] reports.
+///
+/// # Panics
+///
+/// Panics if `width` is not a power of two.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::collections::{sequenced_channel, Arrival};
+///
+/// let (sender, mut receiver) = sequenced_channel(4);
+///
+/// assert_eq!(sender.send("first").unwrap(), 0);
+/// assert_eq!(sender.send("second").unwrap(), 1);
+///
+/// let message = receiver.recv().unwrap();
+/// assert_eq!(message.sequence(), 0);
+/// assert_eq!(message.arrival(), Arrival::InOrder);
+/// assert_eq!(message.into_value(), "first");
+/// ```
+pub fn sequenced_channel<T>(width: usize) -> (SequencedSender<T>, SequencedReceiver<T>) {
+    let (sender, receiver) = mpsc::channel();
+
+    (
+        SequencedSender {
+            counter: Arc::new(BitonicCountingNetwork::new(width)),
+            sender,
+        },
+        SequencedReceiver {
+            receiver,
+            next_sequence: 0,
+        },
+    )
+}
+
+/// The sending half of a [`sequenced_channel`].
+///
+/// Clones share the same counter, so messages from all of them are numbered
+/// in a single sequence.
+pub struct SequencedSender<T> {
+    counter: Arc<BitonicCountingNetwork>,
+    sender: mpsc::Sender<(usize, T)>,
+}
+
+impl<T> SequencedSender<T> {
+    /// Stamp the value with the next sequence number and send it, returning
+    /// the sequence number.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value if the receiver has been dropped. Its sequence number
+    /// has already been taken, and is never used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    /// use std::thread;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(8);
+    ///
+    /// let other = sender.clone();
+    /// thread::spawn(move || other.send('a').unwrap())
+    ///     .join()
+    ///     .unwrap();
+    /// sender.send('b').unwrap();
+    ///
+    /// let mut sequences: Vec<_> = receiver.iter().take(2).map(|m| m.sequence()).collect();
+    /// sequences.sort();
+    /// assert_eq!(sequences, vec![0, 1]);
+    /// ```
+    pub fn send(&self, value: T) -> Result<usize, mpsc::SendError<T>> {
+        let sequence = self.counter.next();
+
+        self.sender
+            .send((sequence, value))
+            .map(|()| sequence)
+            .map_err(|mpsc::SendError((_, value))| mpsc::SendError(value))
+    }
+}
+
+impl<T> Clone for SequencedSender<T> {
+    fn clone(&self) -> Self {
+        SequencedSender {
+            counter: Arc::clone(&self.counter),
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SequencedSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SequencedSender")
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
+
+/// The receiving half of a [`sequenced_channel`].
+///
+/// The receiver remembers the sequence number it expects next, one past the
+/// highest it has received, and compares every message against it.
+pub struct SequencedReceiver<T> {
+    receiver: mpsc::Receiver<(usize, T)>,
+    next_sequence: usize,
+}
+
+impl<T> SequencedReceiver<T> {
+    /// Block until a message arrives, and return it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error once every sender has been dropped and the channel is
+    /// empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// sender.send(5).unwrap();
+    /// drop(sender);
+    ///
+    /// assert_eq!(receiver.recv().unwrap().into_value(), 5);
+    /// assert!(receiver.recv().is_err());
+    /// ```
+    pub fn recv(&mut self) -> Result<Sequenced<T>, mpsc::RecvError> {
+        let (sequence, value) = self.receiver.recv()?;
+
+        Ok(self.arrive(sequence, value))
+    }
+
+    /// Return a message if one is waiting, without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the channel is empty, or if every sender has been
+    /// dropped and the channel is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// assert!(receiver.try_recv().is_err());
+    ///
+    /// sender.send(5).unwrap();
+    /// assert_eq!(receiver.try_recv().unwrap().sequence(), 0);
+    /// ```
+    pub fn try_recv(&mut self) -> Result<Sequenced<T>, mpsc::TryRecvError> {
+        let (sequence, value) = self.receiver.try_recv()?;
+
+        Ok(self.arrive(sequence, value))
+    }
+
+    /// Returns an iterator that blocks waiting for messages, and ends once
+    /// every sender has been dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// sender.send('a').unwrap();
+    /// sender.send('b').unwrap();
+    /// drop(sender);
+    ///
+    /// let values: Vec<_> = receiver.iter().map(|m| m.into_value()).collect();
+    /// assert_eq!(values, vec!['a', 'b']);
+    /// ```
+    pub fn iter(&mut self) -> impl Iterator<Item = Sequenced<T>> + '_ {
+        iter::from_fn(move || self.recv().ok())
+    }
+
+    /// Returns the sequence number one past the highest received so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// assert_eq!(receiver.next_sequence(), 0);
+    ///
+    /// sender.send(()).unwrap();
+    /// receiver.recv().unwrap();
+    /// assert_eq!(receiver.next_sequence(), 1);
+    /// ```
+    pub fn next_sequence(&self) -> usize {
+        self.next_sequence
+    }
+
+    fn arrive(&mut self, sequence: usize, value: T) -> Sequenced<T> {
+        // Sequence numbers wrap around, so compare them the same way as TCP
+        // sequence numbers.
+        let ahead = sequence.wrapping_sub(self.next_sequence) as isize;
+        let arrival = if ahead == 0 {
+            Arrival::InOrder
+        } else if ahead > 0 {
+            Arrival::Skipped {
+                missing: ahead as usize,
+            }
+        } else {
+            Arrival::Late
+        };
+
+        if ahead >= 0 {
+            self.next_sequence = sequence.wrapping_add(1);
+        }
+
+        Sequenced {
+            sequence,
+            arrival,
+            value,
+        }
+    }
+}
+
+impl<T> fmt::Debug for SequencedReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SequencedReceiver")
+            .field("next_sequence", &self.next_sequence)
+            .finish()
+    }
+}
+
+/// How a message from a [`sequenced_channel`] arrived, compared to the
+/// messages received before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arrival {
+    /// The message had the sequence number that was expected next.
+    InOrder,
+    /// The message skipped ahead of the sequence number that was expected
+    /// next. The skipped messages may still arrive late.
+    Skipped {
+        /// The number of sequence numbers that were skipped.
+        missing: usize,
+    },
+    /// A message with a higher sequence number had already been received.
+    Late,
+}
+
+/// A message received from a [`sequenced_channel`], with its sequence number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequenced<T> {
+    sequence: usize,
+    arrival: Arrival,
+    value: T,
+}
+
+impl<T> Sequenced<T> {
+    /// Returns the sequence number the message was stamped with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// sender.send(()).unwrap();
+    ///
+    /// assert_eq!(receiver.recv().unwrap().sequence(), 0);
+    /// ```
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+
+    /// Returns how the message arrived, compared to the messages received
+    /// before it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::{sequenced_channel, Arrival};
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// sender.send(()).unwrap();
+    ///
+    /// assert_eq!(receiver.recv().unwrap().arrival(), Arrival::InOrder);
+    /// ```
+    pub fn arrival(&self) -> Arrival {
+        self.arrival
+    }
+
+    /// Returns a reference to the value that was sent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// sender.send(3).unwrap();
+    ///
+    /// assert_eq!(receiver.recv().unwrap().value(), &3);
+    /// ```
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the value that was sent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::collections::sequenced_channel;
+    ///
+    /// let (sender, mut receiver) = sequenced_channel(2);
+    /// sender.send(String::from("hello")).unwrap();
+    ///
+    /// assert_eq!(receiver.recv().unwrap().into_value(), "hello");
+    /// ```
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    fn sync_only<T: Sync>(_: T) {}
+    fn send_only<T: Send>(_: T) {}
+
+    #[test]
+    fn is_send() {
+        send_only(BalancedSlice::new(&[1, 2]));
+    }
+
+    #[test]
+    fn is_sync() {
+        sync_only(BalancedSlice::new(&[1, 2]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn bad_slice_length() {
+        let _ = BalancedSlice::new(&[1, 2, 3]);
+    }
+
+    #[test]
+    fn concurrent_get_is_balanced() {
+        const NUM_THREADS: usize = 8;
+        const NUM_GETS: usize = 16;
+
+        static ITEMS: [usize; 4] = [0, 1, 2, 3];
+
+        let balanced = Arc::new(BalancedSlice::new(&ITEMS));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let balanced = Arc::clone(&balanced);
+                thread::spawn(move || (0..NUM_GETS).map(|_| *balanced.get()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut counts = [0; 4];
+        for handle in thread_handles {
+            for item in handle.join().unwrap() {
+                counts[item] += 1;
+            }
+        }
+
+        assert_eq!(counts, [NUM_THREADS * NUM_GETS / ITEMS.len(); 4]);
+    }
+
+    #[test]
+    fn allocator_reuses_freed_indices() {
+        let allocator = GenerationalAllocator::new(2, 2);
+
+        let first = allocator.allocate().unwrap();
+        let second = allocator.allocate().unwrap();
+        assert!(allocator.allocate().is_none());
+
+        assert!(allocator.free(first));
+        let third = allocator.allocate().unwrap();
+
+        assert_eq!(third.index(), first.index());
+        assert_eq!(third.generation(), first.generation() + 1);
+        assert!(!allocator.is_valid(first));
+        assert!(allocator.is_valid(second));
+        assert!(allocator.is_valid(third));
+    }
+
+    #[test]
+    fn allocator_rejects_foreign_handles() {
+        let small = GenerationalAllocator::new(1, 1);
+        let large = GenerationalAllocator::new(8, 2);
+
+        let handle = (0..8).map(|_| large.allocate().unwrap()).last().unwrap();
+
+        assert!(!small.is_valid(handle));
+        assert!(!small.free(handle));
+    }
+
+    #[test]
+    fn allocator_rejects_handles_never_handed_out() {
+        let first = GenerationalAllocator::new(8, 2);
+        let second = GenerationalAllocator::new(8, 2);
+
+        let handle = (0..4).map(|_| first.allocate().unwrap()).last().unwrap();
+        second.allocate().unwrap();
+
+        assert!(!second.is_valid(handle));
+        assert!(!second.free(handle));
+
+        // The index is still handed out fresh, rather than from the free list.
+        let handles: Vec<_> = (0..7).map(|_| second.allocate().unwrap()).collect();
+        assert!(handles.iter().all(|handle| handle.generation() == 0));
+        assert!(second.allocate().is_none());
+    }
+
+    #[test]
+    fn allocator_zero_capacity() {
+        let allocator = GenerationalAllocator::new(0, 1);
+
+        assert!(allocator.allocate().is_none());
+    }
+
+    #[test]
+    fn concurrent_allocate_and_free() {
+        const CAPACITY: usize = 16;
+        const NUM_THREADS: usize = 4;
+        const NUM_ROUNDS: usize = 8;
+
+        let allocator = Arc::new(GenerationalAllocator::new(CAPACITY, 4));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let allocator = Arc::clone(&allocator);
+                thread::spawn(move || {
+                    for _ in 0..NUM_ROUNDS {
+                        // Another thread may be between bumping a generation and
+                        // pushing the index onto the free list, so retry on `None`.
+                        let handles: Vec<_> = (0..CAPACITY / NUM_THREADS)
+                            .map(|_| loop {
+                                if let Some(handle) = allocator.allocate() {
+                                    break handle;
+                                }
+                            })
+                            .collect();
+                        for handle in handles {
+                            assert!(allocator.free(handle));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        let mut indices: Vec<_> = (0..CAPACITY)
+            .map(|_| allocator.allocate().unwrap().index())
+            .collect();
+        indices.sort();
+        assert_eq!(indices, (0..CAPACITY).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concurrent_claims_take_every_slot_once() {
+        let assigner = Arc::new(SlotAssigner::new(1000));
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let assigner = Arc::clone(&assigner);
+                thread::spawn(move || {
+                    let mut claimed = Vec::new();
+                    while let Ok(slot) = assigner.claim() {
+                        claimed.push(slot.into_index());
+                    }
+                    claimed
+                })
+            })
+            .collect();
+        let mut claimed: Vec<_> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+        claimed.sort_unstable();
+
+        assert_eq!(claimed, (0..1000).collect::<Vec<_>>());
+        assert!(!assigner.is_poisoned());
+    }
+
+    #[test]
+    fn panicked_slots_are_claimed_again() {
+        let assigner = Arc::new(SlotAssigner::new(3));
+
+        let crashed = {
+            let assigner = Arc::clone(&assigner);
+            thread::spawn(move || {
+                let kept = assigner.claim().unwrap().into_index();
+                let _lost = assigner.claim().unwrap();
+                panic!("kept {}", kept);
+            })
+        };
+        assert!(crashed.join().is_err());
+
+        assert!(assigner.is_poisoned());
+        assert_eq!(assigner.claim().unwrap().index(), 2);
+        assert_eq!(assigner.claim().unwrap().index(), 1);
+        assert_eq!(assigner.claim().unwrap_err(), SlotsExhausted);
+    }
+
+    #[test]
+    fn sequenced_channel_detects_reordering() {
+        let (sender, mut receiver) = sequenced_channel(2);
+
+        // Messages stamped 0 and 1, sent in the opposite order
+        sender.sender.send((1, 'b')).unwrap();
+        sender.sender.send((0, 'a')).unwrap();
+        sender.sender.send((4, 'e')).unwrap();
+        sender.sender.send((5, 'f')).unwrap();
+
+        let arrivals: Vec<_> = (0..4).map(|_| receiver.recv().unwrap().arrival()).collect();
+        assert_eq!(
+            arrivals,
+            vec![
+                Arrival::Skipped { missing: 1 },
+                Arrival::Late,
+                Arrival::Skipped { missing: 2 },
+                Arrival::InOrder,
+            ]
+        );
+        assert_eq!(receiver.next_sequence(), 6);
+    }
+
+    #[test]
+    fn sequenced_channel_many_producers() {
+        let (sender, mut receiver) = sequenced_channel(8);
+
+        let producers: Vec<_> = (0..4)
+            .map(|producer| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for _ in 0..250 {
+                        sender.send(producer).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut sequences: Vec<_> = receiver.iter().map(|m| m.sequence()).collect();
+        sequences.sort_unstable();
+        assert_eq!(sequences, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sequenced_send_to_dropped_receiver() {
+        let (sender, receiver) = sequenced_channel(2);
+        drop(receiver);
+
+        assert_eq!(sender.send(7).unwrap_err().0, 7);
+    }
+}
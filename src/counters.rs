@@ -1,114 +1,5899 @@
 //! Concrete implementations of shared counter using counting networks
 //! implemented in this crate.
 
-use crate::networks::BitonicNetwork;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::{
+    networks::{
+        AllocError, BitonicConfiguration, BitonicNetwork, ButterflyConfiguration, IntegrityError,
+        MemoryUsage, Network, NetworkConfiguration, Topology, Width,
+    },
+    util::{hash_single, try_vec_with_capacity, LazyBox},
+};
+use core::{
+    cell::Cell,
+    convert::TryFrom,
+    fmt, mem,
+    ops::Range,
+    ptr,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    error::Error,
+    sync::{Arc, Mutex, RwLock},
+    thread::{self, ThreadId},
+};
 
-struct CountingBucket {
-    value: AtomicUsize,
+#[cfg(feature = "debug-invariants")]
+use crate::invariants::Invariants;
+#[cfg(feature = "testing")]
+use crate::testing::jitter::{self, InjectionPoint};
+#[cfg(feature = "hdrhistogram")]
+use hdrhistogram::Histogram;
+#[cfg(feature = "rayon")]
+use rayon::ThreadPool;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer};
+#[cfg(feature = "hdrhistogram")]
+use std::{
+    sync::TryLockError,
+    time::{Duration, Instant},
+};
+
+/// Storage for the value on one output wire of a counting network.
+///
+/// Each wire of a [`BitonicCountingNetwork`] ends in a bucket holding the next
+/// value to hand out on that wire. Implementing this trait allows the storage
+/// to be replaced, for example to lay buckets out differently in memory or to
+/// keep them somewhere other than the heap.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, CounterBucket};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// // A bucket that also counts how many values it has handed out
+/// struct Tally {
+///     value: AtomicUsize,
+///     taken: AtomicUsize,
+/// }
+///
+/// impl CounterBucket for Tally {
+///     fn with_value(value: usize) -> Self {
+///         Tally {
+///             value: AtomicUsize::new(value),
+///             taken: AtomicUsize::new(0),
+///         }
+///     }
+///
+///     fn get_and_inc(&self, increment: usize) -> usize {
+///         self.taken.fetch_add(1, Ordering::Relaxed);
+///         self.value.fetch_add(increment, Ordering::SeqCst)
+///     }
+/// }
+///
+/// let counter = BitonicCountingNetwork::builder()
+///     .width(4)
+///     .build_with_buckets::<Tally>();
+///
+/// assert_eq!(counter.next(), 0);
+/// assert_eq!(counter.next(), 1);
+/// ```
+pub trait CounterBucket {
+    /// Create a bucket holding the given value.
+    fn with_value(value: usize) -> Self;
+
+    /// Return the value in the bucket and add `increment` to it, wrapping
+    /// around on overflow.
+    ///
+    /// Reading the value and incrementing it must be a single atomic
+    /// operation, otherwise two tokens leaving on the same wire could read the
+    /// same value.
+    fn get_and_inc(&self, increment: usize) -> usize;
+
+    /// Bring the bucket into the cache of the calling thread, without changing
+    /// its value. Used by [`BitonicCountingNetwork::prefault`].
+    ///
+    /// The default implementation does nothing.
+    fn prefault(&self) {}
+
+    /// Returns the number of bytes of heap memory that the bucket has
+    /// allocated, not counting the bucket itself. Used by
+    /// [`BitonicCountingNetwork::memory_usage`].
+    ///
+    /// The default implementation returns zero.
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+impl CounterBucket for AtomicUsize {
+    fn with_value(value: usize) -> Self {
+        AtomicUsize::new(value)
+    }
+
+    fn get_and_inc(&self, increment: usize) -> usize {
+        self.fetch_add(increment, Ordering::SeqCst)
+    }
+
+    fn prefault(&self) {
+        self.load(Ordering::Relaxed);
+    }
+}
+
+/// A bucket aligned to its own cache line.
+///
+/// This prevents false sharing of buckets between threads that leave the
+/// network on neighbouring wires, at the cost of more memory. Counters built
+/// with [`padded_buckets`](CounterOptions::padded_buckets) wrap their buckets
+/// in this type.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{CounterBucket, PaddedBucket};
+/// use std::{mem, sync::atomic::AtomicUsize};
+///
+/// let bucket = PaddedBucket::<AtomicUsize>::with_value(3);
+///
+/// assert_eq!(bucket.get_and_inc(2), 3);
+/// assert_eq!(bucket.get_and_inc(2), 5);
+/// assert_eq!(mem::align_of_val(&bucket), 64);
+/// ```
+#[repr(align(64))]
+#[derive(Debug, Default)]
+pub struct PaddedBucket<K>(pub K);
+
+impl<K: CounterBucket> CounterBucket for PaddedBucket<K> {
+    fn with_value(value: usize) -> Self {
+        PaddedBucket(K::with_value(value))
+    }
+
+    fn get_and_inc(&self, increment: usize) -> usize {
+        self.0.get_and_inc(increment)
+    }
+
+    fn prefault(&self) {
+        self.0.prefault()
+    }
+
+    fn heap_size(&self) -> usize {
+        self.0.heap_size()
+    }
+}
+
+thread_local! {
+    // The hash that picks the stripe this thread uses in types that are striped
+    // without a network, like `F64Adder`, changed whenever its stripe turns out
+    // to be contended. `const` initializers for thread locals are newer than
+    // the minimum supported Rust version.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static STRIPE_PROBE: Cell<u64> = Cell::new(hash_single(thread::current().id()) | 1);
+}
+
+// Returns the stripe probe of the current thread, reduce it modulo the number
+// of stripes to pick one.
+fn stripe_probe() -> u64 {
+    STRIPE_PROBE.with(Cell::get)
+}
+
+// Move the current thread on from the stripe picked by `probe`, which was
+// contended, and return its new probe.
+fn advance_stripe_probe(mut probe: u64) -> u64 {
+    // xorshift
+    probe ^= probe << 13;
+    probe ^= probe >> 7;
+    probe ^= probe << 17;
+    STRIPE_PROBE.with(|cell| cell.set(probe));
+    probe
+}
+
+/// A bucket that allocates its inner bucket the first time a token leaves on
+/// its wire.
+///
+/// Until then the bucket only holds its starting value and a null pointer.
+/// Every value taken costs an extra pointer load. Inner buckets that have been
+/// allocated are included in the outputs of
+/// [`BitonicCountingNetwork::memory_usage`].
+///
+/// The balancers of the network are not lazy, and with the buckets in this
+/// crate they take far more memory than the buckets do. A counter of width
+/// 1024 with [`PaddedBucket`]s uses 3.8 MB, of which 64 KiB are buckets, and
+/// lazy buckets bring that down by less than 2%. This only pays off for inner
+/// buckets that are much larger, or expensive to create.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, LazyBucket, PaddedBucket};
+/// use std::sync::atomic::AtomicUsize;
+///
+/// let counter = BitonicCountingNetwork::builder()
+///     .width(1024)
+///     .build_with_buckets::<LazyBucket<PaddedBucket<AtomicUsize>>>();
+///
+/// assert_eq!(counter.next(), 0);
+/// assert_eq!(counter.next(), 1);
+/// ```
+pub struct LazyBucket<K> {
+    start: usize,
+    bucket: LazyBox<K>,
+}
+
+impl<K: CounterBucket> LazyBucket<K> {
+    /// Returns true if a token has left on this bucket's wire, and the inner
+    /// bucket has been allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{CounterBucket, LazyBucket};
+    /// use std::sync::atomic::AtomicUsize;
+    ///
+    /// let bucket = LazyBucket::<AtomicUsize>::with_value(3);
+    /// assert!(!bucket.is_initialized());
+    ///
+    /// assert_eq!(bucket.get_and_inc(2), 3);
+    /// assert!(bucket.is_initialized());
+    /// ```
+    pub fn is_initialized(&self) -> bool {
+        self.bucket.get().is_some()
+    }
+}
+
+impl<K: CounterBucket> CounterBucket for LazyBucket<K> {
+    fn with_value(value: usize) -> Self {
+        LazyBucket {
+            start: value,
+            bucket: LazyBox::new(),
+        }
+    }
+
+    fn get_and_inc(&self, increment: usize) -> usize {
+        self.bucket
+            .get_or_init(|| K::with_value(self.start))
+            .get_and_inc(increment)
+    }
+
+    // Prefaulting should not allocate every bucket of the counter, so only
+    // buckets that are already in use are brought into the cache.
+    fn prefault(&self) {
+        if let Some(bucket) = self.bucket.get() {
+            bucket.prefault();
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        match self.bucket.get() {
+            Some(bucket) => mem::size_of::<K>() + bucket.heap_size(),
+            None => 0,
+        }
+    }
+}
+
+impl<K> fmt::Debug for LazyBucket<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyBucket")
+            .field("start", &self.start)
+            .field("initialized", &self.bucket.get().is_some())
+            .finish()
+    }
+}
+
+enum Buckets<K> {
+    Unpadded(BitonicNetwork<K>),
+    Padded(BitonicNetwork<PaddedBucket<K>>),
 }
 
-impl CountingBucket {
-    fn new(starting_value: usize) -> Self {
-        CountingBucket {
-            value: starting_value.into(),
+/// A reference to the network inside a [`BitonicCountingNetwork`], returned by
+/// [`as_network`](BitonicCountingNetwork::as_network).
+///
+/// The outputs of the network are the buckets of the counter, which are
+/// wrapped in [`PaddedBucket`] when the counter was built with
+/// [`padded_buckets`](CounterOptions::padded_buckets).
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, CounterNetwork};
+///
+/// let counter = BitonicCountingNetwork::builder()
+///     .width(4)
+///     .padded_buckets(true)
+///     .build();
+///
+/// match counter.as_network() {
+///     CounterNetwork::Padded(network) => assert_eq!(network.width(), 4),
+///     CounterNetwork::Unpadded(_) => unreachable!(),
+/// }
+/// ```
+#[derive(Debug)]
+pub enum CounterNetwork<'a, K> {
+    /// The network of a counter with unpadded buckets.
+    Unpadded(&'a BitonicNetwork<K>),
+    /// The network of a counter with padded buckets.
+    Padded(&'a BitonicNetwork<PaddedBucket<K>>),
+}
+
+impl<'a, K> CounterNetwork<'a, K> {
+    /// Returns the layout of balancers in the network, see
+    /// [`Network::topology`](crate::networks::Network::topology).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    ///
+    /// assert_eq!(counter.as_network().topology().depth(), 3);
+    /// ```
+    pub fn topology(&self) -> Topology {
+        match self {
+            CounterNetwork::Unpadded(network) => network.topology(),
+            CounterNetwork::Padded(network) => network.topology(),
         }
     }
 
-    fn get(&self) -> usize {
-        self.value.load(Ordering::Relaxed)
+    /// Check the structural integrity of the network, see
+    /// [`Network::verify_integrity`](crate::networks::Network::verify_integrity).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    ///
+    /// assert_eq!(counter.as_network().verify_integrity(), Ok(()));
+    /// ```
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        match self {
+            CounterNetwork::Unpadded(network) => network.verify_integrity(),
+            CounterNetwork::Padded(network) => network.verify_integrity(),
+        }
     }
+}
 
-    fn inc(&self, increment: usize) {
-        self.value.fetch_add(increment, Ordering::SeqCst);
+impl<'a, K> Clone for CounterNetwork<'a, K> {
+    fn clone(&self) -> Self {
+        *self
     }
 }
 
+impl<'a, K> Copy for CounterNetwork<'a, K> {}
+
+/// The strongest progress guarantee that a counter's operations give.
+///
+/// Variants are ordered from weakest to strongest, so generic code can check
+/// for a minimum guarantee with a comparison.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::ProgressGuarantee;
+///
+/// assert!(ProgressGuarantee::WaitFree > ProgressGuarantee::LockFree);
+/// assert!(ProgressGuarantee::LockFree > ProgressGuarantee::Blocking);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProgressGuarantee {
+    /// A thread that is suspended while holding a lock can stop every other
+    /// thread from making progress.
+    Blocking,
+    /// Some thread always makes progress, but an individual thread can be
+    /// delayed forever by the others.
+    LockFree,
+    /// Every operation finishes in a bounded number of steps, no matter what
+    /// the other threads are doing.
+    WaitFree,
+}
+
 /// Output sequential values without duplicates or skips.
+///
+/// Values are `usize`, and wrap around to zero after `usize::MAX`, which is
+/// only 2<sup>32</sup> - 1 on 32-bit targets.
+///
+/// The trait is implemented for references to counters, and for counters in a
+/// `Box` or an `Arc`, so generic code can take whichever one the caller holds.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+/// use std::sync::Arc;
+///
+/// fn next_id<C: Counter>(ids: C) -> usize {
+///     ids.next()
+/// }
+///
+/// let ids = Arc::new(BitonicCountingNetwork::new(4));
+/// let boxed: Box<dyn Counter> = Box::new(BitonicCountingNetwork::new(4));
+///
+/// assert_eq!(next_id(Arc::clone(&ids)), 0);
+/// assert_eq!(next_id(&*ids), 1);
+/// assert_eq!(next_id(boxed), 0);
+/// ```
 pub trait Counter {
     /// Retrieve value from counter and update internal state.
     fn next(&self) -> usize;
-}
 
-/// Concrete counter based on [BitonicNetwork](super::networks::BitonicNetwork).
-pub struct BitonicCountingNetwork(BitonicNetwork<CountingBucket>);
+    /// Returns the number of independent locations that operations on the
+    /// counter are spread over.
+    ///
+    /// The default implementation returns 1, for counters that keep all of
+    /// their state in one place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// fn describe<C: Counter>(counter: &C) -> String {
+    ///     format!("counter over {} locations", counter.width())
+    /// }
+    ///
+    /// assert_eq!(
+    ///     describe(&BitonicCountingNetwork::new(8)),
+    ///     "counter over 8 locations"
+    /// );
+    /// ```
+    fn width(&self) -> usize {
+        1
+    }
 
-impl BitonicCountingNetwork {
-    /// Create a new counter with specified width.
+    /// Returns the progress guarantee of [`next`](Counter::next).
     ///
-    /// Choice of width will not effect output of the counter, but higher values
-    /// will ensure less contention among threads while accessing the
-    /// counter at the cost of more memory.
+    /// The default implementation returns
+    /// [`Blocking`](ProgressGuarantee::Blocking), which is correct for any
+    /// counter.
     ///
     /// # Examples
     ///
     /// ```
-    /// use counting_networks::counters::{Counter, BitonicCountingNetwork};
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, ProgressGuarantee};
     ///
-    /// let counter = BitonicCountingNetwork::new(8);
+    /// let counter = BitonicCountingNetwork::new(4);
     ///
-    /// assert_eq!(counter.next(), 0);
+    /// assert_eq!(counter.progress_guarantee(), ProgressGuarantee::WaitFree);
     /// ```
-    pub fn new(width: usize) -> Self {
-        let outputs = (0..width).map(CountingBucket::new).collect::<Vec<_>>();
-        BitonicCountingNetwork(BitonicNetwork::new(outputs))
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        ProgressGuarantee::Blocking
     }
 
-    /// Returns the output width of the internal bitonic network.
+    /// Returns true if [`next`](Counter::next) is at least lock-free.
     ///
     /// # Examples
     ///
     /// ```
-    /// use counting_networks::counters::BitonicCountingNetwork;
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    ///
+    /// assert!(counter.is_lock_free());
+    /// ```
+    fn is_lock_free(&self) -> bool {
+        self.progress_guarantee() >= ProgressGuarantee::LockFree
+    }
+
+    /// Returns the input wire that values taken with [`next`](Counter::next)
+    /// from the current thread enter the counter on, without taking a value.
+    ///
+    /// The default implementation returns `None`, for counters that don't
+    /// pick an entry wire for each thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
     ///
     /// let counter = BitonicCountingNetwork::new(8);
     ///
-    /// assert_eq!(counter.width(), 8);
+    /// assert_eq!(
+    ///     Counter::entry_wire(&counter),
+    ///     Some(counter.wire_for_current_thread())
+    /// );
     /// ```
-    pub fn width(&self) -> usize {
-        self.0.width()
+    fn entry_wire(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns a counter that passes every value through `f`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    /// let ticks = (&counter).map(|value| value * 1000);
+    ///
+    /// assert_eq!(ticks.next(), 0);
+    /// assert_eq!(ticks.next(), 1000);
+    /// assert_eq!(counter.next(), 2);
+    /// ```
+    fn map<F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(usize) -> usize,
+    {
+        Map { counter: self, f }
+    }
+
+    /// Returns a counter that multiplies every value by `step`, so that it
+    /// outputs `0, step, 2 * step, ...`, wrapping around on overflow.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let even = BitonicCountingNetwork::new(4).step_by(2);
+    ///
+    /// assert_eq!(even.next(), 0);
+    /// assert_eq!(even.next(), 2);
+    /// assert_eq!(even.next(), 4);
+    /// ```
+    fn step_by(self, step: usize) -> StepBy<Self>
+    where
+        Self: Sized,
+    {
+        StepBy {
+            counter: self,
+            step,
+        }
+    }
+
+    /// Returns a counter that adds `offset` to every value, wrapping around on
+    /// overflow.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4).offset(100);
+    ///
+    /// assert_eq!(counter.next(), 100);
+    /// assert_eq!(counter.next(), 101);
+    /// ```
+    fn offset(self, offset: usize) -> Offset<Self>
+    where
+        Self: Sized,
+    {
+        Offset {
+            counter: self,
+            offset,
+        }
+    }
+
+    /// Returns a counter that only outputs the values for which `predicate`
+    /// returns true.
+    ///
+    /// Values that are rejected are discarded, and never returned by any other
+    /// caller. If the predicate rejects every value from some point on, calls
+    /// to [`next`](Counter::next) never return.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4).filtered(|value| value % 3 == 0);
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// assert_eq!(counter.next(), 3);
+    /// assert_eq!(counter.next(), 6);
+    /// ```
+    fn filtered<P>(self, predicate: P) -> Filtered<Self, P>
+    where
+        Self: Sized,
+        P: Fn(usize) -> bool,
+    {
+        Filtered {
+            counter: self,
+            predicate,
+        }
     }
 }
 
-impl Counter for BitonicCountingNetwork {
+impl<C: Counter + ?Sized> Counter for &C {
     fn next(&self) -> usize {
-        let bucket = self.0.traverse();
+        (**self).next()
+    }
 
-        let output = bucket.get();
-        bucket.inc(self.width());
+    fn width(&self) -> usize {
+        (**self).width()
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        (**self).progress_guarantee()
+    }
 
-        output
+    fn entry_wire(&self) -> Option<usize> {
+        (**self).entry_wire()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::{sync::Arc, thread};
+impl<C: Counter + ?Sized> Counter for Box<C> {
+    fn next(&self) -> usize {
+        (**self).next()
+    }
 
-    #[test]
-    fn create_counter() {
-        const WIDTH: usize = 16;
-        let counter = BitonicCountingNetwork::new(WIDTH);
+    fn width(&self) -> usize {
+        (**self).width()
+    }
 
-        assert_eq!(counter.width(), WIDTH);
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        (**self).progress_guarantee()
     }
 
-    fn sync_only<T: Sync>(_: T) {}
-    fn send_only<T: Send>(_: T) {}
+    fn entry_wire(&self) -> Option<usize> {
+        (**self).entry_wire()
+    }
+}
 
-    #[test]
-    fn is_send() {
-        send_only(BitonicCountingNetwork::new(4));
+impl<C: Counter + ?Sized> Counter for Arc<C> {
+    fn next(&self) -> usize {
+        (**self).next()
     }
 
-    #[test]
-    fn is_sync() {
-        sync_only(BitonicCountingNetwork::new(4));
+    fn width(&self) -> usize {
+        (**self).width()
     }
 
-    #[test]
-    #[cfg_attr(miri, ignore)]
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        (**self).progress_guarantee()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        (**self).entry_wire()
+    }
+}
+
+/// A counter that passes the values of another counter through a function.
+///
+/// Created by [`Counter::map`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, Map};
+///
+/// let squares: Map<_, _> = BitonicCountingNetwork::new(2).map(|value| value * value);
+///
+/// assert_eq!(squares.next(), 0);
+/// assert_eq!(squares.next(), 1);
+/// assert_eq!(squares.next(), 4);
+/// ```
+#[derive(Clone)]
+pub struct Map<C, F> {
+    counter: C,
+    f: F,
+}
+
+impl<C: Counter, F: Fn(usize) -> usize> Counter for Map<C, F> {
+    fn next(&self) -> usize {
+        (self.f)(self.counter.next())
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        self.counter.progress_guarantee()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.counter.entry_wire()
+    }
+}
+
+impl<C: fmt::Debug, F> fmt::Debug for Map<C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Map")
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
+
+/// A counter that multiplies the values of another counter by a step.
+///
+/// Created by [`Counter::step_by`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, StepBy};
+///
+/// let counter: StepBy<_> = BitonicCountingNetwork::new(2).step_by(10);
+///
+/// assert_eq!(counter.next(), 0);
+/// assert_eq!(counter.next(), 10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StepBy<C> {
+    counter: C,
+    step: usize,
+}
+
+impl<C: Counter> Counter for StepBy<C> {
+    fn next(&self) -> usize {
+        self.counter.next().wrapping_mul(self.step)
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        self.counter.progress_guarantee()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.counter.entry_wire()
+    }
+}
+
+/// A counter that adds an offset to the values of another counter.
+///
+/// Created by [`Counter::offset`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, Offset};
+///
+/// let counter: Offset<_> = BitonicCountingNetwork::new(2).offset(1);
+///
+/// assert_eq!(counter.next(), 1);
+/// assert_eq!(counter.next(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Offset<C> {
+    counter: C,
+    offset: usize,
+}
+
+impl<C: Counter> Counter for Offset<C> {
+    fn next(&self) -> usize {
+        self.counter.next().wrapping_add(self.offset)
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        self.counter.progress_guarantee()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.counter.entry_wire()
+    }
+}
+
+/// A counter that only outputs the values of another counter that match a
+/// predicate.
+///
+/// Created by [`Counter::filtered`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, Filtered};
+///
+/// let odd: Filtered<_, _> = BitonicCountingNetwork::new(2).filtered(|value| value % 2 == 1);
+///
+/// assert_eq!(odd.next(), 1);
+/// assert_eq!(odd.next(), 3);
+/// ```
+#[derive(Clone)]
+pub struct Filtered<C, P> {
+    counter: C,
+    predicate: P,
+}
+
+impl<C: Counter, P: Fn(usize) -> bool> Counter for Filtered<C, P> {
+    fn next(&self) -> usize {
+        loop {
+            let value = self.counter.next();
+            if (self.predicate)(value) {
+                return value;
+            }
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.counter.entry_wire()
+    }
+
+    // There is no bound on the number of values the predicate can reject, so
+    // this keeps the default guarantee.
+}
+
+impl<C: fmt::Debug, P> fmt::Debug for Filtered<C, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Filtered")
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
+
+/// A counter that records how long each call to [`next`](Counter::next) on
+/// another counter takes, enabled with the `hdrhistogram` feature.
+///
+/// Latencies are recorded in nanoseconds into [`hdrhistogram::Histogram`]s
+/// with three significant digits. There are several histograms, each behind
+/// its own lock, and each thread records into the one picked by a hash of the
+/// thread, moving on to another when it finds its histogram locked. Reading
+/// the latencies merges the histograms, so reads are slower than recording.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+///
+/// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+///
+/// assert_eq!(counter.next(), 0);
+/// assert_eq!(counter.next(), 1);
+/// assert_eq!(counter.len(), 2);
+/// assert!(counter.value_at_quantile(0.5) <= counter.value_at_quantile(0.99));
+/// ```
+#[cfg(feature = "hdrhistogram")]
+pub struct InstrumentedCounter<C> {
+    counter: C,
+    latencies: Box<[PaddedBucket<Mutex<Histogram<u64>>>]>,
+}
+
+// Number of histograms the latencies of an `InstrumentedCounter` are spread
+// over, a power of two.
+#[cfg(feature = "hdrhistogram")]
+const LATENCY_STRIPES: usize = 8;
+
+#[cfg(feature = "hdrhistogram")]
+fn new_latency_histogram() -> Histogram<u64> {
+    Histogram::new(3).expect("three significant digits are always supported")
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl<C> InstrumentedCounter<C> {
+    /// Wrap a counter, starting with no recorded latencies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    ///
+    /// assert_eq!(counter.len(), 0);
+    /// ```
+    pub fn new(counter: C) -> Self {
+        InstrumentedCounter {
+            counter,
+            latencies: (0..LATENCY_STRIPES)
+                .map(|_| PaddedBucket(Mutex::new(new_latency_histogram())))
+                .collect(),
+        }
+    }
+
+    /// Returns a reference to the wrapped counter.
+    ///
+    /// Values taken directly from the wrapped counter are not recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    ///
+    /// assert_eq!(counter.get_ref().width(), 4);
+    /// ```
+    pub fn get_ref(&self) -> &C {
+        &self.counter
+    }
+
+    /// Unwrap the counter, discarding the recorded latencies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    ///
+    /// assert_eq!(counter.into_inner().next(), 1);
+    /// ```
+    pub fn into_inner(self) -> C {
+        self.counter
+    }
+
+    /// Returns the number of calls that have been recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    ///
+    /// assert_eq!(counter.len(), 1);
+    /// ```
+    pub fn len(&self) -> u64 {
+        self.latencies
+            .iter()
+            .map(|PaddedBucket(latencies)| latencies.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Returns true if no calls have been recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    ///
+    /// assert!(counter.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.latencies
+            .iter()
+            .all(|PaddedBucket(latencies)| latencies.lock().unwrap().is_empty())
+    }
+
+    /// Returns the latency that the given fraction of recorded calls took at
+    /// most, for example `0.99` for the 99th percentile.
+    ///
+    /// Returns zero if no calls have been recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+    /// use std::time::Duration;
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    /// assert_eq!(counter.value_at_quantile(0.99), Duration::from_nanos(0));
+    ///
+    /// counter.next();
+    /// assert!(counter.value_at_quantile(0.99) <= counter.max());
+    /// ```
+    pub fn value_at_quantile(&self, quantile: f64) -> Duration {
+        Duration::from_nanos(self.histogram().value_at_quantile(quantile))
+    }
+
+    /// Returns the longest recorded latency.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    ///
+    /// assert!(counter.max() >= counter.value_at_quantile(0.5));
+    /// ```
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(
+            self.latencies
+                .iter()
+                .map(|PaddedBucket(latencies)| latencies.lock().unwrap().max())
+                .max()
+                .unwrap_or(0),
+        )
+    }
+
+    /// Returns a copy of the recorded latencies in nanoseconds, for analysis
+    /// beyond single percentiles.
+    ///
+    /// Calls recorded while the histograms are being merged may or may not be
+    /// included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    /// counter.next();
+    ///
+    /// let histogram = counter.histogram();
+    /// assert_eq!(histogram.len(), 2);
+    /// ```
+    pub fn histogram(&self) -> Histogram<u64> {
+        let mut merged = new_latency_histogram();
+        for PaddedBucket(latencies) in self.latencies.iter() {
+            merged
+                .add(&*latencies.lock().unwrap())
+                .expect("histograms with the same precision auto resize to merge");
+        }
+        merged
+    }
+
+    /// Discard all recorded latencies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, InstrumentedCounter};
+    ///
+    /// let counter = InstrumentedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    ///
+    /// counter.reset();
+    ///
+    /// assert!(counter.is_empty());
+    /// ```
+    pub fn reset(&self) {
+        for PaddedBucket(latencies) in self.latencies.iter() {
+            latencies.lock().unwrap().reset();
+        }
+    }
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl<C> InstrumentedCounter<C> {
+    fn record(&self, nanos: u64) {
+        let stripe = |probe: u64| &self.latencies[probe as usize % LATENCY_STRIPES].0;
+
+        let probe = stripe_probe();
+        let mut latencies = match stripe(probe).try_lock() {
+            Ok(latencies) => latencies,
+            // Another thread is recording into the same histogram, so move on
+            // to another one and wait for that instead.
+            Err(TryLockError::WouldBlock) => stripe(advance_stripe_probe(probe)).lock().unwrap(),
+            Err(TryLockError::Poisoned(error)) => panic!("{}", error),
+        };
+        latencies.saturating_record(nanos);
+    }
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl<C: Counter> Counter for InstrumentedCounter<C> {
+    fn next(&self) -> usize {
+        let started = Instant::now();
+        let value = self.counter.next();
+        let elapsed = started.elapsed();
+
+        // Latencies too long to fit in a u64 of nanoseconds are clamped
+        let nanos = elapsed.as_nanos().min(u128::from(u64::MAX)) as u64;
+        self.record(nanos);
+
+        value
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.counter.entry_wire()
+    }
+
+    // Recording takes a lock, so this keeps the default guarantee.
+}
+
+#[cfg(feature = "hdrhistogram")]
+impl<C: fmt::Debug> fmt::Debug for InstrumentedCounter<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstrumentedCounter")
+            .field("counter", &self.counter)
+            .field("recorded", &self.len())
+            .finish()
+    }
+}
+
+// Sample one in this many calls to a `DiagnosedCounter`.
+const DIAGNOSTIC_SAMPLE_INTERVAL: usize = 64;
+// Number of samples a `DiagnosedCounter` checks at a time, so an advisory is
+// only raised for a pattern that lasted at least this many samples.
+const DIAGNOSTIC_WINDOW: usize = 256;
+// Callers beyond this many per wire mostly queue up on the same balancers.
+const THREADS_PER_WIRE: usize = 4;
+
+/// A counter that watches how it is called and reports configurations that
+/// are likely to perform badly.
+///
+/// A misconfigured counter still hands out the right values, so the only
+/// symptom is poor throughput. About one call in every 64 is sampled. Once a
+/// few hundred samples have been taken, the counter checks them for the
+/// problems described by [`Advisory`], and records each kind of problem the
+/// first time it is seen. Recorded advisories can be read with
+/// [`advisories`](DiagnosedCounter::advisories), and with the `log` feature
+/// they are also logged as warnings.
+///
+/// The wire a sampled call enters on is taken from
+/// [`Counter::entry_wire`] of the wrapped counter, so counters that don't
+/// report one are never found to use a [single entry
+/// wire](Advisory::SingleEntryWire).
+///
+/// Each thread counts down its own calls to the next sample, with gaps of
+/// varying length so that counters called in turn are all sampled. Sampling
+/// never waits for other callers, so calls that are not sampled touch no
+/// shared memory.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{Advisory, BitonicCountingNetwork, Counter, DiagnosedCounter};
+///
+/// let counter = DiagnosedCounter::new(BitonicCountingNetwork::new(8));
+///
+/// // A single thread always enters the network on the same wire
+/// for _ in 0..100_000 {
+///     counter.next();
+/// }
+///
+/// assert!(matches!(
+///     counter.advisories()[..],
+///     [Advisory::SingleEntryWire { width: 8, .. }]
+/// ));
+/// ```
+pub struct DiagnosedCounter<C> {
+    counter: C,
+    window: Mutex<DiagnosticWindow>,
+}
+
+thread_local! {
+    // Calls left on this thread until the next sample, and the state of the
+    // xorshift generator that picks the gap after it. `const` initializers for
+    // thread locals are newer than the minimum supported Rust version.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static DIAGNOSTIC_COUNTDOWN: Cell<(usize, u64)> = Cell::new((
+        DIAGNOSTIC_SAMPLE_INTERVAL,
+        hash_single(thread::current().id()) | 1,
+    ));
+}
+
+// Returns true if the current call on this thread should be sampled.
+fn take_diagnostic_sample() -> bool {
+    DIAGNOSTIC_COUNTDOWN.with(|countdown| {
+        let (left, mut rng) = countdown.get();
+        if left > 1 {
+            countdown.set((left - 1, rng));
+            return false;
+        }
+
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        // Gaps between 1 and twice the interval, so that on average one call
+        // in every interval is sampled
+        let gap = 1 + rng as usize % (2 * DIAGNOSTIC_SAMPLE_INTERVAL - 1);
+        countdown.set((gap, rng));
+        true
+    })
+}
+
+#[derive(Default)]
+struct DiagnosticWindow {
+    samples: usize,
+    threads: HashSet<ThreadId>,
+    entry_wires: HashSet<usize>,
+    advisories: Vec<Advisory>,
+}
+
+impl<C> DiagnosedCounter<C> {
+    /// Wrap a counter, starting with no samples.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, DiagnosedCounter};
+    ///
+    /// let counter = DiagnosedCounter::new(BitonicCountingNetwork::new(4));
+    ///
+    /// assert!(counter.advisories().is_empty());
+    /// ```
+    pub fn new(counter: C) -> Self {
+        DiagnosedCounter {
+            counter,
+            window: Mutex::new(DiagnosticWindow::default()),
+        }
+    }
+
+    /// Returns a reference to the wrapped counter.
+    ///
+    /// Values taken directly from the wrapped counter are not sampled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, DiagnosedCounter};
+    ///
+    /// let counter = DiagnosedCounter::new(BitonicCountingNetwork::new(4));
+    ///
+    /// assert_eq!(counter.get_ref().width(), 4);
+    /// ```
+    pub fn get_ref(&self) -> &C {
+        &self.counter
+    }
+
+    /// Unwrap the counter, discarding the samples and advisories.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, DiagnosedCounter};
+    ///
+    /// let counter = DiagnosedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    ///
+    /// assert_eq!(counter.into_inner().next(), 1);
+    /// ```
+    pub fn into_inner(self) -> C {
+        self.counter
+    }
+
+    /// Returns the advisories raised so far, at most one of each kind, in the
+    /// order they were raised.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, DiagnosedCounter};
+    ///
+    /// let counter = DiagnosedCounter::new(BitonicCountingNetwork::new(4));
+    /// counter.next();
+    ///
+    /// // Far too few calls to raise anything
+    /// assert!(counter.advisories().is_empty());
+    /// ```
+    pub fn advisories(&self) -> Vec<Advisory> {
+        self.window.lock().unwrap().advisories.clone()
+    }
+}
+
+impl<C: Counter> DiagnosedCounter<C> {
+    fn sample(&self) {
+        // Skip the sample rather than wait for another caller
+        let mut window = match self.window.try_lock() {
+            Ok(window) => window,
+            Err(_) => return,
+        };
+        let width = self.counter.width();
+
+        if let Some(wire) = self.counter.entry_wire() {
+            window.entry_wires.insert(wire);
+        }
+        window.threads.insert(thread::current().id());
+        window.samples += 1;
+
+        if window.samples < DIAGNOSTIC_WINDOW {
+            return;
+        }
+
+        let mut found = Vec::new();
+        if window.threads.len() > THREADS_PER_WIRE * width {
+            found.push(Advisory::TooManyThreads {
+                threads: window.threads.len(),
+                width,
+            });
+        }
+        if width > 1 && window.entry_wires.len() == 1 {
+            found.push(Advisory::SingleEntryWire {
+                wire: *window.entry_wires.iter().next().unwrap(),
+                width,
+            });
+        }
+        for advisory in found {
+            let seen = window
+                .advisories
+                .iter()
+                .any(|previous| mem::discriminant(previous) == mem::discriminant(&advisory));
+            if !seen {
+                #[cfg(feature = "log")]
+                log::warn!("{}", advisory);
+                window.advisories.push(advisory);
+            }
+        }
+
+        window.samples = 0;
+        window.threads.clear();
+        window.entry_wires.clear();
+    }
+}
+
+impl<C: Counter> Counter for DiagnosedCounter<C> {
+    fn next(&self) -> usize {
+        if take_diagnostic_sample() {
+            self.sample();
+        }
+
+        self.counter.next()
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    // Samples allocate while holding a lock, though no caller waits for it.
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        self.counter
+            .progress_guarantee()
+            .min(ProgressGuarantee::LockFree)
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for DiagnosedCounter<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DiagnosedCounter")
+            .field("counter", &self.counter)
+            .field("advisories", &self.advisories())
+            .finish()
+    }
+}
+
+/// A likely misconfiguration found by a [`DiagnosedCounter`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::Advisory;
+///
+/// let advisory = Advisory::TooManyThreads {
+///     threads: 64,
+///     width: 4,
+/// };
+///
+/// assert_eq!(
+///     advisory.to_string(),
+///     "64 threads are sharing a counter of width 4, a wider counter would \
+///      spread them out"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Advisory {
+    /// Many more threads are calling the counter than it has wires, so most
+    /// of them contend on the same balancers.
+    TooManyThreads {
+        /// The number of threads seen.
+        threads: usize,
+        /// The width of the counter.
+        width: usize,
+    },
+    /// Every call entered the counter on the same wire, so the other wires
+    /// only add latency and memory. This usually means a single thread is
+    /// using a wide counter.
+    SingleEntryWire {
+        /// The wire every call entered on.
+        wire: usize,
+        /// The width of the counter.
+        width: usize,
+    },
+}
+
+impl fmt::Display for Advisory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Advisory::TooManyThreads { threads, width } => write!(
+                f,
+                "{} threads are sharing a counter of width {}, a wider counter would spread them \
+                 out",
+                threads, width
+            ),
+            Advisory::SingleEntryWire { wire, width } => write!(
+                f,
+                "every call entered a counter of width {} on wire {}, a narrower counter would be \
+                 faster",
+                width, wire
+            ),
+        }
+    }
+}
+
+/// Options for building a [`BitonicCountingNetwork`].
+///
+/// The counter built from these options outputs `start`, `start + stride`,
+/// `start + 2 * stride`, ..., wrapping around on overflow.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+///
+/// let counter = BitonicCountingNetwork::builder()
+///     .width(16)
+///     .start(100)
+///     .stride(2)
+///     .padded_buckets(true)
+///     .build();
+///
+/// assert_eq!(counter.next(), 100);
+/// assert_eq!(counter.next(), 102);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterOptions {
+    width: usize,
+    start: usize,
+    stride: usize,
+    padded_buckets: bool,
+    name: Option<String>,
+    high_priority_wires: usize,
+}
+
+impl CounterOptions {
+    /// Create options with the defaults: a width of 8, starting at 0 with a
+    /// stride of 1, and unpadded buckets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions};
+    ///
+    /// let counter = CounterOptions::new().build();
+    ///
+    /// assert_eq!(counter.width(), 8);
+    /// assert_eq!(counter.next(), 0);
+    /// assert_eq!(counter.next(), 1);
+    /// ```
+    pub fn new() -> Self {
+        CounterOptions {
+            width: 8,
+            start: 0,
+            stride: 1,
+            padded_buckets: false,
+            name: None,
+            high_priority_wires: 0,
+        }
+    }
+
+    /// Set the width of the counting network.
+    ///
+    /// Choice of width will not effect output of the counter, but higher values
+    /// will ensure less contention among threads while accessing the counter
+    /// at the cost of more memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterOptions;
+    ///
+    /// let counter = CounterOptions::new().width(32).build();
+    ///
+    /// assert_eq!(counter.width(), 32);
+    /// ```
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Set the first value that the counter outputs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions};
+    ///
+    /// let counter = CounterOptions::new().start(10).build();
+    ///
+    /// assert_eq!(counter.next(), 10);
+    /// assert_eq!(counter.next(), 11);
+    /// ```
+    pub fn start(mut self, start: usize) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Set the difference between consecutive values that the counter outputs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions};
+    ///
+    /// let counter = CounterOptions::new().stride(5).build();
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// assert_eq!(counter.next(), 5);
+    /// ```
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    /// Set whether each output bucket is padded to its own cache line.
+    ///
+    /// Padding prevents false sharing between threads that leave the network
+    /// on neighbouring wires, at the cost of more memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions};
+    ///
+    /// let counter = CounterOptions::new().padded_buckets(true).build();
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn padded_buckets(mut self, padded_buckets: bool) -> Self {
+        self.padded_buckets = padded_buckets;
+        self
+    }
+
+    /// Set a name for the counter, which is shown in its `Debug` output.
+    ///
+    /// The name is also given to the counter's network, see
+    /// [`Network::with_name`](crate::networks::Network::with_name).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterOptions;
+    ///
+    /// let counter = CounterOptions::new().name("rpc-ids").build();
+    ///
+    /// assert_eq!(counter.name(), Some("rpc-ids"));
+    /// ```
+    pub fn name<N: Into<String>>(mut self, name: N) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Reserve the first `wires` input wires of the network for the
+    /// [`High`](Priority::High) priority lane, see
+    /// [`BitonicCountingNetwork::lane`].
+    ///
+    /// Tokens in the high priority lane only enter on the reserved wires, and
+    /// every other token enters on the remaining wires. The wires are split so
+    /// that the two lanes don't share the balancers that tokens enter the
+    /// network through. Past those balancers the lanes merge, since every
+    /// token has to be able to reach every output.
+    ///
+    /// The default of zero doesn't reserve any wires, and both lanes enter on
+    /// all of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions, Priority};
+    ///
+    /// let counter = CounterOptions::new().width(8).high_priority_wires(2).build();
+    ///
+    /// assert_eq!(counter.high_priority_wires(), 2);
+    /// assert_eq!(counter.lane(Priority::High).next(), 0);
+    /// assert_eq!(counter.lane(Priority::Normal).next(), 1);
+    /// ```
+    pub fn high_priority_wires(mut self, wires: usize) -> Self {
+        self.high_priority_wires = wires;
+        self
+    }
+
+    /// Build a counter with these options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two, if the stride is zero, or if
+    /// the [high priority wires](CounterOptions::high_priority_wires) can't be
+    /// split from the rest of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions};
+    ///
+    /// let options = CounterOptions::new().start(3);
+    /// let first = options.clone().build();
+    /// let second = options.build();
+    ///
+    /// assert_eq!(first.next(), 3);
+    /// assert_eq!(second.next(), 3);
+    /// ```
+    pub fn build(self) -> BitonicCountingNetwork {
+        self.build_with_buckets()
+    }
+
+    /// Build a counter with these options, that stores the value on each
+    /// output wire in a bucket of type `K`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two, if the stride is zero, or if
+    /// the [high priority wires](CounterOptions::high_priority_wires) can't be
+    /// split from the rest of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, CounterOptions};
+    /// use std::sync::atomic::AtomicUsize;
+    ///
+    /// let counter: BitonicCountingNetwork<AtomicUsize> =
+    ///     CounterOptions::new().width(4).build_with_buckets();
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn build_with_buckets<K: CounterBucket>(self) -> BitonicCountingNetwork<K> {
+        self.build_in(false)
+            .unwrap_or_else(|_| unreachable!("only fallible builds return errors"))
+    }
+
+    /// Build a counter with these options, like
+    /// [`build`](CounterOptions::build), returning an error instead of
+    /// aborting if the network can't be allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memory for the buckets or for the segments of
+    /// the network can't be allocated, see
+    /// [`Network::try_new`](crate::networks::Network::try_new).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two, if the stride is zero, or if
+    /// the [high priority wires](CounterOptions::high_priority_wires) can't be
+    /// split from the rest of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions};
+    ///
+    /// let counter = CounterOptions::new().width(64).try_build().unwrap();
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn try_build(self) -> Result<BitonicCountingNetwork, AllocError> {
+        self.build_in(true)
+    }
+
+    // Build the buckets and the network around them, allocating both without
+    // aborting if `fallible` is set.
+    fn build_in<K: CounterBucket>(
+        self,
+        fallible: bool,
+    ) -> Result<BitonicCountingNetwork<K>, AllocError> {
+        fn collect_buckets<L, I: Iterator<Item = L>>(
+            buckets: I,
+            width: usize,
+            fallible: bool,
+        ) -> Result<Vec<L>, AllocError> {
+            let mut collected = if fallible {
+                try_vec_with_capacity(width)?
+            } else {
+                Vec::with_capacity(width)
+            };
+            collected.extend(buckets);
+
+            Ok(collected)
+        }
+
+        fn new_network<L>(
+            outputs: Vec<L>,
+            fallible: bool,
+            name: Option<String>,
+        ) -> Result<BitonicNetwork<L>, AllocError> {
+            let network = if fallible {
+                BitonicNetwork::try_new(outputs)?
+            } else {
+                BitonicNetwork::new(outputs)
+            };
+
+            Ok(match name {
+                Some(name) => network.with_name(name),
+                None => network,
+            })
+        }
+
+        assert!(self.stride > 0);
+        assert!(
+            self.high_priority_wires == 0
+                || (self.high_priority_wires < self.width
+                    && !shares_entry_balancer(self.width, self.high_priority_wires)),
+            "can't reserve {} high priority wires in a network of width {}",
+            self.high_priority_wires,
+            self.width
+        );
+
+        let starts = (0..self.width)
+            .map(|wire| K::with_value(self.start.wrapping_add(wire.wrapping_mul(self.stride))));
+        let buckets = if self.padded_buckets {
+            Buckets::Padded(new_network(
+                collect_buckets(starts.map(PaddedBucket), self.width, fallible)?,
+                fallible,
+                self.name,
+            )?)
+        } else {
+            Buckets::Unpadded(new_network(
+                collect_buckets(starts, self.width, fallible)?,
+                fallible,
+                self.name,
+            )?)
+        };
+
+        let increment = self.width.wrapping_mul(self.stride);
+
+        Ok(BitonicCountingNetwork {
+            buckets,
+            increment,
+            high_priority_wires: self.high_priority_wires,
+            #[cfg(feature = "debug-invariants")]
+            invariants: Invariants::new(self.width, self.start, self.stride, increment),
+        })
+    }
+}
+
+impl Default for CounterOptions {
+    fn default() -> Self {
+        CounterOptions::new()
+    }
+}
+
+// Returns true if a token entering on one of the wires before `split` can
+// share a balancer with a token entering on one of the wires after it, before
+// either has passed through another balancer.
+fn shares_entry_balancer(width: usize, split: usize) -> bool {
+    let mut entered = vec![false; width];
+
+    BitonicConfiguration::from_width(Width::of(width))
+        .into_iter()
+        .any(|(top_wire, bottom_wire)| {
+            let entry = !entered[top_wire] || !entered[bottom_wire];
+            entered[top_wire] = true;
+            entered[bottom_wire] = true;
+
+            entry && (top_wire < split) != (bottom_wire < split)
+        })
+}
+
+/// Concrete counter based on [BitonicNetwork](super::networks::BitonicNetwork).
+///
+/// The value on each output wire is stored in a bucket of type `K`, see
+/// [`CounterBucket`].
+pub struct BitonicCountingNetwork<K = AtomicUsize> {
+    buckets: Buckets<K>,
+    increment: usize,
+    // The first wires are reserved for the high priority lane
+    high_priority_wires: usize,
+    #[cfg(feature = "debug-invariants")]
+    invariants: Invariants,
+}
+
+impl BitonicCountingNetwork {
+    /// Create a new counter with specified width.
+    ///
+    /// Choice of width will not effect output of the counter, but higher values
+    /// will ensure less contention among threads while accessing the
+    /// counter at the cost of more memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two. Use
+    /// [`BitonicCountingNetwork::with_width`] with [`Width::for_threads`] to
+    /// round a width up instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, BitonicCountingNetwork};
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn new(width: usize) -> Self {
+        CounterOptions::new().width(width).build()
+    }
+
+    /// Create a new counter with specified width, like
+    /// [`BitonicCountingNetwork::new`], that can't panic because of the width.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::{BitonicCountingNetwork, Counter},
+    ///     networks::Width,
+    /// };
+    ///
+    /// let counter = BitonicCountingNetwork::with_width(Width::for_threads(6));
+    ///
+    /// assert_eq!(counter.width(), 8);
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn with_width(width: Width) -> Self {
+        BitonicCountingNetwork::new(width.get())
+    }
+
+    /// Create a new counter with specified width, like
+    /// [`BitonicCountingNetwork::new`], returning an error instead of aborting
+    /// if the network can't be allocated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memory for the segments of the network can't be
+    /// allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// match BitonicCountingNetwork::try_new(1 << 10) {
+    ///     Ok(counter) => assert_eq!(counter.next(), 0),
+    ///     Err(error) => eprintln!("falling back to a smaller counter: {}", error),
+    /// }
+    /// ```
+    pub fn try_new(width: usize) -> Result<Self, AllocError> {
+        CounterOptions::new().width(width).try_build()
+    }
+
+    /// Returns options for building a counter, starting from the defaults.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::builder().width(4).start(7).build();
+    ///
+    /// assert_eq!(counter.width(), 4);
+    /// assert_eq!(counter.next(), 7);
+    /// ```
+    pub fn builder() -> CounterOptions {
+        CounterOptions::new()
+    }
+
+    /// Create a new counter with the width and padding read from environment
+    /// variables, see [`CounterConfig::from_env`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration can't be read, if the backend is
+    /// not `bitonic` or `padded-bitonic`, or if the counter is too wide to
+    /// allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    /// use std::env;
+    ///
+    /// env::set_var("COUNTING_NETWORKS_WIDTH", "16");
+    /// let counter = BitonicCountingNetwork::from_env().unwrap();
+    ///
+    /// assert_eq!(counter.width(), 16);
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn from_env() -> Result<Self, ConfigError> {
+        BitonicCountingNetwork::from_config(&CounterConfig::from_env()?)
+    }
+
+    // Build a counter from a configuration that was read at runtime, where a
+    // width that is too large shouldn't abort the process.
+    fn from_config(config: &CounterConfig) -> Result<Self, ConfigError> {
+        let padded_buckets = match config.backend() {
+            BackendKind::Bitonic => false,
+            BackendKind::PaddedBitonic => true,
+            kind => return Err(ConfigError::UnsupportedBackend(kind)),
+        };
+
+        CounterOptions::new()
+            .width(config.width())
+            .padded_buckets(padded_buckets)
+            .try_build()
+            .map_err(ConfigError::Alloc)
+    }
+}
+
+impl<K: CounterBucket> BitonicCountingNetwork<K> {
+    /// Returns the output width of the internal bitonic network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    ///
+    /// assert_eq!(counter.width(), 8);
+    /// ```
+    pub fn width(&self) -> usize {
+        match &self.buckets {
+            Buckets::Unpadded(network) => network.width(),
+            Buckets::Padded(network) => network.width(),
+        }
+    }
+
+    /// Returns the name of the counter, if it was given one with
+    /// [`CounterOptions::name`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    ///
+    /// assert_eq!(counter.name(), None);
+    /// ```
+    pub fn name(&self) -> Option<&str> {
+        match &self.buckets {
+            Buckets::Unpadded(network) => network.name(),
+            Buckets::Padded(network) => network.name(),
+        }
+    }
+
+    /// Returns the number of input wires reserved for the high priority lane,
+    /// see [`CounterOptions::high_priority_wires`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    ///
+    /// assert_eq!(counter.high_priority_wires(), 0);
+    /// ```
+    pub fn high_priority_wires(&self) -> usize {
+        self.high_priority_wires
+    }
+
+    /// Returns a counter that takes values from this one, entering the network
+    /// only on the input wires of the given lane.
+    ///
+    /// Calling [`next`](Counter::next) on this counter directly is the same as
+    /// using the [`Normal`](Priority::Normal) lane. If no wires were reserved
+    /// with [`CounterOptions::high_priority_wires`], both lanes enter on every
+    /// wire.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, Priority};
+    /// use std::{sync::Arc, thread};
+    ///
+    /// let counter = Arc::new(
+    ///     BitonicCountingNetwork::builder()
+    ///         .width(8)
+    ///         .high_priority_wires(2)
+    ///         .build(),
+    /// );
+    ///
+    /// let bulk = {
+    ///     let counter = Arc::clone(&counter);
+    ///     thread::spawn(move || (0..100).map(|_| counter.next()).collect::<Vec<_>>())
+    /// };
+    /// let urgent: Vec<_> = (0..10).map(|_| counter.lane(Priority::High).next()).collect();
+    ///
+    /// let mut values = bulk.join().unwrap();
+    /// values.extend(urgent);
+    /// values.sort();
+    /// assert_eq!(values, (0..110).collect::<Vec<_>>());
+    /// ```
+    pub fn lane(&self, priority: Priority) -> Lane<'_, K> {
+        let entries = match (self.high_priority_wires, priority) {
+            (0, _) => 0..self.width(),
+            (reserved, Priority::High) => 0..reserved,
+            (reserved, Priority::Normal) => reserved..self.width(),
+        };
+
+        Lane {
+            counter: self,
+            priority,
+            entries,
+        }
+    }
+
+    /// Returns the input wire that values taken with [`next`](Counter::next)
+    /// from the current thread enter the network on, without taking a value.
+    ///
+    /// The wire is picked by hashing the id of the thread, out of the wires of
+    /// the [`Normal`](Priority::Normal) lane. Threads that share an entry wire
+    /// contend on the same balancers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::builder()
+    ///     .width(8)
+    ///     .high_priority_wires(4)
+    ///     .build();
+    ///
+    /// assert!((4..8).contains(&counter.wire_for_current_thread()));
+    /// ```
+    pub fn wire_for_current_thread(&self) -> usize {
+        self.entry_within(self.lane(Priority::Normal).wires())
+    }
+
+    /// Returns the network that the counter takes values from, whose outputs
+    /// are the buckets of the counter.
+    ///
+    /// This gives access to the introspection methods of
+    /// [`Network`](crate::networks::Network). Taking values by traversing the
+    /// network directly skips the increment, so it should only be used to
+    /// inspect the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, CounterNetwork};
+    /// use std::sync::atomic::Ordering;
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    /// counter.next();
+    ///
+    /// if let CounterNetwork::Unpadded(network) = counter.as_network() {
+    ///     let buckets: Vec<_> = network
+    ///         .outputs()
+    ///         .iter()
+    ///         .map(|bucket| bucket.load(Ordering::SeqCst))
+    ///         .collect();
+    ///
+    ///     assert_eq!(buckets, vec![4, 1, 2, 3]);
+    /// }
+    /// ```
+    pub fn as_network(&self) -> CounterNetwork<'_, K> {
+        match &self.buckets {
+            Buckets::Unpadded(network) => CounterNetwork::Unpadded(network),
+            Buckets::Padded(network) => CounterNetwork::Padded(network),
+        }
+    }
+
+    /// Bring the network and its buckets into the cache of the calling thread,
+    /// so that the first values taken after construction are not slowed down by
+    /// cache misses.
+    ///
+    /// See [`Network::prefault`](crate::networks::Network::prefault) and
+    /// [`CounterBucket::prefault`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(16);
+    /// counter.prefault();
+    ///
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn prefault(&self) {
+        match &self.buckets {
+            Buckets::Unpadded(network) => {
+                network.prefault();
+                network.outputs().iter().for_each(K::prefault);
+            }
+            Buckets::Padded(network) => {
+                network.prefault();
+                network.outputs().iter().for_each(PaddedBucket::prefault);
+            }
+        }
+    }
+
+    /// Returns the number of bytes of heap memory used by the counter.
+    ///
+    /// When the buckets are padded, the padding around each bucket is
+    /// included in [`padding`](MemoryUsage::padding). Memory that buckets
+    /// allocate themselves, see [`CounterBucket::heap_size`], is included in
+    /// [`outputs`](MemoryUsage::outputs).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::BitonicCountingNetwork;
+    ///
+    /// let unpadded = BitonicCountingNetwork::builder().width(8).build();
+    /// let padded = BitonicCountingNetwork::builder()
+    ///     .width(8)
+    ///     .padded_buckets(true)
+    ///     .build();
+    ///
+    /// assert!(unpadded.memory_usage().total() < padded.memory_usage().total());
+    /// assert!(unpadded.memory_usage().padding() < padded.memory_usage().padding());
+    /// ```
+    pub fn memory_usage(&self) -> MemoryUsage {
+        fn heap_size<K: CounterBucket>(buckets: &[K]) -> usize {
+            buckets.iter().map(K::heap_size).sum()
+        }
+
+        match &self.buckets {
+            Buckets::Unpadded(network) => network
+                .memory_usage()
+                .with_output_heap(heap_size(network.outputs())),
+            Buckets::Padded(network) => network
+                .memory_usage()
+                .with_output_padding(
+                    network.width() * (mem::size_of::<PaddedBucket<K>>() - mem::size_of::<K>()),
+                )
+                .with_output_heap(heap_size(network.outputs())),
+        }
+    }
+
+    /// Save the balancer states and the next value of every bucket, so they
+    /// can be restored with
+    /// [`import_state`](BitonicCountingNetwork::import_state), for example
+    /// in another process.
+    ///
+    /// The state is a version byte, the width and the amount each bucket
+    /// grows by as little endian `u64`s, the value of each bucket as a little
+    /// endian `u64`, and then the balancer states packed into bits. Balancers
+    /// and buckets are read one at a time, so the state is only consistent if
+    /// no values are being taken while it is saved.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    /// counter.next();
+    ///
+    /// // 17 bytes of header, 4 buckets, and 6 balancers
+    /// assert_eq!(counter.export_state().len(), 17 + 4 * 8 + 1);
+    /// ```
+    pub fn export_state(&self) -> Vec<u8> {
+        let (values, toggles) = match &self.buckets {
+            Buckets::Unpadded(network) => (
+                network
+                    .outputs()
+                    .iter()
+                    .map(|bucket| bucket.get_and_inc(0))
+                    .collect::<Vec<_>>(),
+                network.toggles(),
+            ),
+            Buckets::Padded(network) => (
+                network
+                    .outputs()
+                    .iter()
+                    .map(|bucket| bucket.get_and_inc(0))
+                    .collect(),
+                network.toggles(),
+            ),
+        };
+
+        let mut state = Vec::with_capacity(state_len(self.width(), toggles.len()));
+        state.push(STATE_VERSION);
+        state.extend_from_slice(&(self.width() as u64).to_le_bytes());
+        state.extend_from_slice(&(self.increment as u64).to_le_bytes());
+        for value in values {
+            state.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+        for chunk in toggles.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (bit, &up)| byte | ((up as u8) << bit));
+            state.push(byte);
+        }
+
+        state
+    }
+
+    /// Restore the balancer states and bucket values saved by
+    /// [`export_state`](BitonicCountingNetwork::export_state), so the counter
+    /// continues from where the saved counter was.
+    ///
+    /// The counter must have the same width and stride as the saved counter,
+    /// but can use different buckets. The name and priority lanes of the
+    /// counter are not changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, leaving the counter unchanged, if the state is not
+    /// one that was saved from a counter like this one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter};
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    /// for _ in 0..5 {
+    ///     counter.next();
+    /// }
+    /// let state = counter.export_state();
+    ///
+    /// let mut restored = BitonicCountingNetwork::builder()
+    ///     .width(8)
+    ///     .padded_buckets(true)
+    ///     .build();
+    /// restored.import_state(&state).unwrap();
+    ///
+    /// assert_eq!(restored.next(), 5);
+    /// assert_eq!(restored.next(), 6);
+    /// ```
+    pub fn import_state(&mut self, state: &[u8]) -> Result<(), ImportStateError> {
+        fn read_u64(bytes: &[u8]) -> u64 {
+            let mut le_bytes = [0; 8];
+            le_bytes.copy_from_slice(&bytes[..8]);
+            u64::from_le_bytes(le_bytes)
+        }
+
+        let width = self.width();
+        let num_toggles = match &self.buckets {
+            Buckets::Unpadded(network) => network.num_balancers(),
+            Buckets::Padded(network) => network.num_balancers(),
+        };
+        let expected_len = state_len(width, num_toggles);
+
+        match state.first() {
+            Some(&STATE_VERSION) => {}
+            Some(&version) => return Err(ImportStateError::UnknownVersion(version)),
+            None => {
+                return Err(ImportStateError::InvalidLength {
+                    expected: expected_len,
+                    actual: 0,
+                })
+            }
+        }
+        if state.len() >= 17 && read_u64(&state[1..]) != width as u64 {
+            return Err(ImportStateError::WidthMismatch {
+                expected: width,
+                actual: read_u64(&state[1..]),
+            });
+        }
+        if state.len() != expected_len {
+            return Err(ImportStateError::InvalidLength {
+                expected: expected_len,
+                actual: state.len(),
+            });
+        }
+        if read_u64(&state[9..]) != self.increment as u64 {
+            return Err(ImportStateError::IncrementMismatch {
+                expected: self.increment,
+                actual: read_u64(&state[9..]),
+            });
+        }
+
+        let values = state[17..(17 + 8 * width)]
+            .chunks(8)
+            .enumerate()
+            .map(|(wire, bytes)| {
+                let value = read_u64(bytes);
+                usize::try_from(value)
+                    .map_err(|_| ImportStateError::ValueOutOfRange { wire, value })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let toggles: Vec<bool> = (0..num_toggles)
+            .map(|idx| state[17 + 8 * width + idx / 8] & (1 << (idx % 8)) != 0)
+            .collect();
+
+        #[cfg(feature = "debug-invariants")]
+        self.invariants.restart(values.iter().copied());
+        match &mut self.buckets {
+            Buckets::Unpadded(network) => {
+                network.replace_outputs(values.into_iter().map(K::with_value).collect());
+                network.set_toggles(&toggles);
+            }
+            Buckets::Padded(network) => {
+                network.replace_outputs(values.into_iter().map(PaddedBucket::with_value).collect());
+                network.set_toggles(&toggles);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Start the buckets again from `start`, `stride` apart, as if the counter
+    // had been built with that start. The counter must be quiescent.
+    fn restart_at(&mut self, start: usize, stride: usize) {
+        let starts = (0..self.width()).map(|wire| start.wrapping_add(wire.wrapping_mul(stride)));
+
+        #[cfg(feature = "debug-invariants")]
+        self.invariants.restart(starts.clone());
+        match &mut self.buckets {
+            Buckets::Unpadded(network) => {
+                network.replace_outputs(starts.map(K::with_value).collect());
+            }
+            Buckets::Padded(network) => {
+                network.replace_outputs(starts.map(PaddedBucket::with_value).collect());
+            }
+        }
+    }
+}
+
+impl<K: CounterBucket> fmt::Debug for BitonicCountingNetwork<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BitonicCountingNetwork")
+            .field("name", &self.name())
+            .field("width", &self.width())
+            .field(
+                "padded_buckets",
+                &matches!(self.buckets, Buckets::Padded(_)),
+            )
+            .finish()
+    }
+}
+
+// Version of the format written by `BitonicCountingNetwork::export_state`.
+const STATE_VERSION: u8 = 1;
+
+// Length of a saved state: the version, width and increment, the buckets, and
+// the toggles packed into bits. `usize::div_ceil` is newer than the minimum
+// supported Rust version.
+#[allow(clippy::manual_div_ceil)]
+fn state_len(width: usize, num_toggles: usize) -> usize {
+    17 + 8 * width + (num_toggles + 7) / 8
+}
+
+/// An error from restoring the state of a counter with
+/// [`BitonicCountingNetwork::import_state`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, ImportStateError};
+///
+/// let state = BitonicCountingNetwork::new(4).export_state();
+/// let mut counter = BitonicCountingNetwork::new(8);
+///
+/// assert_eq!(
+///     counter.import_state(&state),
+///     Err(ImportStateError::WidthMismatch {
+///         expected: 8,
+///         actual: 4
+///     })
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportStateError {
+    /// The state starts with a version of the format that this version of
+    /// the crate does not know.
+    UnknownVersion(u8),
+    /// The state was saved from a counter of a different width.
+    WidthMismatch {
+        /// The width of the counter being restored.
+        expected: usize,
+        /// The width of the saved counter.
+        actual: u64,
+    },
+    /// The state is not the length of a state saved from a counter of this
+    /// width.
+    InvalidLength {
+        /// The length of a state saved from a counter of this width.
+        expected: usize,
+        /// The length of the state.
+        actual: usize,
+    },
+    /// The state was saved from a counter whose buckets grow by a different
+    /// amount, the width times the stride.
+    IncrementMismatch {
+        /// The increment of the counter being restored.
+        expected: usize,
+        /// The increment of the saved counter.
+        actual: u64,
+    },
+    /// A saved bucket value does not fit in a `usize` on this target.
+    ValueOutOfRange {
+        /// The wire of the bucket.
+        wire: usize,
+        /// The saved value.
+        value: u64,
+    },
+}
+
+impl fmt::Display for ImportStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportStateError::UnknownVersion(version) => {
+                write!(f, "unknown counter state version {}", version)
+            }
+            ImportStateError::WidthMismatch { expected, actual } => write!(
+                f,
+                "counter state has width {}, expected {}",
+                actual, expected
+            ),
+            ImportStateError::InvalidLength { expected, actual } => write!(
+                f,
+                "counter state is {} bytes long, expected {}",
+                actual, expected
+            ),
+            ImportStateError::IncrementMismatch { expected, actual } => write!(
+                f,
+                "counter state has increment {}, expected {}",
+                actual, expected
+            ),
+            ImportStateError::ValueOutOfRange { wire, value } => write!(
+                f,
+                "value {} of wire {} in counter state does not fit in a usize",
+                value, wire
+            ),
+        }
+    }
+}
+
+impl Error for ImportStateError {}
+
+impl<K: CounterBucket> BitonicCountingNetwork<K> {
+    // The wire out of `entries` that the current thread enters the network on.
+    fn entry_within(&self, entries: Range<usize>) -> usize {
+        match &self.buckets {
+            Buckets::Unpadded(network) => network.entry_within(entries),
+            Buckets::Padded(network) => network.entry_within(entries),
+        }
+    }
+
+    // Take a value, entering the network on one of the given input wires.
+    #[cfg(not(feature = "debug-invariants"))]
+    fn next_within(&self, entries: Range<usize>) -> usize {
+        let bucket = match &self.buckets {
+            Buckets::Unpadded(network) => &network.outputs()[network.traverse_wire_within(entries)],
+            Buckets::Padded(network) => &network.outputs()[network.traverse_wire_within(entries)].0,
+        };
+        #[cfg(feature = "testing")]
+        jitter::inject(InjectionPoint::BeforeIncrement);
+
+        bucket.get_and_inc(self.increment)
+    }
+
+    // The same as above, with the wire each token leaves on reported to the
+    // invariant checks.
+    #[cfg(feature = "debug-invariants")]
+    fn next_within(&self, entries: Range<usize>) -> usize {
+        let index = self.invariants.enter();
+        let (wire, bucket) = match &self.buckets {
+            Buckets::Unpadded(network) => {
+                let wire = network.traverse_wire_within(entries);
+                (wire, &network.outputs()[wire])
+            }
+            Buckets::Padded(network) => {
+                let wire = network.traverse_wire_within(entries);
+                (wire, &network.outputs()[wire].0)
+            }
+        };
+        let token = self.invariants.exit(index, wire);
+        #[cfg(feature = "testing")]
+        jitter::inject(InjectionPoint::BeforeIncrement);
+
+        let value = bucket.get_and_inc(self.increment);
+        self.invariants.finish(token, value);
+
+        value
+    }
+}
+
+impl<K: CounterBucket> Counter for BitonicCountingNetwork<K> {
+    fn next(&self) -> usize {
+        let entries = match self.high_priority_wires {
+            0 => 0..self.width(),
+            reserved => reserved..self.width(),
+        };
+
+        self.next_within(entries)
+    }
+
+    fn width(&self) -> usize {
+        BitonicCountingNetwork::width(self)
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        Some(self.wire_for_current_thread())
+    }
+
+    // Traversal toggles one balancer per layer and then increments one
+    // bucket, each with a single atomic operation.
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        ProgressGuarantee::WaitFree
+    }
+}
+
+/// A class of callers that take values from a counter, see
+/// [`BitonicCountingNetwork::lane`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::Priority;
+///
+/// assert!(Priority::High > Priority::Normal);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Bulk callers, that enter on the wires that were not reserved.
+    Normal,
+    /// Latency critical callers, that enter on the reserved wires.
+    High,
+}
+
+/// A counter that takes values from a [`BitonicCountingNetwork`], entering
+/// the network only on the input wires of one lane.
+///
+/// Created by [`BitonicCountingNetwork::lane`]. Values are shared with the
+/// other lane, so together the lanes output every value exactly once.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, Counter, Lane, Priority};
+///
+/// let counter = BitonicCountingNetwork::builder()
+///     .width(4)
+///     .high_priority_wires(2)
+///     .build();
+/// let high: Lane = counter.lane(Priority::High);
+/// let normal = counter.lane(Priority::Normal);
+///
+/// assert_eq!(high.next(), 0);
+/// assert_eq!(normal.next(), 1);
+/// assert_eq!(high.priority(), Priority::High);
+/// ```
+pub struct Lane<'a, K = AtomicUsize> {
+    counter: &'a BitonicCountingNetwork<K>,
+    priority: Priority,
+    entries: Range<usize>,
+}
+
+impl<'a, K: CounterBucket> Lane<'a, K> {
+    /// Returns the priority of the lane.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Priority};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    ///
+    /// assert_eq!(counter.lane(Priority::Normal).priority(), Priority::Normal);
+    /// ```
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Returns the input wires that tokens in the lane enter the network on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Priority};
+    ///
+    /// let counter = BitonicCountingNetwork::builder()
+    ///     .width(8)
+    ///     .high_priority_wires(2)
+    ///     .build();
+    ///
+    /// assert_eq!(counter.lane(Priority::High).wires(), 0..2);
+    /// assert_eq!(counter.lane(Priority::Normal).wires(), 2..8);
+    /// ```
+    pub fn wires(&self) -> Range<usize> {
+        self.entries.clone()
+    }
+}
+
+impl<'a, K: CounterBucket> Counter for Lane<'a, K> {
+    fn next(&self) -> usize {
+        self.counter.next_within(self.entries.clone())
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        Some(self.counter.entry_within(self.entries.clone()))
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        ProgressGuarantee::WaitFree
+    }
+}
+
+impl<'a, K> Clone for Lane<'a, K> {
+    fn clone(&self) -> Self {
+        Lane {
+            counter: self.counter,
+            priority: self.priority,
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<'a, K: CounterBucket> fmt::Debug for Lane<'a, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Lane")
+            .field("counter", self.counter)
+            .field("priority", &self.priority)
+            .field("wires", &self.entries)
+            .finish()
+    }
+}
+
+/// A counter that builds its network the first time it is used, so that it
+/// can be declared in a `static`.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{Counter, LazyCounter};
+///
+/// static REQUEST_IDS: LazyCounter = LazyCounter::new(16);
+///
+/// assert_eq!(REQUEST_IDS.next(), 0);
+/// assert_eq!(REQUEST_IDS.next(), 1);
+/// ```
+pub struct LazyCounter {
+    width: usize,
+    counter: LazyBox<BitonicCountingNetwork>,
+}
+
+impl LazyCounter {
+    /// Create a counter that will build a network of the specified width when
+    /// it is first used.
+    ///
+    /// The width is not checked until then, using a width that is not a power
+    /// of two panics on first use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::LazyCounter;
+    ///
+    /// static COUNTER: LazyCounter = LazyCounter::new(8);
+    ///
+    /// assert_eq!(COUNTER.width(), 8);
+    /// ```
+    pub const fn new(width: usize) -> Self {
+        LazyCounter {
+            width,
+            counter: LazyBox::new(),
+        }
+    }
+
+    /// Returns the output width of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::LazyCounter;
+    ///
+    /// let counter = LazyCounter::new(4);
+    ///
+    /// assert_eq!(counter.width(), 4);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the network, building it if this is the first use.
+    ///
+    /// If several threads use the counter for the first time at once, each of
+    /// them may build a network, but only one is kept and the others are
+    /// dropped before any values are taken from them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, LazyCounter};
+    ///
+    /// static COUNTER: LazyCounter = LazyCounter::new(8);
+    ///
+    /// assert_eq!(COUNTER.get().next(), 0);
+    /// assert_eq!(COUNTER.get().memory_usage(), COUNTER.get().memory_usage());
+    /// ```
+    pub fn get(&self) -> &BitonicCountingNetwork {
+        self.counter
+            .get_or_init(|| BitonicCountingNetwork::new(self.width))
+    }
+}
+
+impl Counter for LazyCounter {
+    fn next(&self) -> usize {
+        self.get().next()
+    }
+
+    fn width(&self) -> usize {
+        LazyCounter::width(self)
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.get().entry_wire()
+    }
+}
+
+/// A counter sized to a rayon thread pool, where each worker of the pool
+/// enters the network on its own wire.
+///
+/// Counters normally pick a thread's entry wire by hashing its id, so a few
+/// threads can end up sharing a wire while other wires go unused. The set of
+/// workers in a pool is fixed, so each worker is given a distinct wire when
+/// the counter is built. Threads outside of the pool still pick their entry
+/// wire by hashing.
+///
+/// Only available with the `rayon` feature.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{Counter, PoolCounter};
+/// use rayon::ThreadPoolBuilder;
+///
+/// let pool = ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+/// let counter = PoolCounter::new(&pool);
+///
+/// let mut values = pool.broadcast(|_| counter.next());
+/// values.sort_unstable();
+///
+/// assert_eq!(counter.width(), 4);
+/// assert_eq!(values, vec![0, 1, 2]);
+/// ```
+#[cfg(feature = "rayon")]
+pub struct PoolCounter {
+    counter: BitonicCountingNetwork,
+    // Entry wire of each worker in the pool
+    workers: HashMap<ThreadId, usize>,
+    // Distinguishes this counter in each thread's `POOL_WIRE`
+    id: usize,
+}
+
+// Ids of pool counters, starting at 1 so that no counter matches an empty
+// `POOL_WIRE`.
+#[cfg(feature = "rayon")]
+static NEXT_POOL_COUNTER_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[cfg(feature = "rayon")]
+thread_local! {
+    // The id of the pool counter last used on this thread, and the entry wire
+    // that it gave the thread, if any. This saves looking the thread up in
+    // `workers` on every call, which hashes its id. `const` initializers for
+    // thread locals are newer than the minimum supported Rust version.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static POOL_WIRE: Cell<(usize, Option<usize>)> = Cell::new((0, None));
+}
+
+#[cfg(feature = "rayon")]
+impl PoolCounter {
+    /// Create a counter whose width is the number of threads in the pool,
+    /// rounded up to a power of two.
+    ///
+    /// This runs a short job on every worker of the pool to learn its thread,
+    /// so it blocks until each worker is free.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, PoolCounter};
+    /// use rayon::ThreadPoolBuilder;
+    ///
+    /// let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+    /// let counter = PoolCounter::new(&pool);
+    ///
+    /// assert_eq!(counter.width(), 8);
+    /// ```
+    pub fn new(pool: &ThreadPool) -> Self {
+        let width = pool.current_num_threads().next_power_of_two();
+        let workers = pool
+            .broadcast(|context| (thread::current().id(), context.index()))
+            .into_iter()
+            .collect();
+
+        PoolCounter {
+            counter: BitonicCountingNetwork::new(width),
+            workers,
+            id: NEXT_POOL_COUNTER_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Returns a reference to the network of the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::PoolCounter;
+    /// use rayon::ThreadPoolBuilder;
+    ///
+    /// let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    /// let counter = PoolCounter::new(&pool);
+    ///
+    /// assert_eq!(counter.get_ref().width(), 2);
+    /// ```
+    pub fn get_ref(&self) -> &BitonicCountingNetwork {
+        &self.counter
+    }
+
+    /// Returns the input wire that values taken from the current thread enter
+    /// the network on, without taking a value.
+    ///
+    /// This is the wire given to the thread if it is a worker of the pool,
+    /// otherwise a wire picked by hashing the id of the thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::PoolCounter;
+    /// use rayon::ThreadPoolBuilder;
+    ///
+    /// let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    /// let counter = PoolCounter::new(&pool);
+    ///
+    /// let mut wires = pool.broadcast(|_| counter.wire_for_current_thread());
+    /// wires.sort_unstable();
+    ///
+    /// assert_eq!(wires, vec![0, 1, 2, 3]);
+    /// ```
+    pub fn wire_for_current_thread(&self) -> usize {
+        match self.worker_wire() {
+            Some(wire) => wire,
+            None => self.counter.wire_for_current_thread(),
+        }
+    }
+
+    // The wire given to the current thread if it is a worker of the pool.
+    fn worker_wire(&self) -> Option<usize> {
+        POOL_WIRE.with(|cached| {
+            let (id, wire) = cached.get();
+            if id == self.id {
+                return wire;
+            }
+
+            let wire = self.workers.get(&thread::current().id()).copied();
+            cached.set((self.id, wire));
+            wire
+        })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Counter for PoolCounter {
+    fn next(&self) -> usize {
+        match self.worker_wire() {
+            Some(wire) => self.counter.next_within(wire..(wire + 1)),
+            None => self.counter.next(),
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        ProgressGuarantee::WaitFree
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        Some(self.wire_for_current_thread())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl fmt::Debug for PoolCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PoolCounter")
+            .field("width", &self.width())
+            .field("workers", &self.workers.len())
+            .finish()
+    }
+}
+
+/// A collection of named counters, created the first time each name is used.
+///
+/// Libraries can take counters for each of their subsystems from the
+/// [global](Registry::global) registry by name, instead of passing counters
+/// around.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{Counter, Registry};
+///
+/// let registry = Registry::new(8);
+///
+/// assert_eq!(registry.counter("rpc.ids").next(), 0);
+/// assert_eq!(registry.counter("rpc.ids").next(), 1);
+/// assert_eq!(registry.counter("jobs").next(), 0);
+/// ```
+pub struct Registry {
+    options: CounterOptions,
+    // Counters are boxed so that they stay in place as the map grows, and are
+    // never removed, so references to them last as long as the registry.
+    counters: RwLock<HashMap<&'static str, Box<BitonicCountingNetwork>>>,
+}
+
+// Built the first time the global registry is used, and never dropped
+static GLOBAL_REGISTRY: LazyBox<Registry> = LazyBox::new();
+
+impl Registry {
+    /// Create an empty registry, whose counters will use networks of the
+    /// specified width.
+    ///
+    /// The width is not checked until a counter is created, using a width that
+    /// is not a power of two panics then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, Registry};
+    ///
+    /// let registry = Registry::new(16);
+    ///
+    /// assert_eq!(registry.counter("a").width(), 16);
+    /// ```
+    pub fn new(width: usize) -> Self {
+        Registry::with_options(CounterOptions::new().width(width))
+    }
+
+    /// Create an empty registry, whose counters will be built with the given
+    /// options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions, Registry};
+    ///
+    /// let registry = Registry::with_options(CounterOptions::new().start(1));
+    ///
+    /// assert_eq!(registry.counter("ids").next(), 1);
+    /// ```
+    pub fn with_options(options: CounterOptions) -> Self {
+        Registry {
+            options,
+            counters: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the process-wide registry, whose counters use the default
+    /// [`CounterOptions`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, Registry};
+    ///
+    /// let first = Registry::global().counter("example.global").next();
+    /// let second = Registry::global().counter("example.global").next();
+    ///
+    /// assert!(first != second);
+    /// ```
+    pub fn global() -> &'static Registry {
+        GLOBAL_REGISTRY.get_or_init(|| Registry::with_options(CounterOptions::new()))
+    }
+
+    /// Returns the counter with the given name, creating it if it does not
+    /// exist yet.
+    ///
+    /// New counters are given the same name, see [`CounterOptions::name`].
+    ///
+    /// Each lookup takes a shared lock on the registry, so keep the returned
+    /// reference rather than looking the counter up for every value. The
+    /// counters of the [global](Registry::global) registry live for the rest
+    /// of the program.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the counter has to be created, and the options of the
+    /// registry are not valid.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, Counter, Registry};
+    /// use std::ptr;
+    ///
+    /// let registry = Registry::new(4);
+    /// let counter = registry.counter("rpc.ids");
+    ///
+    /// assert!(ptr::eq(counter, registry.counter("rpc.ids")));
+    /// assert_eq!(counter.next(), 0);
+    ///
+    /// let global: &'static BitonicCountingNetwork = Registry::global().counter("rpc.ids");
+    /// assert_eq!(global.next(), 0);
+    /// ```
+    pub fn counter(&self, name: &'static str) -> &BitonicCountingNetwork {
+        let counter: *const BitonicCountingNetwork = {
+            let counters = self.counters.read().unwrap();
+            match counters.get(name) {
+                Some(counter) => &**counter,
+                None => {
+                    drop(counters);
+                    let options = &self.options;
+                    &**self
+                        .counters
+                        .write()
+                        .unwrap()
+                        .entry(name)
+                        .or_insert_with(|| Box::new(options.clone().name(name).build()))
+                }
+            }
+        };
+
+        // Counters are boxed and never removed from the map, so they are only
+        // dropped with the registry.
+        unsafe { &*counter }
+    }
+
+    /// Returns the names of the counters that have been created, sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::Registry;
+    ///
+    /// let registry = Registry::new(4);
+    /// registry.counter("rpc.ids");
+    /// registry.counter("jobs");
+    ///
+    /// assert_eq!(registry.names(), vec!["jobs", "rpc.ids"]);
+    /// ```
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.counters.read().unwrap().keys().copied().collect();
+        names.sort_unstable();
+
+        names
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::with_options(CounterOptions::new())
+    }
+}
+
+/// A counter whose underlying network can be replaced while it is in use.
+///
+/// Long-running services can use [`swap`](SwappableCounter::swap) to change the
+/// width of the network, or any other [`CounterOptions`], without a restart.
+///
+/// The hand-off between networks keeps values unique: a swap waits for every
+/// call to [`next`](Counter::next) on the old network to finish, and the new
+/// network starts at the value the old network would have returned next. Once
+/// in-flight calls have finished the old network is quiescent, so it has
+/// returned exactly the values before that one and none after it.
+///
+/// Calls don't take a lock. The current network is behind an atomic pointer,
+/// and each call marks itself in flight on one of several counts, picked by a
+/// hash of the thread, so calls on different threads rarely touch the same
+/// memory. A swap builds the new network before taking the old one out, so
+/// calls made during a swap only wait while the in-flight calls on the old
+/// network finish, then continue on the new network.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{Counter, CounterOptions, SwappableCounter};
+///
+/// let counter = SwappableCounter::new(CounterOptions::new().width(4));
+/// assert_eq!(counter.next(), 0);
+/// assert_eq!(counter.next(), 1);
+///
+/// counter.swap(CounterOptions::new().width(16));
+///
+/// assert_eq!(counter.width(), 16);
+/// assert_eq!(counter.next(), 2);
+/// ```
+pub struct SwappableCounter {
+    // Null while a swap is handing off between networks
+    current: AtomicPtr<BitonicCountingNetwork>,
+    // Calls in flight on the current network
+    in_flight: Box<[PaddedBucket<AtomicUsize>]>,
+    // Held by the swap in progress
+    swapping: Mutex<()>,
+}
+
+// Number of counts that calls to a `SwappableCounter` are spread over.
+const IN_FLIGHT_STRIPES: usize = 8;
+
+// Marks a call in flight until it is dropped, even if the call panics.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        // Releases the use of the network to the swap that frees it
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl SwappableCounter {
+    /// Create a counter from the given options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are not valid, as for [`CounterOptions::build`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions, SwappableCounter};
+    ///
+    /// let counter = SwappableCounter::new(CounterOptions::new().start(5));
+    ///
+    /// assert_eq!(counter.next(), 5);
+    /// ```
+    pub fn new(options: CounterOptions) -> Self {
+        SwappableCounter {
+            current: AtomicPtr::new(Box::into_raw(Box::new(options.build()))),
+            in_flight: (0..IN_FLIGHT_STRIPES)
+                .map(|_| PaddedBucket::default())
+                .collect(),
+            swapping: Mutex::new(()),
+        }
+    }
+
+    // Run `f` on the current network, waiting for a swap in progress to finish.
+    fn with_current<R>(&self, f: impl FnOnce(&BitonicCountingNetwork) -> R) -> R {
+        let PaddedBucket(in_flight) = &self.in_flight[stripe_probe() as usize % IN_FLIGHT_STRIPES];
+
+        loop {
+            // Both sides are `SeqCst`: either this call sees the network that
+            // a swap takes out, and the swap sees it in flight, or the call
+            // sees null and keeps away from it.
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            let _in_flight = InFlight(in_flight);
+            let current = self.current.load(Ordering::SeqCst);
+
+            if !current.is_null() {
+                // The network is only freed once no call is in flight on it
+                return f(unsafe { &*current });
+            }
+
+            drop(_in_flight);
+            while self.current.load(Ordering::Acquire).is_null() {
+                thread::yield_now();
+            }
+        }
+    }
+
+    /// Replace the underlying network with one built from `options`.
+    ///
+    /// The `start` of the options is ignored, the new network continues from
+    /// where the old one stopped. Its stride is used for every value after the
+    /// first one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are not valid, as for [`CounterOptions::build`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, CounterOptions, SwappableCounter};
+    ///
+    /// let counter = SwappableCounter::new(CounterOptions::new());
+    /// assert_eq!(counter.next(), 0);
+    ///
+    /// counter.swap(CounterOptions::new().stride(10));
+    ///
+    /// assert_eq!(counter.next(), 1);
+    /// assert_eq!(counter.next(), 11);
+    /// ```
+    pub fn swap(&self, options: CounterOptions) {
+        let stride = options.stride;
+        let mut next = Box::new(options.build());
+
+        let _swapping = self.swapping.lock().unwrap();
+        let previous = self.current.swap(ptr::null_mut(), Ordering::SeqCst);
+        for PaddedBucket(in_flight) in self.in_flight.iter() {
+            while in_flight.load(Ordering::SeqCst) != 0 {
+                thread::yield_now();
+            }
+        }
+
+        // No call is in flight on the old network and no new call can reach
+        // it, so this is the smallest value it has not returned yet, and it is
+        // safe to free.
+        let previous = unsafe { Box::from_raw(previous) };
+        next.restart_at(previous.next(), stride);
+        self.current.store(Box::into_raw(next), Ordering::SeqCst);
+    }
+
+    /// Returns the output width of the current network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{CounterOptions, SwappableCounter};
+    ///
+    /// let counter = SwappableCounter::new(CounterOptions::new().width(32));
+    ///
+    /// assert_eq!(counter.width(), 32);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.with_current(|current| current.width())
+    }
+}
+
+impl Drop for SwappableCounter {
+    fn drop(&mut self) {
+        let current = *self.current.get_mut();
+        // Null if a swap panicked while handing off
+        if !current.is_null() {
+            drop(unsafe { Box::from_raw(current) });
+        }
+    }
+}
+
+impl Counter for SwappableCounter {
+    fn next(&self) -> usize {
+        self.with_current(|current| current.next())
+    }
+
+    fn width(&self) -> usize {
+        SwappableCounter::width(self)
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        self.with_current(|current| current.entry_wire())
+    }
+}
+
+/// Hands out each of the values `0..total` exactly once across all threads, in
+/// decreasing order.
+///
+/// This is a [`BitonicCountingNetwork`] with its buckets inverted: bucket `i`
+/// starts at `total - 1 - i` and counts down by the width of the network. Once
+/// the values run out the buckets wrap around to values that are too large,
+/// which are reported as `None`.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::Countdown;
+///
+/// let countdown = Countdown::new(3, 4);
+///
+/// assert_eq!(countdown.next(), Some(2));
+/// assert_eq!(countdown.next(), Some(1));
+/// assert_eq!(countdown.next(), Some(0));
+/// assert_eq!(countdown.next(), None);
+/// ```
+pub struct Countdown {
+    counter: BitonicCountingNetwork,
+    total: usize,
+}
+
+impl Countdown {
+    /// Create a countdown over the values `0..total`, using a network of the
+    /// specified width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::Countdown;
+    ///
+    /// let countdown = Countdown::new(100, 8);
+    ///
+    /// assert_eq!(countdown.total(), 100);
+    /// assert_eq!(countdown.next(), Some(99));
+    /// ```
+    pub fn new(total: usize, width: usize) -> Self {
+        let counter = CounterOptions::new()
+            .width(width)
+            .start(total.wrapping_sub(1))
+            // Adding `usize::MAX` with wrapping is the same as subtracting one
+            .stride(usize::MAX)
+            .build();
+
+        Countdown { counter, total }
+    }
+
+    /// Returns the number of values handed out in total.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::Countdown;
+    ///
+    /// assert_eq!(Countdown::new(10, 4).total(), 10);
+    /// ```
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns the output width of the internal bitonic network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::Countdown;
+    ///
+    /// assert_eq!(Countdown::new(10, 4).width(), 4);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.counter.width()
+    }
+
+    /// Retrieve the next value, or `None` if every value has been handed out.
+    ///
+    /// Once all concurrent calls have finished, the values handed out are
+    /// exactly `total - 1` down to `total - n`, for `n` calls that returned a
+    /// value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::Countdown;
+    ///
+    /// let countdown = Countdown::new(1, 2);
+    ///
+    /// assert_eq!(countdown.next(), Some(0));
+    /// assert_eq!(countdown.next(), None);
+    /// assert_eq!(countdown.next(), None);
+    /// ```
+    // Named after `Counter::next`, the sequence has an end but is shared
+    // between threads so it can't be an `Iterator`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&self) -> Option<usize> {
+        let value = self.counter.next();
+
+        if value < self.total {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+/// A floating point sum that many threads can add to at once.
+///
+/// The sum is striped over several cells, each on its own cache line. Like
+/// `LongAdder` in Java, each thread adds to the stripe picked by a hash of the
+/// thread, and moves on to another stripe when its compare and swap fails, so
+/// threads adding at the same time soon stop touching the same memory. Each
+/// stripe holds the bits of an `f64` in an `AtomicU64`.
+/// [`sum`](F64Adder::sum) adds up the stripes.
+///
+/// Floating point addition is not associative, so the sum can differ in the
+/// last bits from the sum of the same values added in another order.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::F64Adder;
+///
+/// let latency = F64Adder::new(4);
+///
+/// latency.add(1.5);
+/// latency.add(2.25);
+///
+/// assert_eq!(latency.sum(), 3.75);
+/// ```
+pub struct F64Adder {
+    stripes: Box<[PaddedBucket<AtomicU64>]>,
+}
+
+impl F64Adder {
+    /// Create a new sum of zero, striped over the specified number of cells.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::F64Adder;
+    ///
+    /// let adder = F64Adder::new(8);
+    ///
+    /// assert_eq!(adder.sum(), 0.0);
+    /// ```
+    pub fn new(width: usize) -> Self {
+        assert!(width.is_power_of_two());
+
+        F64Adder {
+            stripes: (0..width)
+                .map(|_| PaddedBucket(AtomicU64::new(0.0f64.to_bits())))
+                .collect(),
+        }
+    }
+
+    /// Returns the number of stripes that the sum is spread over.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::F64Adder;
+    ///
+    /// assert_eq!(F64Adder::new(8).width(), 8);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.stripes.len()
+    }
+
+    /// Add `value` to the sum.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::F64Adder;
+    ///
+    /// let adder = F64Adder::new(2);
+    ///
+    /// adder.add(-4.0);
+    ///
+    /// assert_eq!(adder.sum(), -4.0);
+    /// ```
+    pub fn add(&self, value: f64) {
+        let mut probe = stripe_probe();
+
+        loop {
+            let PaddedBucket(stripe) = &self.stripes[probe as usize & (self.stripes.len() - 1)];
+            let current = stripe.load(Ordering::Relaxed);
+            let updated = (f64::from_bits(current) + value).to_bits();
+
+            if stripe
+                .compare_exchange(current, updated, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+
+            // Another thread got to the stripe first, try the next one
+            probe = advance_stripe_probe(probe);
+        }
+    }
+
+    /// Returns the sum of all the values added so far.
+    ///
+    /// Values that are added while the stripes are being read may or may not
+    /// be included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::F64Adder;
+    ///
+    /// let adder = F64Adder::new(4);
+    /// for _ in 0..10 {
+    ///     adder.add(0.5);
+    /// }
+    ///
+    /// assert_eq!(adder.sum(), 5.0);
+    /// ```
+    pub fn sum(&self) -> f64 {
+        self.stripes
+            .iter()
+            .map(|PaddedBucket(stripe)| f64::from_bits(stripe.load(Ordering::Relaxed)))
+            .sum()
+    }
+}
+
+impl fmt::Debug for F64Adder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("F64Adder")
+            .field("width", &self.width())
+            .field("sum", &self.sum())
+            .finish()
+    }
+}
+
+/// A counter that hands out unique values slightly out of order, in exchange
+/// for a shallower network than [`BitonicCountingNetwork`].
+///
+/// Values are taken from buckets at the outputs of a network, the same way as
+/// for a counting network, so wire `i` hands out `i`, `i + width`, and so on.
+/// When the bound allows it the network is a
+/// [`ButterflyNetwork`](crate::networks::ButterflyNetwork), with `log2(width)`
+/// layers instead of `log2(width) * (log2(width) + 1) / 2`. Otherwise it is a
+/// bitonic network, and the counter has no skew beyond that of a counting
+/// network.
+///
+/// In a quiescent state the number of values taken from any two wires differs
+/// by at most [`max_skew`](RelaxedCounter::max_skew). So after `n` values, all
+/// of them are less than `n + max_skew * width`, and some smaller values may
+/// not have been handed out yet. Values are never handed out twice.
+///
+/// This does not implement [`Counter`], which promises values without skips.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{counters::RelaxedCounter, networks::Width};
+///
+/// let counter = RelaxedCounter::new(Width::new(16).unwrap(), 4);
+///
+/// assert_eq!(counter.max_skew(), 4);
+/// assert!(counter.next() < 16);
+/// ```
+pub struct RelaxedCounter {
+    network: Network<AtomicUsize, Topology>,
+    max_skew: usize,
+}
+
+impl RelaxedCounter {
+    /// Create a counter with the specified width, whose wires may differ by at
+    /// most `max_skew` values.
+    ///
+    /// The counter uses the shallowest network whose skew is within the
+    /// bound, so the actual [`max_skew`](RelaxedCounter::max_skew) can be
+    /// smaller than requested.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_skew` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{counters::RelaxedCounter, networks::Width};
+    ///
+    /// // Too strict for a butterfly network of this width
+    /// let counter = RelaxedCounter::new(Width::new(16).unwrap(), 2);
+    ///
+    /// assert_eq!(counter.max_skew(), 1);
+    /// assert_eq!(counter.next(), 0);
+    /// assert_eq!(counter.next(), 1);
+    /// ```
+    pub fn new(width: Width, max_skew: usize) -> Self {
+        assert!(max_skew > 0);
+
+        let butterfly_skew = (width.get().trailing_zeros() as usize).max(1);
+        let (topology, max_skew) = if max_skew >= butterfly_skew {
+            (
+                Topology::from_config::<ButterflyConfiguration>(width),
+                butterfly_skew,
+            )
+        } else {
+            (Topology::from_config::<BitonicConfiguration>(width), 1)
+        };
+
+        RelaxedCounter {
+            network: Network::from_topology(
+                &topology,
+                (0..width.get()).map(AtomicUsize::new).collect(),
+            ),
+            max_skew,
+        }
+    }
+
+    /// Returns the output width of the internal network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{counters::RelaxedCounter, networks::Width};
+    ///
+    /// assert_eq!(RelaxedCounter::new(Width::new(8).unwrap(), 3).width(), 8);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.network.width()
+    }
+
+    /// Returns the most that the number of values taken from any two wires
+    /// can differ by in a quiescent state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{counters::RelaxedCounter, networks::Width};
+    ///
+    /// assert_eq!(RelaxedCounter::new(Width::new(8).unwrap(), 100).max_skew(), 3);
+    /// ```
+    pub fn max_skew(&self) -> usize {
+        self.max_skew
+    }
+
+    /// Retrieve the next value from the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{counters::RelaxedCounter, networks::Width};
+    ///
+    /// let counter = RelaxedCounter::new(Width::new(4).unwrap(), 2);
+    /// let first = counter.next();
+    ///
+    /// assert_ne!(counter.next(), first);
+    /// ```
+    // Named after `Counter::next`, but the values skip so it can't implement
+    // `Counter`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&self) -> usize {
+        let bucket = self.network.traverse();
+        #[cfg(feature = "testing")]
+        jitter::inject(InjectionPoint::BeforeIncrement);
+
+        bucket.fetch_add(self.width(), Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for RelaxedCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RelaxedCounter")
+            .field("width", &self.width())
+            .field("max_skew", &self.max_skew)
+            .finish()
+    }
+}
+
+/// A handle to a shared counter that only ever returns strictly increasing
+/// values.
+///
+/// A counting network is quiescently consistent: once all traversals have
+/// finished, the values handed out are exactly `0..n`. While traversals
+/// overlap, a caller can still get a smaller value after a larger one, for
+/// example when a connection moves between threads that enter the network on
+/// different wires. The handle remembers the last value it returned, and
+/// discards any value from the counter that is not larger than it.
+///
+/// Discarded values are never returned by any other caller, so consumers that
+/// need every value to be used should count them with
+/// [`skipped`](MonotonicHandle::skipped). Create one handle per sequence, such
+/// as one per connection.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BitonicCountingNetwork, MonotonicHandle};
+///
+/// let counter = BitonicCountingNetwork::new(4);
+/// let mut handle = MonotonicHandle::new(&counter);
+///
+/// let first = handle.next();
+/// let second = handle.next();
+///
+/// assert!(first < second);
+/// assert_eq!(handle.last(), Some(second));
+/// ```
+#[derive(Debug)]
+pub struct MonotonicHandle<'a, C: ?Sized> {
+    counter: &'a C,
+    last: Option<usize>,
+    skipped: usize,
+}
+
+impl<'a, C: Counter + ?Sized> MonotonicHandle<'a, C> {
+    /// Create a handle that takes values from `counter`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, MonotonicHandle};
+    ///
+    /// let counter = BitonicCountingNetwork::new(8);
+    /// let handle = MonotonicHandle::new(&counter);
+    ///
+    /// assert_eq!(handle.last(), None);
+    /// ```
+    pub fn new(counter: &'a C) -> Self {
+        MonotonicHandle {
+            counter,
+            last: None,
+            skipped: 0,
+        }
+    }
+
+    /// Retrieve the next value from the counter that is larger than every
+    /// value previously returned by this handle.
+    ///
+    /// Values from the counter are unique, so this only has to discard a
+    /// bounded number of them. The counter must not wrap around while the
+    /// handle is in use, otherwise this never returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, MonotonicHandle};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    /// let mut handle = MonotonicHandle::new(&counter);
+    ///
+    /// assert_eq!(handle.next(), 0);
+    /// assert_eq!(handle.next(), 1);
+    /// ```
+    // Named after `Counter::next` rather than implementing `Iterator`, since the
+    // sequence never ends.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> usize {
+        loop {
+            let value = self.counter.next();
+
+            match self.last {
+                Some(last) if value <= last => self.skipped += 1,
+                _ => {
+                    self.last = Some(value);
+                    return value;
+                }
+            }
+        }
+    }
+
+    /// Returns the last value returned by [`next`](MonotonicHandle::next), or
+    /// `None` if it has not been called yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, MonotonicHandle};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    /// let mut handle = MonotonicHandle::new(&counter);
+    /// let value = handle.next();
+    ///
+    /// assert_eq!(handle.last(), Some(value));
+    /// ```
+    pub fn last(&self) -> Option<usize> {
+        self.last
+    }
+
+    /// Returns the number of values from the counter that were discarded
+    /// because they were not larger than the last value returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BitonicCountingNetwork, MonotonicHandle};
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    /// let mut handle = MonotonicHandle::new(&counter);
+    /// handle.next();
+    ///
+    /// // A single thread always sees increasing values.
+    /// assert_eq!(handle.skipped(), 0);
+    /// ```
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// A set of independent counters that share the wiring of a single bitonic
+/// network.
+///
+/// Each output of the network holds one value for every counter, and
+/// [`next`](MultiCounter::next) makes a single traversal of the shared network
+/// on behalf of one counter. The balancers keep a separate toggle for each
+/// counter, so tokens for one counter never skew the values handed out by
+/// another, and each counter outputs `0, 1, 2, ...` on its own.
+///
+/// This uses much less memory than one [`BitonicCountingNetwork`] per counter,
+/// because the balancers are only stored once. The toggles of a counter are
+/// packed next to each other rather than padded to a cache line, so threads
+/// using the same counter contend more than they would on a dedicated network.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::MultiCounter;
+///
+/// let counters = MultiCounter::new(4, 3);
+///
+/// assert_eq!(counters.next(0), 0);
+/// assert_eq!(counters.next(0), 1);
+/// assert_eq!(counters.next(2), 0);
+/// assert_eq!(counters.next(0), 2);
+/// ```
+pub struct MultiCounter {
+    network: BitonicNetwork<Box<[AtomicUsize]>>,
+    num_counters: usize,
+    // The toggles of counter `i` are at `i * num_balancers..(i + 1) * num_balancers`.
+    toggles: Box<[AtomicBool]>,
+}
+
+impl MultiCounter {
+    /// Create `num_counters` counters that share a network of the specified
+    /// width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::MultiCounter;
+    ///
+    /// let counters = MultiCounter::new(8, 16);
+    ///
+    /// assert_eq!(counters.width(), 8);
+    /// assert_eq!(counters.num_counters(), 16);
+    /// ```
+    pub fn new(width: usize, num_counters: usize) -> Self {
+        let buckets = (0..width)
+            .map(|wire| (0..num_counters).map(|_| AtomicUsize::new(wire)).collect())
+            .collect();
+        let network: BitonicNetwork<Box<[AtomicUsize]>> = BitonicNetwork::new(buckets);
+        // Same starting state as the balancers in the network, so the first
+        // token through each balancer leaves on the top wire.
+        let toggles = (0..network.num_balancers() * num_counters)
+            .map(|_| AtomicBool::new(true))
+            .collect();
+
+        MultiCounter {
+            network,
+            num_counters,
+            toggles,
+        }
+    }
+
+    /// Returns the output width of the shared network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::MultiCounter;
+    ///
+    /// let counters = MultiCounter::new(4, 2);
+    ///
+    /// assert_eq!(counters.width(), 4);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.network.width()
+    }
+
+    /// Returns the number of counters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::MultiCounter;
+    ///
+    /// let counters = MultiCounter::new(4, 2);
+    ///
+    /// assert_eq!(counters.num_counters(), 2);
+    /// ```
+    pub fn num_counters(&self) -> usize {
+        self.num_counters
+    }
+
+    /// Retrieve the next value from the counter `counter_id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `counter_id` is not less than
+    /// [`num_counters`](MultiCounter::num_counters).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::MultiCounter;
+    ///
+    /// let counters = MultiCounter::new(2, 2);
+    ///
+    /// assert_eq!(counters.next(1), 0);
+    /// assert_eq!(counters.next(1), 1);
+    /// assert_eq!(counters.next(0), 0);
+    /// ```
+    pub fn next(&self, counter_id: usize) -> usize {
+        assert!(
+            counter_id < self.num_counters,
+            "counter id {} out of range for {} counters",
+            counter_id,
+            self.num_counters
+        );
+
+        let toggles = {
+            let num_balancers = self.network.num_balancers();
+            &self.toggles[counter_id * num_balancers..(counter_id + 1) * num_balancers]
+        };
+        let buckets = self
+            .network
+            .traverse_with(|balancer| toggles[balancer].fetch_xor(true, Ordering::Relaxed));
+        #[cfg(feature = "testing")]
+        jitter::inject(InjectionPoint::BeforeIncrement);
+
+        buckets[counter_id].fetch_add(self.width(), Ordering::SeqCst)
+    }
+}
+
+impl MultiCounter {
+    /// Split the counters into [`SubCounter`]s with disjoint values, one for
+    /// each counter.
+    ///
+    /// Sub-counter `i` outputs `i`, `i + n`, `i + 2 * n`, ..., where `n` is the
+    /// number of counters, so the values of different sub-counters never
+    /// overlap and each one can be handed to a separate subsystem without any
+    /// coordination. Values wrap around on overflow, and are only disjoint
+    /// until the first sub-counter wraps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{Counter, MultiCounter};
+    ///
+    /// let parts = MultiCounter::new(4, 3).split();
+    ///
+    /// assert_eq!(parts[0].next(), 0);
+    /// assert_eq!(parts[0].next(), 3);
+    /// assert_eq!(parts[2].next(), 2);
+    /// assert_eq!(parts[1].next(), 1);
+    /// assert_eq!(parts[2].next(), 5);
+    /// ```
+    pub fn split(self) -> Vec<SubCounter> {
+        let counters = Arc::new(self);
+
+        (0..counters.num_counters)
+            .map(|counter_id| SubCounter {
+                counters: Arc::clone(&counters),
+                counter_id,
+            })
+            .collect()
+    }
+}
+
+/// One part of a [`MultiCounter`] that was [`split`](MultiCounter::split) into
+/// counters with disjoint values.
+///
+/// Sub-counters share the network of the original counters, and can be sent
+/// to other threads independently.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{Counter, MultiCounter};
+/// use std::thread;
+///
+/// let mut parts = MultiCounter::new(8, 2).split();
+/// let odd = parts.pop().unwrap();
+/// let even = parts.pop().unwrap();
+///
+/// let handle = thread::spawn(move || odd.next());
+///
+/// assert_eq!(even.next() % 2, 0);
+/// assert_eq!(handle.join().unwrap() % 2, 1);
+/// ```
+pub struct SubCounter {
+    counters: Arc<MultiCounter>,
+    counter_id: usize,
+}
+
+impl SubCounter {
+    /// Returns the remainder of every value of this sub-counter, when divided
+    /// by the number of parts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::MultiCounter;
+    ///
+    /// let parts = MultiCounter::new(4, 3).split();
+    ///
+    /// assert_eq!(parts[1].index(), 1);
+    /// ```
+    pub fn index(&self) -> usize {
+        self.counter_id
+    }
+
+    /// Returns the number of parts that the counters were split into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::MultiCounter;
+    ///
+    /// let parts = MultiCounter::new(4, 3).split();
+    ///
+    /// assert_eq!(parts[1].parts(), 3);
+    /// ```
+    pub fn parts(&self) -> usize {
+        self.counters.num_counters
+    }
+}
+
+impl Counter for SubCounter {
+    fn next(&self) -> usize {
+        self.counters
+            .next(self.counter_id)
+            .wrapping_mul(self.parts())
+            .wrapping_add(self.counter_id)
+    }
+
+    fn width(&self) -> usize {
+        self.counters.width()
+    }
+
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        ProgressGuarantee::WaitFree
+    }
+}
+
+/// A group of counters that can take a consistent snapshot of how many values
+/// each of them has handed out.
+///
+/// Reading counters one at a time can give a view that was never true at any
+/// single moment, for example more errors than requests if an error was
+/// counted between reading the two. A [`snapshot`](CounterGroup::snapshot)
+/// instead waits until no call to [`next`](CounterGroup::next) is in progress,
+/// and holds back new calls while it reads every counter, so the counts it
+/// returns all come from the same point in time.
+///
+/// Calls to `next` announce themselves on a stripe picked by the calling
+/// thread, so they only contend with each other on the counters themselves.
+/// They wait while a snapshot is being taken, which makes them blocking.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::CounterGroup;
+///
+/// const REQUESTS: usize = 0;
+/// const ERRORS: usize = 1;
+///
+/// let group = CounterGroup::new(8, 2);
+///
+/// assert_eq!(group.next(REQUESTS), 0);
+/// assert_eq!(group.next(REQUESTS), 1);
+/// assert_eq!(group.next(ERRORS), 0);
+///
+/// assert_eq!(group.snapshot(), vec![2, 1]);
+/// ```
+pub struct CounterGroup {
+    counters: Box<[BitonicCountingNetwork]>,
+    // Number of calls to `next` in progress, on stripes picked by thread
+    active: Box<[PaddedBucket<AtomicUsize>]>,
+    snapshotting: AtomicBool,
+    // Only one snapshot is taken at a time
+    snapshots: Mutex<()>,
+}
+
+impl CounterGroup {
+    /// Create `num_counters` counters with the specified width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterGroup;
+    ///
+    /// let group = CounterGroup::new(8, 3);
+    ///
+    /// assert_eq!(group.width(), 8);
+    /// assert_eq!(group.num_counters(), 3);
+    /// ```
+    pub fn new(width: usize, num_counters: usize) -> Self {
+        CounterGroup {
+            counters: (0..num_counters)
+                .map(|_| BitonicCountingNetwork::new(width))
+                .collect(),
+            active: (0..width).map(|_| PaddedBucket::with_value(0)).collect(),
+            snapshotting: AtomicBool::new(false),
+            snapshots: Mutex::new(()),
+        }
+    }
+
+    /// Returns the width of each counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterGroup;
+    ///
+    /// let group = CounterGroup::new(4, 2);
+    ///
+    /// assert_eq!(group.width(), 4);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Returns the number of counters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterGroup;
+    ///
+    /// let group = CounterGroup::new(4, 2);
+    ///
+    /// assert_eq!(group.num_counters(), 2);
+    /// ```
+    pub fn num_counters(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Retrieve the next value from the counter `counter_id`.
+    ///
+    /// Waits if a snapshot is being taken.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `counter_id` is not less than
+    /// [`num_counters`](CounterGroup::num_counters).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterGroup;
+    ///
+    /// let group = CounterGroup::new(2, 2);
+    ///
+    /// assert_eq!(group.next(1), 0);
+    /// assert_eq!(group.next(1), 1);
+    /// assert_eq!(group.next(0), 0);
+    /// ```
+    pub fn next(&self, counter_id: usize) -> usize {
+        assert!(
+            counter_id < self.counters.len(),
+            "counter id {} out of range for {} counters",
+            counter_id,
+            self.counters.len()
+        );
+
+        let PaddedBucket(active) =
+            &self.active[hash_single(thread::current().id()) as usize % self.active.len()];
+        loop {
+            // Announce the call before checking for a snapshot, and the
+            // snapshot announces itself before checking for calls, so at least
+            // one of them sees the other.
+            active.fetch_add(1, Ordering::SeqCst);
+            if !self.snapshotting.load(Ordering::SeqCst) {
+                break;
+            }
+
+            active.fetch_sub(1, Ordering::SeqCst);
+            while self.snapshotting.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+        }
+
+        let value = self.counters[counter_id].next();
+        active.fetch_sub(1, Ordering::SeqCst);
+
+        value
+    }
+
+    /// Returns the number of values that each counter has handed out, all
+    /// taken at the same point in time.
+    ///
+    /// Waits for calls to [`next`](CounterGroup::next) that are in progress to
+    /// finish, and holds back new ones until the counters have been read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterGroup;
+    /// use std::{sync::Arc, thread};
+    ///
+    /// let group = Arc::new(CounterGroup::new(8, 2));
+    ///
+    /// let worker = {
+    ///     let group = Arc::clone(&group);
+    ///     thread::spawn(move || {
+    ///         for _ in 0..1000 {
+    ///             group.next(0);
+    ///             group.next(1);
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// // The second counter is never ahead of the first
+    /// let counts = group.snapshot();
+    /// assert!(counts[1] <= counts[0]);
+    ///
+    /// worker.join().unwrap();
+    /// assert_eq!(group.snapshot(), vec![1000, 1000]);
+    /// ```
+    pub fn snapshot(&self) -> Vec<usize> {
+        let _snapshot = self.snapshots.lock().unwrap();
+
+        self.snapshotting.store(true, Ordering::SeqCst);
+        for PaddedBucket(active) in self.active.iter() {
+            while active.load(Ordering::SeqCst) != 0 {
+                thread::yield_now();
+            }
+        }
+
+        let counts = self.counters.iter().map(issued).collect();
+        self.snapshotting.store(false, Ordering::SeqCst);
+
+        counts
+    }
+}
+
+// The number of values a counter starting at zero with a stride of one has
+// handed out, if no traversals are in progress.
+fn issued(counter: &BitonicCountingNetwork) -> usize {
+    let width = counter.width();
+    let buckets = match counter.as_network() {
+        CounterNetwork::Unpadded(network) => network.outputs(),
+        CounterNetwork::Padded(_) => unreachable!("group counters have unpadded buckets"),
+    };
+
+    // Bucket `i` starts at `i`, and moves on by the width for every value
+    buckets
+        .iter()
+        .enumerate()
+        .map(|(wire, bucket)| bucket.load(Ordering::SeqCst).wrapping_sub(wire) / width)
+        .fold(0, usize::wrapping_add)
+}
+
+impl fmt::Debug for CounterGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CounterGroup")
+            .field("width", &self.width())
+            .field("num_counters", &self.num_counters())
+            .finish()
+    }
+}
+
+/// The kinds of counter that a [`DynCounter`] can be built with.
+///
+/// Kinds can be parsed from their names, so they can be read from a
+/// configuration file.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::BackendKind;
+///
+/// let kind: BackendKind = "padded-bitonic".parse().unwrap();
+///
+/// assert_eq!(kind, BackendKind::PaddedBitonic);
+/// assert_eq!(kind.to_string(), "padded-bitonic");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BackendKind {
+    /// A single atomic integer, named `atomic`. Every thread contends on the
+    /// same memory, but there is no network to traverse.
+    Atomic,
+    /// A [`BitonicCountingNetwork`] with unpadded buckets, named `bitonic`.
+    Bitonic,
+    /// A [`BitonicCountingNetwork`] with
+    /// [padded buckets](CounterOptions::padded_buckets), named
+    /// `padded-bitonic`.
+    PaddedBitonic,
+}
+
+impl BackendKind {
+    // Every kind, in the order they are listed in error messages.
+    const ALL: [BackendKind; 3] = [
+        BackendKind::Atomic,
+        BackendKind::Bitonic,
+        BackendKind::PaddedBitonic,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            BackendKind::Atomic => "atomic",
+            BackendKind::Bitonic => "bitonic",
+            BackendKind::PaddedBitonic => "padded-bitonic",
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = ParseBackendKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BackendKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| ParseBackendKindError(s.to_owned()))
+    }
+}
+
+/// The error returned when parsing an unknown [`BackendKind`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::BackendKind;
+///
+/// let error = "mutex".parse::<BackendKind>().unwrap_err();
+///
+/// assert_eq!(
+///     error.to_string(),
+///     "unknown counter backend \"mutex\", expected one of atomic, bitonic, padded-bitonic"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBackendKindError(String);
+
+impl fmt::Display for ParseBackendKindError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = BackendKind::ALL.iter().map(|kind| kind.name()).collect();
+
+        write!(
+            f,
+            "unknown counter backend {:?}, expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for ParseBackendKindError {}
+
+/// A counter whose kind is chosen at runtime.
+///
+/// This lets applications pick a counter from configuration without making
+/// every type that holds a counter generic over it. Calls are dispatched with
+/// a `match`, which is cheaper than a `Box<dyn Counter>`.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BackendKind, Counter, DynCounter};
+///
+/// let kind: BackendKind = "bitonic".parse().unwrap();
+/// let counter = DynCounter::new(kind, 8);
+///
+/// assert_eq!(counter.kind(), BackendKind::Bitonic);
+/// assert_eq!(counter.next(), 0);
+/// assert_eq!(counter.next(), 1);
+/// ```
+#[derive(Debug)]
+pub enum DynCounter {
+    /// A single atomic integer.
+    Atomic(AtomicUsize),
+    /// A counting network, with padded or unpadded buckets.
+    Bitonic(BitonicCountingNetwork),
+}
+
+impl DynCounter {
+    /// Create a counter of the given kind, starting at zero.
+    ///
+    /// The width is only used by kinds built on a counting network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the kind uses a counting network and the width is not a power
+    /// of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BackendKind, Counter, DynCounter};
+    ///
+    /// let atomic = DynCounter::new(BackendKind::Atomic, 8);
+    /// let padded = DynCounter::new(BackendKind::PaddedBitonic, 8);
+    ///
+    /// assert_eq!(atomic.width(), 1);
+    /// assert_eq!(padded.width(), 8);
+    /// ```
+    pub fn new(kind: BackendKind, width: usize) -> Self {
+        match kind {
+            BackendKind::Atomic => DynCounter::Atomic(AtomicUsize::new(0)),
+            BackendKind::Bitonic => DynCounter::Bitonic(BitonicCountingNetwork::new(width)),
+            BackendKind::PaddedBitonic => DynCounter::Bitonic(
+                CounterOptions::new()
+                    .width(width)
+                    .padded_buckets(true)
+                    .build(),
+            ),
+        }
+    }
+
+    /// Returns the kind of the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BackendKind, DynCounter};
+    ///
+    /// let counter = DynCounter::new(BackendKind::PaddedBitonic, 4);
+    ///
+    /// assert_eq!(counter.kind(), BackendKind::PaddedBitonic);
+    /// ```
+    pub fn kind(&self) -> BackendKind {
+        match self {
+            DynCounter::Atomic(_) => BackendKind::Atomic,
+            DynCounter::Bitonic(counter) => match counter.as_network() {
+                CounterNetwork::Unpadded(_) => BackendKind::Bitonic,
+                CounterNetwork::Padded(_) => BackendKind::PaddedBitonic,
+            },
+        }
+    }
+}
+
+impl Counter for DynCounter {
+    fn next(&self) -> usize {
+        match self {
+            DynCounter::Atomic(counter) => counter.fetch_add(1, Ordering::SeqCst),
+            DynCounter::Bitonic(counter) => counter.next(),
+        }
+    }
+
+    fn width(&self) -> usize {
+        match self {
+            DynCounter::Atomic(_) => 1,
+            DynCounter::Bitonic(counter) => counter.width(),
+        }
+    }
+
+    // Both kinds take a value with a fixed number of atomic operations.
+    fn progress_guarantee(&self) -> ProgressGuarantee {
+        ProgressGuarantee::WaitFree
+    }
+
+    fn entry_wire(&self) -> Option<usize> {
+        match self {
+            DynCounter::Atomic(_) => None,
+            DynCounter::Bitonic(counter) => counter.entry_wire(),
+        }
+    }
+}
+
+/// Settings for building a [`DynCounter`], read at runtime from environment
+/// variables or another source of configuration.
+///
+/// Each setting has a key and a string value:
+///
+///  - `backend`: the name of a [`BackendKind`], `bitonic` by default. Use
+///    `padded-bitonic` for padded buckets.
+///  - `width`: the width of the counting network, a power of two, 8 by default.
+///
+/// With the `serde` feature, configurations can also be deserialized from a
+/// map of these settings.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::counters::{BackendKind, Counter, CounterConfig};
+/// use std::collections::HashMap;
+///
+/// let mut settings = HashMap::new();
+/// settings.insert("backend", "padded-bitonic");
+/// settings.insert("width", "16");
+///
+/// let config =
+///     CounterConfig::from_lookup(|key| settings.get(key).map(|value| value.to_string())).unwrap();
+///
+/// assert_eq!(config.backend(), BackendKind::PaddedBitonic);
+/// assert_eq!(config.build().width(), 16);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CounterConfig {
+    backend: BackendKind,
+    width: usize,
+}
+
+impl CounterConfig {
+    /// Create a configuration with the given settings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BackendKind, CounterConfig};
+    ///
+    /// let config = CounterConfig::new(BackendKind::Atomic, 1);
+    ///
+    /// assert_eq!(config.backend(), BackendKind::Atomic);
+    /// ```
+    pub fn new(backend: BackendKind, width: usize) -> Self {
+        CounterConfig { backend, width }
+    }
+
+    /// Read a configuration from the environment variables
+    /// `COUNTING_NETWORKS_BACKEND` and `COUNTING_NETWORKS_WIDTH`. Settings
+    /// whose variables are not set keep their defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is not unicode, or is not a valid value
+    /// for its setting.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BackendKind, CounterConfig};
+    /// use std::env;
+    ///
+    /// env::set_var("COUNTING_NETWORKS_BACKEND", "atomic");
+    /// let config = CounterConfig::from_env().unwrap();
+    ///
+    /// assert_eq!(config.backend(), BackendKind::Atomic);
+    /// ```
+    pub fn from_env() -> Result<Self, ConfigError> {
+        CounterConfig::from_env_prefixed("COUNTING_NETWORKS")
+    }
+
+    /// Read a configuration from the environment variables `{prefix}_BACKEND`
+    /// and `{prefix}_WIDTH`, so that separate counters can be configured
+    /// separately. Settings whose variables are not set keep their defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a variable is not unicode, or is not a valid value
+    /// for its setting.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterConfig;
+    /// use std::env;
+    ///
+    /// env::set_var("REQUEST_IDS_WIDTH", "32");
+    /// let config = CounterConfig::from_env_prefixed("REQUEST_IDS").unwrap();
+    ///
+    /// assert_eq!(config.width(), 32);
+    /// ```
+    pub fn from_env_prefixed(prefix: &str) -> Result<Self, ConfigError> {
+        let mut not_unicode = None;
+        let config = CounterConfig::from_lookup(|key| {
+            let name = format!("{}_{}", prefix, key.to_uppercase());
+
+            match env::var(&name) {
+                Ok(value) => Some(value),
+                Err(env::VarError::NotPresent) => None,
+                Err(env::VarError::NotUnicode(_)) => {
+                    not_unicode.get_or_insert(ConfigError::NotUnicode { name });
+                    None
+                }
+            }
+        })?;
+
+        match not_unicode {
+            Some(error) => Err(error),
+            None => Ok(config),
+        }
+    }
+
+    /// Read a configuration by looking up the value of each setting from its
+    /// key. Settings that `lookup` returns `None` for keep their defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a value is not valid for its setting.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{ConfigError, CounterConfig};
+    ///
+    /// let error = CounterConfig::from_lookup(|key| match key {
+    ///     "width" => Some("6".to_string()),
+    ///     _ => None,
+    /// })
+    /// .unwrap_err();
+    ///
+    /// assert_eq!(error.to_string(), "invalid counter width \"6\", expected a power of two");
+    /// ```
+    pub fn from_lookup<F>(mut lookup: F) -> Result<Self, ConfigError>
+    where
+        F: FnMut(&str) -> Option<String>,
+    {
+        let mut config = CounterConfig::default();
+
+        if let Some(value) = lookup("backend") {
+            config.backend = value.trim().parse().map_err(ConfigError::InvalidBackend)?;
+        }
+        if let Some(value) = lookup("width") {
+            config.width = match value.trim().parse::<usize>() {
+                Ok(width) if width.is_power_of_two() => width,
+                _ => return Err(ConfigError::InvalidWidth { value }),
+            };
+        }
+
+        Ok(config)
+    }
+
+    /// Returns the kind of counter to build.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BackendKind, CounterConfig};
+    ///
+    /// assert_eq!(CounterConfig::default().backend(), BackendKind::Bitonic);
+    /// ```
+    pub fn backend(&self) -> BackendKind {
+        self.backend
+    }
+
+    /// Returns the width of the counting network to build.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::CounterConfig;
+    ///
+    /// assert_eq!(CounterConfig::default().width(), 8);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Build a counter with this configuration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the backend uses a counting network and the width given to
+    /// [`new`](CounterConfig::new) is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::counters::{BackendKind, Counter, CounterConfig};
+    ///
+    /// let counter = CounterConfig::new(BackendKind::Bitonic, 4).build();
+    ///
+    /// assert_eq!(counter.width(), 4);
+    /// assert_eq!(counter.next(), 0);
+    /// ```
+    pub fn build(&self) -> DynCounter {
+        DynCounter::new(self.backend, self.width)
+    }
+}
+
+impl Default for CounterConfig {
+    fn default() -> Self {
+        CounterConfig::new(BackendKind::Bitonic, 8)
+    }
+}
+
+/// Configurations are deserialized from a map with the same keys and values
+/// that [`from_lookup`](CounterConfig::from_lookup) reads, with the width as a
+/// number. Settings that are left out keep their defaults.
+///
+/// Only available with the `serde` feature.
+///
+/// # Examples
+///
+/// A configuration for padded buckets and a width of 16 is written like this
+/// in JSON:
+///
+/// ```text
+/// {"backend":"padded-bitonic","width":16}
+/// ```
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for CounterConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "CounterConfig", deny_unknown_fields)]
+        struct CounterConfigData {
+            backend: Option<String>,
+            width: Option<usize>,
+        }
+
+        let mut data = CounterConfigData::deserialize(deserializer)?;
+        CounterConfig::from_lookup(|key| match key {
+            "backend" => data.backend.take(),
+            "width" => data.width.map(|width| width.to_string()),
+            _ => None,
+        })
+        .map_err(de::Error::custom)
+    }
+}
+
+/// An error from reading a [`CounterConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The backend is not the name of a [`BackendKind`].
+    InvalidBackend(ParseBackendKindError),
+    /// The width is not a power of two.
+    InvalidWidth {
+        /// The value given for the width.
+        value: String,
+    },
+    /// An environment variable is set, but is not unicode.
+    NotUnicode {
+        /// The name of the variable.
+        name: String,
+    },
+    /// The backend can't be used for the counter being built.
+    UnsupportedBackend(BackendKind),
+    /// The memory for a counter of the configured width can't be allocated.
+    Alloc(AllocError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidBackend(error) => write!(f, "{}", error),
+            ConfigError::InvalidWidth { value } => write!(
+                f,
+                "invalid counter width {:?}, expected a power of two",
+                value
+            ),
+            ConfigError::NotUnicode { name } => {
+                write!(f, "environment variable {} is not unicode", name)
+            }
+            ConfigError::UnsupportedBackend(kind) => {
+                write!(f, "counter backend {} is not supported here", kind)
+            }
+            ConfigError::Alloc(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn create_counter() {
+        const WIDTH: usize = 16;
+        let counter = BitonicCountingNetwork::new(WIDTH);
+
+        assert_eq!(counter.width(), WIDTH);
+    }
+
+    struct Unknown;
+
+    impl Counter for Unknown {
+        fn next(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn default_capabilities() {
+        assert_eq!(Counter::width(&Unknown), 1);
+        assert_eq!(Unknown.progress_guarantee(), ProgressGuarantee::Blocking);
+        assert!(!Unknown.is_lock_free());
+    }
+
+    #[test]
+    fn capabilities_through_trait_object() {
+        let counter: Box<dyn Counter> = Box::new(BitonicCountingNetwork::new(16));
+
+        assert_eq!(counter.width(), 16);
+        assert_eq!(counter.progress_guarantee(), ProgressGuarantee::WaitFree);
+        assert!(counter.is_lock_free());
+    }
+
+    fn sync_only<T: Sync>(_: T) {}
+    fn send_only<T: Send>(_: T) {}
+
+    #[test]
+    fn is_send() {
+        send_only(BitonicCountingNetwork::new(4));
+    }
+
+    #[test]
+    fn is_sync() {
+        sync_only(BitonicCountingNetwork::new(4));
+    }
+
+    #[test]
+    fn builder_start_and_stride() {
+        for &padded_buckets in &[false, true] {
+            let counter = BitonicCountingNetwork::builder()
+                .width(4)
+                .start(100)
+                .stride(3)
+                .padded_buckets(padded_buckets)
+                .build();
+
+            let values: Vec<_> = (0..10).map(|_| counter.next()).collect();
+            assert_eq!(values, (0..10).map(|i| 100 + 3 * i).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn builder_wraps_around() {
+        let counter = BitonicCountingNetwork::builder()
+            .width(2)
+            .start(usize::MAX - 1)
+            .build();
+
+        let values: Vec<_> = (0..4).map(|_| counter.next()).collect();
+        assert_eq!(values, vec![usize::MAX - 1, usize::MAX, 0, 1]);
+    }
+
+    #[test]
+    fn custom_bucket_type() {
+        let counter = BitonicCountingNetwork::builder()
+            .width(4)
+            .stride(2)
+            .build_with_buckets::<PaddedBucket<AtomicUsize>>();
+
+        let values: Vec<_> = (0..6).map(|_| counter.next()).collect();
+        assert_eq!(values, vec![0, 2, 4, 6, 8, 10]);
+        assert_eq!(
+            counter.memory_usage().total(),
+            BitonicCountingNetwork::new(4).memory_usage().total()
+                + 4 * (64 - mem::size_of::<usize>())
+        );
+    }
+
+    #[test]
+    fn lazy_buckets_match_eager_buckets() {
+        let eager = BitonicCountingNetwork::builder().width(8).start(5).build();
+        let lazy = BitonicCountingNetwork::builder()
+            .width(8)
+            .start(5)
+            .build_with_buckets::<LazyBucket<PaddedBucket<AtomicUsize>>>();
+        lazy.prefault();
+
+        for _ in 0..50 {
+            assert_eq!(eager.next(), lazy.next());
+        }
+    }
+
+    #[test]
+    fn lazy_buckets_count_in_memory_usage() {
+        let lazy = BitonicCountingNetwork::builder()
+            .width(8)
+            .build_with_buckets::<LazyBucket<PaddedBucket<AtomicUsize>>>();
+        let unused = lazy.memory_usage();
+
+        for _ in 0..8 {
+            lazy.next();
+        }
+
+        let used = lazy.memory_usage();
+        assert_eq!(
+            used.outputs(),
+            unused.outputs() + 8 * mem::size_of::<PaddedBucket<AtomicUsize>>()
+        );
+        assert_eq!(
+            used.total() - unused.total(),
+            used.outputs() - unused.outputs()
+        );
+    }
+
+    #[test]
+    fn lazy_buckets_save_little_next_to_balancers() {
+        let eager = BitonicCountingNetwork::builder()
+            .width(1024)
+            .padded_buckets(true)
+            .build();
+        let lazy = BitonicCountingNetwork::builder()
+            .width(1024)
+            .build_with_buckets::<LazyBucket<PaddedBucket<AtomicUsize>>>();
+
+        let saved = eager.memory_usage().total() - lazy.memory_usage().total();
+        assert!(saved * 50 < eager.memory_usage().total());
+    }
+
+    #[test]
+    fn lazy_bucket_initialized_once() {
+        let bucket = Arc::new(LazyBucket::<AtomicUsize>::with_value(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let bucket = Arc::clone(&bucket);
+                thread::spawn(move || (0..100).map(|_| bucket.get_and_inc(1)).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut values: Vec<_> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, (0..800).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn high_priority_wires_split_entry_balancers() {
+        assert!(!shares_entry_balancer(8, 2));
+        assert!(!shares_entry_balancer(8, 4));
+        assert!(!shares_entry_balancer(8, 6));
+        assert!(shares_entry_balancer(8, 1));
+        assert!(shares_entry_balancer(8, 3));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't reserve 3 high priority wires")]
+    fn high_priority_wires_must_split_network() {
+        let _ = BitonicCountingNetwork::builder()
+            .width(8)
+            .high_priority_wires(3)
+            .build();
+    }
+
+    #[test]
+    #[should_panic(expected = "can't reserve 8 high priority wires")]
+    fn high_priority_wires_leave_normal_lane() {
+        let _ = BitonicCountingNetwork::builder()
+            .width(8)
+            .high_priority_wires(8)
+            .build();
+    }
+
+    #[test]
+    fn group_snapshots_are_consistent() {
+        let group = Arc::new(CounterGroup::new(8, 2));
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let group = Arc::clone(&group);
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        group.next(0);
+                        group.next(1);
+                    }
+                })
+            })
+            .collect();
+
+        for _ in 0..100 {
+            let counts = group.snapshot();
+            assert!(counts[1] <= counts[0], "{:?}", counts);
+            assert!(counts[0] - counts[1] <= 4, "{:?}", counts);
+        }
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(group.snapshot(), vec![2000, 2000]);
+    }
+
+    #[test]
+    #[should_panic(expected = "counter id 2 out of range for 2 counters")]
+    fn group_counter_out_of_range() {
+        CounterGroup::new(4, 2).next(2);
+    }
+
+    #[test]
+    fn dyn_counter_kinds() {
+        for &kind in BackendKind::ALL.iter() {
+            let counter = DynCounter::new(kind, 4);
+
+            assert_eq!(counter.kind(), kind);
+            assert_eq!(kind.to_string().parse::<BackendKind>(), Ok(kind));
+            let values: Vec<_> = (0..10).map(|_| counter.next()).collect();
+            assert_eq!(values, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn diagnosed_counter_sees_many_threads() {
+        let counter = Arc::new(DiagnosedCounter::new(BitonicCountingNetwork::new(2)));
+        // Enough calls for about one and a half windows of samples
+        let calls_per_thread = DIAGNOSTIC_SAMPLE_INTERVAL * DIAGNOSTIC_WINDOW * 3 / 32;
+
+        // Threads run one after another, so each takes a similar share of the
+        // samples
+        for _ in 0..16 {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || {
+                for _ in 0..calls_per_thread {
+                    counter.next();
+                }
+            })
+            .join()
+            .unwrap();
+        }
+
+        let advisories = counter.advisories();
+        assert!(
+            matches!(
+                advisories[..],
+                [Advisory::TooManyThreads { threads, width: 2 }, ..] if threads > 8
+            ),
+            "{:?}",
+            advisories
+        );
+        assert_eq!(counter.next(), 16 * calls_per_thread);
+    }
+
+    #[test]
+    fn diagnosed_counter_raises_each_advisory_once() {
+        let wide = DiagnosedCounter::new(BitonicCountingNetwork::new(8));
+        let narrow = DiagnosedCounter::new(BitonicCountingNetwork::new(1));
+
+        for _ in 0..(4 * DIAGNOSTIC_SAMPLE_INTERVAL * DIAGNOSTIC_WINDOW) {
+            wide.next();
+            narrow.next();
+        }
+
+        assert_eq!(wide.advisories().len(), 1);
+        // A counter of width 1 only has a single entry wire
+        assert!(narrow.advisories().is_empty());
+        assert_eq!(wide.progress_guarantee(), ProgressGuarantee::LockFree);
+    }
+
+    #[test]
+    fn diagnosed_counter_asks_for_the_entry_wire() {
+        let counter = BitonicCountingNetwork::builder()
+            .width(8)
+            .high_priority_wires(4)
+            .build();
+        let normal = DiagnosedCounter::new(&counter);
+        let high = DiagnosedCounter::new(counter.lane(Priority::High));
+
+        for _ in 0..(4 * DIAGNOSTIC_SAMPLE_INTERVAL * DIAGNOSTIC_WINDOW) {
+            normal.next();
+            high.next();
+        }
+
+        assert_eq!(
+            normal.advisories(),
+            [Advisory::SingleEntryWire {
+                wire: counter.wire_for_current_thread(),
+                width: 8
+            }]
+        );
+        assert!(matches!(
+            high.advisories()[..],
+            [Advisory::SingleEntryWire { wire, width: 8 }] if wire < 4
+        ));
+    }
+
+    #[test]
+    fn import_state_continues_counting() {
+        for &padded_buckets in &[false, true] {
+            let counter = BitonicCountingNetwork::builder()
+                .width(8)
+                .start(3)
+                .stride(2)
+                .build();
+            let mut restored = BitonicCountingNetwork::builder()
+                .width(8)
+                .stride(2)
+                .padded_buckets(padded_buckets)
+                .build();
+
+            for _ in 0..13 {
+                counter.next();
+            }
+            restored.import_state(&counter.export_state()).unwrap();
+
+            assert_eq!(restored.export_state(), counter.export_state());
+            for _ in 0..40 {
+                assert_eq!(restored.next(), counter.next());
+            }
+        }
+    }
+
+    #[test]
+    fn import_state_rejects_other_counters() {
+        let mut counter = BitonicCountingNetwork::new(4);
+        let state = counter.export_state();
+        let stride = BitonicCountingNetwork::builder()
+            .width(4)
+            .stride(3)
+            .build()
+            .export_state();
+        let mut version = state.clone();
+        version[0] = 2;
+        let mut value = state.clone();
+        value[17..25].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        assert_eq!(
+            counter.import_state(&[]),
+            Err(ImportStateError::InvalidLength {
+                expected: state.len(),
+                actual: 0
+            })
+        );
+        assert_eq!(
+            counter.import_state(&version),
+            Err(ImportStateError::UnknownVersion(2))
+        );
+        assert_eq!(
+            counter.import_state(&state[..state.len() - 1]),
+            Err(ImportStateError::InvalidLength {
+                expected: state.len(),
+                actual: state.len() - 1
+            })
+        );
+        assert_eq!(
+            counter.import_state(&stride),
+            Err(ImportStateError::IncrementMismatch {
+                expected: 4,
+                actual: 12
+            })
+        );
+        if mem::size_of::<usize>() < 8 {
+            assert_eq!(
+                counter.import_state(&value),
+                Err(ImportStateError::ValueOutOfRange {
+                    wire: 0,
+                    value: u64::MAX
+                })
+            );
+        }
+        assert_eq!(counter.next(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn pool_workers_enter_on_distinct_wires() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(5)
+            .build()
+            .unwrap();
+        let counter = PoolCounter::new(&pool);
+
+        let mut wires: Vec<_> = counter.workers.values().copied().collect();
+        wires.sort_unstable();
+        assert_eq!(counter.width(), 8);
+        assert_eq!(wires, (0..5).collect::<Vec<_>>());
+
+        let mut values: Vec<_> = pool
+            .broadcast(|_| (0..100).map(|_| counter.next()).collect::<Vec<_>>())
+            .into_iter()
+            .flatten()
+            .collect();
+        values.push(counter.next());
+        values.sort_unstable();
+        assert_eq!(values, (0..501).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn pool_wires_cached_per_counter() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let other_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let first = PoolCounter::new(&pool);
+        let second = PoolCounter::new(&pool);
+        let other = PoolCounter::new(&other_pool);
+
+        // Alternate between the counters on each worker, so that every call
+        // misses the wire cached for the previous counter.
+        let results = pool.broadcast(|context| {
+            let hashed_wire = other.get_ref().wire_for_current_thread();
+            (0..10).all(|_| {
+                first.wire_for_current_thread() == context.index()
+                    && second.wire_for_current_thread() == context.index()
+                    && other.wire_for_current_thread() == hashed_wire
+            })
+        });
+
+        assert_eq!(results, vec![true, true]);
+    }
+
+    #[test]
+    fn config_from_lookup() {
+        let settings: HashMap<&str, &str> = [("backend", " atomic "), ("width", "4")]
+            .iter()
+            .copied()
+            .collect();
+        let config =
+            CounterConfig::from_lookup(|key| settings.get(key).map(|value| value.to_string()))
+                .unwrap();
+
+        assert_eq!(config, CounterConfig::new(BackendKind::Atomic, 4));
+        assert_eq!(
+            CounterConfig::from_lookup(|_| None).unwrap(),
+            CounterConfig::default()
+        );
+    }
+
+    #[test]
+    fn config_invalid_values() {
+        let invalid_backend = CounterConfig::from_lookup(|key| match key {
+            "backend" => Some("mutex".to_string()),
+            _ => None,
+        });
+        assert_eq!(
+            invalid_backend,
+            Err(ConfigError::InvalidBackend(ParseBackendKindError(
+                "mutex".to_string()
+            )))
+        );
+
+        for &width in &["0", "12", "-4", "wide"] {
+            let invalid_width = CounterConfig::from_lookup(|key| match key {
+                "width" => Some(width.to_string()),
+                _ => None,
+            });
+            assert_eq!(
+                invalid_width,
+                Err(ConfigError::InvalidWidth {
+                    value: width.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn config_from_env_prefixed() {
+        env::set_var("CONFIG_FROM_ENV_TEST_BACKEND", "padded-bitonic");
+        env::set_var("CONFIG_FROM_ENV_TEST_WIDTH", "2");
+        let counter = CounterConfig::from_env_prefixed("CONFIG_FROM_ENV_TEST")
+            .unwrap()
+            .build();
+
+        assert_eq!(counter.kind(), BackendKind::PaddedBitonic);
+        assert_eq!(counter.width(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn config_too_wide_to_allocate() {
+        // The buckets would take half of the address space
+        let config = CounterConfig::new(BackendKind::Bitonic, Width::MAX.get() >> 4);
+        assert!(matches!(
+            BitonicCountingNetwork::from_config(&config),
+            Err(ConfigError::Alloc(error)) if error.requested_bytes().is_some()
+        ));
+
+        let config = CounterConfig::new(BackendKind::PaddedBitonic, Width::MAX.get());
+        assert!(matches!(
+            BitonicCountingNetwork::from_config(&config),
+            Err(ConfigError::Alloc(error)) if error.requested_bytes().is_none()
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn config_deserialize() {
+        use crate::util::value::{from_value, Value};
+
+        let config: CounterConfig = from_value(Value::map(vec![
+            ("backend", Value::str("padded-bitonic")),
+            ("width", Value::Unsigned(16)),
+        ]))
+        .unwrap();
+        assert_eq!(config, CounterConfig::new(BackendKind::PaddedBitonic, 16));
+        assert_eq!(
+            from_value::<CounterConfig>(Value::map(vec![])).unwrap(),
+            CounterConfig::default()
+        );
+
+        let invalid_width =
+            from_value::<CounterConfig>(Value::map(vec![("width", Value::Unsigned(12))]));
+        assert_eq!(
+            invalid_width.unwrap_err().to_string(),
+            "invalid counter width \"12\", expected a power of two"
+        );
+        let unknown_setting =
+            from_value::<CounterConfig>(Value::map(vec![("threads", Value::Unsigned(4))]));
+        assert!(unknown_setting.is_err());
+    }
+
+    #[test]
+    fn wire_for_current_thread_is_used_by_next() {
+        let counter = BitonicCountingNetwork::builder()
+            .width(8)
+            .high_priority_wires(2)
+            .build();
+        let wire = counter.wire_for_current_thread();
+
+        counter.next();
+
+        // The token toggled the first balancer on its entry wire
+        let first_balancer = counter
+            .as_network()
+            .topology()
+            .balancers()
+            .iter()
+            .position(|&(top_wire, bottom_wire)| top_wire == wire || bottom_wire == wire)
+            .unwrap();
+        assert!((2..8).contains(&wire));
+        let toggles = match counter.as_network() {
+            CounterNetwork::Unpadded(network) => network.toggles(),
+            CounterNetwork::Padded(network) => network.toggles(),
+        };
+        assert!(!toggles[first_balancer]);
+    }
+
+    #[test]
+    fn lanes_share_values() {
+        let counter = Arc::new(
+            BitonicCountingNetwork::builder()
+                .width(8)
+                .high_priority_wires(4)
+                .build(),
+        );
+
+        let handles: Vec<_> = [Priority::High, Priority::Normal, Priority::High]
+            .iter()
+            .map(|&priority| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    let lane = counter.lane(priority);
+                    (0..200).map(|_| lane.next()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut values: Vec<_> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, (0..600).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn instrumented_counter_records_every_call() {
+        let counter = Arc::new(InstrumentedCounter::new(BitonicCountingNetwork::new(8)));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || (0..250).map(|_| counter.next()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut values: Vec<_> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+        assert_eq!(counter.len(), 1000);
+        assert_eq!(counter.histogram().len(), 1000);
+        assert_eq!(
+            counter.max(),
+            Duration::from_nanos(counter.histogram().max())
+        );
+
+        counter.reset();
+        assert!(counter.is_empty());
+        assert_eq!(counter.histogram().len(), 0);
+    }
+
+    #[test]
+    fn try_build_matches_build() {
+        for &padded_buckets in &[false, true] {
+            let options = CounterOptions::new()
+                .width(8)
+                .start(3)
+                .padded_buckets(padded_buckets);
+            let counter = options.clone().try_build().unwrap();
+            let expected = options.build();
+
+            for _ in 0..20 {
+                assert_eq!(counter.next(), expected.next());
+            }
+            assert_eq!(counter.memory_usage(), expected.memory_usage());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn builder_zero_stride() {
+        let _ = BitonicCountingNetwork::builder().stride(0).build();
+    }
+
+    struct Replay(std::sync::Mutex<std::vec::IntoIter<usize>>);
+
+    impl Counter for Replay {
+        fn next(&self) -> usize {
+            self.0.lock().unwrap().next().unwrap()
+        }
+    }
+
+    #[test]
+    fn monotonic_handle_skips_smaller_values() {
+        let counter = Replay(std::sync::Mutex::new(vec![3, 1, 2, 5, 4, 6].into_iter()));
+        let mut handle = MonotonicHandle::new(&counter);
+
+        assert_eq!(handle.next(), 3);
+        assert_eq!(handle.next(), 5);
+        assert_eq!(handle.skipped(), 2);
+        assert_eq!(handle.next(), 6);
+        assert_eq!(handle.skipped(), 3);
+        assert_eq!(handle.last(), Some(6));
+    }
+
+    #[test]
+    fn concurrent_monotonic_handles() {
+        const NUM_THREADS: usize = 8;
+        const NUM_COUNTS: usize = 100;
+
+        let counter: Arc<dyn Counter + Send + Sync> = Arc::new(BitonicCountingNetwork::new(8));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    let mut handle = MonotonicHandle::new(&*counter);
+                    (0..NUM_COUNTS).map(|_| handle.next()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut all_values = Vec::new();
+        for handle in thread_handles {
+            let values = handle.join().unwrap();
+            assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+            all_values.extend(values);
+        }
+
+        let num_values = all_values.len();
+        all_values.sort();
+        all_values.dedup();
+        assert_eq!(all_values.len(), num_values);
+    }
+
+    #[test]
+    fn lazy_counter_is_send_and_sync() {
+        send_only(LazyCounter::new(4));
+        sync_only(LazyCounter::new(4));
+    }
+
+    #[test]
+    fn lazy_counter_builds_on_first_use() {
+        let counter = LazyCounter::new(4);
+
+        assert_eq!(Counter::width(&counter), 4);
+        assert!(counter.counter.get().is_none());
+
+        assert_eq!(counter.next(), 0);
+        assert!(counter.counter.get().is_some());
+    }
+
+    #[test]
+    fn concurrent_lazy_counting() {
+        const NUM_THREADS: usize = 8;
+        const NUM_COUNTS: usize = 50;
+
+        static COUNTER: LazyCounter = LazyCounter::new(8);
+
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| thread::spawn(|| (0..NUM_COUNTS).map(|_| COUNTER.next()).collect::<Vec<_>>()))
+            .collect();
+
+        let mut results: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        results.sort();
+        assert_eq!(results, (0..(NUM_THREADS * NUM_COUNTS)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn registry_is_send_and_sync() {
+        send_only(Registry::new(4));
+        sync_only(Registry::new(4));
+    }
+
+    #[test]
+    fn global_registry_is_shared() {
+        let registry = Registry::global();
+
+        assert!(ptr::eq(registry, Registry::global()));
+        assert!(ptr::eq(
+            registry.counter("tests.global"),
+            Registry::global().counter("tests.global")
+        ));
+    }
+
+    #[test]
+    fn concurrent_registry_counting() {
+        const NUM_THREADS: usize = 8;
+        const NUM_COUNTS: usize = 50;
+        const NAMES: [&str; 3] = ["a", "b", "c"];
+
+        let registry = Arc::new(Registry::new(4));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|thread_idx| {
+                let registry = Arc::clone(&registry);
+                thread::spawn(move || {
+                    (0..NUM_COUNTS)
+                        .map(|count| {
+                            let name = NAMES[(thread_idx + count) % NAMES.len()];
+                            (name, registry.counter(name).next())
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut values: HashMap<&str, Vec<usize>> = HashMap::new();
+        for handle in thread_handles {
+            for (name, value) in handle.join().unwrap() {
+                values.entry(name).or_default().push(value);
+            }
+        }
+
+        assert_eq!(registry.names(), NAMES.to_vec());
+        for (_, mut counter_values) in values {
+            counter_values.sort();
+            assert_eq!(
+                counter_values,
+                (0..counter_values.len()).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn swappable_counter_is_send_and_sync() {
+        send_only(SwappableCounter::new(CounterOptions::new()));
+        sync_only(SwappableCounter::new(CounterOptions::new()));
+    }
+
+    #[test]
+    fn swap_restarts_new_network() {
+        let counter = SwappableCounter::new(CounterOptions::new().width(2).start(3));
+        assert_eq!(counter.next(), 3);
+
+        counter.swap(
+            CounterOptions::new()
+                .width(8)
+                .stride(2)
+                .padded_buckets(true),
+        );
+
+        assert_eq!(counter.width(), 8);
+        let mut values: Vec<_> = (0..8).map(|_| counter.next()).collect();
+        values.sort_unstable();
+        assert_eq!(values, (4..20).step_by(2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn concurrent_counting_during_swaps() {
+        const NUM_THREADS: usize = 6;
+        const NUM_COUNTS: usize = 200;
+        const NUM_SWAPS: usize = 20;
+
+        let counter = Arc::new(SwappableCounter::new(CounterOptions::new().width(4)));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || (0..NUM_COUNTS).map(|_| counter.next()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let swapper = {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || {
+                for swap in 0..NUM_SWAPS {
+                    counter.swap(CounterOptions::new().width(1 << (swap % 5)));
+                    thread::yield_now();
+                }
+            })
+        };
+
+        let mut results: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        swapper.join().unwrap();
+
+        results.sort();
+        assert_eq!(results, (0..(NUM_THREADS * NUM_COUNTS)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn countdown_in_decreasing_order() {
+        let countdown = Countdown::new(20, 8);
+
+        let values: Vec<_> = (0..25).map(|_| countdown.next()).collect();
+        let expected: Vec<_> = (0..20).rev().map(Some).chain(vec![None; 5]).collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn empty_countdown() {
+        let countdown = Countdown::new(0, 4);
+
+        assert_eq!(countdown.next(), None);
+        assert_eq!(countdown.next(), None);
+    }
+
+    #[test]
+    fn concurrent_countdown() {
+        const NUM_THREADS: usize = 8;
+        const TOTAL: usize = 1000;
+
+        let countdown = Arc::new(Countdown::new(TOTAL, 8));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let countdown = Arc::clone(&countdown);
+                thread::spawn(move || {
+                    let mut values = Vec::new();
+                    while let Some(value) = countdown.next() {
+                        values.push(value);
+                    }
+                    values
+                })
+            })
+            .collect();
+
+        let mut results: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        results.sort();
+        assert_eq!(results, (0..TOTAL).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn counters_behind_pointers() {
+        fn take_two<C: Counter>(counter: C) -> (usize, usize) {
+            (counter.next(), counter.next())
+        }
+
+        let shared = Arc::new(BitonicCountingNetwork::new(4));
+        let boxed: Box<dyn Counter> = Box::new(BitonicCountingNetwork::new(4));
+
+        assert_eq!(take_two(&*shared), (0, 1));
+        assert_eq!(take_two(Arc::clone(&shared)), (2, 3));
+        assert_eq!(take_two(&boxed), (0, 1));
+        assert_eq!(boxed.width(), 4);
+        assert_eq!(take_two(boxed), (2, 3));
+    }
+
+    #[test]
+    fn combined_adapters() {
+        let counter = BitonicCountingNetwork::new(8);
+        let adapted = (&counter)
+            .filtered(|value| value % 3 != 0)
+            .step_by(10)
+            .offset(5)
+            .map(|value| value + 1);
+
+        let values: Vec<_> = (0..4).map(|_| adapted.next()).collect();
+        assert_eq!(values, vec![16, 26, 46, 56]);
+        assert_eq!(adapted.width(), 8);
+        assert_eq!(adapted.progress_guarantee(), ProgressGuarantee::Blocking);
+        assert_eq!(counter.next(), 6);
+    }
+
+    #[test]
+    fn adapters_keep_progress_guarantee() {
+        let counter = Arc::new(BitonicCountingNetwork::new(4));
+
+        assert_eq!(
+            Arc::clone(&counter)
+                .offset(1)
+                .step_by(2)
+                .progress_guarantee(),
+            ProgressGuarantee::WaitFree
+        );
+        assert!(counter.map(|value| value).is_lock_free());
+    }
+
+    #[test]
+    fn concurrent_adapted_counting() {
+        const NUM_THREADS: usize = 4;
+        const NUM_COUNTS: usize = 100;
+
+        let counter = Arc::new(BitonicCountingNetwork::new(8).step_by(3).offset(1));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || (0..NUM_COUNTS).map(|_| counter.next()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut results: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        results.sort();
+        assert_eq!(
+            results,
+            (0..(NUM_THREADS * NUM_COUNTS))
+                .map(|value| value * 3 + 1)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn f64_adder_is_send_and_sync() {
+        send_only(F64Adder::new(4));
+        sync_only(F64Adder::new(4));
+    }
+
+    #[test]
+    #[should_panic]
+    fn f64_adder_width_not_power_of_two() {
+        let _ = F64Adder::new(6);
+    }
+
+    #[test]
+    fn uncontended_f64_adds_share_a_stripe() {
+        let adder = F64Adder::new(8);
+
+        for _ in 0..10 {
+            adder.add(1.0);
+        }
+
+        let used: Vec<_> = adder
+            .stripes
+            .iter()
+            .map(|PaddedBucket(stripe)| f64::from_bits(stripe.load(Ordering::Relaxed)))
+            .filter(|&sum| sum != 0.0)
+            .collect();
+        assert_eq!(used, vec![10.0]);
+    }
+
+    #[test]
+    fn concurrent_f64_adds() {
+        const NUM_THREADS: usize = 8;
+        const NUM_ADDS: usize = 1000;
+
+        let adder = Arc::new(F64Adder::new(8));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let adder = Arc::clone(&adder);
+                thread::spawn(move || {
+                    for _ in 0..NUM_ADDS {
+                        // Exactly representable, so the order of the adds
+                        // doesn't change the sum
+                        adder.add(0.25);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(adder.sum(), (NUM_THREADS * NUM_ADDS) as f64 * 0.25);
+    }
+
+    #[test]
+    fn network_of_counter() {
+        for &padded in &[false, true] {
+            let counter = BitonicCountingNetwork::builder()
+                .width(8)
+                .padded_buckets(padded)
+                .build();
+            let network = counter.as_network();
+
+            assert_eq!(padded, matches!(network, CounterNetwork::Padded(_)));
+            assert_eq!(
+                network.topology(),
+                BitonicNetwork::new(vec![(); 8]).topology()
+            );
+            assert_eq!(network.verify_integrity(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn named_counters() {
+        let counter = BitonicCountingNetwork::builder()
+            .width(4)
+            .padded_buckets(true)
+            .name(String::from("jobs"))
+            .build();
+
+        assert_eq!(counter.name(), Some("jobs"));
+        assert_eq!(
+            format!("{:?}", counter),
+            "BitonicCountingNetwork { name: Some(\"jobs\"), width: 4, padded_buckets: true }"
+        );
+        assert_eq!(Registry::new(2).counter("rpc.ids").name(), Some("rpc.ids"));
+    }
+
+    #[test]
+    fn relaxed_counter_is_send_and_sync() {
+        send_only(RelaxedCounter::new(Width::of(4), 2));
+        sync_only(RelaxedCounter::new(Width::of(4), 2));
+    }
+
+    #[test]
+    fn concurrent_relaxed_counting() {
+        const WIDTH: usize = 16;
+        const NUM_THREADS: usize = 8;
+        const NUM_COUNTS: usize = 500;
+
+        let counter = Arc::new(RelaxedCounter::new(Width::of(WIDTH), 4));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || (0..NUM_COUNTS).map(|_| counter.next()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut results: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        results.sort();
+        results.dedup();
+
+        let total = NUM_THREADS * NUM_COUNTS;
+        assert_eq!(results.len(), total);
+        assert!(*results.last().unwrap() < total + counter.max_skew() * WIDTH);
+    }
+
+    #[test]
+    fn multi_counter_is_send_and_sync() {
+        send_only(MultiCounter::new(4, 2));
+        sync_only(MultiCounter::new(4, 2));
+    }
+
+    #[test]
+    fn multi_counters_are_independent() {
+        let counters = MultiCounter::new(8, 3);
+
+        let mut values = vec![Vec::new(); 3];
+        for step in 0..30 {
+            // Uneven use of the counters, so their tokens interleave in the
+            // shared network.
+            let counter_id = [0, 0, 1, 2, 0, 1][step % 6];
+            values[counter_id].push(counters.next(counter_id));
+        }
+
+        for counter_values in values {
+            assert_eq!(
+                counter_values,
+                (0..counter_values.len()).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn multi_counter_id_out_of_range() {
+        let _ = MultiCounter::new(4, 2).next(2);
+    }
+
+    #[test]
+    fn concurrent_multi_counting() {
+        const WIDTH: usize = 8;
+        const NUM_COUNTERS: usize = 3;
+        const NUM_THREADS: usize = 6;
+        const NUM_COUNTS: usize = 20;
+
+        let counters = Arc::new(MultiCounter::new(WIDTH, NUM_COUNTERS));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|thread_idx| {
+                let counters = Arc::clone(&counters);
+                thread::spawn(move || {
+                    (0..NUM_COUNTS)
+                        .map(|count| {
+                            let counter_id = (thread_idx + count) % NUM_COUNTERS;
+                            (counter_id, counters.next(counter_id))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut values = vec![Vec::new(); NUM_COUNTERS];
+        for handle in thread_handles {
+            for (counter_id, value) in handle.join().unwrap() {
+                values[counter_id].push(value);
+            }
+        }
+
+        for mut counter_values in values {
+            counter_values.sort();
+            assert_eq!(
+                counter_values,
+                (0..counter_values.len()).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn sub_counter_is_send_and_sync() {
+        send_only(MultiCounter::new(4, 2).split().pop().unwrap());
+        sync_only(MultiCounter::new(4, 2).split().pop().unwrap());
+    }
+
+    #[test]
+    fn concurrent_split_counting() {
+        const PARTS: usize = 3;
+        const NUM_COUNTS: usize = 200;
+
+        let thread_handles: Vec<_> = MultiCounter::new(8, PARTS)
+            .split()
+            .into_iter()
+            .map(|part| {
+                thread::spawn(move || {
+                    (0..NUM_COUNTS)
+                        .map(|_| {
+                            let value = part.next();
+                            assert_eq!(value % part.parts(), part.index());
+                            value
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut results: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        results.sort();
+        assert_eq!(results, (0..(PARTS * NUM_COUNTS)).collect::<Vec<_>>());
+    }
+
+    #[test]
     fn concurrent_counting() {
         const WIDTH: usize = 8;
         const NUM_THREADS: usize = 8;
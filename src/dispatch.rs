@@ -0,0 +1,294 @@
+//! Distribution of work between threads using counting networks.
+
+use crate::counters::{BitonicCountingNetwork, Counter};
+use core::{fmt, ops::Range};
+
+#[derive(Debug)]
+enum Schedule {
+    // Every chunk has the same size, except possibly the last.
+    Dynamic { chunk_size: usize },
+    // Start of every chunk, followed by the total number of iterations.
+    Guided { boundaries: Box<[usize]> },
+}
+
+/// Hands out chunks of a loop's iteration space to threads as they ask for
+/// them, in the style of OpenMP's `dynamic` and `guided` schedules.
+///
+/// Each call to [`next_chunk`](ChunkScheduler::next_chunk) takes a chunk
+/// number from a counting network, so threads pulling work at the same time
+/// do not contend on a single counter. Every iteration is handed out exactly
+/// once, after which every call returns `None`.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::dispatch::ChunkScheduler;
+/// use std::{sync::Arc, thread};
+///
+/// let scheduler = Arc::new(ChunkScheduler::new(1000, 64, 4));
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let scheduler = Arc::clone(&scheduler);
+///         thread::spawn(move || {
+///             let mut sum = 0usize;
+///             while let Some(chunk) = scheduler.next_chunk() {
+///                 sum += chunk.sum::<usize>();
+///             }
+///             sum
+///         })
+///     })
+///     .collect();
+///
+/// let total: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+/// assert_eq!(total, (0..1000).sum::<usize>());
+/// ```
+pub struct ChunkScheduler {
+    counter: BitonicCountingNetwork,
+    total_iterations: usize,
+    schedule: Schedule,
+}
+
+impl ChunkScheduler {
+    /// Create a scheduler that splits `0..total_iterations` into chunks of
+    /// `chunk_size` iterations, using a counting network of the given width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero, or if `width` is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::dispatch::ChunkScheduler;
+    ///
+    /// let scheduler = ChunkScheduler::new(10, 4, 2);
+    ///
+    /// assert_eq!(scheduler.next_chunk(), Some(0..4));
+    /// assert_eq!(scheduler.next_chunk(), Some(4..8));
+    /// assert_eq!(scheduler.next_chunk(), Some(8..10));
+    /// assert_eq!(scheduler.next_chunk(), None);
+    /// ```
+    pub fn new(total_iterations: usize, chunk_size: usize, width: usize) -> Self {
+        assert!(chunk_size > 0);
+
+        ChunkScheduler {
+            counter: BitonicCountingNetwork::new(width),
+            total_iterations,
+            schedule: Schedule::Dynamic { chunk_size },
+        }
+    }
+
+    /// Create a scheduler that splits `0..total_iterations` into chunks that
+    /// shrink as the work runs out, using a counting network of the given
+    /// width.
+    ///
+    /// Each chunk is the number of remaining iterations divided by
+    /// `num_threads`, but no smaller than `min_chunk_size` (except for the
+    /// last chunk). Large chunks at the start keep the scheduling overhead
+    /// low, while small chunks at the end balance the load between threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_chunk_size` or `num_threads` is zero, or if `width` is
+    /// not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::dispatch::ChunkScheduler;
+    ///
+    /// let scheduler = ChunkScheduler::guided(100, 10, 2, 2);
+    ///
+    /// assert_eq!(scheduler.next_chunk(), Some(0..50));
+    /// assert_eq!(scheduler.next_chunk(), Some(50..75));
+    /// assert_eq!(scheduler.next_chunk(), Some(75..88));
+    /// assert_eq!(scheduler.next_chunk(), Some(88..98));
+    /// assert_eq!(scheduler.next_chunk(), Some(98..100));
+    /// assert_eq!(scheduler.next_chunk(), None);
+    /// ```
+    pub fn guided(
+        total_iterations: usize,
+        min_chunk_size: usize,
+        num_threads: usize,
+        width: usize,
+    ) -> Self {
+        assert!(min_chunk_size > 0);
+        assert!(num_threads > 0);
+
+        let mut boundaries = vec![0];
+        let mut start = 0;
+        while start < total_iterations {
+            let remaining = total_iterations - start;
+            let chunk_size = div_round_up(remaining, num_threads)
+                .max(min_chunk_size)
+                .min(remaining);
+
+            start += chunk_size;
+            boundaries.push(start);
+        }
+
+        ChunkScheduler {
+            counter: BitonicCountingNetwork::new(width),
+            total_iterations,
+            schedule: Schedule::Guided {
+                boundaries: boundaries.into_boxed_slice(),
+            },
+        }
+    }
+
+    /// Returns the number of iterations being scheduled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::dispatch::ChunkScheduler;
+    ///
+    /// let scheduler = ChunkScheduler::new(10, 4, 2);
+    ///
+    /// assert_eq!(scheduler.total_iterations(), 10);
+    /// ```
+    pub fn total_iterations(&self) -> usize {
+        self.total_iterations
+    }
+
+    /// Returns the number of chunks that the iterations are split into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::dispatch::ChunkScheduler;
+    ///
+    /// assert_eq!(ChunkScheduler::new(10, 4, 2).num_chunks(), 3);
+    /// assert_eq!(ChunkScheduler::guided(100, 10, 2, 2).num_chunks(), 5);
+    /// ```
+    pub fn num_chunks(&self) -> usize {
+        match &self.schedule {
+            Schedule::Dynamic { chunk_size } => div_round_up(self.total_iterations, *chunk_size),
+            Schedule::Guided { boundaries } => boundaries.len() - 1,
+        }
+    }
+
+    /// Take the next chunk of iterations, or `None` if every iteration has
+    /// been handed out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::dispatch::ChunkScheduler;
+    ///
+    /// let scheduler = ChunkScheduler::new(3, 2, 1);
+    ///
+    /// assert_eq!(scheduler.next_chunk(), Some(0..2));
+    /// assert_eq!(scheduler.next_chunk(), Some(2..3));
+    /// assert_eq!(scheduler.next_chunk(), None);
+    /// ```
+    pub fn next_chunk(&self) -> Option<Range<usize>> {
+        let chunk = self.counter.next();
+
+        match &self.schedule {
+            Schedule::Dynamic { chunk_size } => {
+                let start = chunk.checked_mul(*chunk_size)?;
+                if start >= self.total_iterations {
+                    return None;
+                }
+
+                Some(start..start.saturating_add(*chunk_size).min(self.total_iterations))
+            }
+            Schedule::Guided { boundaries } => {
+                let start = *boundaries.get(chunk)?;
+                let end = *boundaries.get(chunk + 1)?;
+
+                Some(start..end)
+            }
+        }
+    }
+}
+
+fn div_round_up(numerator: usize, denominator: usize) -> usize {
+    match numerator % denominator {
+        0 => numerator / denominator,
+        _ => numerator / denominator + 1,
+    }
+}
+
+impl fmt::Debug for ChunkScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChunkScheduler")
+            .field("total_iterations", &self.total_iterations)
+            .field("schedule", &self.schedule)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    fn collect_chunks(scheduler: &ChunkScheduler) -> Vec<Range<usize>> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = scheduler.next_chunk() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[test]
+    fn empty_iteration_space() {
+        assert_eq!(ChunkScheduler::new(0, 4, 2).next_chunk(), None);
+        assert_eq!(ChunkScheduler::guided(0, 4, 2, 2).next_chunk(), None);
+        assert_eq!(ChunkScheduler::guided(0, 4, 2, 2).num_chunks(), 0);
+    }
+
+    #[test]
+    fn guided_chunks_shrink() {
+        let scheduler = ChunkScheduler::guided(1000, 7, 4, 4);
+        let chunks = collect_chunks(&scheduler);
+
+        assert_eq!(chunks.len(), scheduler.num_chunks());
+        assert!(chunks
+            .windows(2)
+            .all(|pair| pair[0].len() >= pair[1].len() && pair[0].end == pair[1].start));
+        assert!(chunks[..chunks.len() - 1]
+            .iter()
+            .all(|chunk| chunk.len() >= 7));
+        assert_eq!(chunks.last().unwrap().end, 1000);
+    }
+
+    #[test]
+    fn exhausted_scheduler_stays_exhausted() {
+        let scheduler = ChunkScheduler::new(4, 4, 2);
+
+        assert_eq!(scheduler.next_chunk(), Some(0..4));
+        for _ in 0..10 {
+            assert_eq!(scheduler.next_chunk(), None);
+        }
+    }
+
+    #[test]
+    fn concurrent_chunks_cover_iterations() {
+        const NUM_THREADS: usize = 4;
+
+        for scheduler in &[
+            Arc::new(ChunkScheduler::new(1003, 10, 4)),
+            Arc::new(ChunkScheduler::guided(1003, 10, NUM_THREADS, 4)),
+        ] {
+            let thread_handles: Vec<_> = (0..NUM_THREADS)
+                .map(|_| {
+                    let scheduler = Arc::clone(scheduler);
+                    thread::spawn(move || collect_chunks(&scheduler))
+                })
+                .collect();
+
+            let mut iterations: Vec<usize> = thread_handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .flatten()
+                .collect();
+            iterations.sort();
+
+            assert_eq!(iterations, (0..1003).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,1083 @@
+//! Generation of unique identifiers and timestamps using counting networks.
+
+use crate::{
+    counters::PaddedBucket,
+    networks::{BitonicNetwork, Width},
+};
+use core::{
+    cell::Cell,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{
+    error::Error,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SEQUENCE_BITS: u32 = 12;
+const NODE_BITS: u32 = 10;
+// The top bit is left clear, so that IDs are positive when stored as `i64`s.
+const TIMESTAMP_BITS: u32 = 63 - NODE_BITS - SEQUENCE_BITS;
+
+const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+const NODE_MASK: u64 = (1 << NODE_BITS) - 1;
+const TIMESTAMP_MASK: u64 = (1 << TIMESTAMP_BITS) - 1;
+
+/// The largest node number that fits in an ID.
+pub const MAX_NODE: u16 = NODE_MASK as u16;
+
+/// The largest counting network width that a [`SnowflakeGenerator`] can use.
+pub const MAX_WIDTH: usize = 1 << SEQUENCE_BITS;
+
+/// A 64-bit identifier made up of a timestamp, a node number and a sequence
+/// number.
+///
+/// From the most significant bit down, an ID holds a 41 bit timestamp in
+/// milliseconds, a 10 bit node number and a 12 bit sequence number. The top
+/// bit is always clear, so IDs are also positive as `i64`s. IDs from one
+/// generator are ordered by the time they were created, to the millisecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SnowflakeId(u64);
+
+impl SnowflakeId {
+    /// Returns the ID as an integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 1, 4);
+    /// let id = generator.next_id();
+    ///
+    /// assert_ne!(id.as_u64(), 0);
+    /// ```
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the number of milliseconds between the generator's epoch and
+    /// the creation of this ID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::{SystemTime, UNIX_EPOCH};
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 1, 4);
+    /// let id = generator.next_id();
+    ///
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    /// assert!(u128::from(id.timestamp()) <= now.as_millis());
+    /// ```
+    pub fn timestamp(self) -> u64 {
+        self.0 >> (NODE_BITS + SEQUENCE_BITS)
+    }
+
+    /// Returns the number of the node that created this ID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 7, 4);
+    ///
+    /// assert_eq!(generator.next_id().node(), 7);
+    /// ```
+    pub fn node(self) -> u16 {
+        ((self.0 >> SEQUENCE_BITS) & NODE_MASK) as u16
+    }
+
+    /// Returns the sequence number of this ID within its millisecond.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 1, 4);
+    ///
+    /// assert!(generator.next_id().sequence() < 4096);
+    /// ```
+    pub fn sequence(self) -> u16 {
+        (self.0 & SEQUENCE_MASK) as u16
+    }
+}
+
+impl From<SnowflakeId> for u64 {
+    fn from(id: SnowflakeId) -> u64 {
+        id.0
+    }
+}
+
+impl fmt::Display for SnowflakeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+// Each sequence packs the last millisecond it issued an ID in into the high
+// bits and the next sequence number to issue into the low bits. The next
+// sequence number gets an extra bit, so that it can run past the last one.
+const STATE_SEQUENCE_BITS: u32 = SEQUENCE_BITS + 4;
+const STATE_SEQUENCE_MASK: u64 = (1 << STATE_SEQUENCE_BITS) - 1;
+
+#[derive(Debug)]
+struct Sequence(AtomicU64);
+
+impl Sequence {
+    fn new(first: u64) -> Self {
+        Sequence(AtomicU64::new(first))
+    }
+
+    // Take the next sequence number for the millisecond `now`, or for the last
+    // millisecond this sequence issued an ID in if the clock has gone back.
+    // Returns `None` if all of the sequence numbers for that millisecond have
+    // been taken.
+    fn take(&self, now: u64, first: u64, step: u64) -> Option<(u64, u64)> {
+        let mut current = self.0.load(Ordering::Relaxed);
+        loop {
+            let last = current >> STATE_SEQUENCE_BITS;
+            let (timestamp, sequence) = if now > last {
+                (now, first)
+            } else {
+                (last, current & STATE_SEQUENCE_MASK)
+            };
+
+            if sequence > SEQUENCE_MASK {
+                return None;
+            }
+
+            let updated = (timestamp << STATE_SEQUENCE_BITS) | (sequence + step);
+            match self.0.compare_exchange_weak(
+                current,
+                updated,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some((timestamp, sequence)),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// A generator of [`SnowflakeId`]s.
+///
+/// The sequence numbers within each millisecond are drawn from a counting
+/// network. The output on wire `i` of a network of width `w` hands out the
+/// sequence numbers `i`, `i + w`, `i + 2w`, ..., so threads creating IDs at
+/// the same time rarely touch the same memory and never create the same ID.
+///
+/// When a wire runs out of sequence numbers for the current millisecond, the
+/// thread waits for the next one. If the system clock goes backwards, the
+/// generator keeps using the latest millisecond it has seen, and waits for the
+/// clock to catch up once it runs out of sequence numbers, so IDs are never
+/// repeated.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::ids::SnowflakeGenerator;
+/// use std::{collections::HashSet, sync::Arc, thread, time::UNIX_EPOCH};
+///
+/// let generator = Arc::new(SnowflakeGenerator::new(UNIX_EPOCH, 42, 4));
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let generator = Arc::clone(&generator);
+///         thread::spawn(move || (0..100).map(|_| generator.next_id()).collect::<Vec<_>>())
+///     })
+///     .collect();
+///
+/// let ids: HashSet<_> = handles
+///     .into_iter()
+///     .flat_map(|h| h.join().unwrap())
+///     .collect();
+/// assert_eq!(ids.len(), 400);
+/// ```
+pub struct SnowflakeGenerator {
+    epoch: SystemTime,
+    node: u16,
+    network: BitonicNetwork<()>,
+    // Each sequence is on its own cache line, so that threads leaving on
+    // neighbouring wires don't contend.
+    sequences: Box<[PaddedBucket<Sequence>]>,
+}
+
+impl SnowflakeGenerator {
+    /// Create a new generator for the given node, with timestamps counted from
+    /// `epoch` and sequence numbers drawn from a network of the given width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `node` is greater than [`MAX_NODE`], or if `width` is not a
+    /// power of two or is greater than [`MAX_WIDTH`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::{Duration, UNIX_EPOCH};
+    ///
+    /// // 2020-01-01T00:00:00Z
+    /// let epoch = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+    /// let generator = SnowflakeGenerator::new(epoch, 3, 8);
+    ///
+    /// assert_eq!(generator.epoch(), epoch);
+    /// assert_eq!(generator.node(), 3);
+    /// ```
+    pub fn new(epoch: SystemTime, node: u16, width: usize) -> Self {
+        assert!(node <= MAX_NODE);
+        assert!(width <= MAX_WIDTH);
+
+        SnowflakeGenerator {
+            epoch,
+            node,
+            network: BitonicNetwork::with_width(Width::of(width)),
+            sequences: (0..width)
+                .map(|wire| PaddedBucket(Sequence::new(wire as u64)))
+                .collect(),
+        }
+    }
+
+    /// Returns the time that timestamps are counted from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 0, 4);
+    ///
+    /// assert_eq!(generator.epoch(), UNIX_EPOCH);
+    /// ```
+    pub fn epoch(&self) -> SystemTime {
+        self.epoch
+    }
+
+    /// Returns the node number that is part of every ID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 12, 4);
+    ///
+    /// assert_eq!(generator.node(), 12);
+    /// ```
+    pub fn node(&self) -> u16 {
+        self.node
+    }
+
+    /// Create a new ID, waiting for the next millisecond if needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the time since the epoch no longer fits in the timestamp
+    /// field, which happens around 69 years after the epoch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::SnowflakeGenerator;
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let generator = SnowflakeGenerator::new(UNIX_EPOCH, 1, 4);
+    ///
+    /// let first = generator.next_id();
+    /// let second = generator.next_id();
+    ///
+    /// assert_ne!(first, second);
+    /// assert!(first.timestamp() <= second.timestamp());
+    /// ```
+    pub fn next_id(&self) -> SnowflakeId {
+        loop {
+            if let Some(id) = self.next_id_at(self.current_timestamp()) {
+                return id;
+            }
+
+            thread::yield_now();
+        }
+    }
+
+    fn current_timestamp(&self) -> u64 {
+        // A clock set to before the epoch is handled like any other clock that
+        // has gone backwards.
+        let elapsed = SystemTime::now()
+            .duration_since(self.epoch)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or(0);
+
+        assert!(
+            elapsed <= u128::from(TIMESTAMP_MASK),
+            "timestamp does not fit in an ID"
+        );
+        elapsed as u64
+    }
+
+    fn next_id_at(&self, timestamp: u64) -> Option<SnowflakeId> {
+        let wire = self.network.traverse_indexed();
+        let (timestamp, sequence) =
+            self.sequences[wire]
+                .0
+                .take(timestamp, wire as u64, self.network.width() as u64)?;
+
+        Some(SnowflakeId(
+            (timestamp << (NODE_BITS + SEQUENCE_BITS))
+                | (u64::from(self.node) << SEQUENCE_BITS)
+                | sequence,
+        ))
+    }
+}
+
+impl fmt::Debug for SnowflakeGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SnowflakeGenerator")
+            .field("epoch", &self.epoch)
+            .field("node", &self.node)
+            .field("width", &self.network.width())
+            .finish()
+    }
+}
+
+const LOGICAL_BITS: u32 = 16;
+// The top bit is left clear, as for `SnowflakeId`s. This still leaves room
+// for millisecond timestamps until the year 6400.
+const PHYSICAL_BITS: u32 = 63 - LOGICAL_BITS;
+
+const LOGICAL_MASK: u64 = (1 << LOGICAL_BITS) - 1;
+const PHYSICAL_MASK: u64 = (1 << PHYSICAL_BITS) - 1;
+
+/// The largest counting network width that a [`HybridClock`] can use.
+pub const MAX_CLOCK_WIDTH: usize = 1 << LOGICAL_BITS;
+
+/// A 64-bit hybrid logical clock timestamp, made up of a physical time and a
+/// logical counter.
+///
+/// From the most significant bit down, a timestamp holds a 47 bit physical
+/// time in milliseconds since the Unix epoch and a 16 bit logical counter. The
+/// top bit is always clear. Timestamps compare by physical time first and then
+/// by the logical counter, which is the same as comparing them as integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HlcTimestamp(u64);
+
+impl HlcTimestamp {
+    /// Create a timestamp from its physical time, in milliseconds since the
+    /// Unix epoch, and its logical counter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `physical` does not fit in 47 bits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HlcTimestamp;
+    ///
+    /// let timestamp = HlcTimestamp::new(1_600_000_000_000, 3);
+    ///
+    /// assert_eq!(timestamp.physical(), 1_600_000_000_000);
+    /// assert_eq!(timestamp.logical(), 3);
+    /// ```
+    pub fn new(physical: u64, logical: u16) -> Self {
+        assert!(
+            physical <= PHYSICAL_MASK,
+            "physical time does not fit in a timestamp"
+        );
+
+        HlcTimestamp((physical << LOGICAL_BITS) | u64::from(logical))
+    }
+
+    /// Create a timestamp from an integer, for example one that was returned
+    /// by [`as_u64`](HlcTimestamp::as_u64) on another node. Returns `None` if
+    /// the top bit is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HlcTimestamp;
+    ///
+    /// let timestamp = HlcTimestamp::new(5, 1);
+    ///
+    /// assert_eq!(HlcTimestamp::from_u64(timestamp.as_u64()), Some(timestamp));
+    /// assert_eq!(HlcTimestamp::from_u64(u64::MAX), None);
+    /// ```
+    pub fn from_u64(value: u64) -> Option<Self> {
+        if value >> 63 == 0 {
+            Some(HlcTimestamp(value))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the timestamp as an integer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HlcTimestamp;
+    ///
+    /// assert_eq!(HlcTimestamp::new(1, 2).as_u64(), (1 << 16) | 2);
+    /// ```
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the physical time of the timestamp, in milliseconds since the
+    /// Unix epoch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HybridClock;
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// let clock = HybridClock::new(4, Duration::from_secs(1));
+    /// let timestamp = clock.now();
+    ///
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    /// assert!(u128::from(timestamp.physical()) <= now.as_millis());
+    /// ```
+    pub fn physical(self) -> u64 {
+        self.0 >> LOGICAL_BITS
+    }
+
+    /// Returns the logical counter of the timestamp, which orders timestamps
+    /// with the same physical time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HlcTimestamp;
+    ///
+    /// assert!(HlcTimestamp::new(10, 0) < HlcTimestamp::new(10, 1));
+    /// assert!(HlcTimestamp::new(10, 1) < HlcTimestamp::new(11, 0));
+    /// ```
+    pub fn logical(self) -> u16 {
+        (self.0 & LOGICAL_MASK) as u16
+    }
+}
+
+impl From<HlcTimestamp> for u64 {
+    fn from(timestamp: HlcTimestamp) -> u64 {
+        timestamp.0
+    }
+}
+
+impl fmt::Display for HlcTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.physical(), self.logical())
+    }
+}
+
+// Each logical counter packs the last physical time it issued a timestamp at
+// into the high bits and the next logical value to issue into the low bits,
+// with an extra bit so that it can run past the last one.
+const STATE_LOGICAL_BITS: u32 = LOGICAL_BITS + 1;
+const STATE_LOGICAL_MASK: u64 = (1 << STATE_LOGICAL_BITS) - 1;
+
+thread_local! {
+    // The latest timestamp that this thread has been given by any clock, so
+    // that the timestamps a thread sees never go backwards. `const`
+    // initializers for thread locals are newer than the minimum supported Rust
+    // version.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static LAST_TIMESTAMP: Cell<u64> = Cell::new(0);
+}
+
+#[derive(Debug)]
+struct LogicalCounter(AtomicU64);
+
+impl LogicalCounter {
+    fn new(first: u64) -> Self {
+        LogicalCounter(AtomicU64::new(first))
+    }
+
+    // Take the next logical value for the latest of the physical time `now`,
+    // the last physical time of this counter and the physical time of `after`,
+    // such that the timestamp is greater than `after`. Returns `None` if all
+    // of the logical values for that physical time have been taken.
+    fn take(&self, now: u64, after: HlcTimestamp, first: u64, step: u64) -> Option<HlcTimestamp> {
+        let mut current = self.0.load(Ordering::Relaxed);
+        loop {
+            let last = current >> STATE_LOGICAL_BITS;
+            let physical = now.max(last).max(after.physical());
+            let mut logical = if physical > last {
+                first
+            } else {
+                current & STATE_LOGICAL_MASK
+            };
+
+            let after_logical = u64::from(after.logical());
+            if physical == after.physical() && logical <= after_logical {
+                // The first value from this counter past `after`
+                logical += ((after_logical - logical) / step + 1) * step;
+            }
+
+            if logical > LOGICAL_MASK {
+                return None;
+            }
+
+            let updated = (physical << STATE_LOGICAL_BITS) | (logical + step);
+            match self.0.compare_exchange_weak(
+                current,
+                updated,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(HlcTimestamp((physical << LOGICAL_BITS) | logical)),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// A hybrid logical clock, which hands out [`HlcTimestamp`]s that follow the
+/// system clock but also capture the order of events across nodes.
+///
+/// Every call to [`now`](HybridClock::now) returns a timestamp that is unique
+/// to this clock, and greater than any timestamp that the calling thread has
+/// been given before. When a message arrives from another node,
+/// [`update`](HybridClock::update) with the timestamp of the message returns a
+/// timestamp that is greater than it, and moves the clock forward so that
+/// timestamps taken after it returns, on any thread, are too. The physical
+/// time of the clock never goes backwards, even if the system clock does.
+/// Timestamps from another node are only accepted if they are at most
+/// `max_drift` ahead of the system clock, so the physical time of a timestamp
+/// never runs far ahead of the real time.
+///
+/// The logical counters are drawn from a counting network in the same way as
+/// the sequence numbers of a [`SnowflakeGenerator`], so threads taking
+/// timestamps at the same time rarely touch the same memory. When a wire runs
+/// out of logical values for a physical time, the thread waits for the system
+/// clock to move on.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::ids::HybridClock;
+/// use std::time::Duration;
+///
+/// let sender = HybridClock::new(4, Duration::from_millis(500));
+/// let receiver = HybridClock::new(4, Duration::from_millis(500));
+///
+/// let sent = sender.now();
+/// let received = receiver.update(sent).unwrap();
+///
+/// assert!(sent < received);
+/// assert!(received < receiver.now());
+/// ```
+pub struct HybridClock {
+    max_drift: Duration,
+    network: BitonicNetwork<()>,
+    // Each logical counter is on its own cache line, so that threads leaving
+    // on neighbouring wires don't contend.
+    counters: Box<[PaddedBucket<LogicalCounter>]>,
+    // Every timestamp taken from now on is at least this one. It is raised
+    // when a timestamp is taken at a later physical time than before, and by
+    // `update`, so it is rarely written.
+    latest: AtomicU64,
+}
+
+impl HybridClock {
+    /// Create a new clock that draws logical counters from a network of the
+    /// given width, and accepts timestamps from other nodes that are at most
+    /// `max_drift` ahead of the system clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is not a power of two or is greater than
+    /// [`MAX_CLOCK_WIDTH`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HybridClock;
+    /// use std::time::Duration;
+    ///
+    /// let clock = HybridClock::new(8, Duration::from_secs(1));
+    ///
+    /// assert_eq!(clock.max_drift(), Duration::from_secs(1));
+    /// ```
+    pub fn new(width: usize, max_drift: Duration) -> Self {
+        assert!(width <= MAX_CLOCK_WIDTH);
+
+        HybridClock {
+            max_drift,
+            network: BitonicNetwork::with_width(Width::of(width)),
+            counters: (0..width)
+                .map(|wire| PaddedBucket(LogicalCounter::new(wire as u64)))
+                .collect(),
+            latest: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns how far ahead of the system clock a timestamp from another node
+    /// may be.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HybridClock;
+    /// use std::time::Duration;
+    ///
+    /// let clock = HybridClock::new(4, Duration::from_millis(250));
+    ///
+    /// assert_eq!(clock.max_drift(), Duration::from_millis(250));
+    /// ```
+    pub fn max_drift(&self) -> Duration {
+        self.max_drift
+    }
+
+    /// Returns a new timestamp for an event on this node, such as sending a
+    /// message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system clock is so far in the future that the time no
+    /// longer fits in a timestamp.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::HybridClock;
+    /// use std::time::Duration;
+    ///
+    /// let clock = HybridClock::new(4, Duration::from_secs(1));
+    ///
+    /// let first = clock.now();
+    /// let second = clock.now();
+    ///
+    /// assert!(first < second);
+    /// ```
+    pub fn now(&self) -> HlcTimestamp {
+        self.next_after(HlcTimestamp(0))
+    }
+
+    /// Returns a new timestamp for receiving a message that was sent with the
+    /// timestamp `remote`. The new timestamp is greater than `remote`.
+    ///
+    /// Returns an error, without changing the clock, if `remote` is more than
+    /// [`max_drift`](HybridClock::max_drift) ahead of the system clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the system clock is so far in the future that the time no
+    /// longer fits in a timestamp.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::{HlcTimestamp, HybridClock};
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// let clock = HybridClock::new(4, Duration::from_secs(1));
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    ///
+    /// let remote = HlcTimestamp::new(now + 100, 7);
+    /// assert!(clock.update(remote).unwrap() > remote);
+    ///
+    /// let remote = HlcTimestamp::new(now + 60_000, 0);
+    /// assert!(clock.update(remote).is_err());
+    /// ```
+    pub fn update(&self, remote: HlcTimestamp) -> Result<HlcTimestamp, ClockDriftError> {
+        let now = current_physical();
+        let max_drift = self.max_drift.as_millis();
+        let drift = u128::from(remote.physical().saturating_sub(now));
+        if drift > max_drift {
+            return Err(ClockDriftError {
+                drift: Duration::from_millis(drift as u64),
+                max_drift: self.max_drift,
+            });
+        }
+
+        let timestamp = self.next_after(remote);
+        // Timestamps from other wires must also be past `remote`
+        self.raise_latest(timestamp.0 + 1);
+
+        Ok(timestamp)
+    }
+
+    fn next_after(&self, after: HlcTimestamp) -> HlcTimestamp {
+        let after = LAST_TIMESTAMP.with(|last| after.max(HlcTimestamp(last.get())));
+        let timestamp = loop {
+            if let Some(timestamp) = self.next_at(current_physical(), after) {
+                break timestamp;
+            }
+
+            thread::yield_now();
+        };
+
+        LAST_TIMESTAMP.with(|last| last.set(timestamp.0));
+        timestamp
+    }
+
+    fn next_at(&self, now: u64, after: HlcTimestamp) -> Option<HlcTimestamp> {
+        let latest = self.latest.load(Ordering::Relaxed);
+        let now = now.max(HlcTimestamp(latest).physical());
+        let after = after.max(HlcTimestamp(latest.saturating_sub(1)));
+
+        let wire = self.network.traverse_indexed();
+        let timestamp =
+            self.counters[wire]
+                .0
+                .take(now, after, wire as u64, self.network.width() as u64)?;
+
+        if timestamp.physical() > HlcTimestamp(latest).physical() {
+            // Timestamps from other wires must not go back to an earlier
+            // physical time, if the system clock does.
+            self.raise_latest(timestamp.physical() << LOGICAL_BITS);
+        }
+
+        Some(timestamp)
+    }
+
+    // Make every timestamp taken from now on at least `latest`. Traversals
+    // that happen after this, such as those on threads that have received a
+    // timestamp from this one, see the new value.
+    fn raise_latest(&self, latest: u64) {
+        if latest > self.latest.load(Ordering::Relaxed) {
+            self.latest.fetch_max(latest, Ordering::Relaxed);
+        }
+    }
+}
+
+fn current_physical() -> u64 {
+    // A clock set to before the epoch is handled like any other clock that has
+    // gone backwards.
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or(0);
+
+    assert!(
+        elapsed <= u128::from(PHYSICAL_MASK),
+        "physical time does not fit in a timestamp"
+    );
+    elapsed as u64
+}
+
+impl fmt::Debug for HybridClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HybridClock")
+            .field("max_drift", &self.max_drift)
+            .field("width", &self.network.width())
+            .field("latest", &HlcTimestamp(self.latest.load(Ordering::Relaxed)))
+            .finish()
+    }
+}
+
+/// The error returned by [`HybridClock::update`] when a timestamp from another
+/// node is too far ahead of the system clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClockDriftError {
+    drift: Duration,
+    max_drift: Duration,
+}
+
+impl ClockDriftError {
+    /// Returns how far ahead of the system clock the timestamp was.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::{HlcTimestamp, HybridClock};
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// let clock = HybridClock::new(4, Duration::from_secs(1));
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    ///
+    /// let error = clock.update(HlcTimestamp::new(now + 60_000, 0)).unwrap_err();
+    /// assert!(error.drift() > error.max_drift());
+    /// ```
+    pub fn drift(&self) -> Duration {
+        self.drift
+    }
+
+    /// Returns the largest drift that the clock accepts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::ids::{HlcTimestamp, HybridClock};
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    ///
+    /// let clock = HybridClock::new(4, Duration::from_secs(1));
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    ///
+    /// let error = clock.update(HlcTimestamp::new(now + 60_000, 0)).unwrap_err();
+    /// assert_eq!(error.max_drift(), Duration::from_secs(1));
+    /// ```
+    pub fn max_drift(&self) -> Duration {
+        self.max_drift
+    }
+}
+
+impl fmt::Display for ClockDriftError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "timestamp is {}ms ahead of the system clock, more than the allowed {}ms",
+            self.drift.as_millis(),
+            self.max_drift.as_millis()
+        )
+    }
+}
+
+impl Error for ClockDriftError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashSet, sync::Arc, time::UNIX_EPOCH};
+
+    fn sync_only<T: Sync>(_: T) {}
+    fn send_only<T: Send>(_: T) {}
+
+    #[test]
+    fn is_send() {
+        send_only(SnowflakeGenerator::new(UNIX_EPOCH, 0, 2));
+    }
+
+    #[test]
+    fn is_sync() {
+        sync_only(SnowflakeGenerator::new(UNIX_EPOCH, 0, 2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn node_too_large() {
+        let _ = SnowflakeGenerator::new(UNIX_EPOCH, MAX_NODE + 1, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn width_too_large() {
+        let _ = SnowflakeGenerator::new(UNIX_EPOCH, 0, MAX_WIDTH * 2);
+    }
+
+    #[test]
+    fn id_fields() {
+        let generator = SnowflakeGenerator::new(UNIX_EPOCH, MAX_NODE, 1);
+
+        let id = generator.next_id_at(TIMESTAMP_MASK).unwrap();
+        assert_eq!(id.timestamp(), TIMESTAMP_MASK);
+        assert_eq!(id.node(), MAX_NODE);
+        assert_eq!(id.sequence(), 0);
+        assert!((id.as_u64() as i64) > 0);
+    }
+
+    #[test]
+    fn sequence_exhaustion() {
+        let width = 4;
+        let generator = SnowflakeGenerator::new(UNIX_EPOCH, 0, width);
+
+        let ids: Vec<_> = (0..MAX_WIDTH)
+            .map(|_| generator.next_id_at(5).unwrap())
+            .collect();
+        let sequences: HashSet<_> = ids.iter().map(|id| id.sequence()).collect();
+        assert_eq!(sequences.len(), MAX_WIDTH);
+        assert!(ids.iter().all(|id| id.timestamp() == 5));
+
+        // Every wire has run out of sequence numbers for this millisecond
+        for _ in 0..width {
+            assert_eq!(generator.next_id_at(5), None);
+        }
+
+        let id = generator.next_id_at(6).unwrap();
+        assert_eq!(id.timestamp(), 6);
+        assert!(id.sequence() < width as u16);
+    }
+
+    #[test]
+    fn clock_goes_backwards() {
+        let generator = SnowflakeGenerator::new(UNIX_EPOCH, 0, 1);
+
+        let first = generator.next_id_at(10).unwrap();
+        let second = generator.next_id_at(3).unwrap();
+
+        assert_eq!(second.timestamp(), 10);
+        assert!(first < second);
+
+        for _ in 2..MAX_WIDTH {
+            generator.next_id_at(3).unwrap();
+        }
+        assert_eq!(generator.next_id_at(3), None);
+        assert_eq!(generator.next_id_at(10), None);
+        assert_eq!(generator.next_id_at(11).unwrap().timestamp(), 11);
+    }
+
+    #[test]
+    fn concurrent_ids_are_unique() {
+        const NUM_THREADS: usize = 4;
+        const IDS_PER_THREAD: usize = 2000;
+
+        let generator = Arc::new(SnowflakeGenerator::new(UNIX_EPOCH, 1, 4));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let generator = Arc::clone(&generator);
+                thread::spawn(move || {
+                    (0..IDS_PER_THREAD)
+                        .map(|_| generator.next_id())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let ids: HashSet<_> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+
+        assert_eq!(ids.len(), NUM_THREADS * IDS_PER_THREAD);
+    }
+
+    #[test]
+    fn clock_is_send_and_sync() {
+        send_only(HybridClock::new(2, Duration::from_secs(1)));
+        sync_only(HybridClock::new(2, Duration::from_secs(1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn clock_width_too_large() {
+        let _ = HybridClock::new(MAX_CLOCK_WIDTH * 2, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn timestamp_fields() {
+        let timestamp = HlcTimestamp::new(PHYSICAL_MASK, u16::MAX);
+
+        assert_eq!(timestamp.physical(), PHYSICAL_MASK);
+        assert_eq!(timestamp.logical(), u16::MAX);
+        assert!((timestamp.as_u64() as i64) > 0);
+        assert_eq!(HlcTimestamp::new(3, 4).to_string(), "3.4");
+    }
+
+    #[test]
+    fn logical_exhaustion() {
+        let width = 4;
+        let clock = HybridClock::new(width, Duration::from_secs(1));
+        let start = HlcTimestamp(0);
+
+        let timestamps: HashSet<_> = (0..MAX_CLOCK_WIDTH)
+            .map(|_| clock.next_at(5, start).unwrap())
+            .collect();
+        assert_eq!(timestamps.len(), MAX_CLOCK_WIDTH);
+        assert!(timestamps.iter().all(|timestamp| timestamp.physical() == 5));
+
+        for _ in 0..width {
+            assert_eq!(clock.next_at(5, start), None);
+        }
+
+        let timestamp = clock.next_at(6, start).unwrap();
+        assert_eq!(timestamp.physical(), 6);
+        assert!(timestamp.logical() < width as u16);
+    }
+
+    #[test]
+    fn clock_follows_later_timestamps() {
+        let clock = HybridClock::new(4, Duration::from_secs(1));
+        let remote = HlcTimestamp::new(20, 9);
+
+        // Every wire moves past the remote timestamp
+        for _ in 0..4 {
+            let timestamp = clock.next_at(10, remote).unwrap();
+            assert!(timestamp > remote);
+            assert_eq!(timestamp.physical(), 20);
+        }
+        for _ in 0..4 {
+            let timestamp = clock.next_at(10, HlcTimestamp(0)).unwrap();
+            assert!(timestamp > remote);
+        }
+
+        assert_eq!(clock.next_at(21, remote).unwrap().physical(), 21);
+    }
+
+    #[test]
+    fn update_rejects_drift() {
+        let clock = HybridClock::new(2, Duration::from_millis(100));
+        let remote = HlcTimestamp::new(current_physical() + 60_000, 0);
+
+        let error = clock.update(remote).unwrap_err();
+        assert!(error.drift() > Duration::from_secs(59));
+        assert_eq!(error.max_drift(), Duration::from_millis(100));
+        assert!(clock.now() < remote);
+    }
+
+    #[test]
+    fn thread_timestamps_never_go_backwards() {
+        let clock = HybridClock::new(4, Duration::from_millis(500));
+        let ahead = HlcTimestamp::new(current_physical() + 200, 100);
+
+        let mut last = clock.update(ahead).unwrap();
+        for _ in 0..1000 {
+            let timestamp = clock.now();
+            assert!(timestamp > last);
+            last = timestamp;
+        }
+    }
+
+    #[test]
+    fn updates_move_every_wire_forward() {
+        let clock = Arc::new(HybridClock::new(16, Duration::from_millis(500)));
+        let ahead = HlcTimestamp::new(current_physical() + 200, 100);
+        let received = clock.update(ahead).unwrap();
+
+        // New threads enter the network on other wires than this one
+        for _ in 0..16 {
+            let clock = Arc::clone(&clock);
+            let timestamp = thread::spawn(move || clock.now()).join().unwrap();
+
+            assert!(timestamp > received, "{} <= {}", timestamp, received);
+        }
+    }
+
+    #[test]
+    fn physical_time_never_goes_backwards() {
+        let clock = HybridClock::new(4, Duration::from_secs(1));
+        let start = HlcTimestamp(0);
+
+        assert_eq!(clock.next_at(10, start).unwrap().physical(), 10);
+        // The system clock going backwards, as seen from every wire
+        for _ in 0..8 {
+            assert_eq!(clock.next_at(7, start).unwrap().physical(), 10);
+        }
+    }
+
+    #[test]
+    fn concurrent_timestamps_are_unique() {
+        const NUM_THREADS: usize = 4;
+        const TIMESTAMPS_PER_THREAD: usize = 2000;
+
+        let clock = Arc::new(HybridClock::new(4, Duration::from_secs(1)));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let clock = Arc::clone(&clock);
+                thread::spawn(move || {
+                    (0..TIMESTAMPS_PER_THREAD)
+                        .map(|_| clock.now())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let timestamps: HashSet<_> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+
+        assert_eq!(timestamps.len(), NUM_THREADS * TIMESTAMPS_PER_THREAD);
+    }
+}
@@ -0,0 +1,201 @@
+//! Runtime checks of the invariants of counting networks, enabled with the
+//! `debug-invariants` feature.
+//!
+//! Every [`BitonicCountingNetwork`](crate::counters::BitonicCountingNetwork)
+//! counts the tokens leaving each output wire, and a sample of the values it
+//! hands out are checked against those counts:
+//!
+//!  - The value from a wire must be the next value in that wire's sequence,
+//!    `start + wire * stride`, then `increment` after that, and so on. Values
+//!    can only be out of order by the number of traversals that overlapped.
+//!  - The number of tokens that left any two wires must differ by at most one,
+//!    plus the number of traversals that could still be in flight.
+//!
+//! A violation panics with a description of what was seen.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Check one in this many values.
+const SAMPLE_INTERVAL: usize = 64;
+
+pub(crate) struct Invariants {
+    // First value expected from each wire
+    initial: Box<[usize]>,
+    increment: usize,
+    // Traversals that have started, and that have handed out a value
+    started: AtomicUsize,
+    finished: AtomicUsize,
+    // Number of tokens that have left on each wire
+    exits: Box<[AtomicUsize]>,
+}
+
+// A traversal in progress.
+pub(crate) struct Token {
+    index: usize,
+    wire: usize,
+    position: usize,
+    finished_before: usize,
+}
+
+impl Invariants {
+    pub(crate) fn new(width: usize, start: usize, stride: usize, increment: usize) -> Self {
+        Invariants {
+            initial: (0..width)
+                .map(|wire| start.wrapping_add(wire.wrapping_mul(stride)))
+                .collect(),
+            increment,
+            started: AtomicUsize::new(0),
+            finished: AtomicUsize::new(0),
+            exits: (0..width).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    // Called when the buckets are replaced while no tokens are in the network,
+    // with the next value of each wire.
+    pub(crate) fn restart<I: IntoIterator<Item = usize>>(&mut self, initial: I) {
+        self.initial = initial.into_iter().collect();
+        *self.started.get_mut() = 0;
+        *self.finished.get_mut() = 0;
+        for exits in self.exits.iter_mut() {
+            *exits.get_mut() = 0;
+        }
+    }
+
+    // Called before the token enters the network.
+    pub(crate) fn enter(&self) -> usize {
+        self.started.fetch_add(1, Ordering::SeqCst)
+    }
+
+    // Called after the token leaves the network on `wire`, before it takes a
+    // value from the bucket.
+    pub(crate) fn exit(&self, index: usize, wire: usize) -> Token {
+        let finished_before = self.finished.load(Ordering::SeqCst);
+        let position = self.exits[wire].fetch_add(1, Ordering::SeqCst);
+
+        Token {
+            index,
+            wire,
+            position,
+            finished_before,
+        }
+    }
+
+    // Called with the value the token took from the bucket.
+    pub(crate) fn finish(&self, token: Token, value: usize) {
+        self.check(&token, value);
+
+        self.finished.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn check(&self, token: &Token, value: usize) {
+        match token.index % SAMPLE_INTERVAL {
+            0 => {}
+            _ => return,
+        }
+
+        self.check_value(token, value);
+        self.check_skew();
+    }
+
+    fn check_value(&self, token: &Token, value: usize) {
+        // Tokens that overlapped with this one can take values from the same
+        // wire in a different order than they were counted in.
+        let overlapping = self
+            .started
+            .load(Ordering::SeqCst)
+            .wrapping_sub(token.finished_before);
+        let initial = self.initial[token.wire];
+        let expected =
+            |position: usize| initial.wrapping_add(position.wrapping_mul(self.increment));
+
+        let lowest = token.position.saturating_sub(overlapping);
+        let highest = token.position.saturating_add(overlapping);
+        assert!(
+            (lowest..=highest).any(|position| expected(position) == value),
+            "counting network invariant violated: wire {} handed out {} as its value number {}, \
+             expected {} with at most {} overlapping traversals",
+            token.wire,
+            value,
+            token.position,
+            expected(token.position),
+            overlapping
+        );
+    }
+
+    fn check_skew(&self) {
+        let finished_before = self.finished.load(Ordering::SeqCst);
+        let exits: Vec<usize> = self
+            .exits
+            .iter()
+            .map(|exits| exits.load(Ordering::SeqCst))
+            .collect();
+        let in_flight = self
+            .started
+            .load(Ordering::SeqCst)
+            .wrapping_sub(finished_before);
+
+        // Once the traversals in flight finish, the counts have the step
+        // property. Tokens can also leave while the counts are being read.
+        let max_skew = 1 + 2 * in_flight;
+        let fewest = exits.iter().min().copied().unwrap_or(0);
+        let most = exits.iter().max().copied().unwrap_or(0);
+        assert!(
+            most - fewest <= max_skew,
+            "counting network invariant violated: tokens leaving each wire {:?} differ by {}, \
+             more than {} with {} traversals in flight",
+            exits,
+            most - fewest,
+            max_skew,
+            in_flight
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn traverse(invariants: &Invariants, wire: usize, value: usize) {
+        let index = invariants.enter();
+        let token = invariants.exit(index, wire);
+        invariants.finish(token, value);
+    }
+
+    #[test]
+    fn sequential_values_pass() {
+        let invariants = Invariants::new(2, 10, 1, 2);
+
+        for step in 0..(4 * SAMPLE_INTERVAL) {
+            traverse(&invariants, step % 2, 10 + step);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "wire 0 handed out 11")]
+    fn wrong_value_panics() {
+        let invariants = Invariants::new(2, 10, 1, 2);
+
+        traverse(&invariants, 0, 11);
+    }
+
+    #[test]
+    fn restart_expects_new_values() {
+        let mut invariants = Invariants::new(2, 0, 1, 2);
+        traverse(&invariants, 0, 0);
+
+        invariants.restart(vec![7, 4]);
+
+        traverse(&invariants, 1, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "differ by 4")]
+    fn skew_panics() {
+        let invariants = Invariants::new(2, 0, 1, 2);
+        invariants.exits[0].store(10, Ordering::SeqCst);
+        invariants.exits[1].store(5, Ordering::SeqCst);
+
+        // The sixth value of wire 1 is correct, but wire 0 is too far ahead
+        traverse(&invariants, 1, 11);
+    }
+}
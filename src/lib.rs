@@ -58,12 +58,33 @@
 //! Lastly the [Wikipedia page on sorting networks][wikipedia] is fairly
 //! intuitive, and you can see how they relate  to the other types of networks.
 //!
+//! # Platform support
+//!
+//! Counters hand out `usize` values and wrap around on overflow, so on 32-bit
+//! targets a counter wraps after 2<sup>32</sup> values. Code that needs more
+//! values than that should use [`ids`](crate::ids), whose identifiers are
+//! `u64` on every target.
+//!
+//! The [`ids`](crate::ids) and [`metrics`](crate::metrics) modules, and
+//! [`F64Adder`](crate::counters::F64Adder), use 64-bit atomics. These are
+//! available on all 64-bit targets, and on common 32-bit targets like `i686`,
+//! `armv7` and `wasm32`, but not on some embedded targets.
+//!
 //! [original]: http://www.hpl.hp.com/techreports/Compaq-DEC/CRL-93-11.pdf
 //! [textbook]: https://www.cs.tau.ac.il/~shanir/concurrent-data-structures.pdf
 //! [smoothing]: http://citeseerx.ist.psu.edu/viewdoc/download?doi=10.1.1.87.5843&rep=rep1&type=pdf
 //! [wikipedia]: https://en.wikipedia.org/wiki/Sorting_network
 
+pub mod collections;
 pub mod counters;
+pub mod dispatch;
+pub mod ids;
+pub mod metrics;
 pub mod networks;
+pub mod sync;
+pub mod testing;
+pub mod unsync;
 
+#[cfg(feature = "debug-invariants")]
+mod invariants;
 mod util;
@@ -0,0 +1,391 @@
+//! Metrics that use counting networks to spread updates across threads.
+
+use crate::{
+    counters::PaddedBucket,
+    networks::{BitonicNetwork, Width},
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::time::Instant;
+
+// Each stripe packs the window epoch it is counting for into the high bits and
+// the count into the low bits, so moving a stripe on to a new window and
+// counting into it is a single atomic operation.
+const COUNT_BITS: u32 = 32;
+const COUNT_MASK: u64 = (1 << COUNT_BITS) - 1;
+
+#[derive(Debug, Default)]
+struct Stripe(AtomicU64);
+
+impl Stripe {
+    fn add(&self, epoch: u32, count: u32) {
+        let mut current = self.0.load(Ordering::Relaxed);
+        loop {
+            let (current_epoch, current_count) = unpack(current);
+            let updated = if current_epoch == epoch {
+                pack(epoch, (current_count as u32).saturating_add(count))
+            } else if current_count == 0 || is_newer(epoch, current_epoch) {
+                pack(epoch, count)
+            } else {
+                // The window this count belongs to has already been rotated out.
+                return;
+            };
+
+            match self.0.compare_exchange_weak(
+                current,
+                updated,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn get(&self, epoch: u32) -> u64 {
+        let (current_epoch, current_count) = unpack(self.0.load(Ordering::Relaxed));
+
+        if current_epoch == epoch {
+            current_count
+        } else {
+            0
+        }
+    }
+}
+
+fn pack(epoch: u32, count: u32) -> u64 {
+    (u64::from(epoch) << COUNT_BITS) | u64::from(count)
+}
+
+fn unpack(value: u64) -> (u32, u64) {
+    ((value >> COUNT_BITS) as u32, value & COUNT_MASK)
+}
+
+// Epochs wrap around, so compare them the same way as TCP sequence numbers.
+fn is_newer(epoch: u32, than: u32) -> bool {
+    (epoch.wrapping_sub(than) as i32) > 0
+}
+
+/// A counter of events in a rolling time window.
+///
+/// Time is divided into windows of a fixed length, and the counter keeps the
+/// counts for the most recent `num_windows` of them. Increments pick a stripe
+/// through a [`BitonicNetwork`](crate::networks::BitonicNetwork), so threads
+/// recording events at the same time rarely touch the same memory. Reads add
+/// up the stripes of the windows they cover.
+///
+/// Each stripe counts up to `u32::MAX` events per window, after which it
+/// saturates. Increments that are delayed past the end of the retained windows
+/// are dropped.
+pub struct WindowedCounter {
+    start: Instant,
+    window: Duration,
+    num_windows: usize,
+    network: BitonicNetwork<()>,
+    // Stripes for wire `i` are at `i * num_windows..(i + 1) * num_windows`,
+    // each on its own cache line.
+    stripes: Box<[PaddedBucket<Stripe>]>,
+}
+
+impl WindowedCounter {
+    /// Create a new counter that keeps `num_windows` windows of the given
+    /// length, with `width` stripes per window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero, if `num_windows` is zero, if `width` is not
+    /// a power of two, or if `width * num_windows` overflows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_secs(1), 60, 8);
+    ///
+    /// assert_eq!(counter.window(), Duration::from_secs(1));
+    /// assert_eq!(counter.num_windows(), 60);
+    /// ```
+    pub fn new(window: Duration, num_windows: usize, width: usize) -> Self {
+        assert!(window > Duration::from_secs(0));
+        assert!(num_windows > 0);
+        let num_stripes = width
+            .checked_mul(num_windows)
+            .expect("number of stripes overflows");
+
+        WindowedCounter {
+            start: Instant::now(),
+            window,
+            num_windows,
+            network: BitonicNetwork::with_width(Width::of(width)),
+            stripes: (0..num_stripes).map(|_| PaddedBucket::default()).collect(),
+        }
+    }
+
+    /// Returns the length of each window.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_millis(100), 10, 4);
+    ///
+    /// assert_eq!(counter.window(), Duration::from_millis(100));
+    /// ```
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the number of windows that are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_millis(100), 10, 4);
+    ///
+    /// assert_eq!(counter.num_windows(), 10);
+    /// ```
+    pub fn num_windows(&self) -> usize {
+        self.num_windows
+    }
+
+    /// Record a single event in the current window.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_secs(60), 5, 4);
+    ///
+    /// counter.increment();
+    /// counter.increment();
+    ///
+    /// assert_eq!(counter.current(), 2);
+    /// ```
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    /// Record `count` events in the current window.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_secs(60), 5, 4);
+    ///
+    /// counter.add(10);
+    ///
+    /// assert_eq!(counter.current(), 10);
+    /// ```
+    pub fn add(&self, count: u32) {
+        self.add_at(self.current_epoch(), count);
+    }
+
+    /// Returns the number of events recorded in the current window.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_secs(60), 5, 4);
+    ///
+    /// assert_eq!(counter.current(), 0);
+    /// ```
+    pub fn current(&self) -> u64 {
+        self.sum_at(self.current_epoch(), 1)
+    }
+
+    /// Returns the number of events recorded in all of the kept windows,
+    /// including the current one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::metrics::WindowedCounter;
+    /// use std::time::Duration;
+    ///
+    /// let counter = WindowedCounter::new(Duration::from_secs(60), 5, 4);
+    ///
+    /// counter.add(3);
+    ///
+    /// assert_eq!(counter.total(), 3);
+    /// ```
+    pub fn total(&self) -> u64 {
+        self.sum_at(self.current_epoch(), self.num_windows)
+    }
+
+    // The slot of a window is picked with the full epoch, so the kept windows
+    // always have different slots, even when `num_windows` does not divide
+    // 2^32. Only the stripes store the truncated epoch.
+    fn current_epoch(&self) -> u64 {
+        (self.start.elapsed().as_nanos() / self.window.as_nanos()) as u64
+    }
+
+    fn slot(&self, epoch: u64) -> usize {
+        (epoch % self.num_windows as u64) as usize
+    }
+
+    fn wire_stripes(&self, wire: usize) -> &[PaddedBucket<Stripe>] {
+        &self.stripes[(wire * self.num_windows)..((wire + 1) * self.num_windows)]
+    }
+
+    fn add_at(&self, epoch: u64, count: u32) {
+        let wire = self.network.traverse_indexed();
+
+        self.wire_stripes(wire)[self.slot(epoch)]
+            .0
+            .add(epoch as u32, count);
+    }
+
+    // Sum the `num_windows` windows ending with `epoch`.
+    fn sum_at(&self, epoch: u64, num_windows: usize) -> u64 {
+        (0..self.network.width())
+            .map(|wire| {
+                let stripes = self.wire_stripes(wire);
+                // Windows before the counter was created are empty
+                (0..=epoch.min(num_windows as u64 - 1))
+                    .map(|offset| {
+                        let window_epoch = epoch - offset;
+                        stripes[self.slot(window_epoch)].0.get(window_epoch as u32)
+                    })
+                    .sum::<u64>()
+            })
+            .sum()
+    }
+}
+
+impl fmt::Debug for WindowedCounter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WindowedCounter")
+            .field("window", &self.window)
+            .field("num_windows", &self.num_windows)
+            .field("width", &self.network.width())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    fn sync_only<T: Sync>(_: T) {}
+    fn send_only<T: Send>(_: T) {}
+
+    #[test]
+    fn is_send() {
+        send_only(WindowedCounter::new(Duration::from_secs(1), 2, 2));
+    }
+
+    #[test]
+    fn is_sync() {
+        sync_only(WindowedCounter::new(Duration::from_secs(1), 2, 2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_windows() {
+        let _ = WindowedCounter::new(Duration::from_secs(1), 0, 2);
+    }
+
+    #[test]
+    fn windows_rotate() {
+        let counter = WindowedCounter::new(Duration::from_secs(1), 3, 4);
+
+        counter.add_at(0, 1);
+        counter.add_at(1, 2);
+        counter.add_at(2, 4);
+        assert_eq!(counter.sum_at(2, 1), 4);
+        assert_eq!(counter.sum_at(2, 3), 7);
+
+        // Epoch 3 reuses the slot of epoch 0
+        counter.add_at(3, 8);
+        assert_eq!(counter.sum_at(3, 1), 8);
+        assert_eq!(counter.sum_at(3, 3), 14);
+
+        // Counts for windows that are no longer kept are dropped
+        counter.add_at(0, 16);
+        assert_eq!(counter.sum_at(3, 3), 14);
+    }
+
+    #[test]
+    fn windows_skip_idle_periods() {
+        let counter = WindowedCounter::new(Duration::from_secs(1), 2, 2);
+
+        counter.add_at(0, 1);
+        counter.add_at(1, 2);
+
+        assert_eq!(counter.sum_at(10, 2), 0);
+    }
+
+    #[test]
+    fn epochs_wrap_around() {
+        let counter = WindowedCounter::new(Duration::from_secs(1), 2, 2);
+
+        counter.add_at(u64::from(u32::MAX), 1);
+        counter.add_at(1 << 32, 2);
+
+        assert_eq!(counter.sum_at(1 << 32, 2), 3);
+    }
+
+    #[test]
+    fn epochs_wrap_around_uneven_windows() {
+        // 3 does not divide 2^32, so the truncated epochs would put the windows
+        // either side of the wrap in the same slot.
+        let counter = WindowedCounter::new(Duration::from_secs(1), 3, 2);
+
+        counter.add_at(u64::from(u32::MAX) - 1, 1);
+        counter.add_at(u64::from(u32::MAX), 2);
+        counter.add_at(1 << 32, 4);
+
+        assert_eq!(counter.sum_at(1 << 32, 1), 4);
+        assert_eq!(counter.sum_at(1 << 32, 3), 7);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_stripes() {
+        let _ = WindowedCounter::new(Duration::from_secs(1), usize::MAX, 2);
+    }
+
+    #[test]
+    fn concurrent_increments() {
+        const NUM_THREADS: usize = 4;
+        const NUM_INCREMENTS: usize = 100;
+
+        let counter = Arc::new(WindowedCounter::new(Duration::from_secs(3600), 2, 4));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    for _ in 0..NUM_INCREMENTS {
+                        counter.add_at(7, 1);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(counter.sum_at(7, 1), (NUM_THREADS * NUM_INCREMENTS) as u64);
+    }
+}
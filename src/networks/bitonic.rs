@@ -1,5 +1,8 @@
-use super::common::{Network, NetworkConfiguration};
-use core::{iter::FusedIterator, ops::Range};
+use super::{
+    common::{Network, NetworkConfiguration},
+    width::Width,
+};
+use core::{iter::FusedIterator, mem, ops::Range};
 use std::vec;
 
 /// A type of counting network
@@ -64,99 +67,92 @@ use std::vec;
 /// network. This is flipped for the bottom 4 inputs, where the odd numbered
 /// inputs (5, 7) go to the upper `Merge[4]` network, while the evens go to
 /// the bottom `Merge[4]` network.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct BitonicNetwork<L>(Network<L, BitonicConfiguration>);
-
-impl<L> BitonicNetwork<L> {
-    /// Construct a new network with given width (which must be a power of 2)
-    /// and outputs.
-    ///
-    /// Outputs must be ordered corresponding to how they should appear in the
-    /// network.
-    ///
-    /// For example in a 4-width network:
-    ///
-    /// ```text
-    /// xi = ith input
-    /// yi = ith output
-    ///
-    /// x1 ─────╥────╥─────╥─── y1
-    /// x2 ─────╨────║──╥──╨─── y2
-    /// x3 ─────╥────║──╨──╥─── y3
-    /// x4 ─────╨────╨─────╨─── y4
-    /// ```
-    ///
-    /// The outputs passed through should appear [y1, y2, y3, y4]
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use counting_networks::networks::BitonicNetwork;
-    ///
-    /// let outputs = vec![1, 2, 3, 4];
-    ///
-    /// let network = BitonicNetwork::new(outputs);
-    ///
-    /// assert_eq!(network.width(), 4);
-    /// assert_eq!(network.outputs(), &[1, 2, 3, 4]);
-    /// ```
-    pub fn new(outputs: Vec<L>) -> Self {
-        assert!(outputs.len().is_power_of_two());
-
-        BitonicNetwork(Network::new(outputs))
-    }
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::BitonicNetwork;
+///
+/// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+///
+/// assert_eq!(network.width(), 4);
+/// assert_eq!(network.traverse(), &1);
+/// ```
+pub type BitonicNetwork<L> = Network<L, BitonicConfiguration>;
 
-    /// Returns the width of the network.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use counting_networks::networks::BitonicNetwork;
-    ///
-    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
-    ///
-    /// assert_eq!(network.width(), 4);
-    /// ```
-    pub fn width(&self) -> usize {
-        self.0.width()
-    }
+/// Returns the depth of a [`BitonicNetwork`] of the given width, which is the
+/// number of balancers that every token passes through.
+///
+/// `Bitonic[w]` has `log2(w) * (log2(w) + 1) / 2` layers of balancers. Widths
+/// that are not a power of two are rounded up to the next one, like
+/// [`Width::for_threads`] does, so a width of zero gives a depth of zero.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::bitonic_depth;
+///
+/// const DEPTH: usize = bitonic_depth(8);
+///
+/// assert_eq!(DEPTH, 6);
+/// assert_eq!(bitonic_depth(1), 0);
+/// assert_eq!(bitonic_depth(6), bitonic_depth(8));
+/// ```
+pub const fn bitonic_depth(width: usize) -> usize {
+    let log_width = log2_rounded_up(width);
 
-    /// Traverse the network and obtain a reference to an output element.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use counting_networks::networks::BitonicNetwork;
-    ///
-    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
-    ///
-    /// assert_eq!(network.traverse(), &1);
-    /// assert_eq!(network.traverse(), &2);
-    /// assert_eq!(network.traverse(), &3);
-    /// assert_eq!(network.traverse(), &4);
-    /// ```
-    pub fn traverse(&self) -> &L {
-        self.0.traverse()
-    }
+    log_width * (log_width + 1) / 2
+}
+
+/// Returns the number of balancers in a [`BitonicNetwork`] of the given width.
+///
+/// Every layer of `Bitonic[w]` has `w / 2` balancers, see [`bitonic_depth`]
+/// for the number of layers. Widths that are not a power of two are rounded
+/// up to the next one, so a width of zero has no balancers.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{bitonic_balancer_count, BitonicNetwork};
+///
+/// // Storage for the toggles of a width 16 network
+/// static TOGGLES: [bool; bitonic_balancer_count(16)] = [true; bitonic_balancer_count(16)];
+///
+/// assert_eq!(TOGGLES.len(), 80);
+/// assert_eq!(
+///     BitonicNetwork::new(vec![(); 16]).topology().balancers().len(),
+///     TOGGLES.len()
+/// );
+/// ```
+pub const fn bitonic_balancer_count(width: usize) -> usize {
+    ((1 << log2_rounded_up(width)) / 2) * bitonic_depth(width)
+}
 
-    /// Get references to all the outputs of the network.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use counting_networks::networks::BitonicNetwork;
-    ///
-    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
-    ///
-    /// assert_eq!(network.outputs(), &[1, 2, 3, 4]);
-    /// ```
-    pub fn outputs(&self) -> &[L] {
-        self.0.outputs()
+// The base 2 logarithm of the width that `Width::for_threads` rounds `width` up
+// to, which is zero for widths of zero and one.
+const fn log2_rounded_up(width: usize) -> usize {
+    let max_log = mem::size_of::<usize>() * 8 - 1;
+
+    if width <= 1 {
+        0
+    } else if (width - 1).leading_zeros() == 0 {
+        max_log
+    } else {
+        max_log + 1 - (width - 1).leading_zeros() as usize
     }
 }
 
+/// The configuration of balancers in a [`BitonicNetwork`].
+///
+/// Only supports widths that are a power of two. This is not a limit of the
+/// bitonic construction alone: a counting network built from balancers with
+/// two wires, like every network in this crate, can only exist when its width
+/// is a power of two, as shown by Aharonson and Attiya in "Counting Networks
+/// with Arbitrary Fan-Out". Padding a wider network with extra wires, for
+/// example as in Batcher's odd-even merge, gives a network that sorts but does
+/// not count on the remaining wires.
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct BitonicConfiguration(usize);
+pub struct BitonicConfiguration(usize);
 
 impl IntoIterator for BitonicConfiguration {
     type IntoIter = BitonicConfigurationIter;
@@ -166,24 +162,64 @@ impl IntoIterator for BitonicConfiguration {
         BitonicConfigurationIter {
             stack: vec![BitonicStep::Split(0..self.0)],
             output_stack: vec![],
+            remaining: bitonic_balancer_count(self.0),
         }
     }
 }
 
 impl NetworkConfiguration for BitonicConfiguration {
-    fn from_width(width: usize) -> Self {
-        BitonicConfiguration(width)
+    fn from_width(width: Width) -> Self {
+        BitonicConfiguration(width.get())
     }
 }
 
+/// An iterator over the balancers of a [`BitonicConfiguration`].
+///
+/// The order that balancers are yielded in is part of the public API, and will
+/// not change without a breaking release. For a width `w` network,
+/// `Bitonic[w]` yields:
+///
+///  1. The balancers of `Bitonic[w / 2]` on the top half of the wires.
+///  2. The balancers of `Bitonic[w / 2]` on the bottom half of the wires.
+///  3. The balancers of `Merge[w]` on all the wires.
+///
+/// `Merge[k]` over a sequence of wires `x` splits `x` into a top half `t` and a
+/// bottom half `b`, then yields:
+///
+///  1. The balancers of `Merge[k / 2]` on the even positions of `t` followed by
+///     the odd positions of `b`.
+///  2. The balancers of `Merge[k / 2]` on the odd positions of `t` followed by
+///     the even positions of `b`.
+///  3. The balancers `(x[0], x[1])`, `(x[2], x[3])` and so on, pairing each
+///     even position of `x` with the position after it.
+///
+/// `Bitonic[1]` yields nothing, and `Merge[2]` yields the single balancer
+/// `(x[0], x[1])`.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{BitonicConfiguration, NetworkConfiguration, Width};
+///
+/// let width = Width::new(4).unwrap();
+/// let balancers: Vec<_> = BitonicConfiguration::from_width(width).into_iter().collect();
+///
+/// assert_eq!(
+///     balancers,
+///     &[(0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)]
+/// );
+/// ```
 #[derive(Debug, Clone)]
 pub struct BitonicConfigurationIter {
     stack: Vec<BitonicStep>,
     output_stack: Vec<usize>,
+    remaining: usize,
 }
 
 impl FusedIterator for BitonicConfigurationIter {}
 
+impl ExactSizeIterator for BitonicConfigurationIter {}
+
 #[derive(Debug, Clone)]
 enum BitonicStep {
     Split(Range<usize>),
@@ -276,6 +312,7 @@ impl Iterator for BitonicConfigurationIter {
                 BitonicStep::Split(wires) => self.split(wires),
                 BitonicStep::Merge(width) => self.merge(width),
                 BitonicStep::Output(balancer) => {
+                    self.remaining -= 1;
                     return Some(balancer);
                 }
             }
@@ -283,11 +320,16 @@ impl Iterator for BitonicConfigurationIter {
 
         None
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{sync::Arc, thread};
 
     /// Split[4]: (0..4)
     ///      - Split[2]: (0..2)
@@ -307,6 +349,26 @@ mod tests {
     ///              - Output (1, 2)
     ///          - Output (0, 1)
     ///          - Output (2, 3)
+    #[test]
+    fn sizes_match_configuration() {
+        for width in (0..8).map(|exp| 1 << exp) {
+            let network = BitonicNetwork::new(vec![(); width]);
+            let topology = network.topology();
+
+            assert_eq!(bitonic_depth(width), topology.depth());
+            assert_eq!(bitonic_balancer_count(width), topology.balancers().len());
+        }
+    }
+
+    #[test]
+    fn sizes_of_other_widths() {
+        assert_eq!(bitonic_depth(0), 0);
+        assert_eq!(bitonic_balancer_count(0), 0);
+        assert_eq!(bitonic_depth(12), bitonic_depth(16));
+        assert_eq!(bitonic_balancer_count(12), bitonic_balancer_count(16));
+        assert_eq!(bitonic_depth(usize::MAX), bitonic_depth(Width::MAX.get()));
+    }
+
     #[test]
     fn bitonic_4_configuration() {
         let config = BitonicConfiguration(4);
@@ -412,6 +474,30 @@ mod tests {
         )
     }
 
+    #[test]
+    fn bitonic_configuration_len() {
+        for width in (0..10).map(|exp| 1 << exp) {
+            let mut iter = BitonicConfiguration(width).into_iter();
+            let expected = iter.len();
+
+            let mut actual = 0;
+            while iter.next().is_some() {
+                actual += 1;
+                assert_eq!(iter.len(), expected - actual);
+            }
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn bitonic_small_configurations() {
+        let balancers: Vec<_> = BitonicConfiguration(1).into_iter().collect();
+        assert_eq!(&balancers, &[]);
+
+        let balancers: Vec<_> = BitonicConfiguration(2).into_iter().collect();
+        assert_eq!(&balancers, &[(0, 1)]);
+    }
+
     #[test]
     fn is_send() {
         fn send_only<T: Send>(_: T) {}
@@ -436,7 +522,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    #[should_panic(expected = "width must be a power of two, not 3")]
     fn initialize_network_bad_width() {
         let _ = BitonicNetwork::new(vec![1, 2, 3]);
     }
@@ -451,4 +537,20 @@ mod tests {
             assert_eq!(network.traverse(), &output);
         }
     }
+
+    #[test]
+    fn traverse_network_from_many_wires() {
+        const WIDTH: usize = 32;
+        let network = Arc::new(BitonicNetwork::new((0..WIDTH).collect::<Vec<_>>()));
+
+        // Each thread enters the network on a wire picked from its id, so running
+        // them one after the other sends sequential tokens in through many
+        // different wires.
+        for expected in (0..WIDTH).cycle().take(4 * WIDTH) {
+            let network = Arc::clone(&network);
+            let output = thread::spawn(move || *network.traverse()).join().unwrap();
+
+            assert_eq!(output, expected);
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use super::{
+    common::{Network, NetworkConfiguration},
+    width::Width,
+};
+use std::vec;
+
+/// A type of smoothing network
+///
+/// A butterfly network of width `w` has `log2(w)` layers. The first layer
+/// pairs each wire in the top half with the wire `w / 2` below it, and each
+/// following layer does the same within halves of the previous pairing, ending
+/// with neighbouring wires:
+///
+/// ```text
+/// x0 ───╥───────╥─── y0
+/// x1 ───║──╥────╨─── y1
+/// x2 ───╨──║────╥─── y2
+/// x3 ──────╨────╨─── y3
+/// ```
+///
+/// This is not a counting network: tokens do not leave in order. It is a
+/// smoothing network, so in every quiescent state the number of tokens that
+/// left on any two wires differs by at most `log2(w)`. In return it is much
+/// shallower than a [`BitonicNetwork`](super::BitonicNetwork), which has
+/// `log2(w) * (log2(w) + 1) / 2` layers.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::ButterflyNetwork;
+///
+/// let network = ButterflyNetwork::new(vec![1, 2, 3, 4]);
+///
+/// assert_eq!(network.width(), 4);
+/// assert_eq!(network.topology().depth(), 2);
+/// ```
+pub type ButterflyNetwork<L> = Network<L, ButterflyConfiguration>;
+
+/// The configuration of balancers in a [`ButterflyNetwork`].
+///
+/// Only supports widths that are a power of two. Balancers are yielded layer
+/// by layer, and from the top wire down within each layer.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{ButterflyConfiguration, NetworkConfiguration, Width};
+///
+/// let width = Width::new(4).unwrap();
+/// let balancers: Vec<_> = ButterflyConfiguration::from_width(width).into_iter().collect();
+///
+/// assert_eq!(balancers, &[(0, 2), (1, 3), (0, 1), (2, 3)]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ButterflyConfiguration(usize);
+
+impl IntoIterator for ButterflyConfiguration {
+    type IntoIter = vec::IntoIter<(usize, usize)>;
+    type Item = (usize, usize);
+
+    fn into_iter(self) -> Self::IntoIter {
+        let width = self.0;
+        let mut balancers = Vec::with_capacity((width / 2) * width.trailing_zeros() as usize);
+
+        let mut distance = width / 2;
+        while distance > 0 {
+            balancers.extend(
+                (0..width)
+                    .filter(|wire| wire & distance == 0)
+                    .map(|wire| (wire, wire | distance)),
+            );
+            distance /= 2;
+        }
+
+        balancers.into_iter()
+    }
+}
+
+impl NetworkConfiguration for ButterflyConfiguration {
+    fn from_width(width: Width) -> Self {
+        ButterflyConfiguration(width.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::Topology;
+
+    #[test]
+    fn butterfly_layers() {
+        for width in (0..8).map(|exp| 1usize << exp) {
+            let topology = Topology::from_config::<ButterflyConfiguration>(Width::of(width));
+
+            assert_eq!(topology.depth(), width.trailing_zeros() as usize);
+            assert_eq!(
+                topology.balancers().len(),
+                (width / 2) * width.trailing_zeros() as usize
+            );
+        }
+    }
+
+    #[test]
+    fn quiescent_skew_is_bounded() {
+        for width in (1..7).map(|exp| 1usize << exp) {
+            let network = ButterflyNetwork::new((0..width).collect());
+            let max_skew = width.trailing_zeros() as usize;
+            let mut counts = vec![0; width];
+
+            // Every state of a single thread's traversals is quiescent
+            for key in 0..(width * 40) {
+                counts[*network.traverse_keyed(&(key * key % 97))] += 1;
+
+                let fewest = counts.iter().min().unwrap();
+                let most = counts.iter().max().unwrap();
+                assert!(most - fewest <= max_skew, "{:?}", counts);
+            }
+        }
+    }
+}
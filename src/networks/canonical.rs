@@ -0,0 +1,267 @@
+use super::{common::NetworkConfiguration, width::Width};
+use core::fmt;
+
+/// Returns the balancers of the configuration `C` for a network of the given
+/// width, in the order the configuration yields them.
+///
+/// The list is in the same form that [`NetworkConfiguration`] describes, so it
+/// can be stored and compared against later versions of a configuration with
+/// [`diff_configs`].
+///
+/// # Panics
+///
+/// Panics if the configuration does not support networks of the given width.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{canonical_config, BitonicConfiguration, Width};
+///
+/// assert_eq!(
+///     canonical_config::<BitonicConfiguration>(Width::new(4).unwrap()),
+///     vec![(0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)]
+/// );
+/// ```
+pub fn canonical_config<C: NetworkConfiguration>(width: Width) -> Vec<(usize, usize)> {
+    C::from_width(width).into_iter().collect()
+}
+
+/// A single difference between two balancer lists, as found by
+/// [`diff_configs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigDifference {
+    /// Both lists have a balancer at `index`, but they are on different wires.
+    Changed {
+        /// Position of the balancer in both lists.
+        index: usize,
+        /// The balancer in the expected list.
+        expected: (usize, usize),
+        /// The balancer in the actual list.
+        actual: (usize, usize),
+    },
+    /// The expected list has a balancer at `index`, but the actual list ends
+    /// before it.
+    Missing {
+        /// Position of the balancer in the expected list.
+        index: usize,
+        /// The balancer in the expected list.
+        expected: (usize, usize),
+    },
+    /// The actual list has a balancer at `index`, but the expected list ends
+    /// before it.
+    Extra {
+        /// Position of the balancer in the actual list.
+        index: usize,
+        /// The balancer in the actual list.
+        actual: (usize, usize),
+    },
+}
+
+impl fmt::Display for ConfigDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigDifference::Changed {
+                index,
+                expected: (expected_top, expected_bottom),
+                actual: (actual_top, actual_bottom),
+            } => write!(
+                f,
+                "balancer {} changed from ({}, {}) to ({}, {})",
+                index, expected_top, expected_bottom, actual_top, actual_bottom
+            ),
+            ConfigDifference::Missing {
+                index,
+                expected: (top, bottom),
+            } => write!(f, "balancer {} ({}, {}) is missing", index, top, bottom),
+            ConfigDifference::Extra {
+                index,
+                actual: (top, bottom),
+            } => write!(f, "balancer {} ({}, {}) is extra", index, top, bottom),
+        }
+    }
+}
+
+/// Compare two balancer lists position by position.
+///
+/// Because the order of balancers determines how a network is wired, two lists
+/// that contain the same balancers in a different order are reported as
+/// different. Returns an empty list if the lists are identical.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{
+///     canonical_config, diff_configs, BitonicConfiguration, ConfigDifference, Width,
+/// };
+///
+/// let expected = vec![(0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)];
+/// let actual = canonical_config::<BitonicConfiguration>(Width::new(4).unwrap());
+/// assert!(diff_configs(&expected, &actual).is_empty());
+///
+/// let differences = diff_configs(&expected, &actual[..5]);
+/// assert_eq!(
+///     differences,
+///     vec![ConfigDifference::Missing {
+///         index: 5,
+///         expected: (2, 3)
+///     }]
+/// );
+/// assert_eq!(differences[0].to_string(), "balancer 5 (2, 3) is missing");
+/// ```
+pub fn diff_configs(
+    expected: &[(usize, usize)],
+    actual: &[(usize, usize)],
+) -> Vec<ConfigDifference> {
+    let common = expected.len().min(actual.len());
+
+    let changed = expected
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|(_, (expected, actual))| expected != actual)
+        .map(|(index, (&expected, &actual))| ConfigDifference::Changed {
+            index,
+            expected,
+            actual,
+        });
+    let missing = expected[common..]
+        .iter()
+        .enumerate()
+        .map(|(offset, &expected)| ConfigDifference::Missing {
+            index: common + offset,
+            expected,
+        });
+    let extra = actual[common..]
+        .iter()
+        .enumerate()
+        .map(|(offset, &actual)| ConfigDifference::Extra {
+            index: common + offset,
+            actual,
+        });
+
+    changed.chain(missing).chain(extra).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::BitonicConfiguration;
+
+    #[test]
+    fn bitonic_16_configuration() {
+        let expected = [
+            (0, 1),
+            (2, 3),
+            (0, 3),
+            (1, 2),
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (4, 7),
+            (5, 6),
+            (4, 5),
+            (6, 7),
+            (0, 7),
+            (2, 5),
+            (0, 2),
+            (5, 7),
+            (1, 6),
+            (3, 4),
+            (1, 3),
+            (4, 6),
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (8, 9),
+            (10, 11),
+            (8, 11),
+            (9, 10),
+            (8, 9),
+            (10, 11),
+            (12, 13),
+            (14, 15),
+            (12, 15),
+            (13, 14),
+            (12, 13),
+            (14, 15),
+            (8, 15),
+            (10, 13),
+            (8, 10),
+            (13, 15),
+            (9, 14),
+            (11, 12),
+            (9, 11),
+            (12, 14),
+            (8, 9),
+            (10, 11),
+            (12, 13),
+            (14, 15),
+            (0, 15),
+            (4, 11),
+            (0, 4),
+            (11, 15),
+            (2, 13),
+            (6, 9),
+            (2, 6),
+            (9, 13),
+            (0, 2),
+            (4, 6),
+            (9, 11),
+            (13, 15),
+            (1, 14),
+            (5, 10),
+            (1, 5),
+            (10, 14),
+            (3, 12),
+            (7, 8),
+            (3, 7),
+            (8, 12),
+            (1, 3),
+            (5, 7),
+            (8, 10),
+            (12, 14),
+            (0, 1),
+            (2, 3),
+            (4, 5),
+            (6, 7),
+            (8, 9),
+            (10, 11),
+            (12, 13),
+            (14, 15),
+        ];
+
+        let actual = canonical_config::<BitonicConfiguration>(Width::of(16));
+
+        assert_eq!(diff_configs(&expected, &actual), vec![]);
+    }
+
+    #[test]
+    fn diff_changed_and_extra() {
+        let expected = [(0, 1), (2, 3)];
+        let actual = [(0, 1), (1, 2), (2, 3)];
+
+        let differences = diff_configs(&expected, &actual);
+
+        assert_eq!(
+            differences,
+            vec![
+                ConfigDifference::Changed {
+                    index: 1,
+                    expected: (2, 3),
+                    actual: (1, 2)
+                },
+                ConfigDifference::Extra {
+                    index: 2,
+                    actual: (2, 3)
+                }
+            ]
+        );
+        assert_eq!(
+            differences[0].to_string(),
+            "balancer 1 changed from (2, 3) to (1, 2)"
+        );
+        assert_eq!(differences[1].to_string(), "balancer 2 (2, 3) is extra");
+    }
+}
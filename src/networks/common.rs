@@ -1,11 +1,28 @@
-use crate::util::{hash_single, slice_to_ptr_range};
+use super::{
+    topology::{check_balancer, group_layers, Topology},
+    width::Width,
+};
+use crate::util::{hash_single, try_vec_with_capacity};
 use core::{
     any::type_name,
     fmt,
     hash::{Hash, Hasher},
     marker::PhantomData,
+    mem,
+    ops::Range,
 };
-use std::thread;
+use std::{error::Error, thread};
+
+#[cfg(feature = "observer")]
+use std::sync::Arc;
+
+#[cfg(feature = "rayon")]
+use core::iter;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(feature = "testing")]
+use crate::testing::jitter::{self, InjectionPoint};
 
 #[cfg(all(test, loom))]
 mod atomic {
@@ -19,33 +36,31 @@ mod atomic {
 
 use atomic::AtomicBool;
 
+// Segments link to each other, and to the outputs, by index rather than by
+// pointer. This keeps the traversal entirely safe code, at the cost of a bounds
+// check per segment.
 #[derive(Debug)]
-pub enum WireSegment<L> {
-    Balancer(Balancer<L>),
-    End(*const L),
+pub enum WireSegment {
+    Balancer(Balancer),
+    // Index into the outputs of the network
+    End(usize),
 }
 
 // Align struct to cache size (Intel)
 // This prevents false sharing of the balancer between multiple cores.
 #[repr(align(64))]
 #[derive(Debug)]
-pub struct Balancer<L> {
+pub struct Balancer {
     pub value: AtomicBool,
-    pub next_segments: [*const WireSegment<L>; 2],
+    // The pair of wires the balancer sits on, as given by the configuration.
+    // Only used to describe the network, never while traversing it.
+    pub wires: [usize; 2],
+    // Indices of the segments that follow this balancer, always less than the
+    // index of the balancer itself.
+    pub next_segments: [usize; 2],
 }
 
-impl<L> Balancer<L> {
-    pub fn next_segment(&self) -> &WireSegment<L> {
-        let next_index = self.toggle_up();
-        // TODO: Write safety comment
-        unsafe {
-            self.next_segments
-                .get_unchecked(next_index)
-                .as_ref()
-                .expect("pointer should never be null")
-        }
-    }
-
+impl Balancer {
     // false -> 0, true -> 1
     pub fn toggle_up(&self) -> usize {
         self.value.fetch_xor(true, atomic::Ordering::Relaxed) as usize
@@ -58,7 +73,7 @@ impl<L> Balancer<L> {
 //
 // Given a width of 4 and the pairs `[(a,b), (c,d), (b,c), (a,d), (a,b), (c,d)]`
 // the network below will be produced. The balancers are always given in a
-// back-to-front, top-to-bottom order.
+// front-to-back, top-to-bottom order.
 //
 // Count depth from end of network to start of network. This will be used to
 // label nodes uniquely. For example, the top wire in the network below will
@@ -87,77 +102,468 @@ impl<L> Balancer<L> {
 // [((a3, b3), a, (a2, d2)), ((a3, b3), b, (b2, c2))]
 // ```
 
-// Creates an `Iterator` that will yield balancers. Each balancer is a link
-// between two wires, represented as two `usize` indexes.
+/// A description of the balancers that make up a network of a given width.
+///
+/// Implementations yield each balancer as a pair of wire indices `(a, b)`.
+/// Tokens leave a balancer alternately on wire `a` and wire `b`, starting with
+/// wire `a`. Balancers are yielded in front-to-back order: the first balancer
+/// yielded is the one closest to the inputs and the last is the one closest to
+/// the outputs. Wire `i` ends at the `i`th output of the network.
+///
+/// Networks are built in a single allocation when the iterator's `size_hint`
+/// is exact.
+///
+/// # Examples
+///
+/// A network consisting of a single balancer:
+///
+/// ```
+/// use counting_networks::networks::{Network, NetworkConfiguration, Width};
+/// use std::iter;
+///
+/// #[derive(Debug)]
+/// struct SingleBalancer;
+///
+/// impl IntoIterator for SingleBalancer {
+///     type IntoIter = iter::Once<(usize, usize)>;
+///     type Item = (usize, usize);
+///
+///     fn into_iter(self) -> Self::IntoIter {
+///         iter::once((0, 1))
+///     }
+/// }
+///
+/// impl NetworkConfiguration for SingleBalancer {
+///     fn from_width(width: Width) -> Self {
+///         assert_eq!(width.get(), 2);
+///
+///         SingleBalancer
+///     }
+/// }
+///
+/// let network: Network<_, SingleBalancer> = Network::new(vec!['a', 'b']);
+///
+/// assert_eq!(network.traverse(), &'a');
+/// assert_eq!(network.traverse(), &'b');
+/// assert_eq!(network.traverse(), &'a');
+/// ```
 pub trait NetworkConfiguration: IntoIterator<Item = (usize, usize)> {
-    fn from_width(width: usize) -> Self;
+    /// Create the configuration for a network with the given width.
+    ///
+    /// Implementations should panic if the configuration does not support
+    /// networks of the given width, which is always a power of two.
+    fn from_width(width: Width) -> Self;
 }
 
+/// The states that the balancers of a network start in.
+///
+/// A balancer whose toggle is up sends its next token to the top wire of its
+/// pair, see [`NetworkConfiguration`]. Networks start with every toggle up,
+/// which sends the first tokens through a network towards the same wires.
+/// The other states spread out the first tokens, or make experiments
+/// reproducible from a seed.
+///
+/// Counting networks are only guaranteed to count when every balancer starts
+/// up. Networks that start in other states still balance tokens between
+/// wires, but a counter built on one can hand out values out of order even
+/// when used from a single thread.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{BitonicNetwork, InitialToggles};
+///
+/// let network = BitonicNetwork::new(vec!['a', 'b']).with_initial_toggles(InitialToggles::Alternating);
+///
+/// // The only balancer is the first one, which starts up.
+/// assert_eq!(network.traverse(), &'a');
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InitialToggles {
+    /// Every balancer starts up.
+    Up,
+    /// Balancers start alternately up and down, in the order they are yielded
+    /// by the configuration, starting with up.
+    Alternating,
+    /// Each balancer starts in a state chosen pseudo-randomly from the seed
+    /// and its position in the configuration. The same seed always gives the
+    /// same states within a version of this crate.
+    Seeded(u64),
+}
+
+impl InitialToggles {
+    // Whether the balancer at `position` in the configuration starts up.
+    fn toggle(self, position: usize) -> bool {
+        match self {
+            InitialToggles::Up => true,
+            InitialToggles::Alternating => position & 1 == 0,
+            InitialToggles::Seeded(seed) => {
+                // SplitMix64 of the seed and position
+                let mut mixed = seed.wrapping_add(
+                    (position as u64)
+                        .wrapping_add(1)
+                        .wrapping_mul(0x9E37_79B9_7F4A_7C15),
+                );
+                mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                mixed ^= mixed >> 31;
+
+                mixed & 1 == 1
+            }
+        }
+    }
+}
+
+/// A balancing network with outputs of type `L`, with the layout of balancers
+/// described by the configuration `B`.
+///
+/// See [the module level documentation](index.html) for general information
+/// about counting networks, and [`NetworkConfiguration`] for how to describe a
+/// new layout of balancers.
 pub struct Network<L, B> {
-    // Marker for network Builder type
-    _marker: PhantomData<B>,
+    // Marker for network Builder type, the configuration is never stored so it
+    // does not effect `Send` or `Sync`.
+    _marker: PhantomData<fn() -> B>,
     // Width of the network
     width: usize,
     // Outputs of the network
     outputs: Box<[L]>,
-    // Pointers to segments' memory locations
-    segments: Box<[WireSegment<L>]>,
+    // Wire ends followed by balancers, each balancer only links to segments before it
+    segments: Box<[WireSegment]>,
     // Indices that point to the last segment for each wire, `len` should be equal to `width`.
     last_segments: Box<[usize]>,
+    // Number of balancers on the longest path through the network.
+    depth: usize,
+    // State the balancers start in, and return to on reset
+    initial_toggles: InitialToggles,
+    // Label shown in diagnostics
+    name: Option<String>,
+    // Called at the end of every traversal
+    #[cfg(feature = "observer")]
+    observer: Option<Arc<Observer>>,
+}
+
+#[cfg(feature = "observer")]
+type Observer = dyn Fn(&TraversalEvent) + Send + Sync;
+
+/// A description of a single traversal of a network, passed to the observer
+/// set with [`Network::with_observer`].
+///
+/// Only available with the `observer` feature.
+#[cfg(feature = "observer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraversalEvent {
+    entry_wire: usize,
+    exit_wire: usize,
+    path_length: usize,
+}
+
+#[cfg(feature = "observer")]
+impl TraversalEvent {
+    /// Returns the wire that the token entered the network on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let entries = Arc::new(Mutex::new(Vec::new()));
+    /// let recorded = Arc::clone(&entries);
+    /// let network = BitonicNetwork::with_observer(vec![1, 2, 3, 4], move |event| {
+    ///     recorded.lock().unwrap().push(event.entry_wire());
+    /// });
+    ///
+    /// network.traverse();
+    ///
+    /// assert!(entries.lock().unwrap()[0] < 4);
+    /// ```
+    pub fn entry_wire(&self) -> usize {
+        self.entry_wire
+    }
+
+    /// Returns the wire that the token left the network on, which is also the
+    /// index of the output it reached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let exits = Arc::new(Mutex::new(Vec::new()));
+    /// let recorded = Arc::clone(&exits);
+    /// let network = BitonicNetwork::with_observer(vec![1, 2, 3, 4], move |event| {
+    ///     recorded.lock().unwrap().push(event.exit_wire());
+    /// });
+    ///
+    /// for _ in 0..4 {
+    ///     network.traverse();
+    /// }
+    ///
+    /// assert_eq!(*exits.lock().unwrap(), vec![0, 1, 2, 3]);
+    /// ```
+    pub fn exit_wire(&self) -> usize {
+        self.exit_wire
+    }
+
+    /// Returns the number of balancers that the token passed through.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let lengths = Arc::new(Mutex::new(Vec::new()));
+    /// let recorded = Arc::clone(&lengths);
+    /// let network = BitonicNetwork::with_observer(vec![1, 2, 3, 4], move |event| {
+    ///     recorded.lock().unwrap().push(event.path_length());
+    /// });
+    ///
+    /// network.traverse();
+    ///
+    /// // Every path through `Bitonic[4]` passes through 3 balancers
+    /// assert_eq!(*lengths.lock().unwrap(), vec![3]);
+    /// ```
+    pub fn path_length(&self) -> usize {
+        self.path_length
+    }
 }
 
 impl<L, B: NetworkConfiguration> Network<L, B> {
+    /// Construct a new network with the given outputs, one for each wire.
+    ///
+    /// Outputs must be ordered corresponding to how they should appear in the
+    /// network.
+    ///
+    /// For example in a 4-width network:
+    ///
+    /// ```text
+    /// xi = ith input
+    /// yi = ith output
+    ///
+    /// x1 ─────╥────╥─────╥─── y1
+    /// x2 ─────╨────║──╥──╨─── y2
+    /// x3 ─────╥────║──╨──╥─── y3
+    /// x4 ─────╨────╨─────╨─── y4
+    /// ```
+    ///
+    /// The outputs passed through should appear [y1, y2, y3, y4]
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of outputs is not a power of two, if the
+    /// configuration does not support a network of that width, or if it
+    /// yields a balancer that connects a wire to itself or uses a wire outside
+    /// the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, Network};
+    ///
+    /// let network: Network<_, BitonicConfiguration> = Network::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.width(), 4);
+    /// assert_eq!(network.outputs(), &[1, 2, 3, 4]);
+    /// ```
     pub fn new(outputs: Vec<L>) -> Self {
-        assert!(!outputs.is_empty());
+        let balancers = B::from_width(Width::of(outputs.len()));
 
-        let outputs = outputs.into_boxed_slice();
-        let width = outputs.len();
-        let config = B::from_width(width);
+        Network::from_balancer_iter(outputs, balancers)
+    }
 
-        let mut next_segment_idx = width;
-        let mut latest_segments: Vec<usize> = (0..width).collect();
-        let mut balancers = Vec::new();
+    /// Construct a new network with the given outputs, like
+    /// [`Network::new`], returning an error instead of aborting if the
+    /// segments of the network can't be allocated.
+    ///
+    /// The segments are the only allocation that grows faster than the width,
+    /// so this is the one that can fail for very wide networks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memory for the segments of the network can't be
+    /// allocated.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`Network::new`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::try_new(vec![1, 2, 3, 4]).unwrap();
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// ```
+    pub fn try_new(outputs: Vec<L>) -> Result<Self, AllocError> {
+        let balancers = B::from_width(Width::of(outputs.len()));
 
-        // Populate a list of pairs of index pointers for the `next_segments` field of
-        // `Balancers`
-        for (top_wire, bottom_wire) in config {
-            let balancer_ptrs = (latest_segments[top_wire], latest_segments[bottom_wire]);
-            balancers.push(balancer_ptrs);
+        Network::try_from_balancer_iter(outputs, balancers)
+    }
 
-            latest_segments[top_wire] = next_segment_idx;
-            latest_segments[bottom_wire] = next_segment_idx;
-            next_segment_idx += 1;
+    /// Construct a new network with the given outputs, like
+    /// [`Network::new`], that calls `observer` at the end of every traversal.
+    ///
+    /// The observer runs on the traversing thread, after the token has left
+    /// the network and before [`traverse`](Network::traverse) returns, so it
+    /// should be cheap. Clones of the network share the observer.
+    ///
+    /// Only available with the `observer` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`Network::new`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use std::sync::{
+    ///     atomic::{AtomicUsize, Ordering},
+    ///     Arc,
+    /// };
+    ///
+    /// let traversals = Arc::new(AtomicUsize::new(0));
+    /// let counted = Arc::clone(&traversals);
+    /// let network = BitonicNetwork::with_observer(vec![1, 2], move |_| {
+    ///     counted.fetch_add(1, Ordering::Relaxed);
+    /// });
+    ///
+    /// network.traverse();
+    /// network.traverse();
+    ///
+    /// assert_eq!(traversals.load(Ordering::Relaxed), 2);
+    /// ```
+    #[cfg(feature = "observer")]
+    pub fn with_observer<F>(outputs: Vec<L>, observer: F) -> Self
+    where
+        F: Fn(&TraversalEvent) + Send + Sync + 'static,
+    {
+        Network {
+            observer: Some(Arc::new(observer)),
+            ..Network::new(outputs)
         }
+    }
+}
 
-        // This `Vec` should never be resized.
-        let mut segments = Vec::with_capacity(next_segment_idx);
+impl<B: NetworkConfiguration> Network<(), B> {
+    /// Construct a new network of the given width without outputs, for using
+    /// the network only to pick one of `width` wires with
+    /// [`traverse_indexed`](Network::traverse_indexed).
+    ///
+    /// The outputs are zero-sized, so no memory is allocated for them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration does not support a network of the given
+    /// width, or yields a balancer that connects a wire to itself or uses a
+    /// wire outside the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicNetwork, Width};
+    ///
+    /// let network = BitonicNetwork::with_width(Width::new(4).unwrap());
+    ///
+    /// assert_eq!(network.traverse_indexed(), 0);
+    /// assert_eq!(network.traverse_indexed(), 1);
+    /// assert_eq!(network.memory_usage().outputs(), 0);
+    /// ```
+    pub fn with_width(width: Width) -> Self {
+        Network::from_balancer_iter(vec![(); width.get()], B::from_width(width))
+    }
+}
 
-        // Add the outputs to the segments
-        segments.extend(
-            outputs
-                .iter()
-                .map(|out_ref| WireSegment::End(out_ref as *const _))
-                .rev(),
-        );
+impl<L, B> Network<L, B> {
+    // Construct a network from the balancers of a configuration, in the order
+    // that `NetworkConfiguration` describes.
+    pub(crate) fn from_balancer_iter<I>(outputs: Vec<L>, balancers: I) -> Self
+    where
+        I: IntoIterator<Item = (usize, usize)>,
+    {
+        let balancers = balancers.into_iter();
+        let segments = Vec::with_capacity(outputs.len() + balancers.size_hint().0);
 
-        // Add the balancers to the segments
-        for (top_segment_idx, bottom_balancer_idx) in balancers {
-            let top_segment_ptr = &segments[top_segment_idx] as *const _;
-            let bottom_segment_ptr = &segments[bottom_balancer_idx] as *const _;
+        Network::from_balancers_in(outputs, balancers, segments)
+    }
+
+    // Like `from_balancer_iter`, returning an error if the segments can't be
+    // allocated.
+    pub(crate) fn try_from_balancer_iter<I>(
+        outputs: Vec<L>,
+        balancers: I,
+    ) -> Result<Self, AllocError>
+    where
+        I: IntoIterator<Item = (usize, usize)>,
+    {
+        let balancers = balancers.into_iter();
+        let capacity = outputs
+            .len()
+            .checked_add(balancers.size_hint().0)
+            .ok_or_else(AllocError::overflow)?;
+        let segments = try_vec_with_capacity(capacity)?;
+
+        Ok(Network::from_balancers_in(outputs, balancers, segments))
+    }
+
+    // Build the network, writing its segments into the empty `segments`. If the
+    // configuration yields more balancers than `segments` has space for, it
+    // grows as usual.
+    fn from_balancers_in<I>(outputs: Vec<L>, balancers: I, mut segments: Vec<WireSegment>) -> Self
+    where
+        I: Iterator<Item = (usize, usize)>,
+    {
+        assert!(!outputs.is_empty());
 
-            let new_balancer = Balancer {
+        let outputs = outputs.into_boxed_slice();
+        let width = outputs.len();
+
+        // Write the wire ends, followed by the balancers in the order they are
+        // configured. The balancers are linked to the segments that follow them below.
+        segments.extend((0..width).map(WireSegment::End));
+        segments.extend(balancers.enumerate().map(|(idx, (top_wire, bottom_wire))| {
+            if let Err(error) = check_balancer(width, idx, (top_wire, bottom_wire)) {
+                panic!("invalid network configuration: {}", error);
+            }
+
+            WireSegment::Balancer(Balancer {
                 value: AtomicBool::new(true),
-                next_segments: [top_segment_ptr, bottom_segment_ptr],
-            };
+                wires: [top_wire, bottom_wire],
+                next_segments: [0, 0],
+            })
+        }));
+
+        // The segments are linked from the outputs backwards, so the balancers closest
+        // to the outputs need to come first.
+        segments[width..].reverse();
 
-            segments.push(WireSegment::Balancer(new_balancer));
+        let mut latest_segments: Vec<usize> = (0..width).collect();
+        let mut wire_depths = vec![0; width];
+
+        for (segment_idx, segment) in segments.iter_mut().enumerate().skip(width) {
+            if let WireSegment::Balancer(balancer) = segment {
+                let [top_wire, bottom_wire] = balancer.wires;
+                // The first token through a balancer takes `next_segments[1]`
+                balancer.next_segments = [latest_segments[bottom_wire], latest_segments[top_wire]];
+
+                latest_segments[top_wire] = segment_idx;
+                latest_segments[bottom_wire] = segment_idx;
+
+                let balancer_depth = wire_depths[top_wire].max(wire_depths[bottom_wire]) + 1;
+                wire_depths[top_wire] = balancer_depth;
+                wire_depths[bottom_wire] = balancer_depth;
+            }
         }
 
-        // Check that all points in WireSegments (the `output` and `End` pointers) fall
-        // within the bounds of either the `outputs` boxed slice or the `segments`
-        // vector.
-        debug_assert!(check_segment_ptrs_in_bounds(&segments, &outputs));
-        debug_assert_eq!(segments.len(), next_segment_idx);
+        // Check that all the indices in WireSegments (the `next_segments` and `End`
+        // indices) fall within the bounds of either the `outputs` boxed slice or the
+        // `segments` vector.
+        debug_assert!(check_segment_indices_in_bounds(&segments, outputs.len()));
 
         Network {
             _marker: PhantomData,
@@ -165,26 +571,275 @@ impl<L, B: NetworkConfiguration> Network<L, B> {
             outputs,
             segments: segments.into_boxed_slice(),
             last_segments: latest_segments.into_boxed_slice(),
+            depth: wire_depths.into_iter().max().unwrap_or(0),
+            initial_toggles: InitialToggles::Up,
+            name: None,
+            #[cfg(feature = "observer")]
+            observer: None,
         }
     }
 
+    /// Label the network with a name, which is shown in its `Debug` output.
+    ///
+    /// Names are only used to tell networks apart in diagnostics, and don't
+    /// need to be unique.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]).with_name("shards");
+    ///
+    /// assert_eq!(network.name(), Some("shards"));
+    /// assert!(format!("{:?}", network).contains("shards"));
+    /// ```
+    pub fn with_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.name = Some(name.into());
+
+        self
+    }
+
+    /// Returns the name of the network, if it was given one with
+    /// [`with_name`](Network::with_name).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]);
+    ///
+    /// assert_eq!(network.name(), None);
+    /// ```
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Start the balancers of the network in the states given by `toggles`,
+    /// instead of all sending their first token to the top wire.
+    ///
+    /// [`reset`](Network::reset) returns the balancers to these states. Only
+    /// [`InitialToggles::Up`] is guaranteed to give a counting network, see
+    /// [`InitialToggles`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicNetwork, InitialToggles};
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4])
+    ///     .with_initial_toggles(InitialToggles::Seeded(7));
+    ///
+    /// assert_eq!(network.initial_toggles(), InitialToggles::Seeded(7));
+    /// ```
+    pub fn with_initial_toggles(mut self, toggles: InitialToggles) -> Self {
+        self.initial_toggles = toggles;
+        self.reset();
+
+        self
+    }
+
+    /// Returns the states that the balancers of the network start in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicNetwork, InitialToggles};
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]);
+    ///
+    /// assert_eq!(network.initial_toggles(), InitialToggles::Up);
+    /// ```
+    pub fn initial_toggles(&self) -> InitialToggles {
+        self.initial_toggles
+    }
+
+    /// Returns the width of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.width(), 4);
+    /// ```
     pub fn width(&self) -> usize {
         self.width
     }
 
+    /// Traverse the network and obtain a reference to an output element.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// assert_eq!(network.traverse(), &2);
+    /// assert_eq!(network.traverse(), &3);
+    /// assert_eq!(network.traverse(), &4);
+    /// ```
     pub fn traverse(&self) -> &L {
-        let input_slot = (hash_single(thread::current().id()) as usize) % self.width;
-        let start_segment_idx = self.last_segments[input_slot];
-        let mut current_segment = &self.segments[start_segment_idx];
+        &self.outputs[self.traverse_indexed()]
+    }
+
+    /// Traverse the network and obtain a reference to an output element,
+    /// entering on a wire chosen from `key` instead of from the calling
+    /// thread.
+    ///
+    /// Traversals with equal keys always enter on the same wire. This is
+    /// useful when work moves between threads, for example in a work-stealing
+    /// runtime, so that entry wires can follow a logical task or connection
+    /// rather than the thread that happens to run it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    /// let connection_id = 42u64;
+    ///
+    /// assert_eq!(network.traverse_keyed(&connection_id), &1);
+    /// assert_eq!(network.traverse_keyed(&"request-7"), &2);
+    /// ```
+    pub fn traverse_keyed<K: Hash + ?Sized>(&self, key: &K) -> &L {
+        self.traverse_from(hash_single(key) as usize % self.width)
+    }
+
+    /// Traverse the network and obtain a reference to an output element,
+    /// entering on the input wire `entry`.
+    ///
+    /// This is useful for callers that route tokens themselves, for example by
+    /// giving each of their workers its own entry wire.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `entry` is not less than the width of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse_from(3), &1);
+    /// assert_eq!(network.traverse_from(3), &2);
+    /// assert_eq!(network.traverse_from(0), &3);
+    /// ```
+    pub fn traverse_from(&self, entry: usize) -> &L {
+        assert!(
+            entry < self.width,
+            "entry wire {} out of range for a network of width {}",
+            entry,
+            self.width
+        );
+
+        &self.outputs[self.traverse_index_from(entry)]
+    }
+
+    /// Traverse the network and obtain the index of the output reached, rather
+    /// than a reference to it.
+    ///
+    /// This is the same traversal as [`traverse`](Network::traverse), and is
+    /// useful when the network is only used to pick a wire, see
+    /// [`with_width`](Network::with_width).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec!['a', 'b']);
+    ///
+    /// assert_eq!(network.traverse_indexed(), 0);
+    /// assert_eq!(network.traverse(), &'b');
+    /// ```
+    pub fn traverse_indexed(&self) -> usize {
+        self.traverse_wire_within(0..self.width)
+    }
+
+    /// Returns the input wire that traversals from the current thread enter
+    /// the network on, without traversing it.
+    ///
+    /// [`traverse`](Network::traverse) and
+    /// [`traverse_indexed`](Network::traverse_indexed) pick the entry wire by
+    /// hashing the id of the calling thread, so threads that share an entry
+    /// wire contend on the same balancers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec!['a', 'b', 'c', 'd']);
+    /// let wire = network.wire_for_current_thread();
+    ///
+    /// assert!(wire < 4);
+    /// // The wire only depends on the thread
+    /// network.traverse();
+    /// assert_eq!(network.wire_for_current_thread(), wire);
+    /// ```
+    pub fn wire_for_current_thread(&self) -> usize {
+        self.entry_within(0..self.width)
+    }
 
-        while let WireSegment::Balancer(balancer) = current_segment {
-            current_segment = balancer.next_segment();
+    // The input wire that the current thread enters on, out of the given wires.
+    pub(crate) fn entry_within(&self, entries: Range<usize>) -> usize {
+        match entries.len() {
+            // Skip hashing the thread when there is only one choice
+            1 => entries.start,
+            len => entries.start + hash_single(thread::current().id()) as usize % len,
         }
+    }
+
+    // Like `traverse_indexed`, entering on one of the given input wires.
+    pub(crate) fn traverse_wire_within(&self, entries: Range<usize>) -> usize {
+        self.traverse_index_from(self.entry_within(entries))
+    }
+
+    fn traverse_index_from(&self, input_slot: usize) -> usize {
+        self.traverse_index_with(input_slot, |_, balancer| balancer.toggle_up())
+    }
+
+    // Traverse from the given input wire, with `toggle` picking which of the
+    // segments after each balancer to take. `toggle` is given the position of
+    // each balancer passed through, in `0..num_balancers()`, along with the
+    // balancer itself.
+    fn traverse_index_with<F>(&self, input_slot: usize, mut toggle: F) -> usize
+    where
+        F: FnMut(usize, &Balancer) -> usize,
+    {
+        let mut segment_idx = self.last_segments[input_slot];
+        #[cfg(feature = "observer")]
+        let mut path_length = 0;
 
-        match current_segment {
-            WireSegment::End(output_ptr) => {
-                // TODO: write unsafe explanation
-                unsafe { output_ptr.as_ref().expect("pointer should never be null") }
+        while let WireSegment::Balancer(balancer) = &self.segments[segment_idx] {
+            segment_idx = balancer.next_segments[toggle(segment_idx - self.width, balancer)];
+            #[cfg(feature = "testing")]
+            jitter::inject(InjectionPoint::AfterToggle);
+            #[cfg(feature = "observer")]
+            {
+                path_length += 1;
+            }
+        }
+
+        match &self.segments[segment_idx] {
+            WireSegment::End(output_idx) => {
+                #[cfg(feature = "observer")]
+                self.notify(TraversalEvent {
+                    entry_wire: input_slot,
+                    exit_wire: *output_idx,
+                    path_length,
+                });
+
+                *output_idx
             }
             WireSegment::Balancer(_) => unreachable!(
                 "previous loop conditioned off of this variable not being a `Balancer`"
@@ -192,14 +847,903 @@ impl<L, B: NetworkConfiguration> Network<L, B> {
         }
     }
 
+    /// Bring the memory that traversals read into the cache of the calling
+    /// thread, so that the first traversals after construction are not slowed
+    /// down by cache misses.
+    ///
+    /// This touches every balancer and entry point, without changing any of
+    /// them. The outputs are not touched, since reading them depends on their
+    /// type, see [`outputs`](Network::outputs). Call this from each thread that
+    /// will traverse the network to warm each of their caches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use std::{sync::Arc, thread};
+    ///
+    /// let network = Arc::new(BitonicNetwork::new(vec![1, 2, 3, 4]));
+    ///
+    /// let workers: Vec<_> = (0..4)
+    ///     .map(|_| {
+    ///         let network = Arc::clone(&network);
+    ///         thread::spawn(move || {
+    ///             network.prefault();
+    ///             *network.traverse()
+    ///         })
+    ///     })
+    ///     .collect();
+    ///
+    /// for worker in workers {
+    ///     assert!(worker.join().unwrap() <= 4);
+    /// }
+    /// ```
+    pub fn prefault(&self) {
+        // Atomic loads can't be optimized away, and each balancer fills a whole
+        // cache line, so loading its toggle brings in the rest of it.
+        let touch = |segment: &WireSegment| {
+            if let WireSegment::Balancer(balancer) = segment {
+                balancer.value.load(atomic::Ordering::Relaxed);
+            }
+        };
+
+        for &segment_idx in self.last_segments.iter() {
+            touch(&self.segments[segment_idx]);
+        }
+        for segment in self.segments.iter() {
+            touch(segment);
+        }
+    }
+
+    /// Restore every balancer to the state it was in when the network was
+    /// constructed, so the next traversals take the same paths as the first
+    /// traversals of a new network. See
+    /// [`with_initial_toggles`](Network::with_initial_toggles).
+    ///
+    /// The outputs are not changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let mut network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    /// let first = *network.traverse();
+    /// network.traverse();
+    ///
+    /// network.reset();
+    ///
+    /// assert_eq!(*network.traverse(), first);
+    /// ```
+    pub fn reset(&mut self) {
+        let num_balancers = self.num_balancers();
+
+        for (idx, segment) in self.segments[self.width..].iter().enumerate() {
+            if let WireSegment::Balancer(balancer) = segment {
+                // Balancers are stored closest to the outputs first
+                let toggle = self.initial_toggles.toggle(num_balancers - 1 - idx);
+                // No traversals can be running, since they borrow the network
+                balancer.value.store(toggle, atomic::Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the current state of every balancer, `true` for up, in the
+    /// order of [`topology().balancers()`](Topology::balancers).
+    ///
+    /// Balancers are read one at a time, so the states only describe a single
+    /// moment if no traversals are running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]);
+    /// assert_eq!(network.toggles(), vec![true]);
+    ///
+    /// network.traverse();
+    ///
+    /// assert_eq!(network.toggles(), vec![false]);
+    /// ```
+    pub fn toggles(&self) -> Vec<bool> {
+        let mut toggles: Vec<bool> = self.segments[self.width..]
+            .iter()
+            .filter_map(|segment| match segment {
+                WireSegment::Balancer(balancer) => {
+                    Some(balancer.value.load(atomic::Ordering::SeqCst))
+                }
+                WireSegment::End(_) => None,
+            })
+            .collect();
+        // Balancers are stored closest to the outputs first
+        toggles.reverse();
+
+        toggles
+    }
+
+    /// Set the state of every balancer, `true` for up, in the order of
+    /// [`topology().balancers()`](Topology::balancers), for example to
+    /// restore states saved with [`toggles`](Network::toggles).
+    ///
+    /// This does not change the [initial toggles](Network::initial_toggles)
+    /// that [`reset`](Network::reset) returns to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `toggles` does not have one state for each balancer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let mut network = BitonicNetwork::new(vec![1, 2]);
+    ///
+    /// network.set_toggles(&[false]);
+    ///
+    /// assert_eq!(network.traverse(), &2);
+    /// ```
+    pub fn set_toggles(&mut self, toggles: &[bool]) {
+        let num_balancers = self.num_balancers();
+        assert_eq!(
+            toggles.len(),
+            num_balancers,
+            "expected {} toggles, one for each balancer",
+            num_balancers
+        );
+
+        for (idx, segment) in self.segments[self.width..].iter().enumerate() {
+            if let WireSegment::Balancer(balancer) = segment {
+                // No traversals can be running, since they borrow the network
+                balancer
+                    .value
+                    .store(toggles[num_balancers - 1 - idx], atomic::Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Clone the network along with the current state of every balancer, so
+    /// the clone hands out outputs in the same order as the network from now
+    /// on. A plain [`clone`](Clone::clone) starts from the
+    /// [initial toggles](Network::initial_toggles) instead, like a new network.
+    ///
+    /// Balancers are read one at a time, see [`toggles`](Network::toggles), so
+    /// the snapshot is only consistent if no traversals are running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    /// network.traverse();
+    ///
+    /// assert_eq!(network.clone().traverse(), &1);
+    /// assert_eq!(network.clone_with_state().traverse(), &2);
+    /// assert_eq!(network.traverse(), &2);
+    /// ```
+    pub fn clone_with_state(&self) -> Self
+    where
+        L: Clone,
+    {
+        let mut cloned = self.clone();
+        cloned.set_toggles(&self.toggles());
+
+        cloned
+    }
+
+    /// Traverse the network and obtain a reference to an output element, along
+    /// with the balancers passed through on the way.
+    ///
+    /// Balancers are identified by their position in
+    /// [`topology().balancers()`](Topology::balancers), and listed in the order
+    /// that the token passed through them. This is the same traversal as
+    /// [`traverse`](Network::traverse), so it toggles the balancers in the same
+    /// way, but it allocates, so it is meant for diagnosing networks rather
+    /// than for normal use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]);
+    ///
+    /// assert_eq!(network.traverse_traced(), (&1, vec![0]));
+    /// assert_eq!(network.traverse_traced(), (&2, vec![0]));
+    /// ```
+    pub fn traverse_traced(&self) -> (&L, Vec<usize>) {
+        let mut path = Vec::with_capacity(self.depth);
+        let num_balancers = self.num_balancers();
+
+        let entry = self.entry_within(0..self.width);
+        let output_idx = self.traverse_index_with(entry, |position, balancer| {
+            // Balancers are stored closest to the outputs first
+            path.push(num_balancers - 1 - position);
+
+            balancer.toggle_up()
+        });
+
+        (&self.outputs[output_idx], path)
+    }
+
+    // Traverse the wiring of the network, with the state of each balancer kept
+    // outside of the network. `toggle` is given the position of each balancer
+    // passed through, in `0..num_balancers()`, and returns the previous value
+    // of its toggle.
+    pub(crate) fn traverse_with<F>(&self, mut toggle: F) -> &L
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let entry = self.entry_within(0..self.width);
+
+        &self.outputs[self.traverse_index_with(entry, |position, _| toggle(position) as usize)]
+    }
+
+    /// Returns the number of balancers in the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.num_balancers(), 6);
+    /// ```
+    pub fn num_balancers(&self) -> usize {
+        self.segments.len() - self.width
+    }
+
+    /// Returns the number of balancers on the longest path through the
+    /// network, which is the most balancers that a traversal passes through.
+    ///
+    /// The depth is worked out when the network is built, so this is cheaper
+    /// than [`Topology::depth`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![(); 8]);
+    ///
+    /// assert_eq!(network.depth(), 6);
+    /// ```
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the number of layers of balancers in the network, where each
+    /// balancer is in the layer after the latest balancer before it on either
+    /// of its wires.
+    ///
+    /// Balancers in the same layer don't share any wires, so they could all be
+    /// passed at the same time. With this layering the number of layers is
+    /// always the same as the [`depth`](Network::depth).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::ButterflyNetwork;
+    ///
+    /// let network = ButterflyNetwork::new(vec![(); 8]);
+    ///
+    /// assert_eq!(network.num_layers(), 3);
+    /// assert_eq!(network.num_balancers(), 3 * 4);
+    /// ```
+    pub fn num_layers(&self) -> usize {
+        self.depth
+    }
+
+    #[cfg(feature = "observer")]
+    fn notify(&self, event: TraversalEvent) {
+        if let Some(observer) = &self.observer {
+            observer(&event);
+        }
+    }
+
+    /// Returns the layout of balancers in the network.
+    ///
+    /// The topology can be written out in a textual format, and used to build
+    /// networks with the same layout with [`Network::from_topology`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(
+    ///     network.topology().to_string(),
+    ///     "width 4\n0 1\n2 3\n0 3\n1 2\n0 1\n2 3\n"
+    /// );
+    /// ```
+    pub fn topology(&self) -> Topology {
+        Topology::from_parts(self.width, self.balancers().collect())
+    }
+
+    /// Returns an iterator over the wire pairs of the balancers, in the order
+    /// that the configuration yielded them when the network was built.
+    ///
+    /// This is the same list as
+    /// [`topology().balancers()`](Topology::balancers), without copying it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(
+    ///     network.balancers().collect::<Vec<_>>(),
+    ///     &[(0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)]
+    /// );
+    /// ```
+    pub fn balancers(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.segments[self.width..]
+            .iter()
+            .rev()
+            .filter_map(|segment| match segment {
+                WireSegment::Balancer(Balancer {
+                    wires: [top_wire, bottom_wire],
+                    ..
+                }) => Some((*top_wire, *bottom_wire)),
+                WireSegment::End(_) => None,
+            })
+    }
+
+    /// Returns the balancers grouped into layers, front-to-back, see
+    /// [`Topology::layers`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![(); 8]);
+    /// let layers = network.layers();
+    ///
+    /// assert_eq!(layers.len(), network.depth());
+    /// assert!(layers.iter().all(|layer| layer.len() == 4));
+    /// ```
+    pub fn layers(&self) -> Vec<Vec<(usize, usize)>> {
+        group_layers(self.width, self.balancers())
+    }
+
+    /// Connect the outputs of this network to the inputs of `other`, wire by
+    /// wire, and return the combined network, which hands out the outputs of
+    /// `other`.
+    ///
+    /// The outputs of this network are dropped, and the combined network starts
+    /// with every balancer up, like a new network. A token that leaves a
+    /// balancer of the first network goes on to the next balancer of `other`
+    /// on the same wire, so the combined network counts whenever `other` does,
+    /// whatever the first network does to the tokens. Balancers never widen the
+    /// gap between the number of tokens on their two wires, so if the first
+    /// network is smoothing then the combined network is at least as smooth.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the networks have different widths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicNetwork, ButterflyNetwork, Width};
+    ///
+    /// let butterfly = ButterflyNetwork::with_width(Width::new(4).unwrap());
+    /// let network = butterfly.chain(BitonicNetwork::new(vec![1, 2, 3, 4]));
+    ///
+    /// assert_eq!(network.num_balancers(), 4 + 6);
+    /// assert_eq!(network.depth(), 2 + 3);
+    /// assert_eq!(network.traverse(), &1);
+    /// assert_eq!(network.traverse(), &2);
+    /// ```
+    pub fn chain<M, C>(self, other: Network<M, C>) -> Network<M, Topology> {
+        assert_eq!(
+            self.width, other.width,
+            "cannot chain networks of widths {} and {}",
+            self.width, other.width
+        );
+
+        let balancers: Vec<_> = self.balancers().chain(other.balancers()).collect();
+        Network::from_balancer_iter(other.outputs.into_vec(), balancers)
+    }
+
+    /// Get references to all the outputs of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.outputs(), &[1, 2, 3, 4]);
+    /// ```
     pub fn outputs(&self) -> &[L] {
         &self.outputs
     }
+
+    /// Replace the outputs of the network, returning the previous outputs.
+    ///
+    /// The balancers are kept as they are, including their current state, so
+    /// this is much cheaper than building a new network. The next traversal
+    /// reaches the same output index it would have reached before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `outputs` does not have one element for each wire.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let mut network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    /// assert_eq!(network.traverse(), &1);
+    ///
+    /// let previous = network.replace_outputs(vec![10, 20, 30, 40]);
+    ///
+    /// assert_eq!(previous, vec![1, 2, 3, 4]);
+    /// assert_eq!(network.traverse(), &20);
+    /// ```
+    pub fn replace_outputs(&mut self, outputs: Vec<L>) -> Vec<L> {
+        assert_eq!(
+            outputs.len(),
+            self.width,
+            "expected {} outputs, one for each wire",
+            self.width
+        );
+
+        mem::replace(&mut self.outputs, outputs.into_boxed_slice()).into_vec()
+    }
+
+    /// Check the structural invariants of the network.
+    ///
+    /// This walks every segment of the network and checks that:
+    ///  - every balancer links to a segment inside the network, and every wire
+    ///    end links to one of the outputs.
+    ///  - every output can be reached from every input wire.
+    ///  - the longest path through the network matches the depth computed from
+    ///    the configuration.
+    ///
+    /// If the links between segments are broken, the reachability and depth
+    /// checks are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert!(network.verify_integrity().is_ok());
+    /// ```
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        let mut violations = Vec::new();
+
+        for (segment_idx, segment) in self.segments.iter().enumerate() {
+            match segment {
+                WireSegment::Balancer(Balancer { next_segments, .. }) => {
+                    // Linking only to earlier segments also rules out cycles.
+                    if next_segments
+                        .iter()
+                        .any(|&next_idx| next_idx >= segment_idx)
+                    {
+                        violations.push(IntegrityViolation::DanglingSegment {
+                            segment: segment_idx,
+                        });
+                    }
+                }
+                WireSegment::End(output_idx) => {
+                    if *output_idx >= self.outputs.len() {
+                        violations.push(IntegrityViolation::DanglingOutput {
+                            segment: segment_idx,
+                        });
+                    }
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            let reachable = reachable_outputs(&self.segments, self.width);
+            for (input, &start_segment_idx) in self.last_segments.iter().enumerate() {
+                for (output, &is_reachable) in reachable[start_segment_idx].iter().enumerate() {
+                    if !is_reachable {
+                        violations.push(IntegrityViolation::UnreachableOutput { input, output });
+                    }
+                }
+            }
+
+            let depths = segment_depths(&self.segments);
+            let actual = self
+                .last_segments
+                .iter()
+                .map(|&start_segment_idx| depths[start_segment_idx])
+                .max()
+                .unwrap_or(0);
+            if actual != self.depth {
+                violations.push(IntegrityViolation::DepthMismatch {
+                    expected: self.depth,
+                    actual,
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(IntegrityError { violations })
+        }
+    }
+
+    /// Returns the number of bytes of heap memory used by the network.
+    ///
+    /// Outputs are counted by their size only, so any heap memory that the
+    /// outputs own themselves is not included.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![0u64; 4]);
+    /// let usage = network.memory_usage();
+    ///
+    /// assert_eq!(usage.outputs(), 4 * 8);
+    /// assert!(usage.padding() < usage.segments());
+    /// assert_eq!(
+    ///     usage.total(),
+    ///     usage.segments() + usage.outputs() + usage.entry_points()
+    /// );
+    /// ```
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let segment_size = mem::size_of::<WireSegment>();
+        let padding = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let used = match segment {
+                    WireSegment::Balancer(_) => {
+                        mem::size_of::<AtomicBool>() + 2 * mem::size_of::<[usize; 2]>()
+                    }
+                    WireSegment::End(_) => mem::size_of::<usize>(),
+                };
+
+                segment_size - used
+            })
+            .sum();
+
+        MemoryUsage {
+            segments: self.segments.len() * segment_size,
+            outputs: self.outputs.len() * mem::size_of::<L>(),
+            entry_points: self.last_segments.len() * mem::size_of::<usize>(),
+            padding,
+        }
+    }
+}
+
+/// A single broken invariant found by [`Network::verify_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityViolation {
+    /// The balancer at the given segment links to a segment that is outside of
+    /// the network, or that is not closer to the outputs.
+    DanglingSegment {
+        /// Index of the segment containing the broken link.
+        segment: usize,
+    },
+    /// The wire end at the given segment links to something other than one of
+    /// the outputs of the network.
+    DanglingOutput {
+        /// Index of the segment containing the broken link.
+        segment: usize,
+    },
+    /// A token entering on the `input` wire can never reach `output`.
+    UnreachableOutput {
+        /// The input wire the token enters on.
+        input: usize,
+        /// The index of the output that cannot be reached.
+        output: usize,
+    },
+    /// The longest path through the network does not match the depth computed
+    /// from the configuration.
+    DepthMismatch {
+        /// The depth computed from the configuration.
+        expected: usize,
+        /// The depth found by walking the segments.
+        actual: usize,
+    },
+}
+
+impl fmt::Display for IntegrityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntegrityViolation::DanglingSegment { segment } => {
+                write!(f, "segment {} links outside of the network", segment)
+            }
+            IntegrityViolation::DanglingOutput { segment } => {
+                write!(f, "segment {} does not link to an output", segment)
+            }
+            IntegrityViolation::UnreachableOutput { input, output } => {
+                write!(f, "output {} is not reachable from input {}", output, input)
+            }
+            IntegrityViolation::DepthMismatch { expected, actual } => write!(
+                f,
+                "network has depth {} but configuration has depth {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+/// The error returned by [`Network::verify_integrity`], listing every broken
+/// invariant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityError {
+    violations: Vec<IntegrityViolation>,
+}
+
+impl IntegrityError {
+    /// Returns all of the broken invariants that were found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]);
+    ///
+    /// if let Err(error) = network.verify_integrity() {
+    ///     for violation in error.violations() {
+    ///         println!("{}", violation);
+    ///     }
+    /// }
+    /// ```
+    pub fn violations(&self) -> &[IntegrityViolation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "network failed integrity check: ")?;
+
+        for (idx, violation) in self.violations.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for IntegrityError {}
+
+/// An error returned when the memory for a network can't be allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AllocError {
+    // None if the size overflowed
+    requested_bytes: Option<usize>,
+}
+
+impl AllocError {
+    pub(crate) fn new(requested_bytes: usize) -> Self {
+        AllocError {
+            requested_bytes: Some(requested_bytes),
+        }
+    }
+
+    pub(crate) fn overflow() -> Self {
+        AllocError {
+            requested_bytes: None,
+        }
+    }
+
+    /// Returns the size of the allocation that failed, or `None` if the size
+    /// was too large to represent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// match BitonicNetwork::try_new(vec![0; 1024]) {
+    ///     Ok(network) => assert_eq!(network.width(), 1024),
+    ///     Err(error) => println!("failed to allocate {:?} bytes", error.requested_bytes()),
+    /// }
+    /// ```
+    pub fn requested_bytes(&self) -> Option<usize> {
+        self.requested_bytes
+    }
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.requested_bytes {
+            Some(bytes) => write!(f, "failed to allocate {} bytes for network", bytes),
+            None => write!(f, "network is too large to allocate"),
+        }
+    }
 }
 
+impl Error for AllocError {}
+
+/// The heap memory used by a network, as returned by
+/// [`Network::memory_usage`].
+///
+/// All sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryUsage {
+    segments: usize,
+    outputs: usize,
+    entry_points: usize,
+    padding: usize,
+}
+
+impl MemoryUsage {
+    /// Returns the memory used by the balancers and wire ends.
+    ///
+    /// Balancers are aligned to a cache line to prevent false sharing, so most
+    /// of this is [`padding`](MemoryUsage::padding).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let small = BitonicNetwork::new(vec![(); 2]).memory_usage();
+    /// let large = BitonicNetwork::new(vec![(); 4]).memory_usage();
+    ///
+    /// assert!(small.segments() < large.segments());
+    /// ```
+    pub fn segments(&self) -> usize {
+        self.segments
+    }
+
+    /// Returns the memory used by the outputs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let usage = BitonicNetwork::new(vec![0u32; 8]).memory_usage();
+    ///
+    /// assert_eq!(usage.outputs(), 8 * 4);
+    /// ```
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Returns the memory used by the table of where each input wire enters
+    /// the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let usage = BitonicNetwork::new(vec![(); 8]).memory_usage();
+    ///
+    /// assert_eq!(usage.entry_points(), 8 * std::mem::size_of::<usize>());
+    /// ```
+    pub fn entry_points(&self) -> usize {
+        self.entry_points
+    }
+
+    /// Returns the part of the total that holds no data, either because it is
+    /// alignment padding or because it is an enum tag.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let usage = BitonicNetwork::new(vec![(); 8]).memory_usage();
+    ///
+    /// assert!(usage.padding() <= usage.total());
+    /// ```
+    pub fn padding(&self) -> usize {
+        self.padding
+    }
+
+    /// Returns the total memory used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let usage = BitonicNetwork::new(vec![(); 8]).memory_usage();
+    ///
+    /// assert_eq!(
+    ///     usage.total(),
+    ///     usage.segments() + usage.outputs() + usage.entry_points()
+    /// );
+    /// ```
+    pub fn total(&self) -> usize {
+        self.segments + self.outputs + self.entry_points
+    }
+
+    // Counts `padding` bytes of the outputs as padding.
+    pub(crate) fn with_output_padding(mut self, padding: usize) -> Self {
+        self.padding += padding;
+        self
+    }
+
+    // Counts `bytes` allocated by the outputs themselves as part of the
+    // outputs.
+    pub(crate) fn with_output_heap(mut self, bytes: usize) -> Self {
+        self.outputs += bytes;
+        self
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<L: Sync, B> Network<L, B> {
+    /// Returns a parallel iterator over the outputs of the network, in the same
+    /// order as [`outputs`](Network::outputs).
+    ///
+    /// Only available with the `rayon` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use rayon::prelude::*;
+    ///
+    /// let network = BitonicNetwork::new((0..1024).collect());
+    ///
+    /// assert_eq!(network.par_outputs().filter(|&&value| value % 2 == 0).count(), 512);
+    /// ```
+    pub fn par_outputs(&self) -> rayon::slice::Iter<'_, L> {
+        self.outputs.par_iter()
+    }
+
+    /// Add up a value computed from each output, in parallel. This is meant
+    /// for reading wide networks whose outputs are the buckets of a counter or
+    /// metric.
+    ///
+    /// Only available with the `rayon` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    /// use std::sync::atomic::{AtomicU64, Ordering};
+    ///
+    /// let network = BitonicNetwork::new((0..1024).map(|_| AtomicU64::new(0)).collect());
+    /// for _ in 0..100 {
+    ///     network.traverse().fetch_add(3, Ordering::Relaxed);
+    /// }
+    ///
+    /// let total: u64 = network.par_sum_outputs(|bucket| bucket.load(Ordering::Relaxed));
+    /// assert_eq!(total, 300);
+    /// ```
+    pub fn par_sum_outputs<T, F>(&self, value: F) -> T
+    where
+        T: Send + iter::Sum<T>,
+        F: Fn(&L) -> T + Sync + Send,
+    {
+        self.outputs.par_iter().map(value).sum()
+    }
+}
+
+// Networks built from different topologies hand out their outputs in different
+// orders, so they are only equal if their balancers match as well as their
+// outputs. The balancer states are not compared.
 impl<L: PartialEq, B> PartialEq for Network<L, B> {
     fn eq(&self, other: &Self) -> bool {
-        self.outputs.eq(&other.outputs)
+        self.width == other.width
+            && self.outputs.eq(&other.outputs)
+            && self.balancers().eq(other.balancers())
     }
 }
 
@@ -211,18 +1755,30 @@ impl<L: Hash, B> Hash for Network<L, B> {
         self.outputs.iter().for_each(|output| {
             output.hash(state);
         });
+        self.balancers().for_each(|balancer| {
+            balancer.hash(state);
+        });
     }
 }
 
-impl<L: Clone, B: NetworkConfiguration> Clone for Network<L, B> {
+// Clones start from the initial toggles, see `Network::clone_with_state` to
+// copy the balancer states as well.
+impl<L: Clone, B> Clone for Network<L, B> {
     fn clone(&self) -> Self {
-        Network::new(self.outputs.to_vec())
+        Network {
+            #[cfg(feature = "observer")]
+            observer: self.observer.clone(),
+            name: self.name.clone(),
+            ..Network::from_balancer_iter(self.outputs.to_vec(), self.balancers())
+        }
+        .with_initial_toggles(self.initial_toggles)
     }
 }
 
 impl<L: fmt::Debug, B: fmt::Debug> fmt::Debug for Network<L, B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Network")
+            .field("name", &self.name)
             .field("width", &self.width)
             .field("outputs", &self.outputs)
             .field("balancer_config", &type_name::<B>())
@@ -230,19 +1786,559 @@ impl<L: fmt::Debug, B: fmt::Debug> fmt::Debug for Network<L, B> {
     }
 }
 
-// TODO: Safety justification
-unsafe impl<L: Send, B> Send for Network<L, B> {}
-// TODO: Safety justification
-unsafe impl<L: Sync, B> Sync for Network<L, B> {}
-
-fn check_segment_ptrs_in_bounds<L>(segments: &[WireSegment<L>], outputs: &[L]) -> bool {
-    let segments_range = slice_to_ptr_range(segments);
-    let outputs_range = slice_to_ptr_range(outputs);
-
+fn check_segment_indices_in_bounds(segments: &[WireSegment], num_outputs: usize) -> bool {
     segments.iter().all(|segment| match segment {
         WireSegment::Balancer(Balancer { next_segments, .. }) => {
-            segments_range.contains(&next_segments[0]) && segments_range.contains(&next_segments[1])
+            next_segments[0] < segments.len() && next_segments[1] < segments.len()
         }
-        WireSegment::End(output_ptr) => outputs_range.contains(output_ptr),
+        WireSegment::End(output_idx) => *output_idx < num_outputs,
     })
 }
+
+// For every segment, which outputs a token starting from that segment could
+// reach. Requires every balancer to link to earlier segments.
+fn reachable_outputs(segments: &[WireSegment], width: usize) -> Vec<Vec<bool>> {
+    let mut reachable: Vec<Vec<bool>> = Vec::with_capacity(segments.len());
+
+    for segment in segments {
+        let segment_reachable = match segment {
+            WireSegment::Balancer(Balancer {
+                next_segments: [top, bottom],
+                ..
+            }) => reachable[*top]
+                .iter()
+                .zip(reachable[*bottom].iter())
+                .map(|(&top, &bottom)| top || bottom)
+                .collect(),
+            WireSegment::End(output_idx) => {
+                let mut segment_reachable = vec![false; width];
+                segment_reachable[*output_idx] = true;
+                segment_reachable
+            }
+        };
+
+        reachable.push(segment_reachable);
+    }
+
+    reachable
+}
+
+// For every segment, the number of balancers on the longest path from that
+// segment to an output. Requires every balancer to link to earlier segments.
+fn segment_depths(segments: &[WireSegment]) -> Vec<usize> {
+    let mut depths: Vec<usize> = Vec::with_capacity(segments.len());
+
+    for segment in segments {
+        let depth = match segment {
+            WireSegment::Balancer(Balancer {
+                next_segments: [top, bottom],
+                ..
+            }) => depths[*top].max(depths[*bottom]) + 1,
+            WireSegment::End(_) => 0,
+        };
+
+        depths.push(depth);
+    }
+
+    depths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{bitonic_depth, BitonicConfiguration, BitonicNetwork, ButterflyNetwork};
+
+    #[test]
+    fn bitonic_networks_pass_integrity_check() {
+        for width in (0..7).map(|exp| 1 << exp) {
+            let network = BitonicNetwork::new(vec![0u8; width]);
+
+            assert_eq!(network.verify_integrity(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn zero_sized_outputs_pass_integrity_check() {
+        let network = BitonicNetwork::new(vec![(); 8]);
+
+        assert_eq!(network.verify_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn memory_usage_of_bitonic_networks() {
+        for width in (0..7).map(|exp| 1 << exp) {
+            let network = BitonicNetwork::new(vec![0u16; width]);
+            let usage = network.memory_usage();
+
+            let log_width = width.trailing_zeros() as usize;
+            let num_balancers = (width / 2) * log_width * (log_width + 1) / 2;
+            let num_segments = width + num_balancers;
+
+            assert_eq!(
+                usage.segments(),
+                num_segments * mem::size_of::<WireSegment>()
+            );
+            assert_eq!(usage.outputs(), width * 2);
+            assert_eq!(usage.entry_points(), width * mem::size_of::<usize>());
+            assert!(usage.padding() >= num_balancers * (64 - 33));
+            assert!(usage.padding() < usage.segments());
+        }
+    }
+
+    #[test]
+    fn keyed_traversals_ignore_the_thread() {
+        let traverse_all = |network: &BitonicNetwork<usize>| -> Vec<usize> {
+            (0..16u32)
+                .map(|key| *network.traverse_keyed(&key))
+                .collect()
+        };
+
+        let here = traverse_all(&BitonicNetwork::new((0..8).collect()));
+        let elsewhere = thread::spawn(move || traverse_all(&BitonicNetwork::new((0..8).collect())))
+            .join()
+            .unwrap();
+        assert_eq!(here, elsewhere);
+
+        let mut outputs = here;
+        outputs.sort();
+        assert_eq!(
+            outputs,
+            (0..8).flat_map(|wire| vec![wire, wire]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn prefault_does_not_change_traversals() {
+        let network = BitonicNetwork::new((0..8).collect());
+        let fresh = BitonicNetwork::new((0..8).collect());
+
+        network.prefault();
+
+        for _ in 0..20 {
+            assert_eq!(network.traverse_traced(), fresh.traverse_traced());
+        }
+    }
+
+    #[test]
+    fn reset_repeats_traversals() {
+        let mut network = BitonicNetwork::new((0..16).collect());
+
+        let first: Vec<_> = (0..11)
+            .map(|_| network.traverse_traced())
+            .map(|(output, path)| (*output, path))
+            .collect();
+        network.reset();
+        let second: Vec<_> = (0..11)
+            .map(|_| network.traverse_traced())
+            .map(|(output, path)| (*output, path))
+            .collect();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn replaced_outputs_keep_balancer_state() {
+        let mut network = BitonicNetwork::new((0..8).collect());
+        let labels = BitonicNetwork::new((0..8).collect());
+
+        for _ in 0..5 {
+            assert_eq!(network.traverse(), labels.traverse());
+        }
+        let previous = network.replace_outputs((0..8).map(|idx| idx * 10).collect());
+
+        assert_eq!(previous, (0..8).collect::<Vec<_>>());
+        for _ in 0..20 {
+            assert_eq!(*network.traverse(), labels.traverse() * 10);
+        }
+        assert!(network.verify_integrity().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 4 outputs")]
+    fn replace_outputs_with_wrong_width() {
+        let mut network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+
+        network.replace_outputs(vec![1, 2]);
+    }
+
+    #[test]
+    fn set_toggles_restores_traversals() {
+        let network = BitonicNetwork::new((0..8).collect());
+        for _ in 0..11 {
+            network.traverse();
+        }
+        let mut restored = BitonicNetwork::new((0..8).collect());
+
+        restored.set_toggles(&network.toggles());
+
+        assert_eq!(restored.toggles(), network.toggles());
+        for _ in 0..20 {
+            assert_eq!(restored.traverse_traced(), network.traverse_traced());
+        }
+    }
+
+    #[test]
+    fn traverse_from_each_wire() {
+        let network = BitonicNetwork::new((0..8).collect());
+
+        for count in 0..64 {
+            assert_eq!(*network.traverse_from(count * 5 % 8), count % 8);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "entry wire 4 out of range for a network of width 4")]
+    fn traverse_from_out_of_range() {
+        BitonicNetwork::new(vec![(); 4]).traverse_from(4);
+    }
+
+    #[test]
+    fn chained_networks_follow_each_other() {
+        let first = ButterflyNetwork::with_width(Width::of(8));
+        let second = BitonicNetwork::new((0..8).collect());
+        let expected: Vec<_> = first.balancers().chain(second.balancers()).collect();
+
+        let network = first.chain(second);
+
+        assert_eq!(network.balancers().collect::<Vec<_>>(), expected);
+        assert_eq!(network.depth(), 3 + bitonic_depth(8));
+        assert_eq!(network.verify_integrity(), Ok(()));
+        for count in 0..(8 * 4) {
+            assert_eq!(*network.traverse_keyed(&(count * 7)), count % 8);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot chain networks of widths 4 and 8")]
+    fn chain_different_widths() {
+        let _ = BitonicNetwork::with_width(Width::of(4))
+            .chain(BitonicNetwork::with_width(Width::of(8)));
+    }
+
+    #[test]
+    fn structure_matches_topology() {
+        for width in (0..6).map(|exp| 1usize << exp) {
+            let network = BitonicNetwork::new(vec![(); width]);
+            let topology = network.topology();
+
+            assert_eq!(network.depth(), topology.depth());
+            assert_eq!(network.depth(), bitonic_depth(width));
+            assert_eq!(network.num_layers(), topology.depth());
+            assert_eq!(network.num_balancers(), topology.balancers().len());
+            assert_eq!(network.balancers().count(), network.num_balancers());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn parallel_outputs_match_outputs() {
+        let network = BitonicNetwork::new((0..256).collect());
+
+        let outputs: Vec<_> = network.par_outputs().copied().collect();
+        assert_eq!(outputs, network.outputs());
+        assert_eq!(
+            network.par_sum_outputs(|&output| output),
+            network.outputs().iter().sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn initial_toggles_set_first_paths() {
+        let network = BitonicNetwork::new((0..8).collect());
+        let topology = network.topology();
+
+        for &toggles in &[
+            InitialToggles::Up,
+            InitialToggles::Alternating,
+            InitialToggles::Seeded(1),
+            InitialToggles::Seeded(2),
+        ] {
+            let network = network.clone().with_initial_toggles(toggles);
+            let (_, path) = network.traverse_traced();
+
+            // The first token through each balancer follows its initial state
+            let mut wire = topology.balancers()[path[0]].0;
+            for &balancer in &path {
+                let (top_wire, bottom_wire) = topology.balancers()[balancer];
+                assert!(wire == top_wire || wire == bottom_wire);
+                wire = if toggles.toggle(balancer) {
+                    top_wire
+                } else {
+                    bottom_wire
+                };
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_toggles_are_reproducible() {
+        let seeded = |seed| {
+            let network = BitonicNetwork::new((0..16).collect())
+                .with_initial_toggles(InitialToggles::Seeded(seed));
+            (0..32)
+                .map(|key| *network.traverse_keyed(&key))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(seeded(5), seeded(5));
+        assert_ne!(seeded(5), seeded(6));
+    }
+
+    #[test]
+    fn equality_includes_topology() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(4));
+        let network = Network::from_topology(&topology, vec![1, 2, 3, 4]);
+        let same = Network::from_topology(&topology, vec![1, 2, 3, 4]);
+        let mirrored = Network::from_topology(&topology.mirror(), vec![1, 2, 3, 4]);
+
+        assert_eq!(network, same);
+        assert_eq!(hash_single(&network), hash_single(&same));
+        assert_ne!(network, mirrored);
+        assert_ne!(hash_single(&network), hash_single(&mirrored));
+    }
+
+    #[test]
+    fn clones_keep_name() {
+        let network = BitonicNetwork::new(vec![0; 4]).with_name("tickets");
+
+        assert_eq!(network.clone().name(), Some("tickets"));
+        assert_eq!(format!("{:?}", network.clone()), format!("{:?}", network));
+    }
+
+    #[test]
+    fn reset_and_clone_keep_initial_toggles() {
+        let mut network =
+            BitonicNetwork::new((0..8).collect()).with_initial_toggles(InitialToggles::Alternating);
+        let first: Vec<_> = (0..8).map(|key| *network.traverse_keyed(&key)).collect();
+
+        let cloned = network.clone();
+        network.reset();
+
+        assert_eq!(cloned.initial_toggles(), InitialToggles::Alternating);
+        assert_eq!(
+            (0..8)
+                .map(|key| *network.traverse_keyed(&key))
+                .collect::<Vec<_>>(),
+            first
+        );
+        assert_eq!(
+            (0..8)
+                .map(|key| *cloned.traverse_keyed(&key))
+                .collect::<Vec<_>>(),
+            first
+        );
+    }
+
+    #[test]
+    fn clone_with_state_keeps_toggles() {
+        let network =
+            BitonicNetwork::new((0..8).collect()).with_initial_toggles(InitialToggles::Seeded(3));
+        for count in 0..5 {
+            network.traverse_keyed(&count);
+        }
+
+        let cloned = network.clone_with_state();
+
+        assert_eq!(cloned.toggles(), network.toggles());
+        assert_eq!(cloned.initial_toggles(), InitialToggles::Seeded(3));
+        for count in 0..16 {
+            assert_eq!(
+                cloned.traverse_keyed(&count),
+                network.traverse_keyed(&count)
+            );
+        }
+    }
+
+    #[test]
+    fn traced_paths_follow_the_wires() {
+        let network = BitonicNetwork::new((0..8).collect());
+        let topology = network.topology();
+
+        let mut outputs = Vec::new();
+        for _ in 0..16 {
+            let (output, path) = network.traverse_traced();
+            outputs.push(*output);
+
+            assert_eq!(path.len(), network.depth);
+            assert!(path.windows(2).all(|pair| pair[0] < pair[1]));
+
+            // Each balancer on the path shares a wire with the one before it,
+            // and the last one is on the output wire.
+            let wires: Vec<_> = path
+                .iter()
+                .map(|&balancer| topology.balancers()[balancer])
+                .collect();
+            assert!(wires.windows(2).all(|pair| {
+                let ((a, b), (c, d)) = (pair[0], pair[1]);
+                a == c || a == d || b == c || b == d
+            }));
+            let (top, bottom) = wires[wires.len() - 1];
+            assert!(*output == top || *output == bottom);
+        }
+
+        outputs.sort();
+        assert_eq!(
+            outputs,
+            (0..8).flat_map(|wire| vec![wire, wire]).collect::<Vec<_>>()
+        );
+    }
+
+    #[cfg(feature = "observer")]
+    #[test]
+    fn observer_sees_every_traversal() {
+        use std::sync::Mutex;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let network = BitonicNetwork::with_observer(vec![0; 8], move |event| {
+            recorded.lock().unwrap().push(*event);
+        });
+        let cloned = network.clone();
+
+        for _ in 0..8 {
+            network.traverse();
+        }
+        cloned.traverse();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 9);
+        assert_eq!(
+            events[..8]
+                .iter()
+                .map(TraversalEvent::exit_wire)
+                .collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
+        );
+        assert!(events
+            .iter()
+            .all(|event| event.entry_wire() < 8 && event.path_length() == network.depth));
+    }
+
+    #[test]
+    fn detect_dangling_segment() {
+        let mut network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+        if let WireSegment::Balancer(balancer) = &mut network.segments[5] {
+            balancer.next_segments[1] = 5;
+        }
+
+        let error = network.verify_integrity().unwrap_err();
+
+        assert_eq!(
+            error.violations(),
+            &[IntegrityViolation::DanglingSegment { segment: 5 }]
+        );
+    }
+
+    #[test]
+    fn detect_unreachable_output() {
+        struct FirstPairOnly;
+
+        impl IntoIterator for FirstPairOnly {
+            type IntoIter = std::iter::Once<(usize, usize)>;
+            type Item = (usize, usize);
+
+            fn into_iter(self) -> Self::IntoIter {
+                std::iter::once((0, 1))
+            }
+        }
+
+        impl NetworkConfiguration for FirstPairOnly {
+            fn from_width(_: Width) -> Self {
+                FirstPairOnly
+            }
+        }
+
+        let network: Network<_, FirstPairOnly> = Network::new(vec![1, 2, 3, 4]);
+        let error = network.verify_integrity().unwrap_err();
+
+        assert!(error
+            .violations()
+            .contains(&IntegrityViolation::UnreachableOutput {
+                input: 2,
+                output: 1
+            }));
+        assert!(!error
+            .violations()
+            .contains(&IntegrityViolation::UnreachableOutput {
+                input: 0,
+                output: 1
+            }));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "invalid network configuration: balancer 1 connects wire 1 to itself"
+    )]
+    fn balancer_on_one_wire() {
+        struct LoopedBalancer;
+
+        impl IntoIterator for LoopedBalancer {
+            type IntoIter = std::vec::IntoIter<(usize, usize)>;
+            type Item = (usize, usize);
+
+            fn into_iter(self) -> Self::IntoIter {
+                vec![(0, 1), (1, 1)].into_iter()
+            }
+        }
+
+        impl NetworkConfiguration for LoopedBalancer {
+            fn from_width(_: Width) -> Self {
+                LoopedBalancer
+            }
+        }
+
+        let _: Network<_, LoopedBalancer> = Network::new(vec![1, 2]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "invalid network configuration: balancer 0 uses wire 4 which is out of range"
+    )]
+    fn balancer_out_of_range() {
+        struct WideBalancer;
+
+        impl IntoIterator for WideBalancer {
+            type IntoIter = std::iter::Once<(usize, usize)>;
+            type Item = (usize, usize);
+
+            fn into_iter(self) -> Self::IntoIter {
+                std::iter::once((0, 4))
+            }
+        }
+
+        impl NetworkConfiguration for WideBalancer {
+            fn from_width(_: Width) -> Self {
+                WideBalancer
+            }
+        }
+
+        let _ = Network::<_, WideBalancer>::try_new(vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn detect_depth_mismatch() {
+        let mut network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+        network.depth = 2;
+
+        let error = network.verify_integrity().unwrap_err();
+
+        assert_eq!(
+            error.violations(),
+            &[IntegrityViolation::DepthMismatch {
+                expected: 2,
+                actual: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn unlabeled_network_matches_labeled() {
+        let unlabeled = BitonicNetwork::with_width(Width::of(16));
+        let labeled = BitonicNetwork::new((0..16).collect());
+
+        for _ in 0..64 {
+            assert_eq!(unlabeled.traverse_indexed(), *labeled.traverse());
+        }
+        assert_eq!(unlabeled.memory_usage().outputs(), 0);
+    }
+}
@@ -0,0 +1,291 @@
+use super::{
+    common::Network,
+    topology::{Topology, TopologyError},
+};
+use core::fmt;
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+};
+
+// The most quiescent states that are explored to check that a network counts.
+// A bitonic network of width 16 has 2^15 of them.
+const MAX_STATES: usize = 1 << 16;
+
+/// A network with a layout of balancers given at runtime, that has been
+/// checked to be a counting network.
+///
+/// See [`CustomNetwork::from_balancers`]. Networks that are built from a
+/// [`Topology`] with [`Network::from_topology`] have the same type, but are not
+/// checked.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::CustomNetwork;
+///
+/// let balancers = vec![(0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)];
+/// let network = CustomNetwork::from_balancers(4, balancers, vec!['a', 'b', 'c', 'd']).unwrap();
+///
+/// assert_eq!(network.traverse(), &'a');
+/// assert_eq!(network.traverse(), &'b');
+/// ```
+pub type CustomNetwork<L> = Network<L, Topology>;
+
+impl<L> Network<L, Topology> {
+    /// Construct a new network of the given width from a list of balancers,
+    /// checking that it is a counting network.
+    ///
+    /// Balancers are listed in the order that tokens reach them, in the same
+    /// form as [`NetworkConfiguration`](super::NetworkConfiguration): a token
+    /// leaves each balancer on the first wire of the pair, then the second,
+    /// then the first again, and so on.
+    ///
+    /// The network counts if in every quiescent state the outputs have the
+    /// step property, which is the same as each token that passes through the
+    /// network alone leaving on the wire after the previous one. Every
+    /// quiescent state can be reached by tokens passing through one at a time,
+    /// so this is checked by sending single tokens in on every wire from every
+    /// state that can be reached that way. This is a proof rather than a test,
+    /// but the number of states grows quickly with the width: a bitonic
+    /// network of width 16 has 2<sup>15</sup> of them, and wider networks have
+    /// too many to check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the balancers don't describe a network of the width,
+    /// if the network does not count, or if it has too many states to check.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of outputs is not `width`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{CustomNetwork, CustomNetworkError};
+    ///
+    /// // A butterfly network balances tokens, but does not count them
+    /// let error = CustomNetwork::from_balancers(4, vec![(0, 2), (1, 3), (0, 1), (2, 3)], vec![(); 4])
+    ///     .unwrap_err();
+    ///
+    /// match error {
+    ///     CustomNetworkError::NotCounting { entries, exit_wire } => {
+    ///         assert_ne!(exit_wire, (entries.len() - 1) % 4);
+    ///     }
+    ///     error => panic!("unexpected error: {}", error),
+    /// }
+    /// ```
+    pub fn from_balancers(
+        width: usize,
+        balancers: Vec<(usize, usize)>,
+        outputs: Vec<L>,
+    ) -> Result<Self, CustomNetworkError> {
+        assert_eq!(outputs.len(), width);
+
+        let topology = Topology::new(width, balancers).map_err(CustomNetworkError::Invalid)?;
+        check_counts(&topology)?;
+
+        Ok(Network::from_topology(&topology, outputs))
+    }
+}
+
+// Explore the quiescent states reachable by sending tokens through one at a
+// time, breadth first so that counterexamples are as short as possible.
+fn check_counts(topology: &Topology) -> Result<(), CustomNetworkError> {
+    let simulation = Simulation::new(topology);
+    let width = topology.width();
+
+    // Each state is the toggles of the balancers, along with the number of
+    // tokens that have passed through modulo the width. The first state has
+    // every toggle up.
+    let first = (vec![true; topology.balancers().len()], 0);
+    // The state that each state was reached from, and the entry wire of the
+    // token that moved between them.
+    let mut parents: Vec<Option<(usize, usize)>> = vec![None];
+    let mut indices = HashMap::new();
+    let mut queue = VecDeque::new();
+    indices.insert(first.clone(), 0);
+    queue.push_back((first, 0));
+
+    while let Some(((toggles, count), idx)) = queue.pop_front() {
+        for entry in 0..width {
+            let mut next_toggles = toggles.clone();
+            let exit_wire = simulation.exit(&mut next_toggles, entry);
+
+            if exit_wire != count {
+                let mut entries = vec![entry];
+                let mut current = idx;
+                while let Some((parent, entry)) = parents[current] {
+                    entries.push(entry);
+                    current = parent;
+                }
+                entries.reverse();
+
+                return Err(CustomNetworkError::NotCounting { entries, exit_wire });
+            }
+
+            let next = (next_toggles, (count + 1) % width);
+            if !indices.contains_key(&next) {
+                if parents.len() == MAX_STATES {
+                    return Err(CustomNetworkError::TooManyStates { limit: MAX_STATES });
+                }
+
+                indices.insert(next.clone(), parents.len());
+                queue.push_back((next, parents.len()));
+                parents.push(Some((idx, entry)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Single tokens passing through the balancers of a topology.
+struct Simulation {
+    // The first balancer on each input wire
+    first: Vec<Option<usize>>,
+    // The balancers after each balancer, on its first and second wire
+    next: Vec<[Option<usize>; 2]>,
+    balancers: Vec<(usize, usize)>,
+}
+
+impl Simulation {
+    fn new(topology: &Topology) -> Self {
+        let balancers = topology.balancers().to_vec();
+        let mut first = vec![None; topology.width()];
+        let mut next = vec![[None; 2]; balancers.len()];
+
+        // Walk backwards, so that the balancer last seen on each wire is the
+        // next one after the current balancer.
+        for (idx, &(top_wire, bottom_wire)) in balancers.iter().enumerate().rev() {
+            next[idx] = [first[top_wire], first[bottom_wire]];
+            first[top_wire] = Some(idx);
+            first[bottom_wire] = Some(idx);
+        }
+
+        Simulation {
+            first,
+            next,
+            balancers,
+        }
+    }
+
+    // Send a token in on `entry`, flipping the toggles it passes, and return
+    // the wire it leaves on.
+    fn exit(&self, toggles: &mut [bool], entry: usize) -> usize {
+        let mut wire = entry;
+        let mut current = self.first[entry];
+
+        while let Some(idx) = current {
+            let up = toggles[idx];
+            toggles[idx] = !up;
+
+            let (top_wire, bottom_wire) = self.balancers[idx];
+            let side = if up { 0 } else { 1 };
+            wire = if up { top_wire } else { bottom_wire };
+            current = self.next[idx][side];
+        }
+
+        wire
+    }
+}
+
+/// The reason that [`CustomNetwork::from_balancers`] could not build a network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomNetworkError {
+    /// The balancers don't describe a network of the width.
+    Invalid(TopologyError),
+    /// The network does not count.
+    NotCounting {
+        /// Input wires of tokens that pass through the network one at a time,
+        /// starting from the initial state.
+        entries: Vec<usize>,
+        /// The wire that the last token leaves on, which is not the wire after
+        /// the one that the token before it left on.
+        exit_wire: usize,
+    },
+    /// The network has too many quiescent states to check that it counts.
+    TooManyStates {
+        /// The most states that are checked.
+        limit: usize,
+    },
+}
+
+impl fmt::Display for CustomNetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CustomNetworkError::Invalid(error) => write!(f, "invalid network: {}", error),
+            CustomNetworkError::NotCounting { entries, exit_wire } => write!(
+                f,
+                "network does not count: after tokens enter on wires {:?}, the last one leaves on \
+                 wire {}",
+                entries, exit_wire
+            ),
+            CustomNetworkError::TooManyStates { limit } => write!(
+                f,
+                "network has more than {} states, too many to check that it counts",
+                limit
+            ),
+        }
+    }
+}
+
+impl Error for CustomNetworkError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{
+        known::known_config, BitonicConfiguration, PeriodicConfiguration, Width,
+    };
+
+    #[test]
+    fn known_networks_count() {
+        for width in (0..5).map(|exp| 1usize << exp) {
+            let balancers = known_config(width).unwrap().to_vec();
+            let network =
+                CustomNetwork::from_balancers(width, balancers, (0..width).collect()).unwrap();
+
+            assert!((0..(width * 3)).all(|count| *network.traverse() == count % width));
+        }
+
+        let topology = Topology::from_config::<PeriodicConfiguration>(Width::of(8));
+        assert!(check_counts(&topology).is_ok());
+    }
+
+    #[test]
+    fn shortest_counterexample() {
+        // Missing the last balancer of `Bitonic[4]`
+        let balancers = vec![(0, 1), (2, 3), (0, 3), (1, 2), (0, 1)];
+
+        assert_eq!(
+            CustomNetwork::from_balancers(4, balancers, vec![(); 4]),
+            Err(CustomNetworkError::NotCounting {
+                entries: vec![0, 0, 0],
+                exit_wire: 3
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_balancers() {
+        assert_eq!(
+            CustomNetwork::from_balancers(2, vec![(1, 1)], vec![(); 2]),
+            Err(CustomNetworkError::Invalid(TopologyError::SameWire {
+                balancer: 0,
+                wire: 1
+            }))
+        );
+    }
+
+    #[test]
+    fn too_many_states() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(32));
+
+        assert_eq!(
+            check_counts(&topology),
+            Err(CustomNetworkError::TooManyStates { limit: MAX_STATES })
+        );
+    }
+}
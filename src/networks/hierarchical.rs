@@ -0,0 +1,432 @@
+use super::{
+    bitonic::{bitonic_depth, BitonicConfiguration, BitonicNetwork},
+    common::Network,
+    topology::Topology,
+    width::Width,
+};
+use crate::util::hash_single;
+use core::{cell::Cell, fmt};
+use std::thread;
+
+/// A counting network split into a small local network for each cluster of
+/// threads, whose outputs feed a global network shared by every cluster.
+///
+/// A `Bitonic[w]` network starts with a `Bitonic[w / c]` network on each of
+/// `c` blocks of neighbouring wires, followed by the merging networks that
+/// combine the blocks. A hierarchical network is built from exactly those
+/// balancers, so it counts in the same way as a
+/// [`BitonicNetwork`](super::BitonicNetwork) of the same width. The difference
+/// is that each block is a separate local network, and a thread always enters
+/// through the local network of its own cluster. Threads of one cluster only
+/// share the balancers of the global network with other clusters, which keeps
+/// most of the traffic between cores of the same cluster, such as one socket
+/// of a large machine.
+///
+/// Threads that know which cluster they run on, for example because they are
+/// pinned to a core, should say so with [`set_cluster_hint`], or pass the
+/// cluster to [`traverse_in`](HierarchicalNetwork::traverse_in). Other threads
+/// are spread over the clusters by their id, see
+/// [`cluster_for_current_thread`](HierarchicalNetwork::cluster_for_current_thread).
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{HierarchicalNetwork, Width};
+///
+/// let network = HierarchicalNetwork::new(Width::new(2).unwrap(), vec![1, 2, 3, 4]);
+///
+/// assert_eq!(network.cluster_width(), 2);
+/// assert_eq!(network.traverse_in(0), &1);
+/// assert_eq!(network.traverse_in(1), &2);
+/// assert_eq!(network.traverse(), &3);
+/// ```
+pub struct HierarchicalNetwork<L> {
+    cluster_width: usize,
+    clusters: Box<[BitonicNetwork<()>]>,
+    global: Network<L, Topology>,
+}
+
+impl<L> HierarchicalNetwork<L> {
+    /// Create a new network with the given number of clusters, that hands out
+    /// the given outputs.
+    ///
+    /// Each cluster gets a local network with a width of
+    /// `outputs.len() / num_clusters`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of outputs is not a power of two, or if there are
+    /// more clusters than outputs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(4).unwrap(), (0..64).collect());
+    ///
+    /// assert_eq!(network.width(), 64);
+    /// assert_eq!(network.num_clusters(), 4);
+    /// assert_eq!(network.cluster_width(), 16);
+    /// ```
+    pub fn new(num_clusters: Width, outputs: Vec<L>) -> Self {
+        let width = Width::of(outputs.len());
+        assert!(num_clusters <= width);
+
+        let cluster_width = Width::of(width.get() / num_clusters.get());
+        let local_depth = bitonic_depth(cluster_width.get());
+
+        // Every balancer in the first layers of `Bitonic[width]` belongs to the
+        // `Bitonic[cluster_width]` network on one of the blocks, and every
+        // later balancer belongs to a merging network.
+        let mut wire_depths = vec![0; width.get()];
+        let mut global_balancers = Vec::new();
+        for (top_wire, bottom_wire) in Topology::from_config::<BitonicConfiguration>(width)
+            .balancers()
+            .iter()
+            .copied()
+        {
+            let layer = wire_depths[top_wire].max(wire_depths[bottom_wire]);
+            wire_depths[top_wire] = layer + 1;
+            wire_depths[bottom_wire] = layer + 1;
+
+            if layer >= local_depth {
+                global_balancers.push((top_wire, bottom_wire));
+            }
+        }
+
+        HierarchicalNetwork {
+            cluster_width: cluster_width.get(),
+            clusters: (0..num_clusters.get())
+                .map(|_| BitonicNetwork::with_width(cluster_width))
+                .collect(),
+            global: Network::from_balancer_iter(outputs, global_balancers),
+        }
+    }
+
+    /// Returns the total number of outputs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(2).unwrap(), vec!['a', 'b', 'c', 'd']);
+    ///
+    /// assert_eq!(network.width(), 4);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.global.width()
+    }
+
+    /// Returns the number of clusters, each with its own local network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(2).unwrap(), vec!['a', 'b', 'c', 'd']);
+    ///
+    /// assert_eq!(network.num_clusters(), 2);
+    /// ```
+    pub fn num_clusters(&self) -> usize {
+        self.clusters.len()
+    }
+
+    /// Returns the width of the local network of each cluster.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(4).unwrap(), vec![(); 32]);
+    ///
+    /// assert_eq!(network.cluster_width(), 8);
+    /// ```
+    pub fn cluster_width(&self) -> usize {
+        self.cluster_width
+    }
+
+    /// Returns the cluster that the current thread enters the network through
+    /// in [`traverse`](HierarchicalNetwork::traverse).
+    ///
+    /// This is the [hint](set_cluster_hint) of the thread if it has one, or a
+    /// cluster picked by hashing the id of the thread otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(4).unwrap(), vec![(); 16]);
+    ///
+    /// assert!(network.cluster_for_current_thread() < 4);
+    /// ```
+    pub fn cluster_for_current_thread(&self) -> usize {
+        self.cluster_for(thread_hash())
+    }
+
+    // The cluster of a thread whose id hashes to `thread_hash`. The low bits of
+    // the hash pick the wire within the local network, so use the bits above
+    // them to pick the cluster.
+    fn cluster_for(&self, thread_hash: usize) -> usize {
+        let cluster = CLUSTER_HINT
+            .with(Cell::get)
+            .unwrap_or(thread_hash / self.cluster_width);
+
+        cluster % self.num_clusters()
+    }
+
+    /// Traverse the network from the cluster of the current thread, and obtain
+    /// a reference to an output element.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(2).unwrap(), vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// assert_eq!(network.traverse(), &2);
+    /// ```
+    pub fn traverse(&self) -> &L {
+        let thread_hash = thread_hash();
+        let wire = self.traverse_entry(self.cluster_for(thread_hash), thread_hash);
+
+        &self.global.outputs()[wire]
+    }
+
+    /// Traverse the network from the given cluster, and obtain a reference to
+    /// an output element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cluster` is not less than
+    /// [`num_clusters`](HierarchicalNetwork::num_clusters).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(4).unwrap(), vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse_in(3), &1);
+    /// assert_eq!(network.traverse_in(3), &2);
+    /// assert_eq!(network.traverse_in(0), &3);
+    /// ```
+    pub fn traverse_in(&self, cluster: usize) -> &L {
+        &self.global.outputs()[self.traverse_indexed_in(cluster)]
+    }
+
+    /// Traverse the network from the given cluster, and obtain the index of
+    /// the output reached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cluster` is not less than
+    /// [`num_clusters`](HierarchicalNetwork::num_clusters).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(2).unwrap(), vec!['a', 'b']);
+    ///
+    /// assert_eq!(network.traverse_indexed_in(1), 0);
+    /// assert_eq!(network.traverse_in(0), &'b');
+    /// ```
+    pub fn traverse_indexed_in(&self, cluster: usize) -> usize {
+        self.traverse_entry(cluster, thread_hash())
+    }
+
+    // Traverse the local network of `cluster`, entering on the wire picked by
+    // `thread_hash`, and then the global network.
+    fn traverse_entry(&self, cluster: usize, thread_hash: usize) -> usize {
+        let local = &self.clusters[cluster];
+        let entry = thread_hash % self.cluster_width;
+        let wire = cluster * self.cluster_width + local.traverse_wire_within(entry..(entry + 1));
+
+        self.global.traverse_wire_within(wire..(wire + 1))
+    }
+
+    /// Returns the outputs of the network, in the order of their wires.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{HierarchicalNetwork, Width};
+    ///
+    /// let network = HierarchicalNetwork::new(Width::new(2).unwrap(), vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.outputs(), &[1, 2, 3, 4]);
+    /// ```
+    pub fn outputs(&self) -> &[L] {
+        self.global.outputs()
+    }
+}
+
+/// Set the cluster that the current thread enters hierarchical networks
+/// through, or clear it with `None`.
+///
+/// The hint applies to every [`HierarchicalNetwork`] that the thread traverses
+/// with [`traverse`](HierarchicalNetwork::traverse), taken modulo the number of
+/// clusters of each network. Without a hint, a thread is assigned to a cluster
+/// by its id, which spreads threads over the clusters but doesn't keep them
+/// near the other threads of their cluster.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{set_cluster_hint, HierarchicalNetwork, Width};
+///
+/// let network = HierarchicalNetwork::new(Width::new(4).unwrap(), vec![(); 16]);
+///
+/// // This thread is pinned to a core of the third cluster
+/// set_cluster_hint(Some(2));
+/// assert_eq!(network.cluster_for_current_thread(), 2);
+///
+/// set_cluster_hint(None);
+/// ```
+pub fn set_cluster_hint(cluster: Option<usize>) {
+    CLUSTER_HINT.with(|hint| hint.set(cluster));
+}
+
+thread_local! {
+    // The hash of the id of the current thread, which only has to be computed
+    // once per thread.
+    static THREAD_HASH: usize = hash_single(thread::current().id()) as usize;
+    // The cluster set with `set_cluster_hint`. `const` initializers for thread
+    // locals are newer than the minimum supported Rust version.
+    #[allow(clippy::missing_const_for_thread_local)]
+    static CLUSTER_HINT: Cell<Option<usize>> = Cell::new(None);
+}
+
+fn thread_hash() -> usize {
+    THREAD_HASH.with(|hash| *hash)
+}
+
+impl<L: fmt::Debug> fmt::Debug for HierarchicalNetwork<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HierarchicalNetwork")
+            .field("num_clusters", &self.num_clusters())
+            .field("cluster_width", &self.cluster_width)
+            .field("outputs", &self.outputs())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::bitonic_balancer_count;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn sync_only<T: Sync>(_: T) {}
+    fn send_only<T: Send>(_: T) {}
+
+    #[test]
+    fn is_send_and_sync() {
+        send_only(HierarchicalNetwork::new(Width::of(2), vec![(); 4]));
+        sync_only(HierarchicalNetwork::new(Width::of(2), vec![(); 4]));
+    }
+
+    #[test]
+    fn balancers_are_split_between_levels() {
+        for &(num_clusters, width) in &[(1, 8), (2, 8), (4, 16), (16, 16)] {
+            let network = HierarchicalNetwork::new(Width::of(num_clusters), vec![(); width]);
+            let cluster_width = width / num_clusters;
+
+            assert_eq!(
+                network.global.num_balancers(),
+                bitonic_balancer_count(width)
+                    - num_clusters * bitonic_balancer_count(cluster_width)
+            );
+            assert!(network
+                .clusters
+                .iter()
+                .all(|local| local.width() == cluster_width));
+        }
+    }
+
+    #[test]
+    fn counts_across_clusters() {
+        let network = HierarchicalNetwork::new(Width::of(4), (0..16).collect());
+
+        // Every state of a single thread's traversals is quiescent, so the
+        // outputs are handed out in order whichever clusters are used.
+        for count in 0..(16 * 8) {
+            assert_eq!(*network.traverse_in(count * count % 4), count % 16);
+        }
+    }
+
+    #[test]
+    fn cluster_hint_picks_cluster() {
+        let hinted = HierarchicalNetwork::new(Width::of(4), (0..16).collect::<Vec<_>>());
+        let explicit = HierarchicalNetwork::new(Width::of(4), (0..16).collect::<Vec<_>>());
+
+        set_cluster_hint(Some(6));
+        assert_eq!(hinted.cluster_for_current_thread(), 2);
+        for _ in 0..32 {
+            assert_eq!(hinted.traverse(), explicit.traverse_in(2));
+        }
+
+        set_cluster_hint(None);
+        assert_eq!(hinted.cluster_for_current_thread(), thread_hash() / 4 % 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_many_clusters() {
+        let _ = HierarchicalNetwork::new(Width::of(8), vec![(); 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn cluster_out_of_range() {
+        let network = HierarchicalNetwork::new(Width::of(2), vec![(); 4]);
+
+        network.traverse_in(2);
+    }
+
+    #[test]
+    fn concurrent_traversals_are_balanced() {
+        const NUM_THREADS: usize = 8;
+        const TRAVERSALS_PER_THREAD: usize = 1000;
+
+        let network = Arc::new(HierarchicalNetwork::new(
+            Width::of(4),
+            (0..16).map(|_| AtomicUsize::new(0)).collect(),
+        ));
+        let thread_handles: Vec<_> = (0..NUM_THREADS)
+            .map(|_| {
+                let network = Arc::clone(&network);
+                thread::spawn(move || {
+                    for _ in 0..TRAVERSALS_PER_THREAD {
+                        network.traverse().fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        let counts: Vec<_> = network
+            .outputs()
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        let expected = NUM_THREADS * TRAVERSALS_PER_THREAD / 16;
+        assert!(
+            counts.iter().all(|&count| count == expected),
+            "{:?}",
+            counts
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Verified configurations of counting networks for small widths.
+//!
+//! The tables in this module are the bitonic counting networks of Aspnes,
+//! Herlihy and Shavit, written out in the order that
+//! [`BitonicConfiguration`](super::BitonicConfiguration) yields them. Each
+//! table is checked against a transcription of the recursive definition in the
+//! paper that shares no code with the crate's networks, and to have the step
+//! property in every quiescent state that its tests try. The tables are fixed,
+//! so they can be used as ground truth for configurations produced by other
+//! means.
+
+/// The widest network that has a known configuration.
+pub const MAX_KNOWN_WIDTH: usize = 32;
+
+/// Returns the known counting network configuration for the given width, or
+/// `None` if there isn't one.
+///
+/// There are known configurations for every power of two up to and including
+/// [`MAX_KNOWN_WIDTH`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::known::known_config;
+///
+/// assert_eq!(
+///     known_config(4),
+///     Some(&[(0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)][..])
+/// );
+/// assert_eq!(known_config(6), None);
+/// assert_eq!(known_config(64), None);
+/// ```
+pub fn known_config(width: usize) -> Option<&'static [(usize, usize)]> {
+    match width {
+        1 => Some(BITONIC_1),
+        2 => Some(BITONIC_2),
+        4 => Some(BITONIC_4),
+        8 => Some(BITONIC_8),
+        16 => Some(BITONIC_16),
+        32 => Some(BITONIC_32),
+        _ => None,
+    }
+}
+
+/// Returns true if `config` is exactly the known counting network
+/// configuration for the given width.
+///
+/// A configuration that is a valid counting network, but is not the one in
+/// the table, is not known to be valid, so this returns false for it.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{
+///     canonical_config, known::is_known_valid, BitonicConfiguration, Width,
+/// };
+///
+/// let config = canonical_config::<BitonicConfiguration>(Width::new(8).unwrap());
+/// assert!(is_known_valid(8, &config));
+///
+/// assert!(!is_known_valid(8, &config[1..]));
+/// assert!(!is_known_valid(4, &config));
+/// ```
+pub fn is_known_valid(width: usize, config: &[(usize, usize)]) -> bool {
+    match known_config(width) {
+        Some(known) => known == config,
+        None => false,
+    }
+}
+
+const BITONIC_1: &[(usize, usize)] = &[];
+
+const BITONIC_2: &[(usize, usize)] = &[(0, 1)];
+
+#[rustfmt::skip]
+const BITONIC_4: &[(usize, usize)] = &[
+    (0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3),
+];
+
+#[rustfmt::skip]
+const BITONIC_8: &[(usize, usize)] = &[
+    (0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3), (4, 5), (6, 7),
+    (4, 7), (5, 6), (4, 5), (6, 7), (0, 7), (2, 5), (0, 2), (5, 7),
+    (1, 6), (3, 4), (1, 3), (4, 6), (0, 1), (2, 3), (4, 5), (6, 7),
+];
+
+#[rustfmt::skip]
+const BITONIC_16: &[(usize, usize)] = &[
+    (0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3), (4, 5), (6, 7),
+    (4, 7), (5, 6), (4, 5), (6, 7), (0, 7), (2, 5), (0, 2), (5, 7),
+    (1, 6), (3, 4), (1, 3), (4, 6), (0, 1), (2, 3), (4, 5), (6, 7),
+    (8, 9), (10, 11), (8, 11), (9, 10), (8, 9), (10, 11), (12, 13), (14, 15),
+    (12, 15), (13, 14), (12, 13), (14, 15), (8, 15), (10, 13), (8, 10), (13, 15),
+    (9, 14), (11, 12), (9, 11), (12, 14), (8, 9), (10, 11), (12, 13), (14, 15),
+    (0, 15), (4, 11), (0, 4), (11, 15), (2, 13), (6, 9), (2, 6), (9, 13),
+    (0, 2), (4, 6), (9, 11), (13, 15), (1, 14), (5, 10), (1, 5), (10, 14),
+    (3, 12), (7, 8), (3, 7), (8, 12), (1, 3), (5, 7), (8, 10), (12, 14),
+    (0, 1), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11), (12, 13), (14, 15),
+];
+
+#[rustfmt::skip]
+const BITONIC_32: &[(usize, usize)] = &[
+    (0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3), (4, 5), (6, 7),
+    (4, 7), (5, 6), (4, 5), (6, 7), (0, 7), (2, 5), (0, 2), (5, 7),
+    (1, 6), (3, 4), (1, 3), (4, 6), (0, 1), (2, 3), (4, 5), (6, 7),
+    (8, 9), (10, 11), (8, 11), (9, 10), (8, 9), (10, 11), (12, 13), (14, 15),
+    (12, 15), (13, 14), (12, 13), (14, 15), (8, 15), (10, 13), (8, 10), (13, 15),
+    (9, 14), (11, 12), (9, 11), (12, 14), (8, 9), (10, 11), (12, 13), (14, 15),
+    (0, 15), (4, 11), (0, 4), (11, 15), (2, 13), (6, 9), (2, 6), (9, 13),
+    (0, 2), (4, 6), (9, 11), (13, 15), (1, 14), (5, 10), (1, 5), (10, 14),
+    (3, 12), (7, 8), (3, 7), (8, 12), (1, 3), (5, 7), (8, 10), (12, 14),
+    (0, 1), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11), (12, 13), (14, 15),
+    (16, 17), (18, 19), (16, 19), (17, 18), (16, 17), (18, 19), (20, 21), (22, 23),
+    (20, 23), (21, 22), (20, 21), (22, 23), (16, 23), (18, 21), (16, 18), (21, 23),
+    (17, 22), (19, 20), (17, 19), (20, 22), (16, 17), (18, 19), (20, 21), (22, 23),
+    (24, 25), (26, 27), (24, 27), (25, 26), (24, 25), (26, 27), (28, 29), (30, 31),
+    (28, 31), (29, 30), (28, 29), (30, 31), (24, 31), (26, 29), (24, 26), (29, 31),
+    (25, 30), (27, 28), (25, 27), (28, 30), (24, 25), (26, 27), (28, 29), (30, 31),
+    (16, 31), (20, 27), (16, 20), (27, 31), (18, 29), (22, 25), (18, 22), (25, 29),
+    (16, 18), (20, 22), (25, 27), (29, 31), (17, 30), (21, 26), (17, 21), (26, 30),
+    (19, 28), (23, 24), (19, 23), (24, 28), (17, 19), (21, 23), (24, 26), (28, 30),
+    (16, 17), (18, 19), (20, 21), (22, 23), (24, 25), (26, 27), (28, 29), (30, 31),
+    (0, 31), (8, 23), (0, 8), (23, 31), (4, 27), (12, 19), (4, 12), (19, 27),
+    (0, 4), (8, 12), (19, 23), (27, 31), (2, 29), (10, 21), (2, 10), (21, 29),
+    (6, 25), (14, 17), (6, 14), (17, 25), (2, 6), (10, 14), (17, 21), (25, 29),
+    (0, 2), (4, 6), (8, 10), (12, 14), (17, 19), (21, 23), (25, 27), (29, 31),
+    (1, 30), (9, 22), (1, 9), (22, 30), (5, 26), (13, 18), (5, 13), (18, 26),
+    (1, 5), (9, 13), (18, 22), (26, 30), (3, 28), (11, 20), (3, 11), (20, 28),
+    (7, 24), (15, 16), (7, 15), (16, 24), (3, 7), (11, 15), (16, 20), (24, 28),
+    (1, 3), (5, 7), (9, 11), (13, 15), (16, 18), (20, 22), (24, 26), (28, 30),
+    (0, 1), (2, 3), (4, 5), (6, 7), (8, 9), (10, 11), (12, 13), (14, 15),
+    (16, 17), (18, 19), (20, 21), (22, 23), (24, 25), (26, 27), (28, 29), (30, 31),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{canonical_config, BitonicConfiguration, Width};
+
+    // Token counts on each wire once all of the tokens that entered with
+    // `inputs` have left the network. A balancer sends half of its tokens to
+    // each output wire, with the extra token going to the first wire.
+    fn quiescent_outputs(config: &[(usize, usize)], inputs: &[usize]) -> Vec<usize> {
+        let mut counts = inputs.to_vec();
+        for &(top, bottom) in config {
+            let total = counts[top] + counts[bottom];
+            counts[bottom] = total / 2;
+            counts[top] = total - counts[bottom];
+        }
+        counts
+    }
+
+    // Counts never increase down the wires, and differ by at most one.
+    fn has_step_property(outputs: &[usize]) -> bool {
+        outputs.windows(2).all(|pair| pair[0] >= pair[1])
+            && outputs[0] - outputs[outputs.len() - 1] <= 1
+    }
+
+    #[test]
+    fn known_configs_have_step_property_for_small_inputs() {
+        for width in (0..4).map(|exp| 1 << exp) {
+            let config = known_config(width).unwrap();
+
+            // Every way of putting up to 3 tokens on each input wire
+            for combination in 0..4usize.pow(width as u32) {
+                let inputs: Vec<_> = (0..width)
+                    .map(|wire| (combination >> (2 * wire)) & 0b11)
+                    .collect();
+
+                let outputs = quiescent_outputs(config, &inputs);
+                assert!(has_step_property(&outputs), "{:?} -> {:?}", inputs, outputs);
+            }
+        }
+    }
+
+    #[test]
+    fn known_configs_have_step_property_for_wide_inputs() {
+        for &width in &[16, 32] {
+            let config = known_config(width).unwrap();
+
+            let mut state: usize = 0x2545_f491;
+            for _ in 0..1000 {
+                let inputs: Vec<_> = (0..width)
+                    .map(|_| {
+                        // xorshift
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        state % 8
+                    })
+                    .collect();
+
+                let outputs = quiescent_outputs(config, &inputs);
+                assert!(has_step_property(&outputs), "{:?} -> {:?}", inputs, outputs);
+            }
+        }
+    }
+
+    // The bitonic counting network as defined by Aspnes, Herlihy and Shavit,
+    // written out directly from the recursive definition in the paper rather
+    // than with `BitonicConfiguration`. Returns the wires that the outputs
+    // `y[0], y[1], ...` leave on.
+    fn reference_bitonic(wires: &[usize], balancers: &mut Vec<(usize, usize)>) -> Vec<usize> {
+        if wires.len() == 1 {
+            return wires.to_vec();
+        }
+
+        let (top, bottom) = wires.split_at(wires.len() / 2);
+        let mut outputs = reference_bitonic(top, balancers);
+        outputs.extend(reference_bitonic(bottom, balancers));
+        reference_merger(&outputs, balancers)
+    }
+
+    // `Merger[2k]` merges the step sequences on `x[..k]` and `x[k..]`.
+    fn reference_merger(x: &[usize], balancers: &mut Vec<(usize, usize)>) -> Vec<usize> {
+        let (top, bottom) = x.split_at(x.len() / 2);
+        if top.len() == 1 {
+            balancers.push((top[0], bottom[0]));
+            return x.to_vec();
+        }
+
+        let even = |wires: &[usize]| wires.iter().copied().step_by(2).collect::<Vec<_>>();
+        let odd = |wires: &[usize]| wires.iter().copied().skip(1).step_by(2).collect::<Vec<_>>();
+
+        // One `Merger[k]` takes the even outputs of the top half and the odd
+        // outputs of the bottom half, the other the odd and the even ones.
+        let z = reference_merger(&[even(top), odd(bottom)].concat(), balancers);
+        let z_prime = reference_merger(&[odd(top), even(bottom)].concat(), balancers);
+
+        // A final layer of balancers joins `z[i]` and `z'[i]` into `y[2i]` and
+        // `y[2i + 1]`. Which of the two wires carries `y[2i]` is a matter of
+        // layout, this puts it on the upper one.
+        z.iter()
+            .zip(&z_prime)
+            .flat_map(|(&a, &b)| {
+                let balancer = (a.min(b), a.max(b));
+                balancers.push(balancer);
+                vec![balancer.0, balancer.1]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn known_configs_match_reference() {
+        for width in (0..6).map(|exp| 1 << exp) {
+            let wires: Vec<_> = (0..width).collect();
+            let mut balancers = Vec::new();
+
+            let outputs = reference_bitonic(&wires, &mut balancers);
+
+            // With that layout each output leaves on the wire with its index.
+            assert_eq!(outputs, wires);
+            assert_eq!(known_config(width), Some(&balancers[..]), "width {}", width);
+        }
+    }
+
+    #[test]
+    fn bitonic_configurations_are_known() {
+        for width in (0..6).map(|exp| 1 << exp) {
+            assert!(is_known_valid(
+                width,
+                &canonical_config::<BitonicConfiguration>(Width::of(width))
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_widths() {
+        assert_eq!(known_config(0), None);
+        assert_eq!(known_config(3), None);
+        assert_eq!(known_config(MAX_KNOWN_WIDTH * 2), None);
+        assert!(!is_known_valid(3, &[(0, 1), (1, 2)]));
+    }
+}
@@ -4,6 +4,45 @@
 //! in general.
 
 mod bitonic;
+mod butterfly;
+mod canonical;
 mod common;
+mod custom;
+mod hierarchical;
+pub mod known;
+pub mod periodic;
+mod render;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "render")]
+mod svg;
+#[cfg(feature = "synthesis")]
+pub mod synthesis;
+mod tla;
+mod topology;
+mod width;
 
-pub use self::bitonic::BitonicNetwork;
+pub use self::{
+    bitonic::{
+        bitonic_balancer_count, bitonic_depth, BitonicConfiguration, BitonicConfigurationIter,
+        BitonicNetwork,
+    },
+    butterfly::{ButterflyConfiguration, ButterflyNetwork},
+    canonical::{canonical_config, diff_configs, ConfigDifference},
+    common::{
+        AllocError, InitialToggles, IntegrityError, IntegrityViolation, MemoryUsage, Network,
+        NetworkConfiguration,
+    },
+    custom::{CustomNetwork, CustomNetworkError},
+    hierarchical::{set_cluster_hint, HierarchicalNetwork},
+    periodic::{PeriodicConfiguration, PeriodicNetwork},
+    topology::{Optimization, ParseTopologyError, Topology, TopologyError},
+    width::Width,
+};
+
+pub(crate) use self::topology::check_balancer;
+
+#[cfg(feature = "observer")]
+pub use self::common::TraversalEvent;
+#[cfg(feature = "render")]
+pub use self::svg::SvgOptions;
@@ -0,0 +1,233 @@
+//! Classification of wires into cochains.
+//!
+//! A cochain selects the wires of a network whose index falls into a fixed set
+//! of residues modulo a power of two. The periodic network construction uses
+//! four of them:
+//!
+//! ```text
+//! E = { i | i mod 2 = 0 }        0 2 4 6 ...
+//! O = { i | i mod 2 = 1 }        1 3 5 7 ...
+//! A = { i | i mod 4 ∈ {0, 3} }   0 3 4 7 ...
+//! B = { i | i mod 4 ∈ {1, 2} }   1 2 5 6 ...
+//! ```
+//!
+//! The same machinery can be used to pick out wires when laying out custom
+//! layered networks.
+
+use crate::networks::Width;
+use core::ops::Range;
+
+/// A subsequence of wires selected by their index modulo a fixed value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cochain {
+    modulus: usize,
+    residues: &'static [usize],
+}
+
+impl Cochain {
+    /// The cochain containing every wire with an index equal to 0 or 3 modulo
+    /// 4.
+    pub const A: Cochain = Cochain {
+        modulus: 4,
+        residues: &[0b00, 0b11],
+    };
+    /// The cochain containing every wire with an index equal to 1 or 2 modulo
+    /// 4.
+    pub const B: Cochain = Cochain {
+        modulus: 4,
+        residues: &[0b01, 0b10],
+    };
+    /// The cochain containing every wire with an even index.
+    pub const EVEN: Cochain = Cochain {
+        modulus: 2,
+        residues: &[0b0],
+    };
+    /// The cochain containing every wire with an odd index.
+    pub const ODD: Cochain = Cochain {
+        modulus: 2,
+        residues: &[0b1],
+    };
+
+    /// Create a new cochain that contains every index equal to one of the
+    /// `residues` modulo `modulus`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the residues is not less than `modulus`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{periodic::cochain::Cochain, Width};
+    ///
+    /// let cochain = Cochain::new(Width::new(8).unwrap(), &[1, 6]);
+    ///
+    /// assert_eq!(cochain.indices(0..16).collect::<Vec<_>>(), &[1, 6, 9, 14]);
+    /// ```
+    pub fn new(modulus: Width, residues: &'static [usize]) -> Self {
+        let modulus = modulus.get();
+        assert!(residues.iter().all(|&residue| residue < modulus));
+
+        Cochain { modulus, residues }
+    }
+
+    /// Returns the modulus that indices are compared under.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::periodic::cochain::Cochain;
+    ///
+    /// assert_eq!(Cochain::EVEN.modulus(), 2);
+    /// assert_eq!(Cochain::A.modulus(), 4);
+    /// ```
+    pub fn modulus(&self) -> usize {
+        self.modulus
+    }
+
+    /// Returns the residues that indices in this cochain are equal to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::periodic::cochain::Cochain;
+    ///
+    /// assert_eq!(Cochain::B.residues(), &[1, 2]);
+    /// ```
+    pub fn residues(&self) -> &'static [usize] {
+        self.residues
+    }
+
+    /// Returns true if the given index is part of this cochain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::periodic::cochain::Cochain;
+    ///
+    /// assert!(Cochain::A.contains(7));
+    /// assert!(!Cochain::A.contains(6));
+    /// ```
+    pub fn contains(&self, index: usize) -> bool {
+        // The modulus is always a power of two, so masking is equivalent to `%`.
+        let masked = index & (self.modulus - 1);
+
+        self.residues.contains(&masked)
+    }
+
+    /// Returns the indices in `range` that are part of this cochain, in
+    /// increasing order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::periodic::cochain::Cochain;
+    ///
+    /// let odd: Vec<_> = Cochain::ODD.indices(0..8).collect();
+    ///
+    /// assert_eq!(odd, &[1, 3, 5, 7]);
+    /// ```
+    pub fn indices(self, range: Range<usize>) -> impl Iterator<Item = usize> {
+        range.filter(move |&idx| self.contains(idx))
+    }
+
+    /// Returns the elements of `items` whose position is part of this cochain,
+    /// in order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::periodic::cochain::Cochain;
+    ///
+    /// let wires = ['a', 'b', 'c', 'd', 'e', 'f'];
+    /// let selected: Vec<_> = Cochain::B.select(&wires).collect();
+    ///
+    /// assert_eq!(selected, &[&'b', &'c', &'f']);
+    /// ```
+    pub fn select<T>(self, items: &[T]) -> impl Iterator<Item = &T> {
+        items
+            .iter()
+            .enumerate()
+            .filter(move |(idx, _)| self.contains(*idx))
+            .map(|(_, item)| item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(cochain: Cochain, range: Range<usize>) -> Vec<usize> {
+        cochain.indices(range).collect()
+    }
+
+    #[test]
+    fn check_even_cochains() {
+        let chain_1 = collect(Cochain::EVEN, 0..20);
+        assert!(chain_1.iter().all(|&x| x % 2 == 0));
+        assert_eq!(chain_1.len(), 10);
+
+        let chain_2 = collect(Cochain::EVEN, 0..1);
+        assert!(chain_2.iter().all(|&x| x % 2 == 0));
+        assert_eq!(chain_2.len(), 1);
+
+        let chain_3 = collect(Cochain::EVEN, 0..13);
+        assert!(chain_3.iter().all(|&x| x % 2 == 0));
+        assert_eq!(chain_3.len(), 7);
+    }
+
+    #[test]
+    fn check_odd_cochain() {
+        let chain_1 = collect(Cochain::ODD, 0..20);
+        assert!(chain_1.iter().all(|&x| x % 2 != 0));
+        assert_eq!(chain_1.len(), 10);
+
+        let chain_2 = collect(Cochain::ODD, 0..1);
+        assert!(chain_2.iter().all(|&x| x % 2 != 0));
+        assert_eq!(chain_2.len(), 0);
+
+        let chain_3 = collect(Cochain::ODD, 0..13);
+        assert!(chain_3.iter().all(|&x| x % 2 != 0));
+        assert_eq!(chain_3.len(), 6);
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn check_A_cochain() {
+        let chain_1 = collect(Cochain::A, 0..20);
+        assert_eq!(chain_1, &[0, 3, 4, 7, 8, 11, 12, 15, 16, 19]);
+
+        let chain_1 = collect(Cochain::A, 0..1);
+        assert_eq!(chain_1, &[0]);
+
+        let chain_1 = collect(Cochain::A, 0..14);
+        assert_eq!(chain_1, &[0, 3, 4, 7, 8, 11, 12]);
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn check_B_cochain() {
+        let chain_1 = collect(Cochain::B, 0..20);
+        assert_eq!(chain_1, &[1, 2, 5, 6, 9, 10, 13, 14, 17, 18]);
+
+        let chain_1 = collect(Cochain::B, 0..1);
+        assert_eq!(chain_1, &[]);
+
+        let chain_1 = collect(Cochain::B, 0..14);
+        assert_eq!(chain_1, &[1, 2, 5, 6, 9, 10, 13]);
+    }
+
+    #[test]
+    fn cochains_partition_wires() {
+        for idx in 0..64 {
+            assert_ne!(Cochain::EVEN.contains(idx), Cochain::ODD.contains(idx));
+            assert_ne!(Cochain::A.contains(idx), Cochain::B.contains(idx));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_cochain_bad_residue() {
+        let _ = Cochain::new(Width::of(4), &[4]);
+    }
+}
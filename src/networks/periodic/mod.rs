@@ -0,0 +1,141 @@
+//! Periodic counting networks, and the building blocks that they are made of.
+//!
+//! The periodic network described by Aspnes et al. is built by repeating a
+//! single `Block[w]` network, which is itself defined recursively in terms of
+//! subsequences of its wires called cochains. See the [`cochain`] module for
+//! the wire classification used by that construction.
+
+pub mod cochain;
+
+use super::{
+    common::{Network, NetworkConfiguration},
+    width::Width,
+};
+use std::vec;
+
+/// A type of counting network
+///
+/// A periodic network of width `w` is `log2(w)` copies of the same `Block[w]`
+/// network, one after the other. Each block has `log2(w)` layers. The first
+/// layer pairs each wire with its mirror image, so wire `0` with wire `w - 1`,
+/// wire `1` with wire `w - 2` and so on. Each following layer does the same
+/// within the halves of the previous layer:
+///
+/// ```text
+/// x0 ───╥─────╥──╥─────╥─── y0
+/// x1 ───║──╥──╨──║──╥──╨─── y1
+/// x2 ───║──╨──╥──║──╨──╥─── y2
+/// x3 ───╨─────╨──╨─────╨─── y3
+/// ```
+///
+/// This is the layered form of the block network, the paper by Aspnes et al.
+/// builds the same network recursively from [cochains](cochain). A periodic
+/// network has `log2(w) * log2(w)` layers, deeper than a
+/// [`BitonicNetwork`](super::BitonicNetwork) of the same width, but every
+/// block is identical, which makes it a useful comparison.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::PeriodicNetwork;
+///
+/// let network = PeriodicNetwork::new(vec![1, 2, 3, 4]);
+///
+/// assert_eq!(network.width(), 4);
+/// assert_eq!(network.traverse(), &1);
+/// assert_eq!(network.topology().depth(), 4);
+/// ```
+pub type PeriodicNetwork<L> = Network<L, PeriodicConfiguration>;
+
+/// The configuration of balancers in a [`PeriodicNetwork`].
+///
+/// Only supports widths that are a power of two. Balancers are yielded block
+/// by block, layer by layer within each block, and from the top wire down
+/// within each layer.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{NetworkConfiguration, PeriodicConfiguration, Width};
+///
+/// let width = Width::new(4).unwrap();
+/// let balancers: Vec<_> = PeriodicConfiguration::from_width(width).into_iter().collect();
+///
+/// assert_eq!(
+///     balancers,
+///     &[(0, 3), (1, 2), (0, 1), (2, 3), (0, 3), (1, 2), (0, 1), (2, 3)]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodicConfiguration(usize);
+
+impl IntoIterator for PeriodicConfiguration {
+    type IntoIter = vec::IntoIter<(usize, usize)>;
+    type Item = (usize, usize);
+
+    fn into_iter(self) -> Self::IntoIter {
+        let width = self.0;
+        let log_width = width.trailing_zeros() as usize;
+
+        let mut block = Vec::with_capacity((width / 2) * log_width);
+        let mut size = width;
+        while size > 1 {
+            // Pair each wire with its mirror image in its group of `size` wires
+            let mirror = size - 1;
+            block.extend(
+                (0..width)
+                    .filter(|&wire| wire & mirror < size / 2)
+                    .map(|wire| (wire, wire ^ mirror)),
+            );
+            size /= 2;
+        }
+
+        let mut balancers = Vec::with_capacity(block.len() * log_width);
+        for _ in 0..log_width {
+            balancers.extend_from_slice(&block);
+        }
+
+        balancers.into_iter()
+    }
+}
+
+impl NetworkConfiguration for PeriodicConfiguration {
+    fn from_width(width: Width) -> Self {
+        PeriodicConfiguration(width.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::Topology;
+
+    #[test]
+    fn periodic_layers() {
+        for width in (0..8).map(|exp| 1usize << exp) {
+            let topology = Topology::from_config::<PeriodicConfiguration>(Width::of(width));
+            let log_width = width.trailing_zeros() as usize;
+
+            assert_eq!(topology.depth(), log_width * log_width);
+            assert_eq!(
+                topology.balancers().len(),
+                (width / 2) * log_width * log_width
+            );
+        }
+    }
+
+    #[test]
+    fn sequential_traversals_count() {
+        for width in (0..7).map(|exp| 1usize << exp) {
+            let network = PeriodicNetwork::new((0..width).collect());
+
+            // Every state of a single thread's traversals is quiescent
+            for count in 0..(width * 40) {
+                assert_eq!(
+                    *network.traverse_keyed(&(count * count % 97)),
+                    count % width
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::{common::Network, topology::Topology};
+use core::fmt::Write;
+
+impl Topology {
+    /// Draw the network as text, with a line for each wire and a column for
+    /// each group of balancers that can be drawn side by side.
+    ///
+    /// Balancers are drawn in the order of
+    /// [`balancers`](Topology::balancers), each in the first column after the
+    /// last balancer that it would cross. Wire `i` enters as `xi` on the left
+    /// and leaves as `yi` on the right.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{ButterflyConfiguration, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<ButterflyConfiguration>(Width::new(4).unwrap());
+    ///
+    /// assert_eq!(
+    ///     topology.to_ascii(),
+    ///     "x0 ───╥─────╥─── y0\n\
+    ///      x1 ───║──╥──╨─── y1\n\
+    ///      x2 ───╨──║──╥─── y2\n\
+    ///      x3 ──────╨──╨─── y3\n"
+    /// );
+    /// ```
+    pub fn to_ascii(&self) -> String {
+        let width = self.width();
+        let mut columns: Vec<Vec<char>> = Vec::new();
+
+        for (&(top_wire, bottom_wire), column_idx) in self.balancers().iter().zip(self.columns()) {
+            let (low, high) = (top_wire.min(bottom_wire), top_wire.max(bottom_wire));
+            if column_idx == columns.len() {
+                columns.push(vec!['─'; width]);
+            }
+
+            let column = &mut columns[column_idx];
+            column[low] = '╥';
+            column[(low + 1)..high]
+                .iter_mut()
+                .for_each(|symbol| *symbol = '║');
+            column[high] = '╨';
+        }
+
+        let label_len = (width - 1).to_string().len();
+        let mut drawing = String::new();
+        for wire in 0..width {
+            write!(drawing, "x{:<len$} ─", wire, len = label_len).unwrap();
+            for column in &columns {
+                write!(drawing, "──{}", column[wire]).unwrap();
+            }
+            writeln!(drawing, "─── y{}", wire).unwrap();
+        }
+
+        drawing
+    }
+
+    // The column that each balancer is drawn in, the first one after the last
+    // balancer that it would cross.
+    pub(crate) fn columns(&self) -> Vec<usize> {
+        // Index of the next free column on each wire
+        let mut free = vec![0; self.width()];
+
+        self.balancers()
+            .iter()
+            .map(|&(top_wire, bottom_wire)| {
+                let (low, high) = (top_wire.min(bottom_wire), top_wire.max(bottom_wire));
+                let column_idx = free[low..=high].iter().copied().max().unwrap_or(0);
+                free[low..=high]
+                    .iter_mut()
+                    .for_each(|next| *next = column_idx + 1);
+
+                column_idx
+            })
+            .collect()
+    }
+
+    /// Write out the network in the DOT language of Graphviz.
+    ///
+    /// Each balancer is a node named `b` followed by its index in
+    /// [`balancers`](Topology::balancers), with an edge for each wire between
+    /// the input nodes `x0`, `x1`, ..., the balancers, and the output nodes
+    /// `y0`, `y1`, .... Edges are labelled with their wire.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+    ///
+    /// assert!(topology.to_dot().contains("    x1 -> b0 [label=\"1\"];\n"));
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n    rankdir=LR;\n");
+
+        for wire in 0..self.width() {
+            writeln!(dot, "    x{} [shape=plaintext];", wire).unwrap();
+            writeln!(dot, "    y{} [shape=plaintext];", wire).unwrap();
+        }
+        for idx in 0..self.balancers().len() {
+            writeln!(dot, "    b{} [shape=box, label=\"{}\"];", idx, idx).unwrap();
+        }
+
+        // The node that each wire last passed through
+        let mut previous: Vec<String> =
+            (0..self.width()).map(|wire| format!("x{}", wire)).collect();
+        for (idx, &(top_wire, bottom_wire)) in self.balancers().iter().enumerate() {
+            for &wire in &[top_wire, bottom_wire] {
+                writeln!(
+                    dot,
+                    "    {} -> b{} [label=\"{}\"];",
+                    previous[wire], idx, wire
+                )
+                .unwrap();
+                previous[wire] = format!("b{}", idx);
+            }
+        }
+        for (wire, node) in previous.iter().enumerate() {
+            writeln!(dot, "    {} -> y{} [label=\"{}\"];", node, wire, wire).unwrap();
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl<L, B> Network<L, B> {
+    /// Draw the network as text, with a line for each wire, see
+    /// [`Topology::to_ascii`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![(); 8]);
+    ///
+    /// print!("{}", network.to_ascii());
+    /// assert_eq!(network.to_ascii().lines().count(), 8);
+    /// ```
+    pub fn to_ascii(&self) -> String {
+        self.topology().to_ascii()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{BitonicConfiguration, BitonicNetwork, Width};
+
+    #[test]
+    fn ascii_for_bitonic() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(4));
+
+        let lines: Vec<_> = topology.to_ascii().lines().map(String::from).collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "x0 ───╥──╥─────╥─── y0",
+                "x1 ───╨──║──╥──╨─── y1",
+                "x2 ───╥──║──╨──╥─── y2",
+                "x3 ───╨──╨─────╨─── y3",
+            ]
+        );
+    }
+
+    #[test]
+    fn ascii_for_network() {
+        let network = BitonicNetwork::new(vec![(); 4]);
+
+        assert_eq!(
+            network.to_ascii(),
+            Topology::from_config::<BitonicConfiguration>(Width::of(4)).to_ascii()
+        );
+    }
+
+    #[test]
+    fn ascii_pads_labels() {
+        let topology = Topology::new(11, vec![]).unwrap();
+        let drawing = topology.to_ascii();
+
+        assert!(drawing.starts_with("x0  ──── y0\n"));
+        assert!(drawing.ends_with("x10 ──── y10\n"));
+    }
+
+    #[test]
+    fn dot_follows_each_wire() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(4));
+        let dot = topology.to_dot();
+
+        assert!(dot.starts_with("digraph network {\n"));
+        assert!(dot.ends_with("}\n"));
+        // Wire 3 goes through balancers 1, 2 and 5
+        for edge in &["x3 -> b1", "b1 -> b2", "b2 -> b5", "b5 -> y3"] {
+            assert!(dot.contains(&format!("    {} [label=\"3\"];\n", edge)));
+        }
+        assert_eq!(dot.matches(" -> ").count(), 4 + 2 * 6);
+    }
+}
@@ -0,0 +1,154 @@
+use super::{common::Network, topology::Topology};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+// The serialized form of a network: its layout and its outputs, but not the
+// states of its balancers.
+#[derive(Serialize)]
+#[serde(rename = "Network")]
+struct NetworkRef<'a, L> {
+    width: usize,
+    balancers: Vec<(usize, usize)>,
+    outputs: &'a [L],
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Network")]
+struct NetworkData<L> {
+    width: usize,
+    balancers: Vec<(usize, usize)>,
+    outputs: Vec<L>,
+}
+
+/// Networks are serialized as their width, their balancers in order and their
+/// outputs. The states of the balancers are not included, so a deserialized
+/// network starts with every balancer up, like a new one.
+///
+/// Only available with the `serde` feature.
+///
+/// # Examples
+///
+/// A `BitonicNetwork` of width 2 with the outputs `"a"` and `"b"` is written
+/// like this in JSON:
+///
+/// ```text
+/// {"width":2,"balancers":[[0,1]],"outputs":["a","b"]}
+/// ```
+impl<L: Serialize, B> Serialize for Network<L, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NetworkRef {
+            width: self.width(),
+            balancers: self.balancers().collect(),
+            outputs: self.outputs(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserialized networks are rebuilt from their balancers, which are checked
+/// to describe a network of the width like [`Topology::new`] does. The
+/// balancers are not checked against the configuration `B`, so a network can
+/// be moved between configurations that have the same layout.
+///
+/// Only available with the `serde` feature.
+impl<'de, L: Deserialize<'de>, B> Deserialize<'de> for Network<L, B> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = NetworkData::deserialize(deserializer)?;
+        let topology = Topology::new(data.width, data.balancers).map_err(de::Error::custom)?;
+        if data.outputs.len() != data.width {
+            return Err(de::Error::custom(format_args!(
+                "expected {} outputs, one for each wire",
+                data.width
+            )));
+        }
+
+        Ok(Network::from_balancer_iter(
+            data.outputs,
+            topology.balancers().iter().copied(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        networks::{BitonicNetwork, CustomNetwork},
+        util::value::{from_value, to_value, Value},
+    };
+
+    fn balancers(pairs: &[(u64, u64)]) -> Value {
+        Value::Seq(
+            pairs
+                .iter()
+                .map(|&(top_wire, bottom_wire)| {
+                    Value::Seq(vec![
+                        Value::Unsigned(top_wire),
+                        Value::Unsigned(bottom_wire),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn network(width: u64, pairs: &[(u64, u64)], outputs: Vec<Value>) -> Value {
+        Value::map(vec![
+            ("width", Value::Unsigned(width)),
+            ("balancers", balancers(pairs)),
+            ("outputs", Value::Seq(outputs)),
+        ])
+    }
+
+    #[test]
+    fn serialized_form() {
+        let network = BitonicNetwork::new(vec!["a", "b"]);
+
+        assert_eq!(
+            to_value(&network).unwrap(),
+            self::network(2, &[(0, 1)], vec![Value::str("a"), Value::str("b")])
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let network = BitonicNetwork::new((0..8).map(|idx| format!("bucket-{}", idx)).collect());
+        network.traverse();
+
+        let copy: BitonicNetwork<String> = from_value(to_value(&network).unwrap()).unwrap();
+
+        assert_eq!(copy.topology(), network.topology());
+        assert_eq!(copy.outputs(), network.outputs());
+        // Balancer states are not carried over
+        assert_eq!(copy.traverse(), "bucket-0");
+        assert_eq!(copy.verify_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn custom_layout() {
+        let value = network(3, &[(2, 0), (1, 2)], (0..3).map(Value::Unsigned).collect());
+        let network: CustomNetwork<u8> = from_value(value.clone()).unwrap();
+
+        assert_eq!(network.balancers().collect::<Vec<_>>(), &[(2, 0), (1, 2)]);
+        assert_eq!(to_value(&network).unwrap(), value);
+    }
+
+    #[test]
+    fn invalid_networks() {
+        let error =
+            from_value::<BitonicNetwork<u8>>(network(2, &[(0, 1)], vec![Value::Unsigned(0)]))
+                .unwrap_err();
+        assert_eq!(error.to_string(), "expected 2 outputs, one for each wire");
+
+        let error = from_value::<BitonicNetwork<u8>>(network(0, &[], vec![])).unwrap_err();
+        assert_eq!(error.to_string(), "network has no wires");
+
+        let error = from_value::<BitonicNetwork<u8>>(network(
+            2,
+            &[(0, 2)],
+            vec![Value::Unsigned(0), Value::Unsigned(1)],
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "balancer 0 uses wire 2 which is out of range"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use super::{common::Network, topology::Topology};
+use core::fmt::Write;
+
+// Layout of the drawing, in pixels
+const WIRE_SPACING: usize = 30;
+const COLUMN_SPACING: usize = 30;
+const LABEL_WIDTH: usize = 50;
+const MARGIN: usize = 10;
+const DOT_RADIUS: usize = 3;
+
+/// Options for drawing a network as SVG, see [`Topology::to_svg`].
+///
+/// Only available with the `render` feature.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{BitonicNetwork, SvgOptions};
+///
+/// let network = BitonicNetwork::new(vec![0, 1, 2, 3]);
+/// let options = SvgOptions::new().output_labels(network.outputs().iter().map(|output| output.to_string()));
+///
+/// assert!(network.to_svg(&options).contains(">3</text>"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SvgOptions {
+    input_labels: Option<Vec<String>>,
+    output_labels: Option<Vec<String>>,
+}
+
+impl SvgOptions {
+    /// Returns the default options, which label input wire `i` as `xi` and
+    /// output wire `i` as `yi`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{SvgOptions, Topology};
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+    /// let svg = topology.to_svg(&SvgOptions::new());
+    ///
+    /// assert!(svg.contains(">x1</text>"));
+    /// assert!(svg.contains(">y1</text>"));
+    /// ```
+    pub fn new() -> Self {
+        SvgOptions::default()
+    }
+
+    /// Set the labels of the input wires, one for each wire from the top down.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{SvgOptions, Topology};
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+    /// let svg = topology.to_svg(&SvgOptions::new().input_labels(vec!["left", "right"]));
+    ///
+    /// assert!(svg.contains(">right</text>"));
+    /// ```
+    pub fn input_labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.input_labels = Some(labels.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the labels of the output wires, one for each wire from the top down.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{SvgOptions, Topology};
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+    /// let svg = topology.to_svg(&SvgOptions::new().output_labels(vec!["even", "odd"]));
+    ///
+    /// assert!(svg.contains(">odd</text>"));
+    /// ```
+    pub fn output_labels<I, S>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.output_labels = Some(labels.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+impl Topology {
+    /// Draw the network as an SVG image, with the wires running left to right
+    /// and each balancer as a vertical line between its two wires.
+    ///
+    /// Balancers are placed in the same columns as in
+    /// [`to_ascii`](Topology::to_ascii). Labels are escaped, so they can hold
+    /// any text.
+    ///
+    /// Only available with the `render` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options have a different number of input or output
+    /// labels than the network has wires.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, SvgOptions, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<BitonicConfiguration>(Width::new(8).unwrap());
+    /// let svg = topology.to_svg(&SvgOptions::new());
+    ///
+    /// assert!(svg.starts_with("<svg "));
+    /// assert_eq!(svg.matches("<line class=\"balancer\"").count(), 24);
+    /// ```
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        let width = self.width();
+        let input_labels = labels(&options.input_labels, width, "x", "input");
+        let output_labels = labels(&options.output_labels, width, "y", "output");
+
+        let columns = self.columns();
+        let num_columns = columns.iter().map(|&column| column + 1).max().unwrap_or(0);
+        let wires_start = MARGIN + LABEL_WIDTH;
+        let wires_end = wires_start + (num_columns + 1) * COLUMN_SPACING;
+        let image_width = wires_end + LABEL_WIDTH + MARGIN;
+        let image_height = 2 * MARGIN + width * WIRE_SPACING;
+        let wire_y = |wire: usize| MARGIN + wire * WIRE_SPACING + WIRE_SPACING / 2;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 \
+             {} {}\">",
+            image_width, image_height, image_width, image_height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "  <g stroke=\"black\" stroke-width=\"2\" fill=\"black\" font-family=\"monospace\" \
+             font-size=\"14\">"
+        )
+        .unwrap();
+
+        for wire in 0..width {
+            let y = wire_y(wire);
+            writeln!(
+                svg,
+                "    <text x=\"{}\" y=\"{}\" stroke=\"none\" text-anchor=\"end\" \
+                 dominant-baseline=\"middle\">{}</text>",
+                wires_start - MARGIN,
+                y,
+                escape(&input_labels[wire])
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                "    <line class=\"wire\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+                wires_start, y, wires_end, y
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                "    <text x=\"{}\" y=\"{}\" stroke=\"none\" text-anchor=\"start\" \
+                 dominant-baseline=\"middle\">{}</text>",
+                wires_end + MARGIN,
+                y,
+                escape(&output_labels[wire])
+            )
+            .unwrap();
+        }
+
+        for (&(top_wire, bottom_wire), column) in self.balancers().iter().zip(columns) {
+            let x = wires_start + (column + 1) * COLUMN_SPACING;
+            let (top_y, bottom_y) = (wire_y(top_wire), wire_y(bottom_wire));
+            writeln!(
+                svg,
+                "    <line class=\"balancer\" x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+                x, top_y, x, bottom_y
+            )
+            .unwrap();
+            for &y in &[top_y, bottom_y] {
+                writeln!(
+                    svg,
+                    "    <circle cx=\"{}\" cy=\"{}\" r=\"{}\"/>",
+                    x, y, DOT_RADIUS
+                )
+                .unwrap();
+            }
+        }
+
+        svg.push_str("  </g>\n</svg>\n");
+        svg
+    }
+}
+
+impl<L, B> Network<L, B> {
+    /// Draw the network as an SVG image, see [`Topology::to_svg`].
+    ///
+    /// Only available with the `render` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options have a different number of input or output
+    /// labels than the network has wires.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{ButterflyNetwork, SvgOptions};
+    ///
+    /// let network = ButterflyNetwork::new(vec![(); 4]);
+    /// let svg = network.to_svg(&SvgOptions::new());
+    ///
+    /// assert_eq!(svg.matches("<line class=\"wire\"").count(), 4);
+    /// ```
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        self.topology().to_svg(options)
+    }
+}
+
+// The labels for each wire, or the default labels made from `prefix` if none
+// were set.
+fn labels(labels: &Option<Vec<String>>, width: usize, prefix: &str, kind: &str) -> Vec<String> {
+    match labels {
+        Some(labels) => {
+            assert_eq!(
+                labels.len(),
+                width,
+                "expected {} {} labels, one for each wire",
+                width,
+                kind
+            );
+            labels.clone()
+        }
+        None => (0..width)
+            .map(|wire| format!("{}{}", prefix, wire))
+            .collect(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for symbol in text.chars() {
+        match symbol {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            symbol => escaped.push(symbol),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{BitonicConfiguration, Width};
+
+    #[test]
+    fn balancers_follow_columns() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(4));
+        let svg = topology.to_svg(&SvgOptions::new());
+
+        // The first two balancers share the first column, and the third
+        // balancer is in the second one.
+        let first_column = wire_x(1);
+        assert!(svg.contains(&format!(
+            "<line class=\"balancer\" x1=\"{0}\" y1=\"25\" x2=\"{0}\" y2=\"55\"/>",
+            first_column
+        )));
+        assert!(svg.contains(&format!(
+            "<line class=\"balancer\" x1=\"{0}\" y1=\"85\" x2=\"{0}\" y2=\"115\"/>",
+            first_column
+        )));
+        assert!(svg.contains(&format!(
+            "<line class=\"balancer\" x1=\"{0}\" y1=\"25\" x2=\"{0}\" y2=\"115\"/>",
+            wire_x(2)
+        )));
+        assert_eq!(svg.matches("<circle ").count(), 2 * 6);
+    }
+
+    fn wire_x(column: usize) -> usize {
+        MARGIN + LABEL_WIDTH + column * COLUMN_SPACING
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+        let options = SvgOptions::new().output_labels(vec!["<a>", "\"b\" & c"]);
+        let svg = topology.to_svg(&options);
+
+        assert!(svg.contains(">&lt;a&gt;</text>"));
+        assert!(svg.contains(">&quot;b&quot; &amp; c</text>"));
+        assert!(svg.contains(">x0</text>"));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 2 input labels, one for each wire")]
+    fn wrong_number_of_labels() {
+        let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+
+        topology.to_svg(&SvgOptions::new().input_labels(vec!["only one"]));
+    }
+}
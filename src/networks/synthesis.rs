@@ -0,0 +1,253 @@
+//! Search for the smallest counting networks of a given width.
+//!
+//! The search tries every network built from layers of balancers, in order of
+//! increasing depth, and returns the shallowest one that counts, using the
+//! fewest balancers among those of that depth. Candidates are first filtered by
+//! running them in quiescent states with a bounded number of tokens, and every
+//! network that passes is then confirmed with the
+//! [exhaustive checker](crate::testing::exhaustive).
+//!
+//! The number of candidates grows very quickly with both width and depth, so
+//! the search is only practical for the widths that the exhaustive checker
+//! supports. Results are returned as a [`Topology`], which can be written out
+//! in its textual format and loaded with [`Network::from_topology`].
+//!
+//! Only available with the `synthesis` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use counting_networks::networks::synthesis;
+//!
+//! let topology = synthesis::search(2, 2).unwrap();
+//!
+//! assert_eq!(topology.to_string(), "width 2\n0 1\n");
+//! ```
+//!
+//! [`Network::from_topology`]: super::Network::from_topology
+
+use super::topology::Topology;
+use crate::testing::exhaustive;
+
+/// The widest network that can be searched for.
+pub const MAX_WIDTH: usize = exhaustive::MAX_WIDTH;
+
+// Concurrency used to confirm candidates with the exhaustive checker.
+const CONFIRM_THREADS: usize = 2;
+const CONFIRM_TRAVERSALS_PER_THREAD: usize = 2;
+
+/// Search for the shallowest counting network of the given width, with a
+/// depth of at most `max_depth`.
+///
+/// Among the networks of the smallest depth, the one with the fewest balancers
+/// is returned. Returns `None` if there is no counting network of the width
+/// within the depth limit.
+///
+/// A network is accepted if it has the step property in every quiescent state
+/// with at most `width` tokens entering on each wire, and passes
+/// [`exhaustive::check_topology`] with two threads that make two traversals
+/// each. This is a strong check for networks this small, but not a proof.
+///
+/// # Panics
+///
+/// Panics if `width` is zero or greater than [`MAX_WIDTH`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::synthesis;
+///
+/// // Counting networks of balancers only exist for powers of two.
+/// assert_eq!(synthesis::search(3, 3), None);
+/// ```
+pub fn search(width: usize, max_depth: usize) -> Option<Topology> {
+    assert!(width > 0 && width <= MAX_WIDTH);
+
+    let layers = layers(width);
+    let inputs = inputs(width);
+
+    (0..=max_depth).find_map(|depth| {
+        let mut search = Search {
+            width,
+            layers: &layers,
+            inputs: &inputs,
+            balancers: Vec::new(),
+            best: None,
+        };
+        search.extend(depth);
+
+        search.best
+    })
+}
+
+struct Search<'a> {
+    width: usize,
+    layers: &'a [Vec<(usize, usize)>],
+    inputs: &'a [Vec<usize>],
+    // Balancers of the layers chosen so far
+    balancers: Vec<(usize, usize)>,
+    best: Option<Topology>,
+}
+
+impl<'a> Search<'a> {
+    // Try every way of adding `remaining_layers` more layers to the balancers.
+    fn extend(&mut self, remaining_layers: usize) {
+        if remaining_layers == 0 {
+            self.consider();
+            return;
+        }
+
+        for layer in self.layers {
+            let len = self.balancers.len();
+            self.balancers.extend_from_slice(layer);
+            self.extend(remaining_layers - 1);
+            self.balancers.truncate(len);
+        }
+    }
+
+    fn consider(&mut self) {
+        let is_smaller = match &self.best {
+            Some(best) => self.balancers.len() < best.balancers().len(),
+            None => true,
+        };
+        if !is_smaller || !self.counts_when_quiescent() {
+            return;
+        }
+
+        let topology = Topology::from_parts(self.width, self.balancers.clone());
+        if exhaustive::check_topology(&topology, CONFIRM_THREADS, CONFIRM_TRAVERSALS_PER_THREAD)
+            .is_ok()
+        {
+            self.best = Some(topology);
+        }
+    }
+
+    fn counts_when_quiescent(&self) -> bool {
+        let mut outputs = vec![0; self.width];
+
+        self.inputs.iter().all(|input| {
+            outputs.copy_from_slice(input);
+            for &(top, bottom) in &self.balancers {
+                // The first token through a balancer leaves on the top wire
+                let total = outputs[top] + outputs[bottom];
+                outputs[top] = total - total / 2;
+                outputs[bottom] = total / 2;
+            }
+
+            has_step_property(&outputs)
+        })
+    }
+}
+
+// Every way of placing non-overlapping balancers on the wires, in either
+// orientation, except for placing none at all.
+fn layers(width: usize) -> Vec<Vec<(usize, usize)>> {
+    fn place(
+        wire: usize,
+        used: &mut Vec<bool>,
+        layer: &mut Vec<(usize, usize)>,
+        layers: &mut Vec<Vec<(usize, usize)>>,
+    ) {
+        if wire == used.len() {
+            if !layer.is_empty() {
+                layers.push(layer.clone());
+            }
+            return;
+        }
+        if used[wire] {
+            place(wire + 1, used, layer, layers);
+            return;
+        }
+
+        place(wire + 1, used, layer, layers);
+        for other in (wire + 1)..used.len() {
+            if used[other] {
+                continue;
+            }
+
+            used[other] = true;
+            for &balancer in &[(wire, other), (other, wire)] {
+                layer.push(balancer);
+                place(wire + 1, used, layer, layers);
+                layer.pop();
+            }
+            used[other] = false;
+        }
+    }
+
+    let mut layers = Vec::new();
+    place(0, &mut vec![false; width], &mut Vec::new(), &mut layers);
+
+    layers
+}
+
+// Every number of tokens from 0 to `width` on each input wire, with the
+// smallest totals first so that most candidates are rejected quickly.
+fn inputs(width: usize) -> Vec<Vec<usize>> {
+    let per_wire = width + 1;
+    let mut inputs: Vec<Vec<usize>> = (0..per_wire.pow(width as u32))
+        .map(|idx| {
+            (0..width)
+                .map(|wire| (idx / per_wire.pow(wire as u32)) % per_wire)
+                .collect()
+        })
+        .collect();
+    inputs.sort_by_key(|input| input.iter().sum::<usize>());
+
+    inputs
+}
+
+// Outputs have the step property when every output has either the same number
+// of tokens as the ones below it, or one more.
+fn has_step_property(outputs: &[usize]) -> bool {
+    outputs
+        .windows(2)
+        .all(|pair| pair[0] >= pair[1] && pair[0] - pair[1] <= 1)
+        && outputs[0] - outputs[outputs.len() - 1] <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::Network;
+
+    #[test]
+    fn layers_of_four_wires() {
+        // 6 single balancers and 3 pairs of balancers, each in two orientations
+        assert_eq!(layers(4).len(), 6 * 2 + 3 * 4);
+    }
+
+    #[test]
+    fn step_property() {
+        assert!(has_step_property(&[2, 2, 1, 1]));
+        assert!(has_step_property(&[3]));
+        assert!(!has_step_property(&[1, 2]));
+        assert!(!has_step_property(&[2, 1, 2]));
+        assert!(!has_step_property(&[2, 0]));
+    }
+
+    #[test]
+    fn search_trivial_widths() {
+        assert_eq!(search(1, 0), Some(Topology::new(1, vec![]).unwrap()));
+        assert_eq!(search(2, 0), None);
+        assert_eq!(search(2, 3), Some(Topology::new(2, vec![(0, 1)]).unwrap()));
+    }
+
+    #[test]
+    fn search_width_four() {
+        assert_eq!(search(4, 2), None);
+
+        let topology = search(4, 3).unwrap();
+        assert_eq!(topology.depth(), 3);
+        assert!(topology.balancers().len() <= 6);
+
+        let parsed: Topology = topology.to_string().parse().unwrap();
+        assert_eq!(parsed, topology);
+        assert!(exhaustive::check_topology(&topology, 3, 2).is_ok());
+
+        let network = Network::from_topology(&topology, (0..4).collect());
+        let mut values: Vec<_> = (0..8).map(|_| *network.traverse()).collect();
+        values.sort();
+        assert_eq!(values, vec![0, 0, 1, 1, 2, 2, 3, 3]);
+    }
+}
@@ -0,0 +1,193 @@
+use super::topology::Topology;
+
+// Everything after the list of balancers is the same for every network.
+const TLA_SPEC: &str = r"\* The first balancer at or after index `from` that is on wire `w`, or 0 if
+\* there is none.
+NextBalancer(w, from) ==
+    LET candidates == {i \in from..Len(Balancers) : w \in {Balancers[i][1], Balancers[i][2]}}
+    IN IF candidates = {}
+       THEN 0
+       ELSE CHOOSE i \in candidates : \A j \in candidates : i <= j
+
+VARIABLES toggle, bucket, wire, next, active, remaining, taken, exited
+
+vars == <<toggle, bucket, wire, next, active, remaining, taken, exited>>
+
+Init ==
+    /\ toggle = [b \in 1..Len(Balancers) |-> FALSE]
+    /\ bucket = [w \in 0..(Width - 1) |-> w]
+    /\ wire = [t \in Threads |-> Entry[t]]
+    /\ next = [t \in Threads |-> 1]
+    /\ active = [t \in Threads |-> FALSE]
+    /\ remaining = [t \in Threads |-> Traversals]
+    /\ taken = {}
+    /\ exited = 0
+
+\* A thread starts a traversal on its entry wire.
+Enter(t) ==
+    /\ ~active[t]
+    /\ remaining[t] > 0
+    /\ active' = [active EXCEPT ![t] = TRUE]
+    /\ wire' = [wire EXCEPT ![t] = Entry[t]]
+    /\ next' = [next EXCEPT ![t] = 1]
+    /\ remaining' = [remaining EXCEPT ![t] = @ - 1]
+    /\ UNCHANGED <<toggle, bucket, taken, exited>>
+
+\* A thread passes through the next balancer on its wire, atomically flipping
+\* the balancer's toggle.
+Toggle(t) ==
+    LET b == NextBalancer(wire[t], next[t])
+    IN /\ active[t]
+       /\ b # 0
+       /\ toggle' = [toggle EXCEPT ![b] = ~@]
+       /\ wire' = [wire EXCEPT ![t] = IF toggle[b] THEN Balancers[b][2] ELSE Balancers[b][1]]
+       /\ next' = [next EXCEPT ![t] = b + 1]
+       /\ UNCHANGED <<bucket, active, remaining, taken, exited>>
+
+\* A thread leaves the network and atomically takes the next value of the
+\* counter on its wire.
+Exit(t) ==
+    /\ active[t]
+    /\ NextBalancer(wire[t], next[t]) = 0
+    /\ taken' = taken \cup {bucket[wire[t]]}
+    /\ bucket' = [bucket EXCEPT ![wire[t]] = @ + Width]
+    /\ exited' = exited + 1
+    /\ active' = [active EXCEPT ![t] = FALSE]
+    /\ UNCHANGED <<toggle, wire, next, remaining>>
+
+Done ==
+    /\ \A t \in Threads : ~active[t] /\ remaining[t] = 0
+    /\ UNCHANGED vars
+
+Next == Done \/ \E t \in Threads : Enter(t) \/ Toggle(t) \/ Exit(t)
+
+Spec == Init /\ [][Next]_vars
+
+\* Whenever no thread is inside the network, the values taken so far are
+\* exactly 0, 1, ..., n - 1.
+Counting == (\A t \in Threads : ~active[t]) => taken = 0..(exited - 1)
+
+=============================================================================
+";
+
+impl Topology {
+    /// Write out a TLA+ module that models concurrent traversals of this
+    /// topology, so that its exact structure can be checked with an external
+    /// model checker such as TLC.
+    ///
+    /// The module has the constants `Threads`, the set of threads; `Entry`, a
+    /// function from each thread to the wire it enters on; and `Traversals`,
+    /// the number of traversals each thread makes. Each traversal is modelled
+    /// as atomic steps, one toggle per balancer followed by taking a value from
+    /// a counter on the output wire, the same way [`Network`] runs them. The
+    /// invariant `Counting` holds if the values taken in every quiescent state
+    /// are exactly `0..n`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `module_name` is not a valid TLA+ identifier made of ASCII
+    /// letters, digits and underscores.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<BitonicConfiguration>(Width::new(4).unwrap());
+    /// let module = topology.to_tla("Bitonic4");
+    ///
+    /// assert!(module.starts_with("---- MODULE Bitonic4 ----"));
+    /// assert!(module.contains(
+    ///     "Balancers == << <<0, 1>>, <<2, 3>>, <<0, 3>>, <<1, 2>>, <<0, 1>>, <<2, 3>> >>"
+    /// ));
+    /// ```
+    ///
+    /// [`Network`]: super::Network
+    pub fn to_tla(&self, module_name: &str) -> String {
+        assert!(
+            module_name.chars().any(|c| c.is_ascii_alphabetic())
+                && module_name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "invalid TLA+ module name: {:?}",
+            module_name
+        );
+
+        let balancers = if self.balancers().is_empty() {
+            String::from("<<>>")
+        } else {
+            let pairs: Vec<_> = self
+                .balancers()
+                .iter()
+                .map(|(top_wire, bottom_wire)| format!("<<{}, {}>>", top_wire, bottom_wire))
+                .collect();
+            format!("<< {} >>", pairs.join(", "))
+        };
+
+        format!(
+            r"---- MODULE {name} ----
+\* Balancer semantics of a width {width} network, generated by counting-networks.
+EXTENDS Naturals, Sequences
+
+CONSTANTS Threads, Entry, Traversals
+
+Width == {width}
+
+ASSUME Entry \in [Threads -> 0..(Width - 1)]
+ASSUME Traversals \in Nat
+
+\* Balancers front-to-back, as <<top wire, bottom wire>>. The first token
+\* through a balancer leaves on the top wire.
+Balancers == {balancers}
+
+{spec}",
+            name = module_name,
+            width = self.width(),
+            balancers = balancers,
+            spec = TLA_SPEC
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tla_module_for_single_balancer() {
+        let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+        let module = topology.to_tla("Single");
+
+        let header = "---- MODULE Single ----
+\\* Balancer semantics of a width 2 network, generated by counting-networks.
+EXTENDS Naturals, Sequences
+
+CONSTANTS Threads, Entry, Traversals
+
+Width == 2
+
+ASSUME Entry \\in [Threads -> 0..(Width - 1)]
+ASSUME Traversals \\in Nat
+
+\\* Balancers front-to-back, as <<top wire, bottom wire>>. The first token
+\\* through a balancer leaves on the top wire.
+Balancers == << <<0, 1>> >>
+
+";
+        assert_eq!(&module[..header.len()], header);
+        assert!(module.ends_with("====\n"));
+    }
+
+    #[test]
+    fn tla_module_without_balancers() {
+        let topology = Topology::new(1, vec![]).unwrap();
+
+        assert!(topology.to_tla("Wire").contains("Balancers == <<>>\n"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn tla_module_bad_name() {
+        let _ = Topology::new(1, vec![]).unwrap().to_tla("not a name");
+    }
+}
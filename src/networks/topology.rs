@@ -0,0 +1,823 @@
+use super::{
+    common::{Network, NetworkConfiguration},
+    width::Width,
+};
+use core::{fmt, str::FromStr};
+use std::error::Error;
+
+/// The layout of balancers in a network, independent of any outputs.
+///
+/// A topology lists its balancers the same way that a
+/// [`NetworkConfiguration`] yields them: front-to-back, with each balancer
+/// given as the pair of wires `(a, b)` it sits on, where the first token leaves
+/// on `a`.
+///
+/// Topologies have a textual format, so that networks designed by hand or
+/// generated by other tools can be shared. The first line gives the width of
+/// the network, and every following line gives the two wires of a balancer.
+/// Blank lines and everything after a `#` are ignored.
+///
+/// ```text
+/// # Bitonic[4]
+/// width 4
+/// 0 1
+/// 2 3
+/// 0 3
+/// 1 2
+/// 0 1
+/// 2 3
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::networks::{Network, Topology};
+///
+/// let topology: Topology = "width 2\n0 1\n".parse().unwrap();
+/// let network = Network::from_topology(&topology, vec!['a', 'b']);
+///
+/// assert_eq!(network.traverse(), &'a');
+/// assert_eq!(network.traverse(), &'b');
+/// assert_eq!(network.topology(), topology);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Topology {
+    width: usize,
+    balancers: Vec<(usize, usize)>,
+}
+
+impl Topology {
+    /// Create a topology of the given width from a list of balancers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the width is zero, or if any balancer uses a wire
+    /// that is not less than the width or connects a wire to itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{Topology, TopologyError};
+    ///
+    /// assert!(Topology::new(2, vec![(0, 1)]).is_ok());
+    /// assert_eq!(
+    ///     Topology::new(2, vec![(0, 2)]),
+    ///     Err(TopologyError::WireOutOfRange {
+    ///         balancer: 0,
+    ///         wire: 2
+    ///     })
+    /// );
+    /// ```
+    pub fn new(width: usize, balancers: Vec<(usize, usize)>) -> Result<Self, TopologyError> {
+        if width == 0 {
+            return Err(TopologyError::ZeroWidth);
+        }
+
+        for (idx, &balancer) in balancers.iter().enumerate() {
+            check_balancer(width, idx, balancer)?;
+        }
+
+        Ok(Topology::from_parts(width, balancers))
+    }
+
+    /// Returns the topology of the configuration `C` for a network of the
+    /// given width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration does not support networks of the given
+    /// width.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<BitonicConfiguration>(Width::new(2).unwrap());
+    ///
+    /// assert_eq!(topology.to_string(), "width 2\n0 1\n");
+    /// ```
+    pub fn from_config<C: NetworkConfiguration>(width: Width) -> Self {
+        Topology::from_parts(width.get(), C::from_width(width).into_iter().collect())
+    }
+
+    pub(crate) fn from_parts(width: usize, balancers: Vec<(usize, usize)>) -> Self {
+        Topology { width, balancers }
+    }
+
+    /// Returns the width of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(4, vec![]).unwrap();
+    ///
+    /// assert_eq!(topology.width(), 4);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the balancers of the network, front-to-back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(4, vec![(0, 1), (2, 3)]).unwrap();
+    ///
+    /// assert_eq!(topology.balancers(), &[(0, 1), (2, 3)]);
+    /// ```
+    pub fn balancers(&self) -> &[(usize, usize)] {
+        &self.balancers
+    }
+
+    /// Returns the balancers grouped into layers, front-to-back.
+    ///
+    /// Each balancer is in the layer after the latest balancer before it on
+    /// either of its wires, so the balancers in a layer don't share any wires,
+    /// and there are [`depth`](Topology::depth) layers. Within a layer,
+    /// balancers keep their order from [`balancers`](Topology::balancers).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<BitonicConfiguration>(Width::new(4).unwrap());
+    ///
+    /// assert_eq!(
+    ///     topology.layers(),
+    ///     vec![
+    ///         vec![(0, 1), (2, 3)],
+    ///         vec![(0, 3), (1, 2)],
+    ///         vec![(0, 1), (2, 3)],
+    ///     ]
+    /// );
+    /// ```
+    pub fn layers(&self) -> Vec<Vec<(usize, usize)>> {
+        group_layers(self.width, self.balancers.iter().copied())
+    }
+
+    /// Returns the number of balancers on the longest path through the
+    /// network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<BitonicConfiguration>(Width::new(8).unwrap());
+    ///
+    /// assert_eq!(topology.depth(), 6);
+    /// ```
+    pub fn depth(&self) -> usize {
+        let mut wire_depths = vec![0; self.width];
+        for &(top_wire, bottom_wire) in &self.balancers {
+            let balancer_depth = wire_depths[top_wire].max(wire_depths[bottom_wire]) + 1;
+            wire_depths[top_wire] = balancer_depth;
+            wire_depths[bottom_wire] = balancer_depth;
+        }
+
+        wire_depths.into_iter().max().unwrap_or(0)
+    }
+
+    /// Returns the topology flipped top-to-bottom, so that wire `i` becomes
+    /// wire `width - 1 - i`.
+    ///
+    /// Each balancer still sends its first token along the same wire as
+    /// before, so the mirror of a counting network counts from the bottom
+    /// output up. The result is always a valid topology of the same width.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{BitonicConfiguration, Network, Topology, Width};
+    ///
+    /// let topology = Topology::from_config::<BitonicConfiguration>(Width::new(4).unwrap()).mirror();
+    /// let network = Network::from_topology(&topology, vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(topology.balancers()[0], (3, 2));
+    /// assert_eq!(network.traverse(), &4);
+    /// assert_eq!(network.traverse(), &3);
+    /// ```
+    pub fn mirror(&self) -> Self {
+        let last_wire = self.width - 1;
+        let balancers = self
+            .balancers
+            .iter()
+            .map(|&(top_wire, bottom_wire)| (last_wire - top_wire, last_wire - bottom_wire))
+            .collect();
+
+        Topology::from_parts(self.width, balancers)
+    }
+
+    /// Returns the topology with its balancers in the opposite order, so that
+    /// the balancers closest to the outputs are closest to the inputs.
+    ///
+    /// The reverse of a counting network is not always a counting network. The
+    /// result is always a valid topology of the same width and depth.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(3, vec![(0, 1), (1, 2)]).unwrap();
+    ///
+    /// assert_eq!(topology.reverse().balancers(), &[(1, 2), (0, 1)]);
+    /// ```
+    pub fn reverse(&self) -> Self {
+        Topology::from_parts(self.width, self.balancers.iter().rev().copied().collect())
+    }
+
+    /// Remove balancers that can never change how tokens are distributed.
+    ///
+    /// When two balancers sit on the same pair of wires, and no balancer
+    /// between them uses either wire, every token that passes through the
+    /// first also passes through the second. Once the network is quiescent,
+    /// the second balancer splits those tokens the same way no matter how the
+    /// first one did, so the first can be removed. This is repeated until no
+    /// such pair is left, which leaves the counts on every output wire the same
+    /// for every input, while reducing the work of each traversal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(4, vec![(0, 1), (2, 3), (1, 0), (0, 3)]).unwrap();
+    /// let optimized = topology.optimize();
+    ///
+    /// assert_eq!(optimized.removed(), &[0]);
+    /// assert_eq!(optimized.depth_before(), 3);
+    /// assert_eq!(optimized.depth_after(), 2);
+    /// assert_eq!(
+    ///     optimized.topology().balancers(),
+    ///     &[(2, 3), (1, 0), (0, 3)]
+    /// );
+    /// ```
+    pub fn optimize(&self) -> Optimization {
+        let mut kept: Vec<Option<(usize, usize)>> = Vec::with_capacity(self.balancers.len());
+        // Index into `kept` of the last balancer on each wire
+        let mut last_balancers: Vec<Option<usize>> = vec![None; self.width];
+        let mut removed = Vec::new();
+
+        for (idx, &(top_wire, bottom_wire)) in self.balancers.iter().enumerate() {
+            if let (Some(top_last), Some(bottom_last)) =
+                (last_balancers[top_wire], last_balancers[bottom_wire])
+            {
+                // Both wires were last used by the same balancer, so it must be
+                // on this same pair of wires.
+                if top_last == bottom_last {
+                    kept[top_last] = None;
+                    removed.push(top_last);
+                }
+            }
+
+            last_balancers[top_wire] = Some(idx);
+            last_balancers[bottom_wire] = Some(idx);
+            kept.push(Some((top_wire, bottom_wire)));
+        }
+        removed.sort();
+
+        let topology = Topology::from_parts(self.width, kept.into_iter().flatten().collect());
+
+        Optimization {
+            depth_before: self.depth(),
+            depth_after: topology.depth(),
+            topology,
+            removed,
+        }
+    }
+}
+
+/// The result of [`Topology::optimize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimization {
+    topology: Topology,
+    removed: Vec<usize>,
+    depth_before: usize,
+    depth_after: usize,
+}
+
+impl Optimization {
+    /// Returns the optimized topology.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1), (0, 1)]).unwrap();
+    ///
+    /// assert_eq!(topology.optimize().topology().balancers(), &[(0, 1)]);
+    /// ```
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Returns the optimized topology, consuming the result.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{Network, Topology};
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1), (0, 1)]).unwrap();
+    /// let network = Network::from_topology(&topology.optimize().into_topology(), vec![1, 2]);
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// ```
+    pub fn into_topology(self) -> Topology {
+        self.topology
+    }
+
+    /// Returns the indices of the removed balancers in the original topology,
+    /// in increasing order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1), (0, 1), (1, 0)]).unwrap();
+    ///
+    /// assert_eq!(topology.optimize().removed(), &[0, 1]);
+    /// ```
+    pub fn removed(&self) -> &[usize] {
+        &self.removed
+    }
+
+    /// Returns the depth of the original topology.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1), (0, 1)]).unwrap();
+    ///
+    /// assert_eq!(topology.optimize().depth_before(), 2);
+    /// ```
+    pub fn depth_before(&self) -> usize {
+        self.depth_before
+    }
+
+    /// Returns the depth of the optimized topology.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Topology;
+    ///
+    /// let topology = Topology::new(2, vec![(0, 1), (0, 1)]).unwrap();
+    ///
+    /// assert_eq!(topology.optimize().depth_after(), 1);
+    /// ```
+    pub fn depth_after(&self) -> usize {
+        self.depth_after
+    }
+}
+
+pub(crate) fn check_balancer(
+    width: usize,
+    idx: usize,
+    (top_wire, bottom_wire): (usize, usize),
+) -> Result<(), TopologyError> {
+    if let Some(&wire) = [top_wire, bottom_wire].iter().find(|&&wire| wire >= width) {
+        return Err(TopologyError::WireOutOfRange {
+            balancer: idx,
+            wire,
+        });
+    }
+
+    if top_wire == bottom_wire {
+        return Err(TopologyError::SameWire {
+            balancer: idx,
+            wire: top_wire,
+        });
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "width {}", self.width)?;
+        for (top_wire, bottom_wire) in &self.balancers {
+            writeln!(f, "{} {}", top_wire, bottom_wire)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Topology {
+    type Err = ParseTopologyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| {
+                let content = match line.find('#') {
+                    Some(comment) => &line[..comment],
+                    None => line,
+                };
+                (idx + 1, content.trim())
+            })
+            .filter(|(_, content)| !content.is_empty());
+
+        let (header_line, header) = lines.next().ok_or(ParseTopologyError::MissingWidth)?;
+        let width = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            ["width", width] => width
+                .parse::<usize>()
+                .map_err(|_| ParseTopologyError::InvalidWidth { line: header_line })?,
+            _ => return Err(ParseTopologyError::InvalidWidth { line: header_line }),
+        };
+        if width == 0 {
+            return Err(ParseTopologyError::Invalid {
+                line: header_line,
+                error: TopologyError::ZeroWidth,
+            });
+        }
+
+        let mut balancers = Vec::new();
+        for (line, content) in lines {
+            let wires: Vec<_> = content
+                .split_whitespace()
+                .map(|wire| wire.parse::<usize>())
+                .collect();
+            let balancer = match wires[..] {
+                [Ok(top_wire), Ok(bottom_wire)] => (top_wire, bottom_wire),
+                _ => return Err(ParseTopologyError::InvalidBalancer { line }),
+            };
+
+            check_balancer(width, balancers.len(), balancer)
+                .map_err(|error| ParseTopologyError::Invalid { line, error })?;
+            balancers.push(balancer);
+        }
+
+        Ok(Topology::from_parts(width, balancers))
+    }
+}
+
+/// A reason that a list of balancers does not describe a network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyError {
+    /// The network has no wires.
+    ZeroWidth,
+    /// A balancer uses a wire that is not less than the width.
+    WireOutOfRange {
+        /// Index of the balancer in the list.
+        balancer: usize,
+        /// The wire that is out of range.
+        wire: usize,
+    },
+    /// A balancer connects a wire to itself.
+    SameWire {
+        /// Index of the balancer in the list.
+        balancer: usize,
+        /// The wire that the balancer is on.
+        wire: usize,
+    },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopologyError::ZeroWidth => write!(f, "network has no wires"),
+            TopologyError::WireOutOfRange { balancer, wire } => {
+                write!(
+                    f,
+                    "balancer {} uses wire {} which is out of range",
+                    balancer, wire
+                )
+            }
+            TopologyError::SameWire { balancer, wire } => {
+                write!(f, "balancer {} connects wire {} to itself", balancer, wire)
+            }
+        }
+    }
+}
+
+impl Error for TopologyError {}
+
+/// An error from parsing the textual format of a [`Topology`].
+///
+/// Line numbers start from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseTopologyError {
+    /// The text has no width line.
+    MissingWidth,
+    /// The first line is not `width` followed by a number.
+    InvalidWidth {
+        /// The line that should give the width.
+        line: usize,
+    },
+    /// A line is not two wire numbers.
+    InvalidBalancer {
+        /// The line of the balancer.
+        line: usize,
+    },
+    /// A line is well formed, but does not describe part of a valid network.
+    Invalid {
+        /// The line of the width or balancer.
+        line: usize,
+        /// Why the network is not valid.
+        error: TopologyError,
+    },
+}
+
+impl fmt::Display for ParseTopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseTopologyError::MissingWidth => write!(f, "missing width line"),
+            ParseTopologyError::InvalidWidth { line } => {
+                write!(f, "line {}: expected `width <number>`", line)
+            }
+            ParseTopologyError::InvalidBalancer { line } => {
+                write!(f, "line {}: expected two wire numbers", line)
+            }
+            ParseTopologyError::Invalid { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for ParseTopologyError {}
+
+// Group balancers into layers, putting each one in the layer after the latest
+// balancer before it on either of its wires.
+pub(crate) fn group_layers<I>(width: usize, balancers: I) -> Vec<Vec<(usize, usize)>>
+where
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut wire_depths = vec![0; width];
+    let mut layers: Vec<Vec<(usize, usize)>> = Vec::new();
+
+    for (top_wire, bottom_wire) in balancers {
+        let layer = wire_depths[top_wire].max(wire_depths[bottom_wire]);
+        wire_depths[top_wire] = layer + 1;
+        wire_depths[bottom_wire] = layer + 1;
+
+        if layer == layers.len() {
+            layers.push(Vec::new());
+        }
+        layers[layer].push((top_wire, bottom_wire));
+    }
+
+    layers
+}
+
+impl<L> Network<L, Topology> {
+    /// Construct a new network with the layout of balancers given by a
+    /// topology, and the given outputs, one for each wire.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of outputs is not the width of the topology.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::{Network, Topology};
+    ///
+    /// let topology: Topology = "width 4\n0 1\n2 3\n0 3\n1 2\n0 1\n2 3\n"
+    ///     .parse()
+    ///     .unwrap();
+    /// let network = Network::from_topology(&topology, vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// assert!(network.verify_integrity().is_ok());
+    /// ```
+    pub fn from_topology(topology: &Topology, outputs: Vec<L>) -> Self {
+        assert_eq!(outputs.len(), topology.width());
+
+        Network::from_balancer_iter(outputs, topology.balancers().iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{BitonicConfiguration, BitonicNetwork};
+
+    #[test]
+    fn layers_cover_balancers() {
+        for width in (0..6).map(|exp| 1usize << exp) {
+            let topology = Topology::from_config::<BitonicConfiguration>(Width::of(width));
+            let layers = topology.layers();
+
+            assert_eq!(layers.len(), topology.depth());
+            assert_eq!(
+                layers.iter().map(Vec::len).sum::<usize>(),
+                topology.balancers().len()
+            );
+            for layer in &layers {
+                let mut wires: Vec<_> = layer
+                    .iter()
+                    .flat_map(|&(top, bottom)| vec![top, bottom])
+                    .collect();
+                wires.sort_unstable();
+                wires.dedup();
+                assert_eq!(wires.len(), 2 * layer.len());
+            }
+
+            let flattened: Vec<_> = layers.into_iter().flatten().collect();
+            assert_eq!(
+                Network::from_topology(&Topology::new(width, flattened).unwrap(), vec![(); width])
+                    .verify_integrity(),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn transforms_are_involutions() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(16));
+
+        assert_eq!(topology.mirror().mirror(), topology);
+        assert_eq!(topology.reverse().reverse(), topology);
+        assert_eq!(topology.mirror().depth(), topology.depth());
+        assert_eq!(topology.reverse().depth(), topology.depth());
+
+        let mirrored = topology.mirror();
+        assert_eq!(
+            Topology::new(mirrored.width(), mirrored.balancers().to_vec()),
+            Ok(mirrored)
+        );
+    }
+
+    #[test]
+    fn mirrored_network_counts_upwards() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(8)).mirror();
+        let network = Network::from_topology(&topology, (0..8).collect());
+
+        let outputs: Vec<_> = (0..16).map(|key| *network.traverse_keyed(&key)).collect();
+        assert_eq!(
+            outputs,
+            (0..8).rev().chain((0..8).rev()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_with_comments_and_blank_lines() {
+        let text = "
+            # A single layer
+            width 4   # four wires
+
+            0 1
+            2 3 # the last balancer
+        ";
+
+        let topology: Topology = text.parse().unwrap();
+
+        assert_eq!(topology, Topology::new(4, vec![(0, 1), (2, 3)]).unwrap());
+    }
+
+    #[test]
+    fn round_trip_bitonic_topologies() {
+        for width in (0..6).map(|exp| 1 << exp) {
+            let topology = Topology::from_config::<BitonicConfiguration>(Width::of(width));
+
+            assert_eq!(topology.to_string().parse(), Ok(topology.clone()));
+            assert_eq!(BitonicNetwork::new(vec![(); width]).topology(), topology);
+        }
+    }
+
+    #[test]
+    fn network_from_topology_counts() {
+        let topology = Topology::from_config::<BitonicConfiguration>(Width::of(8));
+        let network = Network::from_topology(&topology, (0..8).collect());
+
+        for expected in (0..8).cycle().take(32) {
+            assert_eq!(*network.traverse(), expected);
+        }
+        assert_eq!(network.verify_integrity(), Ok(()));
+
+        let cloned = network.clone();
+        assert_eq!(cloned.topology(), topology);
+    }
+
+    // Token counts on each wire once `inputs` tokens have passed through the
+    // balancers, with the extra token of a balancer going to its first wire.
+    fn quiescent_outputs(topology: &Topology, inputs: &[usize]) -> Vec<usize> {
+        let mut counts = inputs.to_vec();
+        for &(top, bottom) in topology.balancers() {
+            let total = counts[top] + counts[bottom];
+            counts[bottom] = total / 2;
+            counts[top] = total - counts[bottom];
+        }
+        counts
+    }
+
+    #[test]
+    fn optimize_leaves_bitonic_unchanged() {
+        for width in (0..6).map(|exp| 1 << exp) {
+            let topology = Topology::from_config::<BitonicConfiguration>(Width::of(width));
+            let optimized = topology.optimize();
+
+            assert_eq!(optimized.removed(), &[]);
+            assert_eq!(optimized.topology(), &topology);
+        }
+    }
+
+    #[test]
+    fn optimize_preserves_quiescent_outputs() {
+        // Bitonic[4] with repeated layers and reversed duplicates mixed in
+        let topology = Topology::new(
+            4,
+            vec![
+                (0, 1),
+                (0, 1),
+                (2, 3),
+                (3, 2),
+                (2, 3),
+                (0, 3),
+                (1, 2),
+                (0, 3),
+                (0, 1),
+                (2, 3),
+                (1, 0),
+                (0, 1),
+                (2, 3),
+            ],
+        )
+        .unwrap();
+        let optimized = topology.optimize();
+
+        assert_eq!(optimized.removed(), &[0, 2, 3, 5, 8, 9, 10]);
+        assert_eq!(optimized.depth_before(), 8);
+        assert_eq!(optimized.depth_after(), 3);
+        assert_eq!(
+            optimized.topology().balancers(),
+            &[(0, 1), (2, 3), (1, 2), (0, 3), (0, 1), (2, 3)]
+        );
+
+        for combination in 0..4usize.pow(4) {
+            let inputs: Vec<_> = (0..4)
+                .map(|wire| (combination >> (2 * wire)) & 0b11)
+                .collect();
+
+            assert_eq!(
+                quiescent_outputs(optimized.topology(), &inputs),
+                quiescent_outputs(&topology, &inputs)
+            );
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            "".parse::<Topology>(),
+            Err(ParseTopologyError::MissingWidth)
+        );
+        assert_eq!(
+            "\n# nothing\nwidth four\n".parse::<Topology>(),
+            Err(ParseTopologyError::InvalidWidth { line: 3 })
+        );
+        assert_eq!(
+            "width 4\n0 1\n2\n".parse::<Topology>(),
+            Err(ParseTopologyError::InvalidBalancer { line: 3 })
+        );
+        assert_eq!(
+            "width 4\n0 1 2\n".parse::<Topology>(),
+            Err(ParseTopologyError::InvalidBalancer { line: 2 })
+        );
+        assert_eq!(
+            "width 0\n".parse::<Topology>(),
+            Err(ParseTopologyError::Invalid {
+                line: 1,
+                error: TopologyError::ZeroWidth
+            })
+        );
+
+        let error = "width 4\n0 1\n3 3\n".parse::<Topology>().unwrap_err();
+        assert_eq!(
+            error,
+            ParseTopologyError::Invalid {
+                line: 3,
+                error: TopologyError::SameWire {
+                    balancer: 1,
+                    wire: 3
+                }
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 3: balancer 1 connects wire 3 to itself"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_topology_wrong_width() {
+        let topology = Topology::new(2, vec![(0, 1)]).unwrap();
+
+        let _ = Network::from_topology(&topology, vec![1, 2, 3]);
+    }
+}
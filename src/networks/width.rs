@@ -0,0 +1,132 @@
+use core::{fmt, mem};
+
+/// The width of a network, which is always a power of two.
+///
+/// Constructors that take a `Width` don't need to check that their width is a
+/// power of two, so they can't panic because of it.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{
+///     counters::{BitonicCountingNetwork, Counter},
+///     networks::Width,
+/// };
+///
+/// let width = Width::new(16).expect("16 is a power of two");
+/// let counter = BitonicCountingNetwork::with_width(width);
+///
+/// assert_eq!(counter.width(), 16);
+/// assert_eq!(Width::new(12), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Width(usize);
+
+impl Width {
+    /// The widest network that a `Width` can describe.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Width;
+    ///
+    /// assert!(Width::MAX.get().is_power_of_two());
+    /// assert_eq!(Width::MAX.get().checked_mul(2), None);
+    /// ```
+    pub const MAX: Width = Width(1 << (mem::size_of::<usize>() * 8 - 1));
+
+    /// Returns the width `width` if it is a power of two, or `None` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Width;
+    ///
+    /// assert_eq!(Width::new(8).map(Width::get), Some(8));
+    /// assert_eq!(Width::new(1).map(Width::get), Some(1));
+    /// assert_eq!(Width::new(0), None);
+    /// assert_eq!(Width::new(6), None);
+    /// ```
+    pub fn new(width: usize) -> Option<Self> {
+        if width.is_power_of_two() {
+            Some(Width(width))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the narrowest width with at least one wire for each of
+    /// `threads` threads, so that threads can enter the network without
+    /// sharing a wire.
+    ///
+    /// Returns a width of 1 for zero threads, and [`Width::MAX`] if there is no
+    /// large enough power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Width;
+    ///
+    /// assert_eq!(Width::for_threads(6).get(), 8);
+    /// assert_eq!(Width::for_threads(8).get(), 8);
+    /// assert_eq!(Width::for_threads(0).get(), 1);
+    /// ```
+    pub fn for_threads(threads: usize) -> Self {
+        match threads.checked_next_power_of_two() {
+            Some(width) => Width(width),
+            None => Width::MAX,
+        }
+    }
+
+    /// Returns the width as a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::networks::Width;
+    ///
+    /// assert_eq!(Width::for_threads(3).get(), 4);
+    /// ```
+    pub fn get(self) -> usize {
+        self.0
+    }
+
+    // The width `width`, for constructors that take a number of outputs
+    // rather than a `Width`.
+    pub(crate) fn of(width: usize) -> Self {
+        Width::new(width).unwrap_or_else(|| panic!("width must be a power of two, not {}", width))
+    }
+}
+
+impl From<Width> for usize {
+    fn from(width: Width) -> Self {
+        width.0
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_are_powers_of_two() {
+        for width in 0..=1024 {
+            assert_eq!(Width::new(width).is_some(), width.is_power_of_two());
+            assert!(Width::for_threads(width).get().is_power_of_two());
+            assert!(Width::for_threads(width).get() >= width);
+        }
+    }
+
+    #[test]
+    fn width_for_too_many_threads() {
+        assert_eq!(Width::for_threads(usize::MAX), Width::MAX);
+        assert_eq!(Width::for_threads(Width::MAX.get()), Width::MAX);
+        assert_eq!(Width::for_threads(Width::MAX.get() + 1), Width::MAX);
+    }
+}
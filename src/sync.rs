@@ -0,0 +1,751 @@
+//! Synchronization primitives that use counting networks to spread threads
+//! across several locks, or to hand out work in order.
+
+use crate::{
+    counters::{BitonicCountingNetwork, Counter, PaddedBucket},
+    networks::{BitonicNetwork, Width},
+    util::hash_single,
+};
+use core::{
+    fmt,
+    hash::Hash,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::sync::{Condvar, LockResult, Mutex, MutexGuard, TryLockResult};
+
+/// A set of locks where each acquisition picks a lock through a balancing
+/// network.
+///
+/// Lock striping usually picks a stripe by hashing, which leaves some stripes
+/// hotter than others whenever the hashes are uneven. Here every acquisition
+/// traverses a [`BitonicNetwork`](crate::networks::BitonicNetwork) whose
+/// outputs are the stripes. Once all concurrent acquisitions have picked their
+/// stripes, every stripe has been picked either `n` or `n + 1` times.
+///
+/// This suits state that can be split into interchangeable parts, such as
+/// partial sums or pools of buffers, where it doesn't matter which stripe a
+/// thread updates. Reading the whole state means locking every stripe, see
+/// [`lock`](StripedLocks::lock).
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::sync::StripedLocks;
+/// use std::{sync::Arc, thread};
+///
+/// let totals = Arc::new(StripedLocks::new(4, |_| 0u64));
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let totals = Arc::clone(&totals);
+///         thread::spawn(move || {
+///             for _ in 0..100 {
+///                 *totals.lock_for_current_thread().unwrap() += 1;
+///             }
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+///
+/// let total: u64 = (0..totals.len()).map(|idx| *totals.lock(idx).unwrap()).sum();
+/// assert_eq!(total, 400);
+/// ```
+pub struct StripedLocks<T> {
+    network: BitonicNetwork<()>,
+    // Each lock is on its own cache line, so that threads holding neighbouring
+    // stripes don't contend.
+    stripes: Box<[PaddedBucket<Mutex<T>>]>,
+}
+
+impl<T> StripedLocks<T> {
+    /// Create `num_stripes` locks, protecting the values returned by `init`
+    /// for each stripe index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_stripes` is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(8, |idx| vec![idx]);
+    ///
+    /// assert_eq!(locks.len(), 8);
+    /// assert_eq!(*locks.lock(3).unwrap(), vec![3]);
+    /// ```
+    pub fn new<F: FnMut(usize) -> T>(num_stripes: usize, mut init: F) -> Self {
+        StripedLocks {
+            network: BitonicNetwork::with_width(Width::of(num_stripes)),
+            stripes: (0..num_stripes)
+                .map(|idx| PaddedBucket(Mutex::new(init(idx))))
+                .collect(),
+        }
+    }
+
+    /// Returns the number of stripes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(4, |_| ());
+    ///
+    /// assert_eq!(locks.len(), 4);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.stripes.len()
+    }
+
+    /// Returns true if there are no stripes, which is never the case since
+    /// the number of stripes must be a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(1, |_| ());
+    ///
+    /// assert!(!locks.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.stripes.is_empty()
+    }
+
+    /// Lock the stripe picked by traversing the network, entering on a wire
+    /// chosen from the calling thread. Blocks until the lock is acquired.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stripe is poisoned, see
+    /// [`Mutex::lock`](std::sync::Mutex::lock).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(2, |idx| idx);
+    ///
+    /// assert_eq!(*locks.lock_for_current_thread().unwrap(), 0);
+    /// assert_eq!(*locks.lock_for_current_thread().unwrap(), 1);
+    /// ```
+    pub fn lock_for_current_thread(&self) -> LockResult<MutexGuard<'_, T>> {
+        self.lock(self.network.traverse_indexed())
+    }
+
+    /// Lock the stripe picked by traversing the network, entering on a wire
+    /// chosen from `key`. Blocks until the lock is acquired.
+    ///
+    /// The key only picks where the traversal starts, so equal keys do not
+    /// always reach the same stripe. Use [`lock`](StripedLocks::lock) to lock
+    /// a particular stripe.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stripe is poisoned, see
+    /// [`Mutex::lock`](std::sync::Mutex::lock).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(2, |idx| idx);
+    ///
+    /// assert_eq!(*locks.lock_keyed("connection-1").unwrap(), 0);
+    /// assert_eq!(*locks.lock_keyed("connection-1").unwrap(), 1);
+    /// ```
+    pub fn lock_keyed<K: Hash + ?Sized>(&self, key: &K) -> LockResult<MutexGuard<'_, T>> {
+        let entry = hash_single(key) as usize % self.len();
+
+        self.lock(self.network.traverse_wire_within(entry..(entry + 1)))
+    }
+
+    /// Lock the stripe picked by traversing the network, entering on a wire
+    /// chosen from the calling thread, if it is not already locked.
+    ///
+    /// A traversal is made even if the lock can't be acquired, so calling
+    /// this again picks a different stripe.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stripe is already locked or is poisoned, see
+    /// [`Mutex::try_lock`](std::sync::Mutex::try_lock).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(2, |idx| idx);
+    ///
+    /// let first = locks.lock(0).unwrap();
+    /// assert!(locks.try_lock_for_current_thread().is_err());
+    /// assert_eq!(*locks.try_lock_for_current_thread().unwrap(), 1);
+    /// ```
+    pub fn try_lock_for_current_thread(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        self.stripes[self.network.traverse_indexed()].0.try_lock()
+    }
+
+    /// Lock the stripe at the given index, without traversing the network.
+    ///
+    /// This is meant for reading or resetting every stripe, rather than for
+    /// picking one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the stripe is poisoned, see
+    /// [`Mutex::lock`](std::sync::Mutex::lock).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is not less than [`len`](StripedLocks::len).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(4, |idx| idx * 10);
+    ///
+    /// assert_eq!(*locks.lock(2).unwrap(), 20);
+    /// ```
+    pub fn lock(&self, idx: usize) -> LockResult<MutexGuard<'_, T>> {
+        self.stripes[idx].0.lock()
+    }
+
+    /// Consume the locks, returning the value of each stripe in order.
+    ///
+    /// Poisoned stripes are returned like any other.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::StripedLocks;
+    ///
+    /// let locks = StripedLocks::new(2, |idx| idx);
+    /// *locks.lock_for_current_thread().unwrap() += 10;
+    ///
+    /// assert_eq!(locks.into_inner(), vec![10, 1]);
+    /// ```
+    pub fn into_inner(self) -> Vec<T> {
+        self.stripes
+            .into_vec()
+            .into_iter()
+            .map(|PaddedBucket(stripe)| stripe.into_inner().unwrap_or_else(|err| err.into_inner()))
+            .collect()
+    }
+}
+
+impl<T> fmt::Debug for StripedLocks<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StripedLocks")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A chain of stages that items pass through in order, where an item can only
+/// enter a stage once the previous stage has finished with it and with every
+/// item before it.
+///
+/// Each stage hands out its items with a counting network, so the workers of a
+/// stage take items `0, 1, 2, ...` between them without contending on a single
+/// counter. Workers report each item they finish with
+/// [`complete`](Pipeline::complete), in any order. A stage has *passed* an item
+/// once it has completed that item and all the items before it, and only then
+/// can the next stage start on it.
+///
+/// Completions are recorded with atomics in a window of
+/// `64 * width` items per stage, so workers only block to wait for the
+/// previous stage. Completing an item that is a whole window ahead of the
+/// oldest item still in progress in its stage blocks until that item is
+/// completed.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::sync::Pipeline;
+/// use std::{sync::Arc, thread};
+///
+/// // Parse, then write out, 100 records
+/// let pipeline = Arc::new(Pipeline::new(2, 4));
+///
+/// let workers: Vec<_> = (0..2)
+///     .map(|stage| {
+///         let pipeline = Arc::clone(&pipeline);
+///         thread::spawn(move || {
+///             for _ in 0..100 {
+///                 let item = pipeline.next(stage);
+///                 // ... process the item in this stage
+///                 pipeline.complete(stage, item);
+///             }
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     worker.join().unwrap();
+/// }
+///
+/// assert_eq!(pipeline.passed(1), 100);
+/// ```
+pub struct Pipeline {
+    stages: Box<[Stage]>,
+}
+
+// Slots in the completion window of a stage, for each wire of its network
+const COMPLETION_WINDOW_PER_WIRE: usize = 64;
+
+struct Stage {
+    items: BitonicCountingNetwork,
+    // Every item before this one has been completed
+    passed: AtomicUsize,
+    // Slot `item % completed.len()` holds `item + 1` once the item has been
+    // completed, and the mark of an earlier item before that
+    completed: Box<[AtomicUsize]>,
+    // Threads blocked in `wait_past`, which have to be woken when `passed`
+    // moves
+    sleepers: AtomicUsize,
+    sleep: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl Stage {
+    fn new(width: usize) -> Self {
+        let items = BitonicCountingNetwork::new(width);
+        let window = items.width() * COMPLETION_WINDOW_PER_WIRE;
+
+        Stage {
+            items,
+            passed: AtomicUsize::new(0),
+            completed: (0..window).map(|_| AtomicUsize::new(0)).collect(),
+            sleepers: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    // Block until `item` and every item before it have been completed.
+    fn wait_past(&self, item: usize) {
+        // Pairs with the compare exchange in `complete`, so that everything the
+        // completing workers did is visible once the item has passed
+        if self.passed.load(Ordering::Acquire) > item {
+            return;
+        }
+
+        // Either this load of `passed` sees the completion that moves it, or
+        // that completion sees this sleeper and wakes it. Checking under the
+        // lock keeps the wake up from landing between the check and the wait.
+        self.sleepers.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.sleep.lock().unwrap();
+        while self.passed.load(Ordering::SeqCst) <= item {
+            guard = self.wakeup.wait(guard).unwrap();
+        }
+        drop(guard);
+        self.sleepers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    // Mark `item` as completed, returning false if it already was.
+    fn complete(&self, item: usize) -> bool {
+        let window = self.completed.len();
+        // The slot belongs to an earlier item until that one has passed
+        if let Some(earlier) = item.checked_sub(window) {
+            self.wait_past(earlier);
+        }
+        if item < self.passed.load(Ordering::SeqCst) {
+            return false;
+        }
+        if self.completed[item % window].swap(item + 1, Ordering::SeqCst) == item + 1 {
+            return false;
+        }
+
+        // Move `passed` over every completed item. The slot was marked before
+        // loading `passed`, and a thread moving `passed` loads the next slot
+        // after, so whichever completes last of a run of items moves `passed`
+        // over all of them.
+        let mut advanced = false;
+        let mut passed = self.passed.load(Ordering::SeqCst);
+        while self.completed[passed % window].load(Ordering::SeqCst) == passed + 1 {
+            match self.passed.compare_exchange(
+                passed,
+                passed + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    advanced = true;
+                    passed += 1;
+                }
+                Err(current) => passed = current,
+            }
+        }
+
+        if advanced && self.sleepers.load(Ordering::SeqCst) > 0 {
+            drop(self.sleep.lock().unwrap());
+            self.wakeup.notify_all();
+        }
+
+        true
+    }
+}
+
+/// An item handed out by [`Pipeline::try_next`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::sync::{Pipeline, PipelineItem};
+///
+/// let pipeline = Pipeline::new(2, 4);
+///
+/// assert_eq!(pipeline.try_next(0), PipelineItem::Ready(0));
+/// assert_eq!(pipeline.try_next(1).item(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineItem {
+    /// The previous stage has passed the item, so it can be processed.
+    Ready(usize),
+    /// The previous stage has not passed the item yet. The item still belongs
+    /// to the caller, who has to [`wait_for`](Pipeline::wait_for) it before
+    /// processing it.
+    Pending(usize),
+}
+
+impl PipelineItem {
+    /// Returns the item, whether or not it is ready.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::PipelineItem;
+    ///
+    /// assert_eq!(PipelineItem::Pending(3).item(), 3);
+    /// ```
+    pub fn item(self) -> usize {
+        match self {
+            PipelineItem::Ready(item) | PipelineItem::Pending(item) => item,
+        }
+    }
+}
+
+impl Pipeline {
+    /// Create a pipeline with `num_stages` stages, each handing out items
+    /// through a counting network of the given width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_stages` is zero, or if `width` is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::Pipeline;
+    ///
+    /// let pipeline = Pipeline::new(3, 8);
+    ///
+    /// assert_eq!(pipeline.num_stages(), 3);
+    /// ```
+    pub fn new(num_stages: usize, width: usize) -> Self {
+        assert!(num_stages > 0, "a pipeline needs at least one stage");
+
+        Pipeline {
+            stages: (0..num_stages).map(|_| Stage::new(width)).collect(),
+        }
+    }
+
+    /// Returns the number of stages.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::Pipeline;
+    ///
+    /// let pipeline = Pipeline::new(2, 4);
+    ///
+    /// assert_eq!(pipeline.num_stages(), 2);
+    /// ```
+    pub fn num_stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Take the next item of a stage, blocking until the previous stage has
+    /// passed it. Items of the first stage are always ready.
+    ///
+    /// Each item is handed out exactly once in each stage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stage` is not less than [`num_stages`](Pipeline::num_stages).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::Pipeline;
+    ///
+    /// let pipeline = Pipeline::new(2, 4);
+    ///
+    /// assert_eq!(pipeline.next(0), 0);
+    /// pipeline.complete(0, 0);
+    ///
+    /// // Item 0 has passed the first stage, so it doesn't block
+    /// assert_eq!(pipeline.next(1), 0);
+    /// ```
+    pub fn next(&self, stage: usize) -> usize {
+        let item = self.stages[stage].items.next();
+        self.wait_for(stage, item);
+
+        item
+    }
+
+    /// Take the next item of a stage without blocking, and report whether the
+    /// previous stage has passed it yet.
+    ///
+    /// The item is handed out even if it is not ready. A
+    /// [`Pending`](PipelineItem::Pending) item must be waited for with
+    /// [`wait_for`](Pipeline::wait_for) before it is processed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stage` is not less than [`num_stages`](Pipeline::num_stages).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::{Pipeline, PipelineItem};
+    ///
+    /// let pipeline = Pipeline::new(2, 4);
+    ///
+    /// assert_eq!(pipeline.try_next(1), PipelineItem::Pending(0));
+    /// pipeline.complete(0, 0);
+    /// pipeline.wait_for(1, 0);
+    /// ```
+    pub fn try_next(&self, stage: usize) -> PipelineItem {
+        let item = self.stages[stage].items.next();
+
+        match stage.checked_sub(1) {
+            Some(previous) if self.passed(previous) <= item => PipelineItem::Pending(item),
+            _ => PipelineItem::Ready(item),
+        }
+    }
+
+    /// Block until the stage before `stage` has passed `item`, so the item is
+    /// ready to be processed in `stage`. Returns immediately in the first
+    /// stage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stage` is not less than [`num_stages`](Pipeline::num_stages).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::Pipeline;
+    /// use std::{sync::Arc, thread};
+    ///
+    /// let pipeline = Arc::new(Pipeline::new(2, 4));
+    ///
+    /// let producer = {
+    ///     let pipeline = Arc::clone(&pipeline);
+    ///     thread::spawn(move || {
+    ///         for item in 0..3 {
+    ///             pipeline.complete(0, item);
+    ///         }
+    ///     })
+    /// };
+    ///
+    /// pipeline.wait_for(1, 2);
+    /// assert!(pipeline.passed(0) >= 3);
+    /// producer.join().unwrap();
+    /// ```
+    pub fn wait_for(&self, stage: usize, item: usize) {
+        assert!(
+            stage < self.stages.len(),
+            "stage {} out of range for {} stages",
+            stage,
+            self.stages.len()
+        );
+        if let Some(previous) = stage.checked_sub(1) {
+            self.stages[previous].wait_past(item);
+        }
+    }
+
+    /// Record that a stage has finished with an item, waking any workers of
+    /// the next stage that are waiting for it.
+    ///
+    /// Items can be completed in any order. Completing an item blocks if it is
+    /// a whole window ahead of the oldest item still in progress in the stage,
+    /// see [`Pipeline`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stage` is not less than [`num_stages`](Pipeline::num_stages),
+    /// or if the item was already completed in this stage.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::Pipeline;
+    ///
+    /// let pipeline = Pipeline::new(1, 4);
+    ///
+    /// pipeline.complete(0, 1);
+    /// assert_eq!(pipeline.passed(0), 0);
+    ///
+    /// pipeline.complete(0, 0);
+    /// assert_eq!(pipeline.passed(0), 2);
+    /// ```
+    pub fn complete(&self, stage: usize, item: usize) {
+        assert!(
+            self.stages[stage].complete(item),
+            "item {} completed twice in stage {}",
+            item,
+            stage
+        );
+    }
+
+    /// Returns the number of items a stage has passed: every item before this
+    /// one has been completed in the stage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stage` is not less than [`num_stages`](Pipeline::num_stages).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::sync::Pipeline;
+    ///
+    /// let pipeline = Pipeline::new(1, 4);
+    /// pipeline.complete(0, 0);
+    ///
+    /// assert_eq!(pipeline.passed(0), 1);
+    /// ```
+    pub fn passed(&self, stage: usize) -> usize {
+        self.stages[stage].passed.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let passed: Vec<_> = (0..self.num_stages())
+            .map(|stage| self.passed(stage))
+            .collect();
+
+        f.debug_struct("Pipeline").field("passed", &passed).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn concurrent_locks_are_balanced() {
+        let locks = Arc::new(StripedLocks::new(8, |_| 0usize));
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let locks = Arc::clone(&locks);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        *locks.lock_for_current_thread().unwrap() += 1;
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let counts = Arc::try_unwrap(locks).unwrap().into_inner();
+        assert_eq!(counts, vec![500; 8]);
+    }
+
+    #[test]
+    fn keyed_locks_are_balanced() {
+        let locks = StripedLocks::new(4, |_| 0usize);
+
+        for key in 0..10 {
+            *locks.lock_keyed(&(key * 7)).unwrap() += 1;
+        }
+
+        assert_eq!(locks.into_inner(), vec![3, 3, 2, 2]);
+    }
+
+    #[test]
+    fn pipeline_stages_see_items_in_order() {
+        const ITEMS: usize = 200;
+        let pipeline = Arc::new(Pipeline::new(3, 4));
+        let seen = Arc::new(StripedLocks::new(1, |_| Vec::new()));
+
+        // Two workers per stage, with the last stage recording what it saw
+        let workers: Vec<_> = (0..6)
+            .map(|worker| {
+                let pipeline = Arc::clone(&pipeline);
+                let seen = Arc::clone(&seen);
+                thread::spawn(move || {
+                    let stage = worker / 2;
+                    for _ in 0..(ITEMS / 2) {
+                        let item = pipeline.next(stage);
+                        if stage > 0 {
+                            assert!(pipeline.passed(stage - 1) > item);
+                        }
+                        if stage == 2 {
+                            seen.lock(0).unwrap().push(item);
+                        }
+                        pipeline.complete(stage, item);
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let mut seen = Arc::try_unwrap(seen).unwrap().into_inner().remove(0);
+        seen.sort_unstable();
+        assert_eq!(seen, (0..ITEMS).collect::<Vec<_>>());
+        for stage in 0..3 {
+            assert_eq!(pipeline.passed(stage), ITEMS);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "item 3 completed twice in stage 0")]
+    fn pipeline_item_completed_twice() {
+        let pipeline = Pipeline::new(1, 2);
+
+        pipeline.complete(0, 3);
+        pipeline.complete(0, 3);
+    }
+
+    #[test]
+    fn pipeline_completion_waits_for_window() {
+        let pipeline = Arc::new(Pipeline::new(1, 1));
+
+        // The slot of item 64 is still held by item 0
+        let ahead = {
+            let pipeline = Arc::clone(&pipeline);
+            thread::spawn(move || pipeline.complete(0, 64))
+        };
+        for item in 0..64 {
+            pipeline.complete(0, item);
+        }
+        ahead.join().unwrap();
+
+        assert_eq!(pipeline.passed(0), 65);
+    }
+
+    #[test]
+    #[should_panic]
+    fn stripes_not_power_of_two() {
+        let _ = StripedLocks::new(3, |_| ());
+    }
+}
@@ -0,0 +1,571 @@
+//! An adversarial scheduler for traversals of a network, that searches for
+//! the interleavings that leave its outputs most unbalanced.
+//!
+//! Like the [`exhaustive`](super::exhaustive) checker, the scheduler runs a
+//! model of a network where each traversal is a sequence of atomic steps: one
+//! toggle for every balancer on its path, followed by leaving on an output
+//! wire. Instead of trying every interleaving, which is only possible for tiny
+//! networks, it picks the next step greedily. Tokens are let into the network
+//! on any wire, steps that leave a token on the most used output are taken
+//! right away, and tokens that would leave on the least used output are paused
+//! mid-network for as long as possible. Ties are broken randomly, and each
+//! round explores a different schedule.
+//!
+//! The skew of a state is the difference between the number of tokens that
+//! left on the most and least used output wires. In a quiescent state, with no
+//! tokens in the network, the skew of a counting network is at most one, and
+//! the skew of a k-smoothing network is at most k. While tokens are paused the
+//! skew can be larger, by up to one for each paused token in a counting
+//! network.
+//!
+//! # Examples
+//!
+//! ```
+//! use counting_networks::{
+//!     networks::{BitonicConfiguration, ButterflyConfiguration},
+//!     testing::adversary::{self, AdversaryConfig},
+//! };
+//!
+//! let config = AdversaryConfig::new().rounds(20);
+//!
+//! let bitonic = adversary::explore::<BitonicConfiguration>(8, &config);
+//! assert!(bitonic.max_quiescent_skew() <= 1);
+//!
+//! let butterfly = adversary::explore::<ButterflyConfiguration>(8, &config);
+//! assert!(butterfly.max_quiescent_skew() > 1);
+//! ```
+
+use crate::networks::{NetworkConfiguration, Topology, Width};
+
+/// Options for an adversarial [`explore`] run.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::adversary::AdversaryConfig;
+///
+/// let config = AdversaryConfig::new()
+///     .tokens(1_000)
+///     .max_in_flight(8)
+///     .rounds(50)
+///     .seed(7);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdversaryConfig {
+    tokens: usize,
+    max_in_flight: usize,
+    rounds: usize,
+    seed: u64,
+}
+
+impl AdversaryConfig {
+    /// Create options with the defaults: 100 rounds of 64 tokens, with at most
+    /// 4 tokens in the network at once, and a seed of zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::adversary::AdversaryConfig;
+    ///
+    /// assert_eq!(AdversaryConfig::new(), AdversaryConfig::default());
+    /// ```
+    pub fn new() -> Self {
+        AdversaryConfig {
+            tokens: 64,
+            max_in_flight: 4,
+            rounds: 100,
+            seed: 0,
+        }
+    }
+
+    /// Set the number of tokens that traverse the network in each round.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::adversary::AdversaryConfig;
+    ///
+    /// let config = AdversaryConfig::new().tokens(256);
+    /// ```
+    pub fn tokens(mut self, tokens: usize) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Set the most tokens that can be in the network at once, which plays the
+    /// role of the number of threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::adversary::AdversaryConfig;
+    ///
+    /// let config = AdversaryConfig::new().max_in_flight(16);
+    /// ```
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight;
+        self
+    }
+
+    /// Set the number of rounds, each of which starts from a fresh network and
+    /// explores a different schedule.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::adversary::AdversaryConfig;
+    ///
+    /// let config = AdversaryConfig::new().rounds(1_000);
+    /// ```
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds;
+        self
+    }
+
+    /// Set the seed that ties between steps are broken with. Runs with the
+    /// same options and seed explore the same schedules.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::adversary::AdversaryConfig;
+    ///
+    /// let config = AdversaryConfig::new().seed(42);
+    /// ```
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Default for AdversaryConfig {
+    fn default() -> Self {
+        AdversaryConfig::new()
+    }
+}
+
+/// The worst skews found by an [`explore`] run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdversaryReport {
+    rounds: usize,
+    max_skew: usize,
+    in_flight_at_max_skew: usize,
+    max_quiescent_skew: usize,
+    quiescent_exits: Vec<usize>,
+}
+
+impl AdversaryReport {
+    /// Returns the number of rounds that were run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::BitonicConfiguration,
+    ///     testing::adversary::{self, AdversaryConfig},
+    /// };
+    ///
+    /// let config = AdversaryConfig::new().rounds(3);
+    /// let report = adversary::explore::<BitonicConfiguration>(4, &config);
+    ///
+    /// assert_eq!(report.rounds(), 3);
+    /// ```
+    pub fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Returns the largest skew found in any state, including states with
+    /// tokens paused in the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::BitonicConfiguration,
+    ///     testing::adversary::{self, AdversaryConfig},
+    /// };
+    ///
+    /// let config = AdversaryConfig::new().max_in_flight(4).rounds(10);
+    /// let report = adversary::explore::<BitonicConfiguration>(8, &config);
+    ///
+    /// assert!(report.max_skew() <= 1 + report.in_flight_at_max_skew());
+    /// ```
+    pub fn max_skew(&self) -> usize {
+        self.max_skew
+    }
+
+    /// Returns the number of tokens that were paused in the network in the
+    /// state with the largest skew.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::BitonicConfiguration,
+    ///     testing::adversary::{self, AdversaryConfig},
+    /// };
+    ///
+    /// let config = AdversaryConfig::new().max_in_flight(2).rounds(10);
+    /// let report = adversary::explore::<BitonicConfiguration>(8, &config);
+    ///
+    /// assert!(report.in_flight_at_max_skew() <= 2);
+    /// ```
+    pub fn in_flight_at_max_skew(&self) -> usize {
+        self.in_flight_at_max_skew
+    }
+
+    /// Returns the largest skew found in a quiescent state.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::BitonicConfiguration,
+    ///     testing::adversary::{self, AdversaryConfig},
+    /// };
+    ///
+    /// let config = AdversaryConfig::new().rounds(10);
+    /// let report = adversary::explore::<BitonicConfiguration>(8, &config);
+    ///
+    /// assert!(report.max_quiescent_skew() <= 1);
+    /// ```
+    pub fn max_quiescent_skew(&self) -> usize {
+        self.max_quiescent_skew
+    }
+
+    /// Returns the number of tokens that had left on each output wire in the
+    /// quiescent state with the largest skew.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::ButterflyConfiguration,
+    ///     testing::adversary::{self, AdversaryConfig},
+    /// };
+    ///
+    /// let config = AdversaryConfig::new().rounds(10);
+    /// let report = adversary::explore::<ButterflyConfiguration>(4, &config);
+    /// let exits = report.quiescent_exits();
+    ///
+    /// assert_eq!(exits.len(), 4);
+    /// assert_eq!(
+    ///     exits.iter().max().unwrap() - exits.iter().min().unwrap(),
+    ///     report.max_quiescent_skew()
+    /// );
+    /// ```
+    pub fn quiescent_exits(&self) -> &[usize] {
+        &self.quiescent_exits
+    }
+}
+
+/// Run the adversarial scheduler against the network of the given width
+/// described by `C`.
+///
+/// # Panics
+///
+/// Panics if `width` is not a power of two, or if the configuration does not
+/// support networks of that width.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{
+///     networks::BitonicConfiguration,
+///     testing::adversary::{self, AdversaryConfig},
+/// };
+///
+/// let report = adversary::explore::<BitonicConfiguration>(16, &AdversaryConfig::new());
+///
+/// assert!(report.max_quiescent_skew() <= 1);
+/// ```
+pub fn explore<C: NetworkConfiguration>(width: usize, config: &AdversaryConfig) -> AdversaryReport {
+    explore_topology(&Topology::from_config::<C>(Width::of(width)), config)
+}
+
+/// Run the adversarial scheduler against the network described by `topology`,
+/// in the same way as [`explore`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{
+///     networks::Topology,
+///     testing::adversary::{self, AdversaryConfig},
+/// };
+///
+/// // A single layer of balancers only smooths pairs of wires
+/// let topology = Topology::new(4, vec![(0, 1), (2, 3)]).unwrap();
+/// let report = adversary::explore_topology(&topology, &AdversaryConfig::new());
+///
+/// assert!(report.max_quiescent_skew() > 1);
+/// ```
+pub fn explore_topology(topology: &Topology, config: &AdversaryConfig) -> AdversaryReport {
+    let wiring = Wiring::new(topology);
+    let mut rng = Rng::new(config.seed);
+    let mut report = AdversaryReport {
+        rounds: config.rounds,
+        max_skew: 0,
+        in_flight_at_max_skew: 0,
+        max_quiescent_skew: 0,
+        quiescent_exits: vec![0; topology.width()],
+    };
+
+    for _ in 0..config.rounds {
+        Round::new(&wiring, config).run(&mut rng, &mut report);
+    }
+
+    report
+}
+
+// The balancers of a topology, linked to each other.
+struct Wiring {
+    width: usize,
+    balancers: Vec<(usize, usize)>,
+    // The first balancer on each input wire
+    entries: Vec<Option<usize>>,
+    // The next balancer after each balancer, on its top and bottom wires
+    next: Vec<[Option<usize>; 2]>,
+}
+
+impl Wiring {
+    fn new(topology: &Topology) -> Self {
+        let balancers = topology.balancers().to_vec();
+        let mut entries = vec![None; topology.width()];
+        let mut next = vec![[None, None]; balancers.len()];
+
+        for (idx, &(top, bottom)) in balancers.iter().enumerate().rev() {
+            next[idx] = [entries[top], entries[bottom]];
+            entries[top] = Some(idx);
+            entries[bottom] = Some(idx);
+        }
+
+        Wiring {
+            width: topology.width(),
+            balancers,
+            entries,
+            next,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token {
+    wire: usize,
+    // The next balancer the token passes through, if it has not left
+    next: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    // Let a new token into the network on an input wire
+    Enter(usize),
+    // Move the token at an index of `in_flight` through its next balancer, or
+    // out of the network
+    Advance(usize),
+}
+
+struct Round<'a> {
+    wiring: &'a Wiring,
+    config: &'a AdversaryConfig,
+    toggles: Vec<bool>,
+    exits: Vec<usize>,
+    in_flight: Vec<Token>,
+    entered: usize,
+}
+
+impl<'a> Round<'a> {
+    fn new(wiring: &'a Wiring, config: &'a AdversaryConfig) -> Self {
+        Round {
+            wiring,
+            config,
+            toggles: vec![false; wiring.balancers.len()],
+            exits: vec![0; wiring.width],
+            in_flight: Vec::new(),
+            entered: 0,
+        }
+    }
+
+    fn run(mut self, rng: &mut Rng, report: &mut AdversaryReport) {
+        while let Some(step) = self.choose(rng) {
+            self.take(step);
+
+            let skew = skew(&self.exits);
+            if skew > report.max_skew {
+                report.max_skew = skew;
+                report.in_flight_at_max_skew = self.in_flight.len();
+            }
+            if self.in_flight.is_empty() && skew > report.max_quiescent_skew {
+                report.max_quiescent_skew = skew;
+                report.quiescent_exits.clone_from(&self.exits);
+            }
+        }
+    }
+
+    // Pick the step that leaves the outputs most unbalanced, breaking ties
+    // randomly. Returns `None` once every token has left.
+    fn choose(&self, rng: &mut Rng) -> Option<Step> {
+        let can_enter =
+            self.entered < self.config.tokens && self.in_flight.len() < self.config.max_in_flight;
+        let entries = (0..self.wiring.width)
+            .filter(|_| can_enter)
+            .map(Step::Enter);
+        let advances = (0..self.in_flight.len()).map(Step::Advance);
+
+        let mut best = None;
+        let mut best_score = 0;
+        let mut ties = 0;
+        for step in entries.chain(advances) {
+            let score = self.score(step);
+
+            if best.is_none() || score > best_score {
+                best = Some(step);
+                best_score = score;
+                ties = 1;
+            } else if score == best_score {
+                // Keep each of the tied steps with equal probability
+                ties += 1;
+                if rng.below(ties) == 0 {
+                    best = Some(step);
+                }
+            }
+        }
+
+        best
+    }
+
+    // The skew after taking the step. Only tokens leaving the network change
+    // the outputs.
+    fn score(&self, step: Step) -> usize {
+        match step {
+            Step::Advance(idx) => match self.in_flight[idx] {
+                Token { wire, next: None } => {
+                    let mut exits = self.exits.clone();
+                    exits[wire] += 1;
+                    skew(&exits)
+                }
+                Token { next: Some(_), .. } => skew(&self.exits),
+            },
+            Step::Enter(_) => skew(&self.exits),
+        }
+    }
+
+    fn take(&mut self, step: Step) {
+        match step {
+            Step::Enter(wire) => {
+                self.in_flight.push(Token {
+                    wire,
+                    next: self.wiring.entries[wire],
+                });
+                self.entered += 1;
+            }
+            Step::Advance(idx) => match self.in_flight[idx] {
+                Token {
+                    wire,
+                    next: Some(balancer),
+                } => {
+                    let (top, bottom) = self.wiring.balancers[balancer];
+                    // The first token through a balancer leaves on the top wire
+                    let toggle = self.toggles[balancer];
+                    self.toggles[balancer] = !toggle;
+                    let (exit, side) = if toggle { (bottom, 1) } else { (top, 0) };
+                    debug_assert!(wire == top || wire == bottom);
+
+                    self.in_flight[idx] = Token {
+                        wire: exit,
+                        next: self.wiring.next[balancer][side],
+                    };
+                }
+                Token { wire, next: None } => {
+                    self.exits[wire] += 1;
+                    self.in_flight.swap_remove(idx);
+                }
+            },
+        }
+    }
+}
+
+fn skew(exits: &[usize]) -> usize {
+    let most = exits.iter().max().copied().unwrap_or(0);
+    let fewest = exits.iter().min().copied().unwrap_or(0);
+
+    most - fewest
+}
+
+// Simple xorshift generator
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    // A random number less than `bound`, which must not be zero
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::{BitonicConfiguration, ButterflyConfiguration};
+
+    #[test]
+    fn bitonic_quiescent_skew_is_at_most_one() {
+        for &width in &[2, 4, 8, 16] {
+            let config = AdversaryConfig::new().max_in_flight(width).rounds(20);
+            let report = explore::<BitonicConfiguration>(width, &config);
+
+            assert!(report.max_quiescent_skew() <= 1, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn bitonic_skew_is_bounded_by_paused_tokens() {
+        for &max_in_flight in &[1, 2, 4, 8] {
+            let config = AdversaryConfig::new()
+                .max_in_flight(max_in_flight)
+                .rounds(20);
+            let report = explore::<BitonicConfiguration>(8, &config);
+
+            assert!(report.max_skew() <= 1 + report.in_flight_at_max_skew());
+            assert!(report.in_flight_at_max_skew() <= max_in_flight);
+        }
+    }
+
+    #[test]
+    fn pausing_tokens_increases_skew() {
+        let config = AdversaryConfig::new().max_in_flight(8).rounds(20);
+        let report = explore::<BitonicConfiguration>(8, &config);
+
+        assert!(report.max_skew() > 1, "{:?}", report);
+    }
+
+    #[test]
+    fn butterfly_skew_is_found() {
+        for &width in &[4, 8, 16] {
+            let config = AdversaryConfig::new().max_in_flight(width).rounds(50);
+            let report = explore::<ButterflyConfiguration>(width, &config);
+            let log_width = width.trailing_zeros() as usize;
+
+            assert!(report.max_quiescent_skew() > 1, "{:?}", report);
+            assert!(report.max_quiescent_skew() <= log_width, "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_report() {
+        let config = AdversaryConfig::new().rounds(5).seed(3);
+
+        assert_eq!(
+            explore::<ButterflyConfiguration>(8, &config),
+            explore::<ButterflyConfiguration>(8, &config)
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! An exhaustive checker of every interleaving of concurrent traversals, for
+//! networks small enough to explore completely.
+//!
+//! The checker runs a model of a counting network built from a
+//! [`NetworkConfiguration`], where each traversal is a sequence of atomic
+//! steps: one toggle for every balancer on its path, followed by taking a value
+//! from the counter on the wire it leaves on. Every order in which the steps of
+//! several threads can happen is explored, and whenever no token is inside the
+//! network the values taken so far must be exactly `0..n`.
+//!
+//! Unlike the randomized concurrent tests, which only see the interleavings
+//! that the scheduler happens to produce, this covers all of them. It needs no
+//! dependencies and runs as a normal test.
+//!
+//! # Examples
+//!
+//! ```
+//! use counting_networks::{networks::BitonicConfiguration, testing::exhaustive};
+//!
+//! let report = exhaustive::check::<BitonicConfiguration>(4, 2, 2).unwrap();
+//!
+//! assert!(report.states() > 0);
+//! ```
+
+use crate::networks::{NetworkConfiguration, Topology, Width};
+use core::fmt;
+use std::{collections::HashSet, error::Error};
+
+/// The widest network that can be checked.
+pub const MAX_WIDTH: usize = 4;
+/// The most threads that can be checked at once.
+pub const MAX_THREADS: usize = 3;
+/// The most traversals that each thread can make.
+pub const MAX_TRAVERSALS_PER_THREAD: usize = 3;
+
+/// Statistics about a successful check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    assignments: usize,
+    states: usize,
+}
+
+impl Report {
+    /// Returns the number of different ways of assigning input wires to the
+    /// threads that were checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{networks::BitonicConfiguration, testing::exhaustive};
+    ///
+    /// let report = exhaustive::check::<BitonicConfiguration>(4, 2, 1).unwrap();
+    ///
+    /// assert_eq!(report.assignments(), 16);
+    /// ```
+    pub fn assignments(&self) -> usize {
+        self.assignments
+    }
+
+    /// Returns the number of distinct states of the network and threads that
+    /// were explored, summed over all assignments.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{networks::BitonicConfiguration, testing::exhaustive};
+    ///
+    /// let one_thread = exhaustive::check::<BitonicConfiguration>(2, 1, 2).unwrap();
+    /// let two_threads = exhaustive::check::<BitonicConfiguration>(2, 2, 2).unwrap();
+    ///
+    /// assert!(one_thread.states() < two_threads.states());
+    /// ```
+    pub fn states(&self) -> usize {
+        self.states
+    }
+}
+
+/// An interleaving of traversals that broke the counting property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    entry_wires: Vec<usize>,
+    schedule: Vec<usize>,
+    values: Vec<usize>,
+}
+
+impl Counterexample {
+    /// Returns the input wire that each thread entered the network on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::{NetworkConfiguration, Width},
+    ///     testing::exhaustive,
+    /// };
+    ///
+    /// // Wires with no balancers between them are not a counting network
+    /// struct Empty;
+    ///
+    /// impl IntoIterator for Empty {
+    ///     type Item = (usize, usize);
+    ///     type IntoIter = std::vec::IntoIter<(usize, usize)>;
+    ///
+    ///     fn into_iter(self) -> Self::IntoIter {
+    ///         Vec::new().into_iter()
+    ///     }
+    /// }
+    ///
+    /// impl NetworkConfiguration for Empty {
+    ///     fn from_width(_: Width) -> Self {
+    ///         Empty
+    ///     }
+    /// }
+    ///
+    /// let counterexample = exhaustive::check::<Empty>(4, 1, 1).unwrap_err();
+    ///
+    /// assert_eq!(counterexample.entry_wires(), &[1]);
+    /// ```
+    pub fn entry_wires(&self) -> &[usize] {
+        &self.entry_wires
+    }
+
+    /// Returns the order that the threads took their steps in, one entry per
+    /// step, up to the point where the counting property was broken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::{NetworkConfiguration, Width},
+    ///     testing::exhaustive,
+    /// };
+    ///
+    /// # struct Empty;
+    /// #
+    /// # impl IntoIterator for Empty {
+    /// #     type Item = (usize, usize);
+    /// #     type IntoIter = std::vec::IntoIter<(usize, usize)>;
+    /// #
+    /// #     fn into_iter(self) -> Self::IntoIter {
+    /// #         Vec::new().into_iter()
+    /// #     }
+    /// # }
+    /// #
+    /// # impl NetworkConfiguration for Empty {
+    /// #     fn from_width(_: Width) -> Self {
+    /// #         Empty
+    /// #     }
+    /// # }
+    /// #
+    /// let counterexample = exhaustive::check::<Empty>(2, 1, 1).unwrap_err();
+    ///
+    /// assert_eq!(counterexample.schedule(), &[0]);
+    /// ```
+    pub fn schedule(&self) -> &[usize] {
+        &self.schedule
+    }
+
+    /// Returns the values taken from the counter by the end of the schedule,
+    /// sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     networks::{NetworkConfiguration, Width},
+    ///     testing::exhaustive,
+    /// };
+    ///
+    /// # struct Empty;
+    /// #
+    /// # impl IntoIterator for Empty {
+    /// #     type Item = (usize, usize);
+    /// #     type IntoIter = std::vec::IntoIter<(usize, usize)>;
+    /// #
+    /// #     fn into_iter(self) -> Self::IntoIter {
+    /// #         Vec::new().into_iter()
+    /// #     }
+    /// # }
+    /// #
+    /// # impl NetworkConfiguration for Empty {
+    /// #     fn from_width(_: Width) -> Self {
+    /// #         Empty
+    /// #     }
+    /// # }
+    /// #
+    /// let counterexample = exhaustive::check::<Empty>(2, 1, 1).unwrap_err();
+    ///
+    /// assert_eq!(counterexample.values(), &[1]);
+    /// ```
+    pub fn values(&self) -> &[usize] {
+        &self.values
+    }
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "threads entering on wires {:?} took values {:?} with the schedule {:?}",
+            self.entry_wires, self.values, self.schedule
+        )
+    }
+}
+
+impl Error for Counterexample {}
+
+/// Check every interleaving of `threads` threads that each make
+/// `traversals_per_thread` traversals of the network described by `C`, for
+/// every way of assigning input wires to the threads.
+///
+/// Returns the first interleaving found that leaves the network in a quiescent
+/// state where the values taken are not exactly `0..n`.
+///
+/// # Panics
+///
+/// Panics if `width` is greater than [`MAX_WIDTH`], `threads` is greater than
+/// [`MAX_THREADS`], or `traversals_per_thread` is greater than
+/// [`MAX_TRAVERSALS_PER_THREAD`]. Also panics if `width` is not a power of
+/// two, or if the configuration does not support networks of that width.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{networks::BitonicConfiguration, testing::exhaustive};
+///
+/// for &width in &[1, 2, 4] {
+///     assert!(exhaustive::check::<BitonicConfiguration>(width, 2, 2).is_ok());
+/// }
+/// ```
+pub fn check<C: NetworkConfiguration>(
+    width: usize,
+    threads: usize,
+    traversals_per_thread: usize,
+) -> Result<Report, Counterexample> {
+    assert!(width <= MAX_WIDTH);
+
+    check_topology(
+        &Topology::from_config::<C>(Width::of(width)),
+        threads,
+        traversals_per_thread,
+    )
+}
+
+/// Check every interleaving of traversals of the network described by
+/// `topology`, in the same way as [`check`].
+///
+/// # Panics
+///
+/// Panics if the width of the topology is greater than [`MAX_WIDTH`],
+/// `threads` is greater than [`MAX_THREADS`], or `traversals_per_thread` is
+/// greater than [`MAX_TRAVERSALS_PER_THREAD`].
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{networks::Topology, testing::exhaustive};
+///
+/// // A butterfly is a balancing network, but not a counting network
+/// let topology = Topology::new(4, vec![(0, 1), (2, 3), (0, 2), (1, 3)]).unwrap();
+///
+/// assert!(exhaustive::check_topology(&topology, 1, 3).is_ok());
+/// assert!(exhaustive::check_topology(&topology, 2, 1).is_err());
+/// ```
+pub fn check_topology(
+    topology: &Topology,
+    threads: usize,
+    traversals_per_thread: usize,
+) -> Result<Report, Counterexample> {
+    let width = topology.width();
+    assert!(width <= MAX_WIDTH);
+    assert!(threads <= MAX_THREADS);
+    assert!(traversals_per_thread <= MAX_TRAVERSALS_PER_THREAD);
+
+    let model = Model::new(width, topology.balancers().to_vec());
+    let assignments = width.pow(threads as u32);
+    let mut states = 0;
+
+    for assignment in 0..assignments {
+        let entry_wires: Vec<usize> = (0..threads)
+            .map(|thread| (assignment / width.pow(thread as u32)) % width)
+            .collect();
+
+        let mut explorer = Explorer {
+            model: &model,
+            entry_wires: &entry_wires,
+            visited: HashSet::new(),
+            schedule: Vec::new(),
+        };
+        let initial = State {
+            toggles: vec![false; model.balancers.len()],
+            buckets: (0..width).collect(),
+            threads: vec![
+                ThreadState {
+                    remaining: traversals_per_thread,
+                    position: None,
+                };
+                threads
+            ],
+            values: Vec::new(),
+        };
+
+        explorer.explore(initial)?;
+        states += explorer.visited.len();
+    }
+
+    Ok(Report {
+        assignments,
+        states,
+    })
+}
+
+struct Model {
+    width: usize,
+    balancers: Vec<(usize, usize)>,
+}
+
+impl Model {
+    fn new(width: usize, balancers: Vec<(usize, usize)>) -> Self {
+        Model { width, balancers }
+    }
+
+    // The first balancer at or after `from` that is on `wire`
+    fn next_balancer(&self, wire: usize, from: usize) -> Option<usize> {
+        (from..self.balancers.len()).find(|&idx| {
+            let (top, bottom) = self.balancers[idx];
+            top == wire || bottom == wire
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ThreadState {
+    // Traversals left to start
+    remaining: usize,
+    // The wire a token is on, and the first balancer it has not passed yet
+    position: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct State {
+    toggles: Vec<bool>,
+    // Next value on each output wire
+    buckets: Vec<usize>,
+    threads: Vec<ThreadState>,
+    // Sorted values taken so far
+    values: Vec<usize>,
+}
+
+struct Explorer<'a> {
+    model: &'a Model,
+    entry_wires: &'a [usize],
+    visited: HashSet<State>,
+    schedule: Vec<usize>,
+}
+
+impl<'a> Explorer<'a> {
+    fn explore(&mut self, state: State) -> Result<(), Counterexample> {
+        if self.visited.contains(&state) {
+            return Ok(());
+        }
+
+        let quiescent = state.threads.iter().all(|thread| thread.position.is_none());
+        if quiescent && !state.values.iter().copied().eq(0..state.values.len()) {
+            return Err(Counterexample {
+                entry_wires: self.entry_wires.to_vec(),
+                schedule: self.schedule.clone(),
+                values: state.values,
+            });
+        }
+
+        for thread in 0..state.threads.len() {
+            if let Some(next) = self.step(&state, thread) {
+                self.schedule.push(thread);
+                self.explore(next)?;
+                self.schedule.pop();
+            }
+        }
+
+        self.visited.insert(state);
+        Ok(())
+    }
+
+    // Take the next atomic step of `thread`, if it has any left.
+    fn step(&self, state: &State, thread: usize) -> Option<State> {
+        let thread_state = &state.threads[thread];
+        let (wire, from) = match thread_state.position {
+            Some(position) => position,
+            None if thread_state.remaining > 0 => (self.entry_wires[thread], 0),
+            None => return None,
+        };
+
+        let mut next = state.clone();
+        if thread_state.position.is_none() {
+            next.threads[thread].remaining -= 1;
+        }
+
+        match self.model.next_balancer(wire, from) {
+            Some(balancer) => {
+                let (top, bottom) = self.model.balancers[balancer];
+                // The first token through a balancer leaves on the top wire
+                let toggle = next.toggles[balancer];
+                next.toggles[balancer] = !toggle;
+                let exit = if toggle { bottom } else { top };
+
+                next.threads[thread].position = Some((exit, balancer + 1));
+            }
+            None => {
+                let value = next.buckets[wire];
+                next.buckets[wire] += self.model.width;
+                let idx = next.values.binary_search(&value).unwrap_or_else(|idx| idx);
+                next.values.insert(idx, value);
+
+                next.threads[thread].position = None;
+            }
+        }
+
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::BitonicConfiguration;
+
+    struct Pairs(usize);
+
+    // Only the first layer of a bitonic network, which balances pairs of wires
+    // but is not a counting network for widths above 2.
+    impl IntoIterator for Pairs {
+        type IntoIter = std::vec::IntoIter<(usize, usize)>;
+        type Item = (usize, usize);
+
+        fn into_iter(self) -> Self::IntoIter {
+            (0..self.0)
+                .step_by(2)
+                .map(|top| (top, top + 1))
+                .collect::<Vec<_>>()
+                .into_iter()
+        }
+    }
+
+    impl NetworkConfiguration for Pairs {
+        fn from_width(width: Width) -> Self {
+            Pairs(width.get())
+        }
+    }
+
+    #[test]
+    fn bitonic_networks_count() {
+        for &width in &[1, 2, 4] {
+            for threads in 1..=MAX_THREADS {
+                let report = check::<BitonicConfiguration>(width, threads, 2).unwrap();
+
+                assert_eq!(report.assignments(), width.pow(threads as u32));
+            }
+        }
+    }
+
+    #[test]
+    fn bitonic_4_many_traversals() {
+        assert!(check::<BitonicConfiguration>(4, 2, MAX_TRAVERSALS_PER_THREAD).is_ok());
+    }
+
+    #[test]
+    fn pairs_are_not_a_counting_network() {
+        assert!(check::<Pairs>(2, 2, 2).is_ok());
+
+        let counterexample = check::<Pairs>(4, 2, 1).unwrap_err();
+
+        assert_eq!(counterexample.entry_wires(), &[2, 0]);
+        assert_eq!(counterexample.schedule(), &[0, 0]);
+        assert_eq!(counterexample.values(), &[2]);
+        assert_eq!(
+            counterexample.to_string(),
+            "threads entering on wires [2, 0] took values [2] with the schedule [0, 0]"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_wide() {
+        let _ = check::<BitonicConfiguration>(MAX_WIDTH * 2, 1, 1);
+    }
+}
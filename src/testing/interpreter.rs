@@ -0,0 +1,472 @@
+//! An interpreter for scripts of network operations, intended as the body of
+//! fuzz targets.
+//!
+//! Any sequence of bytes decodes into a valid script, which makes the
+//! interpreter a good fit for coverage guided fuzzers. Every operation is
+//! checked against the invariants of the structure it runs on, and the first
+//! broken invariant is returned as a [`Violation`]. Some operations should be
+//! rejected, such as building a network whose width is not a power of two, and
+//! those are checked to fail.
+//!
+//! For example a `cargo fuzz` target could look like:
+//!
+//! ```no_run
+//! use counting_networks::testing::interpreter;
+//!
+//! fn fuzz_target(data: &[u8]) {
+//!     if let Err(violation) = interpreter::run(data) {
+//!         panic!("{:?}: {}", interpreter::decode(data), violation);
+//!     }
+//! }
+//! ```
+
+use crate::{
+    counters::{
+        BitonicCountingNetwork, ConfigError, Counter, CounterConfig, CounterOptions,
+        ImportStateError,
+    },
+    networks::{AllocError, BitonicNetwork, IntegrityError},
+};
+use core::fmt;
+use std::{error::Error, sync::Arc, thread};
+
+/// The widest network that a script can build.
+pub const MAX_WIDTH: usize = 64;
+/// The most threads that a single [`Op::Count`] can spawn.
+pub const MAX_THREADS: usize = 4;
+/// The most values that each thread of a single [`Op::Count`] can take.
+pub const MAX_COUNTS_PER_THREAD: usize = 16;
+
+/// A single step of a script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Replace the current network and counter with new ones of the given
+    /// width, read through a [`CounterConfig`] and built with the fallible
+    /// constructors.
+    ///
+    /// Widths that are not a power of two should be rejected, leaving the
+    /// current network and counter in place.
+    Build {
+        /// Width of the new network and counter.
+        width: usize,
+    },
+    /// Traverse the network from the current thread, checking that the
+    /// outputs are visited in order.
+    Traverse {
+        /// Number of traversals to perform.
+        count: usize,
+    },
+    /// Take values from the counter on several threads at once, checking that
+    /// the values taken continue the sequence without duplicates or skips.
+    Count {
+        /// Number of threads to spawn.
+        threads: usize,
+        /// Number of values each thread takes.
+        per_thread: usize,
+    },
+    /// Check the structural integrity of the network.
+    Verify,
+    /// Restore the balancers of the network with
+    /// [`Network::reset`](crate::networks::Network::reset), restarting the
+    /// sequence of outputs. The counter is not changed.
+    Reset,
+    /// Save the state of the counter and restore it into a new counter of the
+    /// given width.
+    ///
+    /// If the width is the same as the counter's, the new counter replaces it
+    /// and continues its sequence. Otherwise restoring should be rejected.
+    Restore {
+        /// Width of the new counter.
+        width: usize,
+        /// Whether the new counter uses
+        /// [`padded_buckets`](CounterOptions::padded_buckets).
+        padded_buckets: bool,
+    },
+}
+
+/// A broken invariant found while running a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A sequential traversal reached the wrong output.
+    OutOfOrder {
+        /// The output that should have been reached.
+        expected: usize,
+        /// The output that was reached.
+        actual: usize,
+    },
+    /// The values taken by concurrent threads did not continue the counter's
+    /// sequence.
+    CountMismatch {
+        /// The first value that should have been taken.
+        start: usize,
+        /// The values that were taken, sorted.
+        values: Vec<usize>,
+    },
+    /// The network failed its integrity check.
+    Integrity(IntegrityError),
+    /// A network or counter of a supported width could not be allocated.
+    Alloc(AllocError),
+    /// A valid width was rejected by [`CounterConfig`].
+    Config(ConfigError),
+    /// A saved counter state could not be restored into a counter like the
+    /// one it was saved from.
+    Import(ImportStateError),
+    /// An operation that should have been rejected succeeded.
+    Accepted {
+        /// The operation.
+        op: Op,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::OutOfOrder { expected, actual } => write!(
+                f,
+                "traversal reached output {} instead of {}",
+                actual, expected
+            ),
+            Violation::CountMismatch { start, values } => write!(
+                f,
+                "counter values {:?} do not continue the sequence from {}",
+                values, start
+            ),
+            Violation::Integrity(error) => write!(f, "{}", error),
+            Violation::Alloc(error) => write!(f, "{}", error),
+            Violation::Config(error) => write!(f, "{}", error),
+            Violation::Import(error) => write!(f, "{}", error),
+            Violation::Accepted { op } => write!(f, "{:?} should have been rejected", op),
+        }
+    }
+}
+
+impl Error for Violation {}
+
+/// Decode a sequence of bytes into a script.
+///
+/// Every byte sequence decodes successfully; trailing bytes that are not
+/// enough to complete an operation are ignored. Scripts that use the network
+/// before building one start with a network of width 1.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::interpreter::{decode, Op};
+///
+/// let ops = decode(&[0, 4, 1, 7, 3]);
+///
+/// assert_eq!(ops, &[Op::Build { width: 4 }, Op::Traverse { count: 7 }, Op::Verify]);
+/// ```
+pub fn decode(script: &[u8]) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let mut bytes = script.iter().copied();
+
+    while let Some(opcode) = bytes.next() {
+        let op = match opcode % 6 {
+            0 => match bytes.next() {
+                Some(width) => Op::Build {
+                    width: width as usize % (MAX_WIDTH + 1),
+                },
+                None => break,
+            },
+            1 => match bytes.next() {
+                Some(count) => Op::Traverse {
+                    count: count as usize,
+                },
+                None => break,
+            },
+            2 => match (bytes.next(), bytes.next()) {
+                (Some(threads), Some(per_thread)) => Op::Count {
+                    threads: threads as usize % MAX_THREADS + 1,
+                    per_thread: per_thread as usize % MAX_COUNTS_PER_THREAD + 1,
+                },
+                _ => break,
+            },
+            3 => Op::Verify,
+            4 => Op::Reset,
+            _ => match bytes.next() {
+                Some(byte) => Op::Restore {
+                    width: 1 << ((byte >> 1) as usize % (MAX_WIDTH.trailing_zeros() as usize + 1)),
+                    padded_buckets: byte & 1 == 1,
+                },
+                None => break,
+            },
+        };
+
+        ops.push(op);
+    }
+
+    ops
+}
+
+/// Execute a decoded script, stopping at the first broken invariant.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::interpreter::{execute, Op};
+///
+/// let ops = [
+///     Op::Build { width: 8 },
+///     Op::Count {
+///         threads: 4,
+///         per_thread: 10,
+///     },
+///     Op::Traverse { count: 20 },
+///     Op::Verify,
+/// ];
+///
+/// assert_eq!(execute(&ops), Ok(()));
+///
+/// // Counting networks need a power of two width, so this is rejected
+/// assert_eq!(execute(&[Op::Build { width: 3 }]), Ok(()));
+/// ```
+pub fn execute(ops: &[Op]) -> Result<(), Violation> {
+    let mut state = State::try_new(1)?;
+
+    for op in ops {
+        match *op {
+            Op::Build { width } => {
+                let config = CounterConfig::from_lookup(|key| match key {
+                    "width" => Some(width.to_string()),
+                    _ => None,
+                });
+
+                match config {
+                    Ok(config) if width.is_power_of_two() => {
+                        state = State::try_new(config.width())?
+                    }
+                    Err(ConfigError::InvalidWidth { .. }) if !width.is_power_of_two() => {}
+                    Ok(_) => return Err(Violation::Accepted { op: *op }),
+                    Err(error) => return Err(Violation::Config(error)),
+                }
+            }
+            Op::Traverse { count } => state.traverse(count)?,
+            Op::Count {
+                threads,
+                per_thread,
+            } => state.count(threads, per_thread)?,
+            Op::Verify => state
+                .network
+                .verify_integrity()
+                .map_err(Violation::Integrity)?,
+            Op::Reset => {
+                state.network.reset();
+                state.traversed = 0;
+            }
+            Op::Restore {
+                width,
+                padded_buckets,
+            } => state.restore(width, padded_buckets)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode and execute a script.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::interpreter::run;
+///
+/// assert_eq!(run(b"fuzz me"), Ok(()));
+/// ```
+pub fn run(script: &[u8]) -> Result<(), Violation> {
+    execute(&decode(script))
+}
+
+struct State {
+    network: BitonicNetwork<usize>,
+    // Number of traversals since the network was built
+    traversed: usize,
+    counter: Arc<BitonicCountingNetwork>,
+    // Number of values taken since the counter was built
+    counted: usize,
+}
+
+impl State {
+    fn try_new(width: usize) -> Result<Self, Violation> {
+        Ok(State {
+            network: BitonicNetwork::try_new((0..width).collect()).map_err(Violation::Alloc)?,
+            traversed: 0,
+            counter: Arc::new(BitonicCountingNetwork::try_new(width).map_err(Violation::Alloc)?),
+            counted: 0,
+        })
+    }
+
+    // Sequential traversals satisfy the step property after every token, so the
+    // outputs are reached in round robin order.
+    fn traverse(&mut self, count: usize) -> Result<(), Violation> {
+        for _ in 0..count {
+            let expected = self.traversed % self.network.width();
+            let actual = *self.network.traverse();
+            if actual != expected {
+                return Err(Violation::OutOfOrder { expected, actual });
+            }
+
+            self.traversed += 1;
+        }
+
+        Ok(())
+    }
+
+    fn count(&mut self, threads: usize, per_thread: usize) -> Result<(), Violation> {
+        let thread_handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let counter = Arc::clone(&self.counter);
+                thread::spawn(move || (0..per_thread).map(|_| counter.next()).collect::<Vec<_>>())
+            })
+            .collect();
+
+        let mut values: Vec<usize> = thread_handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        values.sort();
+
+        let start = self.counted;
+        self.counted += threads * per_thread;
+
+        if values.iter().copied().eq(start..self.counted) {
+            Ok(())
+        } else {
+            Err(Violation::CountMismatch { start, values })
+        }
+    }
+
+    fn restore(&mut self, width: usize, padded_buckets: bool) -> Result<(), Violation> {
+        let saved = self.counter.export_state();
+        let mut restored = CounterOptions::new()
+            .width(width)
+            .padded_buckets(padded_buckets)
+            .try_build()
+            .map_err(Violation::Alloc)?;
+
+        match restored.import_state(&saved) {
+            Ok(()) if width == self.counter.width() => {
+                self.counter = Arc::new(restored);
+                Ok(())
+            }
+            Err(error) if width == self.counter.width() => Err(Violation::Import(error)),
+            Ok(()) => Err(Violation::Accepted {
+                op: Op::Restore {
+                    width,
+                    padded_buckets,
+                },
+            }),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_ignores_incomplete_ops() {
+        assert_eq!(decode(&[]), &[]);
+        assert_eq!(decode(&[0]), &[]);
+        assert_eq!(decode(&[3, 2, 1]), &[Op::Verify]);
+    }
+
+    #[test]
+    fn decode_limits_sizes() {
+        assert_eq!(
+            decode(&[6, 64, 8, 255, 255, 11, 13]),
+            &[
+                Op::Build { width: MAX_WIDTH },
+                Op::Count {
+                    threads: MAX_THREADS,
+                    per_thread: MAX_COUNTS_PER_THREAD
+                },
+                Op::Restore {
+                    width: 64,
+                    padded_buckets: true
+                }
+            ]
+        );
+        assert_eq!(decode(&[0, 255]), &[Op::Build { width: 60 }]);
+    }
+
+    #[test]
+    fn run_pseudo_random_scripts() {
+        // Simple xorshift generator, so the scripts are the same on every run.
+        let mut state: u32 = 0x2545_f491;
+        let mut next_byte = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+
+        for _ in 0..16 {
+            let script: Vec<u8> = (0..32).map(|_| next_byte()).collect();
+
+            assert_eq!(run(&script), Ok(()), "script: {:?}", decode(&script));
+        }
+    }
+
+    #[test]
+    fn reset_restarts_traversals() {
+        let ops = [
+            Op::Build { width: 4 },
+            Op::Traverse { count: 3 },
+            Op::Count {
+                threads: 2,
+                per_thread: 3,
+            },
+            Op::Reset,
+            Op::Traverse { count: 4 },
+            // The counter carries on from 6
+            Op::Count {
+                threads: 1,
+                per_thread: 2,
+            },
+        ];
+
+        assert_eq!(execute(&ops), Ok(()));
+    }
+
+    #[test]
+    fn build_rejects_other_widths() {
+        let ops = [
+            Op::Build { width: 4 },
+            Op::Traverse { count: 3 },
+            Op::Build { width: 0 },
+            Op::Build { width: 6 },
+            // The width 4 network is still in place
+            Op::Traverse { count: 1 },
+            Op::Verify,
+        ];
+
+        assert_eq!(execute(&ops), Ok(()));
+    }
+
+    #[test]
+    fn restore_continues_counting() {
+        let ops = [
+            Op::Build { width: 8 },
+            Op::Count {
+                threads: 2,
+                per_thread: 5,
+            },
+            Op::Restore {
+                width: 8,
+                padded_buckets: true,
+            },
+            Op::Restore {
+                width: 4,
+                padded_buckets: false,
+            },
+            Op::Count {
+                threads: 3,
+                per_thread: 4,
+            },
+        ];
+
+        assert_eq!(execute(&ops), Ok(()));
+    }
+}
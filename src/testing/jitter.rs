@@ -0,0 +1,156 @@
+//! Randomized delays injected into traversals, to widen the range of
+//! interleavings that stress tests exercise on real hardware.
+//!
+//! A run where every thread moves through the network at the same pace tends
+//! to repeat the same few interleavings. Pausing threads at random points, such
+//! as between toggling a balancer and moving to the next one, exposes orderings
+//! that clean runs never hit.
+//!
+//! Delays are configured for the whole process, and affect every network and
+//! counter in it, including those of other tests running at the same time.
+//! Tests that turn on delays are best kept in a test binary of their own. Only
+//! available with the `testing` feature, without it there are no injection
+//! points at all.
+//!
+//! # Examples
+//!
+//! ```
+//! use counting_networks::{
+//!     counters::{BitonicCountingNetwork, Counter},
+//!     testing::jitter::{self, InjectionPoint},
+//! };
+//! use std::time::Duration;
+//!
+//! jitter::inject_delays(InjectionPoint::AfterToggle, 4, Duration::from_micros(10));
+//!
+//! let counter = BitonicCountingNetwork::new(8);
+//! assert_eq!(counter.next(), 0);
+//!
+//! jitter::clear_delays();
+//! ```
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{thread, time::Duration};
+
+/// A point in a traversal where a delay can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InjectionPoint {
+    /// After a token toggles a balancer, before it moves on to the next
+    /// segment.
+    AfterToggle,
+    /// After a token leaves the network, before a counter takes a value from
+    /// the bucket on its wire.
+    BeforeIncrement,
+}
+
+impl InjectionPoint {
+    fn index(self) -> usize {
+        match self {
+            InjectionPoint::AfterToggle => 0,
+            InjectionPoint::BeforeIncrement => 1,
+        }
+    }
+}
+
+// Settings for each injection point, zero means no delays
+static ONE_IN: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+static MAX_DELAY_NANOS: [AtomicUsize; 2] = [AtomicUsize::new(0), AtomicUsize::new(0)];
+static INJECTED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // Simple xorshift generator, seeded differently on each thread
+    static RNG: Cell<u64> = Cell::new(crate::util::hash_single(thread::current().id()) | 1);
+}
+
+/// Pause threads that reach `point`, one time in `one_in` on average, for a
+/// random duration of up to `max_delay`.
+///
+/// A `max_delay` of zero yields to the scheduler instead of sleeping. A
+/// `one_in` of zero turns off delays at the point.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::jitter::{self, InjectionPoint};
+/// use std::time::Duration;
+///
+/// // Yield before every increment
+/// jitter::inject_delays(InjectionPoint::BeforeIncrement, 1, Duration::from_secs(0));
+/// # jitter::clear_delays();
+/// ```
+pub fn inject_delays(point: InjectionPoint, one_in: usize, max_delay: Duration) {
+    let max_delay_nanos = if max_delay.as_nanos() > usize::MAX as u128 {
+        usize::MAX
+    } else {
+        max_delay.as_nanos() as usize
+    };
+
+    MAX_DELAY_NANOS[point.index()].store(max_delay_nanos, Ordering::Relaxed);
+    ONE_IN[point.index()].store(one_in, Ordering::Relaxed);
+}
+
+/// Turn off delays at every injection point.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::jitter;
+///
+/// jitter::clear_delays();
+/// ```
+pub fn clear_delays() {
+    for one_in in ONE_IN.iter() {
+        one_in.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Returns the number of delays injected so far, across all points and
+/// threads.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::jitter;
+///
+/// let before = jitter::injected_delays();
+///
+/// assert!(jitter::injected_delays() >= before);
+/// ```
+pub fn injected_delays() -> usize {
+    INJECTED.load(Ordering::Relaxed)
+}
+
+// Called by traversals at each injection point.
+pub(crate) fn inject(point: InjectionPoint) {
+    let one_in = ONE_IN[point.index()].load(Ordering::Relaxed);
+    if one_in == 0 {
+        return;
+    }
+
+    let random = RNG.with(|rng| {
+        let mut state = rng.get();
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        rng.set(state);
+
+        state as usize
+    });
+    match random % one_in {
+        0 => {}
+        _ => return,
+    }
+
+    INJECTED.fetch_add(1, Ordering::Relaxed);
+    match MAX_DELAY_NANOS[point.index()].load(Ordering::Relaxed) {
+        0 => thread::yield_now(),
+        max_delay_nanos => {
+            // Use the bits not used by the check above
+            let nanos = (random >> 16) % max_delay_nanos.saturating_add(1);
+            thread::sleep(Duration::from_nanos(nanos as u64));
+        }
+    }
+}
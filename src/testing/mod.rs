@@ -0,0 +1,11 @@
+//! Utilities for testing the data structures in this crate, and code that is
+//! built on top of them.
+
+pub mod adversary;
+pub mod exhaustive;
+pub mod interpreter;
+#[cfg(feature = "testing")]
+pub mod jitter;
+mod soak;
+
+pub use self::soak::{soak, SoakConfig, SoakReport, SoakViolation};
@@ -0,0 +1,624 @@
+use crate::counters::Counter;
+use core::{
+    fmt, mem,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Only the first violations are kept, a broken counter can produce one for
+// every value it hands out.
+const MAX_RECORDED_VIOLATIONS: usize = 100;
+
+// Values recorded in each word of the bitmap
+const WORD_BITS: usize = mem::size_of::<usize>() * 8;
+
+/// Options for a [`soak`] run.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::testing::SoakConfig;
+/// use std::time::Duration;
+///
+/// let config = SoakConfig::new()
+///     .threads(8)
+///     .operations(1_000_000)
+///     .check_interval(Duration::from_millis(50))
+///     .max_skew(4096);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoakConfig {
+    threads: usize,
+    operations: usize,
+    check_interval: Duration,
+    max_skew: Option<usize>,
+}
+
+impl SoakConfig {
+    /// Create options with the defaults: 4 threads taking 100,000 values in
+    /// total, checked every 10 milliseconds, with no limit on skew.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::SoakConfig;
+    ///
+    /// assert_eq!(SoakConfig::new(), SoakConfig::default());
+    /// ```
+    pub fn new() -> Self {
+        SoakConfig {
+            threads: 4,
+            operations: 100_000,
+            check_interval: Duration::from_millis(10),
+            max_skew: None,
+        }
+    }
+
+    /// Set the number of threads that take values from the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::SoakConfig;
+    ///
+    /// let config = SoakConfig::new().threads(16);
+    /// ```
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Set the total number of values taken from the counter, across all
+    /// threads.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::SoakConfig;
+    ///
+    /// let config = SoakConfig::new().operations(10_000_000);
+    /// ```
+    pub fn operations(mut self, operations: usize) -> Self {
+        self.operations = operations;
+        self
+    }
+
+    /// Set how often the invariants are checked while the threads are running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::SoakConfig;
+    /// use std::time::Duration;
+    ///
+    /// let config = SoakConfig::new().check_interval(Duration::from_secs(1));
+    /// ```
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    /// Set the largest allowed skew, the distance between the largest value
+    /// taken and the smallest value not taken yet.
+    ///
+    /// A thread that is descheduled in the middle of a traversal holds back
+    /// the smallest value not taken, so the skew depends on the load of the
+    /// machine. By default it is only measured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::testing::SoakConfig;
+    ///
+    /// let config = SoakConfig::new().max_skew(1 << 16);
+    /// ```
+    pub fn max_skew(mut self, max_skew: usize) -> Self {
+        self.max_skew = Some(max_skew);
+        self
+    }
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        SoakConfig::new()
+    }
+}
+
+/// A broken invariant found during a [`soak`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoakViolation {
+    /// The value was returned more than once.
+    Duplicate {
+        /// The repeated value.
+        value: usize,
+    },
+    /// The value was not less than the number of operations, so some other
+    /// value must have been skipped.
+    OutOfRange {
+        /// The value returned.
+        value: usize,
+    },
+    /// The largest value taken was too far ahead of the smallest value not
+    /// taken yet.
+    Skew {
+        /// The smallest value not taken yet.
+        watermark: usize,
+        /// The largest value taken.
+        max_value: usize,
+    },
+    /// The value was never returned, even though every operation finished.
+    Missing {
+        /// The first value that was not returned.
+        value: usize,
+    },
+}
+
+impl fmt::Display for SoakViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SoakViolation::Duplicate { value } => write!(f, "value {} returned twice", value),
+            SoakViolation::OutOfRange { value } => write!(f, "value {} out of range", value),
+            SoakViolation::Skew {
+                watermark,
+                max_value,
+            } => write!(
+                f,
+                "value {} taken while value {} was still missing",
+                max_value, watermark
+            ),
+            SoakViolation::Missing { value } => write!(f, "value {} never returned", value),
+        }
+    }
+}
+
+/// The results of a [`soak`] run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoakReport {
+    operations: usize,
+    elapsed: Duration,
+    checks: usize,
+    max_skew: usize,
+    violations: Vec<SoakViolation>,
+    num_violations: usize,
+}
+
+impl SoakReport {
+    /// Returns true if no invariant was broken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::BitonicCountingNetwork,
+    ///     testing::{soak, SoakConfig},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// let counter = Arc::new(BitonicCountingNetwork::new(8));
+    /// let report = soak(counter, &SoakConfig::new().operations(1000));
+    ///
+    /// assert!(report.is_ok());
+    /// ```
+    pub fn is_ok(&self) -> bool {
+        self.num_violations == 0
+    }
+
+    /// Returns the number of values taken from the counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::BitonicCountingNetwork,
+    ///     testing::{soak, SoakConfig},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// let counter = Arc::new(BitonicCountingNetwork::new(8));
+    /// let report = soak(counter, &SoakConfig::new().operations(1000));
+    ///
+    /// assert_eq!(report.operations(), 1000);
+    /// ```
+    pub fn operations(&self) -> usize {
+        self.operations
+    }
+
+    /// Returns how long the threads took to take every value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::BitonicCountingNetwork,
+    ///     testing::{soak, SoakConfig},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// let counter = Arc::new(BitonicCountingNetwork::new(8));
+    /// let report = soak(counter, &SoakConfig::new().operations(1000));
+    ///
+    /// println!("{:?} per operation", report.elapsed() / 1000);
+    /// ```
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns the number of times the invariants were checked, including the
+    /// final check after every thread finished.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::BitonicCountingNetwork,
+    ///     testing::{soak, SoakConfig},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// let counter = Arc::new(BitonicCountingNetwork::new(8));
+    /// let report = soak(counter, &SoakConfig::new().operations(1000));
+    ///
+    /// assert!(report.checks() >= 1);
+    /// ```
+    pub fn checks(&self) -> usize {
+        self.checks
+    }
+
+    /// Returns the largest skew seen by any check, see
+    /// [`SoakConfig::max_skew`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::BitonicCountingNetwork,
+    ///     testing::{soak, SoakConfig},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// let counter = Arc::new(BitonicCountingNetwork::new(8));
+    /// let report = soak(counter, &SoakConfig::new().operations(1000));
+    ///
+    /// assert!(report.max_skew() < 1000);
+    /// ```
+    pub fn max_skew(&self) -> usize {
+        self.max_skew
+    }
+
+    /// Returns the first violations found, in the order they were found.
+    ///
+    /// At most 100 violations are kept, see
+    /// [`num_violations`](SoakReport::num_violations) for the total.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::Counter,
+    ///     testing::{soak, SoakConfig, SoakViolation},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// struct Constant;
+    ///
+    /// impl Counter for Constant {
+    ///     fn next(&self) -> usize {
+    ///         0
+    ///     }
+    /// }
+    ///
+    /// let report = soak(Arc::new(Constant), &SoakConfig::new().operations(10));
+    ///
+    /// assert!(!report.is_ok());
+    /// assert!(report
+    ///     .violations()
+    ///     .contains(&SoakViolation::Duplicate { value: 0 }));
+    /// ```
+    pub fn violations(&self) -> &[SoakViolation] {
+        &self.violations
+    }
+
+    /// Returns the total number of violations found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::{
+    ///     counters::BitonicCountingNetwork,
+    ///     testing::{soak, SoakConfig},
+    /// };
+    /// use std::sync::Arc;
+    ///
+    /// let counter = Arc::new(BitonicCountingNetwork::new(8));
+    /// let report = soak(counter, &SoakConfig::new().operations(1000));
+    ///
+    /// assert_eq!(report.num_violations(), 0);
+    /// ```
+    pub fn num_violations(&self) -> usize {
+        self.num_violations
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} operations in {:?}, {} checks, max skew {}, {} violations",
+            self.operations, self.elapsed, self.checks, self.max_skew, self.num_violations
+        )?;
+        for violation in &self.violations {
+            write!(f, "\n  {}", violation)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run a mixed workload against a counter on several threads, checking its
+/// invariants while it runs, and report what was found.
+///
+/// The counter must be new, and hand out `0, 1, 2, ...`. Each thread takes
+/// values in bursts of varying length, yielding between bursts so that
+/// traversals from different threads overlap in different ways. Every value is
+/// recorded in a bitmap as it is taken, which finds any value returned twice.
+/// Meanwhile, if [`SoakConfig::max_skew`] is set, the largest value taken is
+/// periodically checked to not be too far ahead of the smallest value not
+/// taken yet, the watermark.
+///
+/// Once every thread has finished, every value up to the number of operations
+/// must have been taken exactly once.
+///
+/// This is meant for burning in a counter on the hardware it will be deployed
+/// to, with many more operations than the unit tests use.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{
+///     counters::BitonicCountingNetwork,
+///     testing::{soak, SoakConfig},
+/// };
+/// use std::sync::Arc;
+///
+/// let counter = Arc::new(BitonicCountingNetwork::new(16));
+/// let report = soak(counter, &SoakConfig::new().threads(4).operations(10_000));
+///
+/// assert!(report.is_ok(), "{}", report);
+/// ```
+pub fn soak<C>(counter: Arc<C>, config: &SoakConfig) -> SoakReport
+where
+    C: Counter + Send + Sync + 'static,
+{
+    let state = Arc::new(SoakState {
+        taken: (0..=config.operations / WORD_BITS)
+            .map(|_| AtomicUsize::new(0))
+            .collect(),
+        started: AtomicUsize::new(0),
+        max_value: AtomicUsize::new(0),
+        violations: Mutex::new(Violations::default()),
+    });
+    let operations = config.operations;
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..config.threads)
+        .map(|thread_idx| {
+            let counter = Arc::clone(&counter);
+            let state = Arc::clone(&state);
+            thread::spawn(move || state.work(&*counter, operations, thread_idx))
+        })
+        .collect();
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let checker = {
+        let state = Arc::clone(&state);
+        let finished = Arc::clone(&finished);
+        let check_interval = config.check_interval;
+        let max_skew = config.max_skew;
+        thread::spawn(move || {
+            let mut checker = Checker::default();
+            while !finished.load(Ordering::Acquire) {
+                thread::sleep(check_interval);
+                checker.check(&state, max_skew);
+            }
+            checker
+        })
+    };
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+    finished.store(true, Ordering::Release);
+    let mut checker = checker.join().unwrap();
+
+    // Every thread has finished, so every value should have been taken.
+    checker.check(&state, config.max_skew);
+    if checker.watermark < operations {
+        state.violate(SoakViolation::Missing {
+            value: checker.watermark,
+        });
+    }
+
+    let violations = state.violations.lock().unwrap();
+    SoakReport {
+        operations: state.started.load(Ordering::SeqCst).min(operations),
+        elapsed,
+        checks: checker.checks,
+        max_skew: checker.max_skew,
+        violations: violations.recorded.clone(),
+        num_violations: violations.total,
+    }
+}
+
+#[derive(Default)]
+struct Violations {
+    recorded: Vec<SoakViolation>,
+    total: usize,
+}
+
+struct SoakState {
+    // Bit `i` is set once value `i` has been taken
+    taken: Box<[AtomicUsize]>,
+    // Number of operations started by all threads
+    started: AtomicUsize,
+    max_value: AtomicUsize,
+    violations: Mutex<Violations>,
+}
+
+impl SoakState {
+    fn work<C: Counter + ?Sized>(&self, counter: &C, operations: usize, thread_idx: usize) {
+        // Simple xorshift generator for the burst lengths, seeded differently
+        // on each thread.
+        let mut rng = 0x2545_f491_4f6c_dd1d_u64 ^ (thread_idx as u64 + 1);
+
+        loop {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+
+            for _ in 0..(rng % 64 + 1) {
+                if self.started.fetch_add(1, Ordering::Relaxed) >= operations {
+                    return;
+                }
+
+                self.record(counter.next(), operations);
+            }
+            thread::yield_now();
+        }
+    }
+
+    fn record(&self, value: usize, operations: usize) {
+        if value >= operations {
+            self.violate(SoakViolation::OutOfRange { value });
+            return;
+        }
+
+        let bit = 1 << (value % WORD_BITS);
+        if self.taken[value / WORD_BITS].fetch_or(bit, Ordering::SeqCst) & bit != 0 {
+            self.violate(SoakViolation::Duplicate { value });
+        }
+        self.max_value.fetch_max(value, Ordering::SeqCst);
+    }
+
+    fn violate(&self, violation: SoakViolation) {
+        let mut violations = self.violations.lock().unwrap();
+        if violations.recorded.len() < MAX_RECORDED_VIOLATIONS {
+            violations.recorded.push(violation);
+        }
+        violations.total += 1;
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    // Smallest value not taken yet, all the words before
+    // `watermark / WORD_BITS` are full.
+    watermark: usize,
+    checks: usize,
+    max_skew: usize,
+}
+
+impl Checker {
+    fn check(&mut self, state: &SoakState, max_skew: Option<usize>) {
+        self.checks += 1;
+
+        // Read the largest value first, so that it is never older than the
+        // watermark and the skew is not underestimated.
+        let max_value = state.max_value.load(Ordering::SeqCst);
+
+        let mut word_idx = self.watermark / WORD_BITS;
+        while word_idx < state.taken.len()
+            && state.taken[word_idx].load(Ordering::SeqCst) == usize::MAX
+        {
+            word_idx += 1;
+        }
+        let watermark = match state.taken.get(word_idx) {
+            Some(word) => {
+                word_idx * WORD_BITS + (!word.load(Ordering::SeqCst)).trailing_zeros() as usize
+            }
+            None => word_idx * WORD_BITS,
+        };
+
+        // Bits are never cleared, so the watermark can only move forwards.
+        self.watermark = watermark;
+
+        if max_value >= watermark {
+            let skew = max_value - watermark;
+            self.max_skew = self.max_skew.max(skew);
+            match max_skew {
+                Some(max_skew) if skew > max_skew => state.violate(SoakViolation::Skew {
+                    watermark,
+                    max_value,
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::BitonicCountingNetwork;
+
+    struct Doubling(AtomicUsize);
+
+    // Skips every odd value
+    impl Counter for Doubling {
+        fn next(&self) -> usize {
+            self.0.fetch_add(2, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn soak_bitonic_counter() {
+        let counter = Arc::new(BitonicCountingNetwork::new(8));
+        let config = SoakConfig::new()
+            .threads(4)
+            .operations(50_000)
+            .check_interval(Duration::from_millis(1));
+
+        let report = soak(counter, &config);
+
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.operations(), 50_000);
+        assert!(report.checks() >= 1);
+    }
+
+    #[test]
+    fn soak_finds_skipped_values() {
+        let counter = Arc::new(Doubling(AtomicUsize::new(0)));
+
+        let report = soak(counter, &SoakConfig::new().operations(100));
+
+        assert!(!report.is_ok());
+        // Half the values are out of range, and the first odd value is missing
+        assert_eq!(report.num_violations(), 51);
+        assert!(report
+            .violations()
+            .contains(&SoakViolation::Missing { value: 1 }));
+        assert!(report
+            .violations()
+            .contains(&SoakViolation::OutOfRange { value: 100 }));
+    }
+
+    #[test]
+    fn soak_reports_skew() {
+        let counter = Arc::new(Doubling(AtomicUsize::new(0)));
+
+        let report = soak(
+            counter,
+            &SoakConfig::new().threads(1).operations(10).max_skew(0),
+        );
+
+        assert!(report.violations().contains(&SoakViolation::Skew {
+            watermark: 1,
+            max_value: 8
+        }));
+        assert_eq!(report.max_skew(), 7);
+    }
+}
@@ -0,0 +1,373 @@
+//! Single-threaded counting networks, with balancers and counters stored in
+//! [`Cell`]s instead of atomics.
+//!
+//! The types here mirror [`networks::Network`] and
+//! [`counters::BitonicCountingNetwork`], and produce the same outputs in the
+//! same order when used from a single thread. They can't be shared between
+//! threads, but they avoid the cost of atomic operations and cache line
+//! padding, which is pure overhead in simulations and deterministic tests.
+//!
+//! The wiring is built independently of the atomic networks, so the two can
+//! also be compared against each other in differential tests.
+//!
+//! # Examples
+//!
+//! ```
+//! use counting_networks::{counters::Counter, unsync::BitonicCountingNetwork};
+//!
+//! let counter = BitonicCountingNetwork::new(4);
+//!
+//! assert_eq!(counter.next(), 0);
+//! assert_eq!(counter.next(), 1);
+//! assert_eq!(counter.next(), 2);
+//! ```
+//!
+//! [`networks::Network`]: crate::networks::Network
+//! [`counters::BitonicCountingNetwork`]: crate::counters::BitonicCountingNetwork
+
+use crate::{
+    counters::Counter,
+    networks::{check_balancer, BitonicConfiguration, NetworkConfiguration, Width},
+    util::hash_single,
+};
+use core::{cell::Cell, hash::Hash, marker::PhantomData};
+use std::thread;
+
+// Where a wire leads after a balancer, or where a token enters the network.
+#[derive(Debug, Clone, Copy)]
+enum Link {
+    // Index into the balancers of the network
+    Balancer(usize),
+    // Index into the outputs of the network
+    End(usize),
+}
+
+#[derive(Debug)]
+struct Balancer {
+    toggle: Cell<bool>,
+    // The first token through the balancer takes `next[1]`
+    next: [Link; 2],
+}
+
+/// A single-threaded balancing network with outputs of type `L`, with the
+/// layout of balancers described by the configuration `B`.
+///
+/// This is the single-threaded twin of [`networks::Network`], see its
+/// documentation for details.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::unsync::BitonicNetwork;
+///
+/// let network = BitonicNetwork::new(vec!['a', 'b']);
+///
+/// assert_eq!(network.traverse(), &'a');
+/// assert_eq!(network.traverse(), &'b');
+/// ```
+///
+/// [`networks::Network`]: crate::networks::Network
+#[derive(Debug)]
+pub struct Network<L, B> {
+    _marker: PhantomData<fn() -> B>,
+    outputs: Box<[L]>,
+    // Balancers closest to the outputs first
+    balancers: Box<[Balancer]>,
+    // Where tokens entering on each wire start
+    entries: Box<[Link]>,
+}
+
+/// A single-threaded network with the bitonic layout of balancers.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::unsync::BitonicNetwork;
+///
+/// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+///
+/// assert_eq!(network.width(), 4);
+/// ```
+pub type BitonicNetwork<L> = Network<L, BitonicConfiguration>;
+
+impl<L, B: NetworkConfiguration> Network<L, B> {
+    /// Create a new network with the given outputs, and balancers laid out by
+    /// the configuration for a network of the same width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of outputs is not a power of two, if the
+    /// configuration does not support that width, or if it yields a balancer
+    /// that connects a wire to itself or uses a wire outside the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![(); 8]);
+    ///
+    /// assert_eq!(network.width(), 8);
+    /// ```
+    pub fn new(outputs: Vec<L>) -> Self {
+        let width = outputs.len();
+        let mut configured: Vec<(usize, usize)> =
+            B::from_width(Width::of(width)).into_iter().collect();
+        for (idx, &balancer) in configured.iter().enumerate() {
+            if let Err(error) = check_balancer(width, idx, balancer) {
+                panic!("invalid network configuration: {}", error);
+            }
+        }
+        // Link from the outputs backwards, like the atomic networks
+        configured.reverse();
+
+        let mut entries: Vec<Link> = (0..width).map(Link::End).collect();
+        let balancers = configured
+            .into_iter()
+            .enumerate()
+            .map(|(balancer_idx, (top_wire, bottom_wire))| {
+                let balancer = Balancer {
+                    toggle: Cell::new(true),
+                    next: [entries[bottom_wire], entries[top_wire]],
+                };
+                entries[top_wire] = Link::Balancer(balancer_idx);
+                entries[bottom_wire] = Link::Balancer(balancer_idx);
+
+                balancer
+            })
+            .collect();
+
+        Network {
+            _marker: PhantomData,
+            outputs: outputs.into_boxed_slice(),
+            balancers,
+            entries: entries.into_boxed_slice(),
+        }
+    }
+}
+
+impl<L, B> Network<L, B> {
+    /// Returns the width of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2]);
+    ///
+    /// assert_eq!(network.width(), 2);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Traverse the network and obtain a reference to an output element.
+    ///
+    /// Tokens enter on the same wire as they would for
+    /// [`Network::traverse`](crate::networks::Network::traverse) on the same
+    /// thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// assert_eq!(network.traverse(), &2);
+    /// assert_eq!(network.traverse(), &3);
+    /// assert_eq!(network.traverse(), &4);
+    /// ```
+    pub fn traverse(&self) -> &L {
+        self.traverse_from(hash_single(thread::current().id()))
+    }
+
+    /// Traverse the network and obtain a reference to an output element,
+    /// entering on a wire chosen from `key`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.traverse_keyed(&7u64), &1);
+    /// assert_eq!(network.traverse_keyed("seven"), &2);
+    /// ```
+    pub fn traverse_keyed<K: Hash + ?Sized>(&self, key: &K) -> &L {
+        self.traverse_from(hash_single(key))
+    }
+
+    fn traverse_from(&self, entry_hash: u64) -> &L {
+        let mut link = self.entries[(entry_hash as usize) % self.width()];
+
+        loop {
+            match link {
+                Link::Balancer(balancer_idx) => {
+                    let balancer = &self.balancers[balancer_idx];
+                    let up = balancer.toggle.get();
+                    balancer.toggle.set(!up);
+
+                    link = balancer.next[up as usize];
+                }
+                Link::End(output_idx) => return &self.outputs[output_idx],
+            }
+        }
+    }
+
+    /// Restore every balancer to the state it was in when the network was
+    /// constructed. The outputs are not changed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicNetwork;
+    ///
+    /// let mut network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    /// network.traverse();
+    ///
+    /// network.reset();
+    ///
+    /// assert_eq!(network.traverse(), &1);
+    /// ```
+    pub fn reset(&mut self) {
+        for balancer in self.balancers.iter() {
+            balancer.toggle.set(true);
+        }
+    }
+
+    /// Get references to all the outputs of the network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicNetwork;
+    ///
+    /// let network = BitonicNetwork::new(vec![1, 2, 3, 4]);
+    ///
+    /// assert_eq!(network.outputs(), &[1, 2, 3, 4]);
+    /// ```
+    pub fn outputs(&self) -> &[L] {
+        &self.outputs
+    }
+}
+
+/// A single-threaded counter built from a bitonic network.
+///
+/// This is the single-threaded twin of
+/// [`counters::BitonicCountingNetwork`](crate::counters::BitonicCountingNetwork),
+/// and outputs the same sequence.
+///
+/// # Examples
+///
+/// ```
+/// use counting_networks::{counters::Counter, unsync::BitonicCountingNetwork};
+///
+/// let counter = BitonicCountingNetwork::new(8);
+///
+/// let values: Vec<_> = (0..10).map(|_| counter.next()).collect();
+/// assert_eq!(values, (0..10).collect::<Vec<_>>());
+/// ```
+#[derive(Debug)]
+pub struct BitonicCountingNetwork(BitonicNetwork<Cell<usize>>);
+
+impl BitonicCountingNetwork {
+    /// Create a new counter with the specified width.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the width is not a power of two.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(4);
+    ///
+    /// assert_eq!(counter.width(), 4);
+    /// ```
+    pub fn new(width: usize) -> Self {
+        let outputs = (0..width).map(Cell::new).collect();
+
+        BitonicCountingNetwork(BitonicNetwork::new(outputs))
+    }
+
+    /// Returns the output width of the internal bitonic network.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use counting_networks::unsync::BitonicCountingNetwork;
+    ///
+    /// let counter = BitonicCountingNetwork::new(16);
+    ///
+    /// assert_eq!(counter.width(), 16);
+    /// ```
+    pub fn width(&self) -> usize {
+        self.0.width()
+    }
+}
+
+impl Counter for BitonicCountingNetwork {
+    fn next(&self) -> usize {
+        let bucket = self.0.traverse();
+        let value = bucket.get();
+        bucket.set(value.wrapping_add(self.width()));
+
+        value
+    }
+
+    fn width(&self) -> usize {
+        self.width()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{counters, networks};
+
+    #[test]
+    fn same_traversals_as_atomic_network() {
+        for &width in &[1, 2, 4, 8, 16] {
+            let atomic = networks::BitonicNetwork::new((0..width).collect());
+            let unsync = BitonicNetwork::new((0..width).collect());
+
+            for key in 0..(4 * width) {
+                assert_eq!(atomic.traverse(), unsync.traverse());
+                assert_eq!(atomic.traverse_keyed(&key), unsync.traverse_keyed(&key));
+            }
+        }
+    }
+
+    #[test]
+    fn same_values_as_atomic_counter() {
+        let atomic = counters::BitonicCountingNetwork::new(8);
+        let unsync = BitonicCountingNetwork::new(8);
+
+        for _ in 0..100 {
+            assert_eq!(atomic.next(), unsync.next());
+        }
+    }
+
+    #[test]
+    fn reset_repeats_traversals() {
+        let mut network = BitonicNetwork::new((0..8).collect());
+        let first: Vec<_> = (0..5).map(|key| *network.traverse_keyed(&key)).collect();
+
+        network.reset();
+
+        let second: Vec<_> = (0..5).map(|key| *network.traverse_keyed(&key)).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    #[should_panic]
+    fn width_not_power_of_two() {
+        let _ = BitonicCountingNetwork::new(6);
+    }
+}
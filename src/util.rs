@@ -1,8 +1,14 @@
+use crate::networks::AllocError;
 use core::{
     hash::{Hash, Hasher},
-    ops::Range,
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
 };
-use std::collections::hash_map::DefaultHasher;
+use std::{alloc, collections::hash_map::DefaultHasher};
+
+#[cfg(all(test, feature = "serde"))]
+pub mod value;
 
 pub fn hash_single<T>(value: T) -> u64
 where
@@ -15,115 +21,162 @@ where
     hasher.finish()
 }
 
-// TODO: remove and use `slice.as_ptr_range` when it becomes stable.
-pub fn slice_to_ptr_range<T>(slice: &[T]) -> Range<*const T> {
-    // The `add` here is safe, because:
-    //
-    //   - Both pointers are part of the same object, as pointing directly past the
-    //     object also counts.
-    //
-    //   - The size of the slice is never larger than isize::MAX bytes, as noted
-    //     here:
-    //       - https://github.com/rust-lang/unsafe-code-guidelines/issues/102#issuecomment-473340447
-    //       - https://doc.rust-lang.org/reference/behavior-considered-undefined.html
-    //       - https://doc.rust-lang.org/core/slice/fn.from_raw_parts.html#safety
-    //     (This doesn't seem normative yet, but the very same assumption is
-    //     made in many places, including the Index implementation of slices.)
-    //
-    //   - There is no wrapping around involved, as slices do not wrap past the end
-    //     of the address space.
-    //
-    // See the documentation of pointer::add.
-    let start = slice.as_ptr();
-    let end = unsafe { start.add(slice.len()) };
-    start..end
+// Create an empty vector with space for `capacity` elements, returning an error
+// instead of aborting if the memory can't be allocated. `Vec::try_reserve` is
+// newer than the minimum supported Rust version.
+pub fn try_vec_with_capacity<T>(capacity: usize) -> Result<Vec<T>, AllocError> {
+    let layout = alloc::Layout::array::<T>(capacity).map_err(|_| AllocError::overflow())?;
+    if layout.size() == 0 {
+        return Ok(Vec::new());
+    }
+
+    // The allocation is made with the global allocator and the layout of an
+    // array of `capacity` elements, which is what `Vec` expects.
+    let ptr = unsafe { alloc::alloc(layout) } as *mut T;
+    if ptr.is_null() {
+        return Err(AllocError::new(layout.size()));
+    }
+
+    Ok(unsafe { Vec::from_raw_parts(ptr, 0, capacity) })
 }
 
-// TODO: remove #[allow(dead_code)]
+// A value on the heap that is built the first time it is needed, through a
+// shared reference. `OnceLock` is newer than the minimum supported Rust
+// version, and this can be built in a `const` context for use in statics.
+//
+// If several threads need the value for the first time at once, each of them
+// may build one, but only one is kept and the others are dropped before they
+// are shared.
+pub struct LazyBox<T> {
+    // Null until the value is built, never changes after that
+    value: AtomicPtr<T>,
+    // Owns a `T`, but is not `Send` or `Sync` on its own, see below
+    _marker: PhantomData<*const T>,
+}
 
-#[allow(dead_code)]
-pub const E_COCHAIN: [usize; 1] = [0b0];
-#[allow(dead_code)]
-pub const O_COCHAIN: [usize; 1] = [0b1];
-#[allow(dead_code)]
-pub const A_COCHAIN: [usize; 2] = [0b00, 0b11];
-#[allow(dead_code)]
-pub const B_COCHAIN: [usize; 2] = [0b01, 0b10];
+impl<T> LazyBox<T> {
+    pub const fn new() -> Self {
+        LazyBox {
+            value: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
 
-#[allow(dead_code)]
-pub fn generate_cochain(range: Range<usize>, prefixes: &[usize]) -> Vec<usize> {
-    let mask = (1 << prefixes.len()) - 1;
+    // Returns the value if it has been built.
+    pub fn get(&self) -> Option<&T> {
+        // The pointer came from `Box::into_raw` and is only freed when the
+        // `LazyBox` is dropped.
+        unsafe { self.value.load(Ordering::Acquire).as_ref() }
+    }
 
-    let mut output = Vec::new();
+    pub fn get_or_init<F: FnOnce() -> T>(&self, init: F) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
 
-    for idx in range {
-        for &prefix in prefixes {
-            if (idx & mask) == prefix {
-                output.push(idx);
+        let built = Box::into_raw(Box::new(init()));
+        let value = match self.value.compare_exchange(
+            ptr::null_mut(),
+            built,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => built,
+            Err(existing) => {
+                // Another thread built the value first, and this one was never
+                // shared.
+                drop(unsafe { Box::from_raw(built) });
+                existing
             }
-        }
+        };
+
+        unsafe { &*value }
     }
+}
+
+impl<T> Drop for LazyBox<T> {
+    fn drop(&mut self) {
+        let value = *self.value.get_mut();
 
-    output
+        if !value.is_null() {
+            drop(unsafe { Box::from_raw(value) });
+        }
+    }
 }
 
+// Like `OnceLock`, the value may be built on one thread and dropped on
+// another, so sharing also needs `T: Send`.
+unsafe impl<T: Send> Send for LazyBox<T> {}
+unsafe impl<T: Send + Sync> Sync for LazyBox<T> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::sync::atomic::AtomicUsize;
+    use std::{sync::Arc, thread};
 
     #[test]
-    fn check_even_cochains() {
-        let chain_1 = generate_cochain(0..20, &E_COCHAIN);
-        assert!(chain_1.iter().all(|&x| x % 2 == 0));
-        assert_eq!(chain_1.len(), 10);
-
-        let chain_2 = generate_cochain(0..1, &E_COCHAIN);
-        assert!(chain_2.iter().all(|&x| x % 2 == 0));
-        assert_eq!(chain_2.len(), 1);
-
-        let chain_3 = generate_cochain(0..13, &E_COCHAIN);
-        assert!(chain_3.iter().all(|&x| x % 2 == 0));
-        assert_eq!(chain_3.len(), 7);
+    fn try_vec_with_capacity_succeeds() {
+        let mut values = try_vec_with_capacity::<u32>(10).unwrap();
+        values.extend(0..10);
+
+        assert_eq!(values.capacity(), 10);
+        assert_eq!(values, (0..10).collect::<Vec<_>>());
+        assert_eq!(try_vec_with_capacity::<()>(10).unwrap().len(), 0);
     }
 
     #[test]
-    fn check_odd_cochain() {
-        let chain_1 = generate_cochain(0..20, &O_COCHAIN);
-        assert!(chain_1.iter().all(|&x| x % 2 != 0));
-        assert_eq!(chain_1.len(), 10);
-
-        let chain_2 = generate_cochain(0..1, &O_COCHAIN);
-        assert!(chain_2.iter().all(|&x| x % 2 != 0));
-        assert_eq!(chain_2.len(), 0);
-
-        let chain_3 = generate_cochain(0..13, &O_COCHAIN);
-        assert!(chain_3.iter().all(|&x| x % 2 != 0));
-        assert_eq!(chain_3.len(), 6);
+    fn try_vec_with_capacity_overflow() {
+        assert_eq!(
+            try_vec_with_capacity::<u64>(usize::MAX).unwrap_err(),
+            AllocError::overflow()
+        );
     }
 
     #[test]
-    #[allow(non_snake_case)]
-    fn check_A_cochain() {
-        let chain_1 = generate_cochain(0..20, &A_COCHAIN);
-        assert_eq!(chain_1, &[0, 3, 4, 7, 8, 11, 12, 15, 16, 19]);
-
-        let chain_1 = generate_cochain(0..1, &A_COCHAIN);
-        assert_eq!(chain_1, &[0]);
-
-        let chain_1 = generate_cochain(0..14, &A_COCHAIN);
-        assert_eq!(chain_1, &[0, 3, 4, 7, 8, 11, 12]);
+    #[cfg_attr(miri, ignore)]
+    fn try_vec_with_capacity_too_large() {
+        // Too large for any address space, but small enough for a layout
+        let bytes = isize::MAX as usize - 63;
+
+        assert_eq!(
+            try_vec_with_capacity::<u8>(bytes)
+                .unwrap_err()
+                .requested_bytes(),
+            Some(bytes)
+        );
     }
 
     #[test]
-    #[allow(non_snake_case)]
-    fn check_B_cochain() {
-        let chain_1 = generate_cochain(0..20, &B_COCHAIN);
-        assert_eq!(chain_1, &[1, 2, 5, 6, 9, 10, 13, 14, 17, 18]);
+    fn lazy_box_builds_once() {
+        let lazy = LazyBox::new();
+        assert_eq!(lazy.get(), None);
 
-        let chain_1 = generate_cochain(0..1, &B_COCHAIN);
-        assert_eq!(chain_1, &[]);
+        assert_eq!(lazy.get_or_init(|| 3), &3);
+        assert_eq!(lazy.get_or_init(|| 4), &3);
+        assert_eq!(lazy.get(), Some(&3));
+    }
 
-        let chain_1 = generate_cochain(0..14, &B_COCHAIN);
-        assert_eq!(chain_1, &[1, 2, 5, 6, 9, 10, 13]);
+    #[test]
+    fn concurrent_lazy_box_keeps_one_value() {
+        static BUILT: AtomicUsize = AtomicUsize::new(0);
+        let lazy = Arc::new(LazyBox::new());
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let lazy = Arc::clone(&lazy);
+                thread::spawn(move || {
+                    lazy.get_or_init(|| BUILT.fetch_add(1, Ordering::SeqCst)) as *const usize
+                        as usize
+                })
+            })
+            .collect();
+
+        let addresses: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert!(BUILT.load(Ordering::SeqCst) >= 1);
+        assert!(addresses.iter().all(|&address| address == addresses[0]));
     }
 }
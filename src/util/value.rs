@@ -0,0 +1,294 @@
+// A small self-describing value for testing serde support, so that the tests
+// don't need a data format crate. Only the parts of the data model that the
+// types in this crate use are supported.
+
+use serde::{
+    de::{
+        self,
+        value::{Error, MapDeserializer, SeqDeserializer},
+        IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::{self, Impossible, Serialize},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Unsigned(u64),
+    Str(String),
+    Seq(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    // Build a map from its entries.
+    pub fn map<'a, I>(entries: I) -> Value
+    where
+        I: IntoIterator<Item = (&'a str, Value)>,
+    {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn str(value: &str) -> Value {
+        Value::Str(value.to_string())
+    }
+}
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+pub fn from_value<'de, T: de::Deserialize<'de>>(value: Value) -> Result<T, Error> {
+    T::deserialize(value)
+}
+
+struct ValueSerializer;
+
+fn unsupported<T>(kind: &str) -> Result<T, Error> {
+    Err(ser::Error::custom(format_args!(
+        "{} is not supported",
+        kind
+    )))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Error = Error;
+    type Ok = Value;
+    type SerializeMap = Impossible<Value, Error>;
+    type SerializeSeq = SeqSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = Impossible<Value, Error>;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+
+    fn serialize_bool(self, _: bool) -> Result<Value, Error> {
+        unsupported("bool")
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<Value, Error> {
+        unsupported("i8")
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<Value, Error> {
+        unsupported("i16")
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<Value, Error> {
+        unsupported("i32")
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<Value, Error> {
+        unsupported("i64")
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Value, Error> {
+        self.serialize_u64(u64::from(value))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Value, Error> {
+        self.serialize_u64(u64::from(value))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Value, Error> {
+        self.serialize_u64(u64::from(value))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Value, Error> {
+        Ok(Value::Unsigned(value))
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Value, Error> {
+        unsupported("f32")
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Value, Error> {
+        unsupported("f64")
+    }
+
+    fn serialize_char(self, value: char) -> Result<Value, Error> {
+        Ok(Value::Str(value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Value, Error> {
+        Ok(Value::str(value))
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Value, Error> {
+        unsupported("bytes")
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        unsupported("none")
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _: &T) -> Result<Value, Error> {
+        unsupported("some")
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        unsupported("unit")
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Value, Error> {
+        unsupported("unit struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::str(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Value, Error> {
+        unsupported("newtype variant")
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        unsupported("tuple struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        unsupported("tuple variant")
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        unsupported("map")
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<StructSerializer, Error> {
+        Ok(StructSerializer(Vec::with_capacity(len)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        unsupported("struct variant")
+    }
+}
+
+struct SeqSerializer(Vec<Value>);
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Error = Error;
+    type Ok = Value;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Seq(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Error = Error;
+    type Ok = Value;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct StructSerializer(Vec<(String, Value)>);
+
+impl ser::SerializeStruct for StructSerializer {
+    type Error = Error;
+    type Ok = Value;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.0.push((key.to_string(), to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Map(self.0))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+
+    // There is no value for `None`, so a value that is present is always
+    // `Some`.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Unsigned(value) => visitor.visit_u64(value),
+            Value::Str(value) => visitor.visit_string(value),
+            Value::Seq(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Value::Map(entries) => visitor.visit_map(MapDeserializer::new(entries.into_iter())),
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "testing")]
+
+// Delays are set for the whole process, so this runs in its own test binary
+// rather than next to the unit tests.
+
+use counting_networks::{
+    counters::{BitonicCountingNetwork, Counter},
+    testing::jitter::{self, InjectionPoint},
+};
+use std::{sync::Arc, thread, time::Duration};
+
+#[test]
+fn counting_with_delays() {
+    const NUM_THREADS: usize = 4;
+    const NUM_COUNTS: usize = 100;
+
+    jitter::inject_delays(InjectionPoint::AfterToggle, 3, Duration::from_micros(20));
+    jitter::inject_delays(InjectionPoint::BeforeIncrement, 2, Duration::from_secs(0));
+    let before = jitter::injected_delays();
+
+    let counter = Arc::new(BitonicCountingNetwork::new(8));
+    let thread_handles: Vec<_> = (0..NUM_THREADS)
+        .map(|_| {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || (0..NUM_COUNTS).map(|_| counter.next()).collect::<Vec<_>>())
+        })
+        .collect();
+
+    let mut results: Vec<usize> = thread_handles
+        .into_iter()
+        .flat_map(|handle| handle.join().unwrap())
+        .collect();
+    jitter::clear_delays();
+
+    results.sort();
+    assert_eq!(results, (0..(NUM_THREADS * NUM_COUNTS)).collect::<Vec<_>>());
+    assert!(jitter::injected_delays() > before);
+}